@@ -22,6 +22,7 @@ in pkgs.mkShell {
 	buildInputs = [
 		llvm
 		pkgs.cmake pkgs.ninja
+		pkgs.spirv-tools
 	];
 	shellHook = ''
 		export PATH=${llvm}/bin:$PATH
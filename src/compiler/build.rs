@@ -0,0 +1,59 @@
+//! Identifies the build of the compiler by a hash of the sources it lowers
+//! files with, its own and those of melior, which the cache of lowered files
+//! is keyed on.
+
+use std::{
+	fs, io,
+	path::{Path, PathBuf},
+};
+
+use mlnx_store::hash;
+
+/// The directories of the sources, relative to the package.
+const SOURCES: &[&str] = &[".", "../melior"];
+
+fn main() -> io::Result<()> {
+	let mut files = Vec::new();
+
+	for directory in SOURCES {
+		println!("cargo:rerun-if-changed={directory}");
+		collect(Path::new(directory), &mut files)?;
+	}
+
+	files.sort();
+
+	let mut sources = Vec::new();
+
+	for file in files {
+		sources.extend(file.to_string_lossy().as_bytes());
+		sources.push(0);
+		sources.extend(fs::read(&file)?);
+		sources.push(0);
+	}
+
+	println!(
+		"cargo:rustc-env=MLNX_BUILD={}",
+		hash::base32(&hash::sha256(&sources))
+	);
+
+	Ok(())
+}
+
+/// Collects the Rust sources and manifests in a directory, except tests.
+fn collect(directory: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+	for entry in fs::read_dir(directory)? {
+		let path = entry?.path();
+
+		if path.is_dir() {
+			if !path.ends_with("tests") {
+				collect(&path, files)?;
+			}
+		} else if path.extension().is_some_and(|extension| extension == "rs")
+			|| path.ends_with("Cargo.toml")
+		{
+			files.push(path);
+		}
+	}
+
+	Ok(())
+}
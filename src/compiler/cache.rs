@@ -0,0 +1,199 @@
+//! A cache of lowered files.
+//!
+//! Each file is lowered to a function of its own (see [`lower`](crate::lower)),
+//! which depends on nothing but the file, so that the functions of unchanged
+//! files can be reused across runs. The cache stores them in a directory, one
+//! textual module per file with the locations of its operations, named after
+//! the hash of the build of the compiler, the path of the file as given and as
+//! absolute, and its contents.
+
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+
+use anyhow::{Context as _, Result};
+use melior::{
+	ir::{Module, Operation, OperationRef},
+	Context,
+};
+use mlnx_store::hash;
+
+use crate::{dialect, source::Source};
+
+/// The build of the compiler, a hash of its sources computed by the build
+/// script, which invalidates the cache whenever the compiler changes.
+const BUILD: &str = env!("MLNX_BUILD");
+
+/// The attribute of cached modules holding the paths the file refers to.
+const REFERENCES: &str = "mlnx.references";
+
+/// A cache of lowered files in a directory.
+#[derive(Clone, Debug)]
+pub struct Cache {
+	directory: PathBuf,
+}
+
+impl Cache {
+	pub fn new(directory: impl Into<PathBuf>) -> Self {
+		Self {
+			directory: directory.into(),
+		}
+	}
+
+	/// Gets the default directory of the cache, `mlnx` in the cache directory
+	/// of the user.
+	pub fn default_directory() -> Option<PathBuf> {
+		std::env::var_os("XDG_CACHE_HOME")
+			.filter(|directory| !directory.is_empty())
+			.map(PathBuf::from)
+			.or_else(|| {
+				std::env::var_os("HOME")
+					.map(|home| Path::new(&home).join(".cache"))
+			})
+			.map(|directory| directory.join("mlnx"))
+	}
+
+	/// Gets the lowered file with an absolute path and a source, if it is
+	/// cached and readable.
+	pub fn get<'c>(
+		&self,
+		context: &'c Context,
+		path: &str,
+		source: &Source,
+	) -> Option<Entry<'c>> {
+		let text = fs::read_to_string(self.file(path, source)).ok()?;
+		let entry = Entry(Module::parse(context, &text)?);
+
+		// Entries written by other builds of the compiler are named
+		// differently, but truncated ones are lowered again.
+		entry.function()?;
+		entry.references()?;
+
+		Some(entry)
+	}
+
+	/// Caches a lowered file with an absolute path and a source.
+	pub fn insert(
+		&self,
+		path: &str,
+		source: &Source,
+		entry: &Entry,
+	) -> Result<()> {
+		let file = self.file(path, source);
+		// Files are renamed into place so that concurrent runs never read
+		// them partially written.
+		let temporary =
+			file.with_extension(format!("{}.tmp", std::process::id()));
+
+		fs::create_dir_all(&self.directory).with_context(|| {
+			format!("Failed to create {}", self.directory.display())
+		})?;
+		fs::write(&temporary, entry.0.as_operation().debug_print())
+			.with_context(|| {
+				format!("Failed to write {}", temporary.display())
+			})?;
+		fs::rename(&temporary, &file)
+			.with_context(|| format!("Failed to write {}", file.display()))
+	}
+
+	fn file(&self, path: &str, source: &Source) -> PathBuf {
+		let key = hash::sha256(
+			format!("{BUILD}\0{path}\0{}\0{}", source.path(), source.text())
+				.as_bytes(),
+		);
+
+		self.directory
+			.join(hash::base32(&key))
+			.with_extension("mlir")
+	}
+}
+
+/// A lowered file, as a module holding the function of the file.
+pub struct Entry<'c>(Module<'c>);
+
+impl<'c> Entry<'c> {
+	/// Creates an entry of the function of a file, with the paths the file
+	/// refers to, which are queued for lowering in turn.
+	pub fn new(
+		context: &'c Context,
+		function: &Operation,
+		references: &[String],
+	) -> Result<Self> {
+		let references = references
+			.iter()
+			.map(|path| dialect::string(path))
+			.collect::<Vec<_>>()
+			.join(", ");
+		let module = Module::parse(
+			context,
+			&format!(
+				"module attributes {{{REFERENCES} = [{references}]}} {{}}"
+			),
+		)
+		.context("Failed to create cache entry")?;
+		module.body().append_operation(function.clone());
+
+		Ok(Self(module))
+	}
+
+	/// Gets the function of the file.
+	pub fn function(&self) -> Option<OperationRef> {
+		self.0.body().first_operation()
+	}
+
+	/// Gets the paths the file refers to.
+	pub fn references(&self) -> Option<Vec<String>> {
+		self.0
+			.as_operation()
+			.attribute(REFERENCES)?
+			.elements()?
+			.iter()
+			.map(|path| path.string_value().map(String::from))
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::lower::Lowerer;
+
+	#[test]
+	fn reuse_entries() {
+		let context = Context::new();
+		dialect::load(&context);
+
+		let directory = std::env::temp_dir().join("mlnx-reuse-entries");
+		let _ = fs::remove_dir_all(&directory);
+		let cache = Cache::new(&directory);
+		let text = "[ 1 ./a.nix ]";
+		let source = Source::new("/a/b.nix", text);
+		let module = Lowerer::new(&context, Source::new("/a/b.nix", text))
+			.lower_root(&rnix::Root::parse(text).tree())
+			.unwrap();
+		let function = module.body().first_operation().unwrap();
+		let references = vec!["/a/a.nix".to_owned()];
+
+		assert!(cache.get(&context, "/a/b.nix", &source).is_none());
+
+		cache
+			.insert(
+				"/a/b.nix",
+				&source,
+				&Entry::new(&context, &function, &references).unwrap(),
+			)
+			.unwrap();
+		let entry = cache.get(&context, "/a/b.nix", &source).unwrap();
+
+		assert_eq!(entry.references(), Some(references));
+		assert_eq!(
+			entry.function().unwrap().debug_print(),
+			function.debug_print()
+		);
+		assert!(cache
+			.get(&context, "/a/b.nix", &Source::new("/a/b.nix", "[ 2 ]"))
+			.is_none());
+		assert!(cache.get(&context, "/a/c.nix", &source).is_none());
+	}
+}
@@ -0,0 +1,1613 @@
+//! Conversion of the `nix` dialect to the `llvm` dialect.
+//!
+//! Nix values become pointers to values of the runtime (see the `mlnx_runtime`
+//! crate and [`layout`]) and operations become calls to its functions, except
+//! for `nix.force` which only calls the runtime for thunks, and arithmetic and
+//! `nix.lt` which only call it when an operand is not an integer. The regions
+//! of `nix.thunk` and `nix.lambda` are outlined to functions taking an
+//! environment of the values they capture, `nix.letrec` binds blank thunks and
+//! `nix.if` becomes `scf.if`.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{bail, Context as _, Result};
+use melior::{
+	dialect::llvm,
+	ir::{
+		operation, Block, BlockRef, Location, Module, NamedAttribute,
+		OperationRef, Region, RegionRef, Type, Value,
+	},
+	Context,
+};
+
+use crate::{dialect, layout, lower::MAIN};
+
+/// The runtime functions called by converted code, with their result and
+/// parameter types. Values are passed as `value` pointers (see [`layout`]) and
+/// other pointers as opaque `ptr`s.
+const RUNTIME: &[(&str, &str, &[&str])] = &[
+	("nix_null", "value", &[]),
+	("nix_bool", "value", &["i32"]),
+	("nix_int", "value", &["i64"]),
+	("nix_float", "value", &["f64"]),
+	("nix_string", "value", &["ptr", "i64"]),
+	("nix_path", "value", &["ptr", "i64"]),
+	("nix_builtin", "value", &["ptr", "i64"]),
+	("nix_file", "void", &["ptr", "i64", "ptr"]),
+	("nix_env_new", "ptr", &["i64"]),
+	("nix_env_set", "void", &["ptr", "i64", "value"]),
+	("nix_env_get", "value", &["ptr", "i64"]),
+	(
+		"nix_thunk",
+		"value",
+		&["ptr", "ptr", "ptr", "i64", "ptr", "i64"],
+	),
+	("nix_blank", "value", &[]),
+	("nix_bind", "void", &["value", "value"]),
+	("nix_force", "value", &["value"]),
+	("nix_lambda", "value", &["ptr", "ptr", "ptr", "i64"]),
+	("nix_apply", "value", &["value", "value", "ptr", "i64"]),
+	(
+		"nix_formals",
+		"void",
+		&["value", "ptr", "i64", "ptr", "i64", "ptr", "i64", "i32"],
+	),
+	("nix_attrset_new", "value", &[]),
+	(
+		"nix_attrset_insert",
+		"void",
+		&["value", "ptr", "i64", "value"],
+	),
+	(
+		"nix_attrset_insert_dynamic",
+		"void",
+		&["value", "value", "value"],
+	),
+	("nix_select", "value", &["value", "ptr", "i64", "value"]),
+	("nix_select_dynamic", "value", &["value", "ptr", "value"]),
+	("nix_has_attr", "value", &["value", "ptr", "i64"]),
+	("nix_has_attr_dynamic", "value", &["value", "ptr"]),
+	("nix_update", "value", &["value", "value"]),
+	("nix_list_new", "value", &["i64"]),
+	("nix_list_set", "void", &["value", "i64", "value"]),
+	("nix_concat", "value", &["value", "value"]),
+	("nix_interpolate", "value", &["ptr"]),
+	("nix_with", "value", &["ptr", "i64", "ptr", "i64"]),
+	("nix_truthy", "i32", &["value"]),
+	("nix_assert", "void", &["value"]),
+	("nix_add", "value", &["value", "value", "ptr", "i64"]),
+	("nix_sub", "value", &["value", "value", "ptr", "i64"]),
+	("nix_mul", "value", &["value", "value", "ptr", "i64"]),
+	("nix_div", "value", &["value", "value", "ptr", "i64"]),
+	(
+		"nix_overflow",
+		"value",
+		&["i64", "i32", "i64", "ptr", "i64"],
+	),
+	("nix_division_by_zero", "value", &["ptr", "i64"]),
+	("nix_eq", "value", &["value", "value"]),
+	("nix_lt", "value", &["value", "value", "ptr", "i64"]),
+	("nix_not", "value", &["value"]),
+	("nix_run", "i32", &["ptr"]),
+];
+
+/// Converts a module of the `nix` dialect into a new module of the `llvm`
+/// dialect.
+pub fn convert<'c>(
+	context: &'c Context,
+	module: &'c Module<'c>,
+) -> Result<Module<'c>> {
+	Ok(converter(context, module)?.module)
+}
+
+/// Converts a module of the `nix` dialect as [`convert`] does, adding a C
+/// `main` function which prints the value of the program, so that the module
+/// can be linked with the runtime into an executable.
+pub fn convert_executable<'c>(
+	context: &'c Context,
+	module: &'c Module<'c>,
+) -> Result<Module<'c>> {
+	let mut converter = converter(context, module)?;
+	converter.entry(module.as_operation().location())?;
+
+	Ok(converter.module)
+}
+
+fn converter<'c>(
+	context: &'c Context,
+	module: &'c Module<'c>,
+) -> Result<Converter<'c>> {
+	let mut converter = Converter {
+		context,
+		module: Module::new(module.as_operation().location()),
+		declared: HashSet::new(),
+		strings: HashMap::new(),
+		functions: 0,
+	};
+	let mut function = module.body().first_operation();
+
+	while let Some(operation) = function {
+		converter.function(operation)?;
+		function = operation.next_in_block();
+	}
+
+	Ok(converter)
+}
+
+/// The converted values of a function, by their values in the `nix` dialect.
+struct Frame<'p, 'o, 'n> {
+	/// The frame of the region enclosing the converted one, if it belongs to
+	/// the same function.
+	parent: Option<&'p Frame<'p, 'o, 'n>>,
+	values: HashMap<Value<'o>, Value<'n>>,
+}
+
+impl<'p, 'o, 'n> Frame<'p, 'o, 'n> {
+	fn new(parent: Option<&'p Frame<'p, 'o, 'n>>) -> Self {
+		Self {
+			parent,
+			values: HashMap::new(),
+		}
+	}
+
+	fn get(&self, value: Value<'o>) -> Result<Value<'n>> {
+		match self.values.get(&value) {
+			Some(&value) => Ok(value),
+			None => self
+				.parent
+				.context("value used outside of its function")?
+				.get(value),
+		}
+	}
+}
+
+struct Converter<'c> {
+	context: &'c Context,
+	module: Module<'c>,
+	declared: HashSet<&'static str>,
+	/// The globals holding string constants, by their contents.
+	strings: HashMap<String, String>,
+	/// The number of outlined functions.
+	functions: usize,
+}
+
+impl<'c> Converter<'c> {
+	fn function(&mut self, function: OperationRef<'c>) -> Result<()> {
+		let operation = operation_name(function)?;
+
+		if operation != "func.func" {
+			bail!("cannot convert {operation} at module level");
+		}
+
+		let name = function
+			.attribute("sym_name")
+			.and_then(|name| name.string_value())
+			.context("function without name")?;
+		let body = Block::new(&[]);
+
+		{
+			let mut frame = Frame::new(None);
+			let values =
+				self.block(&body, &mut frame, entry(function.region(0))?)?;
+			self.r#return(&body, &values, function.location());
+		}
+
+		self.define(name, &[], body, "external", function.location())
+	}
+
+	/// Defines the C `main` function of an executable, which evaluates the
+	/// program with `nix_run` and returns its exit status.
+	fn entry(&mut self, location: Location<'c>) -> Result<()> {
+		let body = Block::new(&[]);
+
+		{
+			let main = self.address(&body, MAIN, location)?;
+			let status = self.call(&body, "nix_run", &[main], location)?;
+			self.r#return(&body, &[status], location);
+		}
+
+		let region = Region::new();
+		region.append_block(body);
+
+		self.module.body().append_operation(
+			operation::Builder::new("llvm.func", location)
+				.add_attributes(&NamedAttribute::new_parsed_vec(
+					self.context,
+					&[
+						(
+							"function_type",
+							&llvm::r#type::function(
+								Type::integer(self.context, 32),
+								&[],
+								false,
+							)
+							.to_string(),
+						),
+						("sym_name", &dialect::string("main")),
+					],
+				)?)
+				.add_regions(vec![region])
+				.build(),
+		);
+
+		Ok(())
+	}
+
+	/// Converts the operations of a block, except its terminator, and returns
+	/// the converted operands of the terminator.
+	fn block<'a>(
+		&mut self,
+		block: &'a Block<'c>,
+		frame: &mut Frame<'_, 'c, 'a>,
+		source: BlockRef<'c>,
+	) -> Result<Vec<Value<'a>>> {
+		let mut current = source.first_operation();
+
+		while let Some(operation) = current {
+			if operation.next_in_block().is_none() {
+				return operands(frame, operation);
+			}
+
+			self.operation(block, frame, operation)?;
+			current = operation.next_in_block();
+		}
+
+		bail!("block without terminator")
+	}
+
+	fn operation<'a>(
+		&mut self,
+		block: &'a Block<'c>,
+		frame: &mut Frame<'_, 'c, 'a>,
+		operation: OperationRef<'c>,
+	) -> Result<()> {
+		let name = operation_name(operation)?;
+		let location = operation.location();
+		let operands = operands(frame, operation)?;
+
+		let value = match name.as_str() {
+			"nix.constant" => {
+				let value = operation
+					.attribute("value")
+					.context("constant without value")?;
+				let literal = if let Some(value) = value.bool_value() {
+					Some(("nix_bool", "i32", (value as i32).to_string()))
+				} else if let Some(value) = value.integer_value() {
+					Some(("nix_int", "i64", value.to_string()))
+				} else {
+					value.float_value().map(|value| {
+						(
+							"nix_float",
+							"f64",
+							format!("0x{:016X}", value.to_bits()),
+						)
+					})
+				};
+
+				match (literal, value.string_value()) {
+					(Some((function, r#type, literal)), _) => {
+						let constant =
+							self.constant(block, r#type, &literal, location)?;
+
+						self.call(block, function, &[constant], location)?
+					}
+					(None, Some(value)) => {
+						self.string_value(block, value, location)?
+					}
+					(None, None) => {
+						self.call(block, "nix_null", &[], location)?
+					}
+				}
+			}
+			"nix.path" => {
+				let path = operation
+					.attribute("value")
+					.and_then(|value| value.string_value())
+					.context("path without value")?;
+				let (pointer, length) = self.string(block, path, location)?;
+
+				self.call(block, "nix_path", &[pointer, length], location)?
+			}
+			"nix.builtin" => {
+				let name = operation
+					.attribute("name")
+					.and_then(|name| name.string_value())
+					.context("builtin without name")?;
+				let (pointer, length) = self.string(block, name, location)?;
+
+				self.call(block, "nix_builtin", &[pointer, length], location)?
+			}
+			"nix.file" => {
+				let path = operation
+					.attribute("path")
+					.and_then(|path| path.string_value())
+					.context("file without path")?;
+				let (pointer, length) = self.string(block, path, location)?;
+				let function = self.address(block, path, location)?;
+
+				return self.call_void(
+					block,
+					"nix_file",
+					&[pointer, length, function],
+					location,
+				);
+			}
+			"nix.thunk" => {
+				self.closure(block, frame, operation, "nix_thunk")?
+			}
+			"nix.lambda" => {
+				self.closure(block, frame, operation, "nix_lambda")?
+			}
+			"nix.letrec" => {
+				let body = entry(operation.region(0))?;
+				let blanks = (0..operation.result_count())
+					.map(|_| self.call(block, "nix_blank", &[], location))
+					.collect::<Result<Vec<_>>>()?;
+
+				for (index, &blank) in blanks.iter().enumerate() {
+					frame.values.insert(body.argument(index)?.into(), blank);
+				}
+
+				let values = self.block(block, frame, body)?;
+
+				for (&blank, &value) in blanks.iter().zip(&values) {
+					self.call_void(
+						block,
+						"nix_bind",
+						&[blank, value],
+						location,
+					)?;
+				}
+
+				return self.map(frame, operation, &blanks);
+			}
+			"nix.attrset" => {
+				let set = self.call(block, "nix_attrset_new", &[], location)?;
+				let names = strings(operation, "names")?;
+
+				for (name, &value) in names.iter().zip(&operands) {
+					let (pointer, length) =
+						self.string(block, name, location)?;
+					self.call_void(
+						block,
+						"nix_attrset_insert",
+						&[set, pointer, length, value],
+						location,
+					)?;
+				}
+
+				for pair in operands[names.len()..].chunks(2) {
+					self.call_void(
+						block,
+						"nix_attrset_insert_dynamic",
+						&[set, pair[0], pair[1]],
+						location,
+					)?;
+				}
+
+				set
+			}
+			"nix.list" => {
+				let size = self.constant(
+					block,
+					"i64",
+					&operands.len().to_string(),
+					location,
+				)?;
+				let list =
+					self.call(block, "nix_list_new", &[size], location)?;
+
+				for (index, &element) in operands.iter().enumerate() {
+					let index = self.constant(
+						block,
+						"i64",
+						&index.to_string(),
+						location,
+					)?;
+					self.call_void(
+						block,
+						"nix_list_set",
+						&[list, index, element],
+						location,
+					)?;
+				}
+
+				list
+			}
+			"nix.interpolate" => {
+				let parts = self.env(block, &operands, location)?;
+
+				self.call(block, "nix_interpolate", &[parts], location)?
+			}
+			"nix.force" => self.force(block, operands[0], location)?,
+			"nix.select" | "nix.has_attr" => {
+				let path = operation
+					.attribute("path")
+					.and_then(|path| path.elements())
+					.context("missing path attribute")?;
+				let dynamic = path.iter().filter(|name| name.is_unit()).count();
+				let mut arguments = vec![operands[0]];
+				let mut function = name.replace('.', "_");
+
+				if dynamic == 0 {
+					let path = strings(operation, "path")?.join("\0");
+					let (pointer, length) =
+						self.string(block, &path, location)?;
+					arguments.extend([pointer, length]);
+				} else {
+					let mut computed = operands[1..].iter();
+					let names = path
+						.iter()
+						.map(|name| match name.string_value() {
+							Some(name) => {
+								self.string_value(block, name, location)
+							}
+							None => computed
+								.next()
+								.copied()
+								.context("missing computed name"),
+						})
+						.collect::<Result<Vec<_>>>()?;
+
+					arguments.push(self.env(block, &names, location)?);
+					function.push_str("_dynamic");
+				}
+
+				if name == "nix.select" {
+					arguments.push(match operands.get(1 + dynamic) {
+						Some(&default) => default,
+						None => self.null(
+							block,
+							layout::value_pointer_type(self.context),
+							location,
+						)?,
+					});
+				}
+
+				self.call(block, &function, &arguments, location)?
+			}
+			"nix.with" => {
+				let name = operation
+					.attribute("name")
+					.and_then(|name| name.string_value())
+					.context("with without name")?;
+				let namespaces = self.env(block, &operands, location)?;
+				let count = self.constant(
+					block,
+					"i64",
+					&operands.len().to_string(),
+					location,
+				)?;
+				let (pointer, length) = self.string(block, name, location)?;
+
+				self.call(
+					block,
+					"nix_with",
+					&[namespaces, count, pointer, length],
+					location,
+				)?
+			}
+			"nix.formals" => {
+				let function = operation
+					.attribute("function")
+					.and_then(|function| function.string_value())
+					.context("formals without function")?;
+				let (function, function_length) =
+					self.string(block, function, location)?;
+				let (names, names_length) = self.string(
+					block,
+					&strings(operation, "names")?.join("\0"),
+					location,
+				)?;
+				let (required, required_length) = self.string(
+					block,
+					&strings(operation, "required")?.join("\0"),
+					location,
+				)?;
+				let ellipsis = operation
+					.attribute("ellipsis")
+					.and_then(|ellipsis| ellipsis.bool_value())
+					.context("formals without ellipsis")?;
+				let ellipsis = self.constant(
+					block,
+					"i32",
+					&(ellipsis as i32).to_string(),
+					location,
+				)?;
+
+				return self.call_void(
+					block,
+					"nix_formals",
+					&[
+						operands[0],
+						function,
+						function_length,
+						names,
+						names_length,
+						required,
+						required_length,
+						ellipsis,
+					],
+					location,
+				);
+			}
+			"nix.assert" => {
+				return self.call_void(block, "nix_assert", &operands, location)
+			}
+			"nix.if" => {
+				let condition =
+					self.call(block, "nix_truthy", &operands, location)?;
+				let zero = self.constant(block, "i32", "0", location)?;
+				let condition =
+					self.compare(block, "ne", condition, zero, location)?;
+				let then = self.branch(frame, operation.region(0), location)?;
+				let r#else =
+					self.branch(frame, operation.region(1), location)?;
+
+				self.r#if(block, condition, then, r#else, location)?
+			}
+			"nix.apply" => {
+				let (site, site_length) =
+					self.string(block, &position(location), location)?;
+
+				self.call(
+					block,
+					"nix_apply",
+					&[operands[0], operands[1], site, site_length],
+					location,
+				)?
+			}
+			// Type errors, integer overflows and divisions by zero are
+			// reported at the operator.
+			"nix.add" | "nix.sub" | "nix.mul" | "nix.div" | "nix.lt" => self
+				.arithmetic(block, &name, operands[0], operands[1], location)?,
+			"nix.update" | "nix.concat" | "nix.eq" | "nix.not" => {
+				self.call(block, &name.replace('.', "_"), &operands, location)?
+			}
+			_ => bail!("cannot convert {name}"),
+		};
+
+		self.map(frame, operation, &[value])
+	}
+
+	/// Outlines the region of a thunk or lambda to a function, and creates a
+	/// closure of it with `constructor`.
+	fn closure<'a>(
+		&mut self,
+		block: &'a Block<'c>,
+		frame: &Frame<'_, 'c, 'a>,
+		operation: OperationRef<'c>,
+		constructor: &str,
+	) -> Result<Value<'a>> {
+		let location = operation.location();
+		let region = operation.region(0).context("missing region")?;
+		let body = entry(Some(region))?;
+		let captures = captures(region)?;
+		let parameters = std::iter::once(layout::pointer_type(self.context))
+			.chain(
+				(0..body.argument_count())
+					.map(|_| layout::value_pointer_type(self.context)),
+			)
+			.collect::<Vec<_>>();
+		let function = Block::new(
+			&parameters
+				.iter()
+				.map(|&parameter| (parameter, location))
+				.collect::<Vec<_>>(),
+		);
+
+		{
+			let mut inner = Frame::new(None);
+			let env = function.argument(0)?.into();
+
+			for (index, &capture) in captures.iter().enumerate() {
+				let index = self.constant(
+					&function,
+					"i64",
+					&index.to_string(),
+					location,
+				)?;
+				let value = self.call(
+					&function,
+					"nix_env_get",
+					&[env, index],
+					location,
+				)?;
+				inner.values.insert(capture, value);
+			}
+
+			for index in 0..body.argument_count() {
+				inner.values.insert(
+					body.argument(index)?.into(),
+					function.argument(index + 1)?.into(),
+				);
+			}
+
+			let values = self.block(&function, &mut inner, body)?;
+			self.r#return(&function, &values, location);
+		}
+
+		let name = format!(
+			"{}.{}",
+			constructor.trim_start_matches("nix_"),
+			self.functions
+		);
+		self.functions += 1;
+		self.define(&name, &parameters, function, "internal", location)?;
+
+		let captures = captures
+			.into_iter()
+			.map(|capture| frame.get(capture))
+			.collect::<Result<Vec<_>>>()?;
+		let env = self.env(block, &captures, location)?;
+		let function = self.address(block, &name, location)?;
+
+		// Closures are named after the binding they are the value of, and
+		// thunks also take its position, to trace errors to them.
+		let (name, name_length) =
+			self.string(block, &binding(location), location)?;
+		let mut arguments = vec![function, env, name, name_length];
+
+		if constructor == "nix_thunk" {
+			let (site, site_length) =
+				self.string(block, &position(location), location)?;
+			arguments.extend([site, site_length]);
+		}
+
+		self.call(block, constructor, &arguments, location)
+	}
+
+	/// Converts a region of a `nix.if` to a region of an `scf.if`.
+	fn branch(
+		&mut self,
+		frame: &Frame<'_, 'c, '_>,
+		region: Option<RegionRef<'c>>,
+		location: Location<'c>,
+	) -> Result<Region> {
+		let block = Block::new(&[]);
+
+		{
+			let mut inner = Frame::new(Some(frame));
+			let values = self.block(&block, &mut inner, entry(region)?)?;
+
+			block.append_operation(
+				operation::Builder::new("scf.yield", location)
+					.add_operands(&values)
+					.build(),
+			);
+		}
+
+		let region = Region::new();
+		region.append_block(block);
+
+		Ok(region)
+	}
+
+	/// Forces a value, calling the runtime only if it is a thunk.
+	fn force<'a>(
+		&mut self,
+		block: &'a Block<'c>,
+		value: Value<'a>,
+		location: Location<'c>,
+	) -> Result<Value<'a>> {
+		let tag = self.tag(block, value, location)?;
+		let thunk = self.constant(
+			block,
+			"i32",
+			&layout::tag::THUNK.to_string(),
+			location,
+		)?;
+		let condition = self.compare(block, "eq", tag, thunk, location)?;
+
+		let then = Block::new(&[]);
+		let forced = self.call(&then, "nix_force", &[value], location)?;
+		self.r#yield(&then, forced, location);
+
+		let r#else = Block::new(&[]);
+		self.r#yield(&r#else, value, location);
+
+		self.select(block, condition, then, r#else, location)
+	}
+
+	/// Applies an arithmetic operator or `<` to values, inline if both are
+	/// integers and with the runtime otherwise. Type errors, integer overflows
+	/// and divisions by zero are reported at the operator.
+	fn arithmetic<'a>(
+		&mut self,
+		block: &'a Block<'c>,
+		name: &str,
+		lhs: Value<'a>,
+		rhs: Value<'a>,
+		location: Location<'c>,
+	) -> Result<Value<'a>> {
+		let site = self.string(block, &position(location), location)?;
+		let int = self.constant(
+			block,
+			"i32",
+			&layout::tag::INT.to_string(),
+			location,
+		)?;
+		let lhs_tag = self.tag(block, lhs, location)?;
+		let lhs_int = self.compare(block, "eq", lhs_tag, int, location)?;
+		let rhs_tag = self.tag(block, rhs, location)?;
+		let rhs_int = self.compare(block, "eq", rhs_tag, int, location)?;
+		let ints = self.and(block, lhs_int, rhs_int, location)?;
+
+		let then = Block::new(&[]);
+		{
+			let lhs = self.payload(&then, lhs, location)?;
+			let rhs = self.payload(&then, rhs, location)?;
+			let value = if name == "nix.lt" {
+				let less = self.compare(&then, "slt", lhs, rhs, location)?;
+				let less = append(
+					&then,
+					operation::Builder::new("arith.extui", location)
+						.add_operands(&[less])
+						.add_results(&[Type::integer(self.context, 32)]),
+				)?;
+
+				self.call(&then, "nix_bool", &[less], location)?
+			} else {
+				self.integers(&then, name, lhs, rhs, site, location)?
+			};
+			self.r#yield(&then, value, location);
+		}
+
+		let r#else = Block::new(&[]);
+		{
+			let value = self.call(
+				&r#else,
+				&name.replace('.', "_"),
+				&[lhs, rhs, site.0, site.1],
+				location,
+			)?;
+			self.r#yield(&r#else, value, location);
+		}
+
+		self.select(block, ints, then, r#else, location)
+	}
+
+	/// Applies an arithmetic operator to integers, throwing at the operator on
+	/// divisions by zero.
+	fn integers<'a>(
+		&mut self,
+		block: &'a Block<'c>,
+		name: &str,
+		lhs: Value<'a>,
+		rhs: Value<'a>,
+		site: (Value<'a>, Value<'a>),
+		location: Location<'c>,
+	) -> Result<Value<'a>> {
+		if name != "nix.div" {
+			return self.checked(block, name, lhs, rhs, site, location);
+		}
+
+		let zero = self.constant(block, "i64", "0", location)?;
+		let condition = self.compare(block, "eq", rhs, zero, location)?;
+
+		let then = Block::new(&[]);
+		{
+			let error = self.call(
+				&then,
+				"nix_division_by_zero",
+				&[site.0, site.1],
+				location,
+			)?;
+			self.r#yield(&then, error, location);
+		}
+
+		let r#else = Block::new(&[]);
+		{
+			let value =
+				self.checked(&r#else, name, lhs, rhs, site, location)?;
+			self.r#yield(&r#else, value, location);
+		}
+
+		self.select(block, condition, then, r#else, location)
+	}
+
+	/// Applies an arithmetic operator to integers, throwing at the operator on
+	/// overflow, which the `llvm.intr.*.with.overflow` intrinsics detect except
+	/// for the division of the minimum by -1.
+	fn checked<'a>(
+		&mut self,
+		block: &'a Block<'c>,
+		name: &str,
+		lhs: Value<'a>,
+		rhs: Value<'a>,
+		(site, site_length): (Value<'a>, Value<'a>),
+		location: Location<'c>,
+	) -> Result<Value<'a>> {
+		let (operation, intrinsic, symbol) = match name {
+			"nix.add" => ("arith.addi", "llvm.intr.sadd.with.overflow", '+'),
+			"nix.sub" => ("arith.subi", "llvm.intr.ssub.with.overflow", '-'),
+			"nix.mul" => ("arith.muli", "llvm.intr.smul.with.overflow", '*'),
+			"nix.div" => ("arith.divsi", "", '/'),
+			_ => bail!("unknown arithmetic operator {name}"),
+		};
+		let int = Type::integer(self.context, 64);
+		let bool = Type::integer(self.context, 1);
+
+		let overflow = if intrinsic.is_empty() {
+			let min =
+				self.constant(block, "i64", &i64::MIN.to_string(), location)?;
+			let minus_one = self.constant(block, "i64", "-1", location)?;
+			let lhs_min = self.compare(block, "eq", lhs, min, location)?;
+			let rhs_minus_one =
+				self.compare(block, "eq", rhs, minus_one, location)?;
+
+			self.and(block, lhs_min, rhs_minus_one, location)?
+		} else {
+			let result = append(
+				block,
+				operation::Builder::new(intrinsic, location)
+					.add_operands(&[lhs, rhs])
+					.add_results(&[llvm::r#type::r#struct(
+						self.context,
+						&[int, bool],
+						false,
+					)]),
+			)?;
+
+			append(
+				block,
+				operation::Builder::new("llvm.extractvalue", location)
+					.add_operands(&[result])
+					.add_results(&[bool])
+					.add_attributes(&NamedAttribute::new_parsed_vec(
+						self.context,
+						&[("position", "array<i64: 1>")],
+					)?),
+			)?
+		};
+
+		let then = Block::new(&[]);
+		{
+			let symbol = self.constant(
+				&then,
+				"i32",
+				&(symbol as u32).to_string(),
+				location,
+			)?;
+			let error = self.call(
+				&then,
+				"nix_overflow",
+				&[lhs, symbol, rhs, site, site_length],
+				location,
+			)?;
+			self.r#yield(&then, error, location);
+		}
+
+		let r#else = Block::new(&[]);
+		{
+			let result = append(
+				&r#else,
+				operation::Builder::new(operation, location)
+					.add_operands(&[lhs, rhs])
+					.add_results(&[int]),
+			)?;
+			let value = self.call(&r#else, "nix_int", &[result], location)?;
+			self.r#yield(&r#else, value, location);
+		}
+
+		self.select(block, overflow, then, r#else, location)
+	}
+
+	/// Loads the tag of a value.
+	fn tag<'a>(
+		&self,
+		block: &'a Block<'c>,
+		value: Value<'a>,
+		location: Location<'c>,
+	) -> Result<Value<'a>> {
+		let tag = append(
+			block,
+			operation::Builder::new("llvm.getelementptr", location)
+				.add_operands(&[value])
+				.add_results(&[layout::tag_pointer_type(self.context)])
+				.add_attributes(&NamedAttribute::new_parsed_vec(
+					self.context,
+					&[("rawConstantIndices", "array<i32: 0, 0>")],
+				)?),
+		)?;
+
+		append(
+			block,
+			operation::Builder::new("llvm.load", location)
+				.add_operands(&[tag])
+				.add_results(&[Type::integer(self.context, 32)]),
+		)
+	}
+
+	/// Loads the payload of a value, which is the integer itself for integers.
+	fn payload<'a>(
+		&self,
+		block: &'a Block<'c>,
+		value: Value<'a>,
+		location: Location<'c>,
+	) -> Result<Value<'a>> {
+		let payload = append(
+			block,
+			operation::Builder::new("llvm.getelementptr", location)
+				.add_operands(&[value])
+				.add_results(&[layout::payload_pointer_type(self.context)])
+				.add_attributes(&NamedAttribute::new_parsed_vec(
+					self.context,
+					&[("rawConstantIndices", "array<i32: 0, 1>")],
+				)?),
+		)?;
+
+		append(
+			block,
+			operation::Builder::new("llvm.load", location)
+				.add_operands(&[payload])
+				.add_results(&[Type::integer(self.context, 64)]),
+		)
+	}
+
+	/// Compares integers with an `arith.cmpi` predicate.
+	fn compare<'a>(
+		&self,
+		block: &'a Block<'c>,
+		predicate: &str,
+		lhs: Value<'a>,
+		rhs: Value<'a>,
+		location: Location<'c>,
+	) -> Result<Value<'a>> {
+		let predicate = match predicate {
+			"eq" => 0,
+			"ne" => 1,
+			"slt" => 2,
+			_ => bail!("unknown predicate {predicate}"),
+		};
+
+		append(
+			block,
+			operation::Builder::new("arith.cmpi", location)
+				.add_operands(&[lhs, rhs])
+				.add_results(&[Type::integer(self.context, 1)])
+				.add_attributes(&NamedAttribute::new_parsed_vec(
+					self.context,
+					&[("predicate", &format!("{predicate} : i64"))],
+				)?),
+		)
+	}
+
+	/// Computes the conjunction of Booleans.
+	fn and<'a>(
+		&self,
+		block: &'a Block<'c>,
+		lhs: Value<'a>,
+		rhs: Value<'a>,
+		location: Location<'c>,
+	) -> Result<Value<'a>> {
+		append(
+			block,
+			operation::Builder::new("arith.andi", location)
+				.add_operands(&[lhs, rhs])
+				.add_results(&[Type::integer(self.context, 1)]),
+		)
+	}
+
+	/// Creates an `scf.if` evaluating to a value, whose branches are blocks
+	/// ended with [`Self::r#yield`].
+	fn select<'a>(
+		&self,
+		block: &'a Block<'c>,
+		condition: Value<'a>,
+		then: Block<'c>,
+		r#else: Block<'c>,
+		location: Location<'c>,
+	) -> Result<Value<'a>> {
+		let [then, r#else] = [then, r#else].map(|block| {
+			let region = Region::new();
+			region.append_block(block);
+			region
+		});
+
+		self.r#if(block, condition, then, r#else, location)
+	}
+
+	/// Ends a block of an `scf.if` with the value it evaluates to.
+	fn r#yield(&self, block: &Block<'c>, value: Value, location: Location<'c>) {
+		block.append_operation(
+			operation::Builder::new("scf.yield", location)
+				.add_operands(&[value])
+				.build(),
+		);
+	}
+
+	/// Creates an `scf.if` evaluating to a value.
+	fn r#if<'a>(
+		&self,
+		block: &'a Block<'c>,
+		condition: Value<'a>,
+		then: Region,
+		r#else: Region,
+		location: Location<'c>,
+	) -> Result<Value<'a>> {
+		append(
+			block,
+			operation::Builder::new("scf.if", location)
+				.add_operands(&[condition])
+				.add_results(&[layout::value_pointer_type(self.context)])
+				.add_regions(vec![then, r#else]),
+		)
+	}
+
+	fn map<'a>(
+		&self,
+		frame: &mut Frame<'_, 'c, 'a>,
+		operation: OperationRef<'c>,
+		values: &[Value<'a>],
+	) -> Result<()> {
+		for (index, &value) in values.iter().enumerate() {
+			frame.values.insert(operation.result(index)?.into(), value);
+		}
+
+		Ok(())
+	}
+
+	/// Creates an environment of values, which is null if there are none.
+	fn env<'a>(
+		&mut self,
+		block: &'a Block<'c>,
+		values: &[Value<'a>],
+		location: Location<'c>,
+	) -> Result<Value<'a>> {
+		if values.is_empty() {
+			return self.null(
+				block,
+				layout::pointer_type(self.context),
+				location,
+			);
+		}
+
+		let size =
+			self.constant(block, "i64", &values.len().to_string(), location)?;
+		let env = self.call(block, "nix_env_new", &[size], location)?;
+
+		for (index, &value) in values.iter().enumerate() {
+			let index =
+				self.constant(block, "i64", &index.to_string(), location)?;
+			self.call_void(
+				block,
+				"nix_env_set",
+				&[env, index, value],
+				location,
+			)?;
+		}
+
+		Ok(env)
+	}
+
+	/// Gets a pointer to a constant string and its length.
+	fn string<'a>(
+		&mut self,
+		block: &'a Block<'c>,
+		value: &str,
+		location: Location<'c>,
+	) -> Result<(Value<'a>, Value<'a>)> {
+		let length =
+			self.constant(block, "i64", &value.len().to_string(), location)?;
+
+		if value.is_empty() {
+			return Ok((
+				self.null(block, layout::pointer_type(self.context), location)?,
+				length,
+			));
+		}
+
+		let count = self.strings.len();
+		let symbol = self
+			.strings
+			.entry(value.into())
+			.or_insert_with(|| format!("string.{count}"))
+			.clone();
+
+		if self.strings.len() > count {
+			self.module.body().append_operation(
+				operation::Builder::new("llvm.mlir.global", location)
+					.add_attributes(&NamedAttribute::new_parsed_vec(
+						self.context,
+						&[
+							("constant", "unit"),
+							(
+								"global_type",
+								&llvm::r#type::array(
+									Type::integer(self.context, 8),
+									value.len() as u32,
+								)
+								.to_string(),
+							),
+							("linkage", "#llvm.linkage<internal>"),
+							("sym_name", &dialect::string(&symbol)),
+							("value", &dialect::string(value)),
+						],
+					)?)
+					.add_regions(vec![Region::new()])
+					.build(),
+			);
+		}
+
+		Ok((self.address(block, &symbol, location)?, length))
+	}
+
+	/// Creates a string value without context.
+	fn string_value<'a>(
+		&mut self,
+		block: &'a Block<'c>,
+		value: &str,
+		location: Location<'c>,
+	) -> Result<Value<'a>> {
+		let (pointer, length) = self.string(block, value, location)?;
+
+		self.call(block, "nix_string", &[pointer, length], location)
+	}
+
+	fn define(
+		&self,
+		name: &str,
+		parameters: &[Type<'c>],
+		body: Block<'c>,
+		linkage: &str,
+		location: Location<'c>,
+	) -> Result<()> {
+		let region = Region::new();
+		region.append_block(body);
+
+		self.module.body().append_operation(
+			operation::Builder::new("llvm.func", location)
+				.add_attributes(&NamedAttribute::new_parsed_vec(
+					self.context,
+					&[
+						(
+							"function_type",
+							&llvm::r#type::function(
+								layout::value_pointer_type(self.context),
+								parameters,
+								false,
+							)
+							.to_string(),
+						),
+						("linkage", &format!("#llvm.linkage<{linkage}>")),
+						("sym_name", &dialect::string(name)),
+					],
+				)?)
+				.add_regions(vec![region])
+				.build(),
+		);
+
+		Ok(())
+	}
+
+	fn r#return(
+		&self,
+		block: &Block<'c>,
+		values: &[Value],
+		location: Location<'c>,
+	) {
+		block.append_operation(
+			operation::Builder::new("llvm.return", location)
+				.add_operands(values)
+				.build(),
+		);
+	}
+
+	fn call<'a>(
+		&mut self,
+		block: &'a Block<'c>,
+		function: &str,
+		arguments: &[Value<'a>],
+		location: Location<'c>,
+	) -> Result<Value<'a>> {
+		self.call_runtime(block, function, arguments, location)?
+			.with_context(|| format!("{function} returns no value"))
+	}
+
+	fn call_void<'a>(
+		&mut self,
+		block: &'a Block<'c>,
+		function: &str,
+		arguments: &[Value<'a>],
+		location: Location<'c>,
+	) -> Result<()> {
+		self.call_runtime(block, function, arguments, location)?;
+
+		Ok(())
+	}
+
+	/// Calls a runtime function, declaring it on first use.
+	fn call_runtime<'a>(
+		&mut self,
+		block: &'a Block<'c>,
+		function: &str,
+		arguments: &[Value<'a>],
+		location: Location<'c>,
+	) -> Result<Option<Value<'a>>> {
+		let &(name, result, parameters) = RUNTIME
+			.iter()
+			.find(|(name, ..)| *name == function)
+			.with_context(|| format!("unknown runtime function {function}"))?;
+		let result = self.r#type(result);
+
+		if self.declared.insert(name) {
+			let parameters = parameters
+				.iter()
+				.map(|parameter| {
+					self.r#type(parameter).context("void parameter")
+				})
+				.collect::<Result<Vec<_>>>()?;
+			let function_type = llvm::r#type::function(
+				result.unwrap_or_else(|| llvm::r#type::void(self.context)),
+				&parameters,
+				false,
+			);
+
+			self.module.body().append_operation(
+				operation::Builder::new("llvm.func", location)
+					.add_attributes(&NamedAttribute::new_parsed_vec(
+						self.context,
+						&[
+							("function_type", &function_type.to_string()),
+							("sym_name", &dialect::string(name)),
+						],
+					)?)
+					.add_regions(vec![Region::new()])
+					.build(),
+			);
+		}
+
+		let call = block.append_operation(
+			operation::Builder::new("llvm.call", location)
+				.add_operands(arguments)
+				.add_results(result.as_slice())
+				.add_attributes(&NamedAttribute::new_parsed_vec(
+					self.context,
+					&[("callee", &format!("@{name}"))],
+				)?)
+				.build(),
+		);
+
+		Ok(match result {
+			Some(_) => Some(call.result(0)?.into()),
+			None => None,
+		})
+	}
+
+	fn constant<'a>(
+		&self,
+		block: &'a Block<'c>,
+		r#type: &str,
+		literal: &str,
+		location: Location<'c>,
+	) -> Result<Value<'a>> {
+		append(
+			block,
+			operation::Builder::new("llvm.mlir.constant", location)
+				.add_results(&[self.r#type(r#type).context("void constant")?])
+				.add_attributes(&NamedAttribute::new_parsed_vec(
+					self.context,
+					&[("value", &format!("{literal} : {type}"))],
+				)?),
+		)
+	}
+
+	fn null<'a>(
+		&self,
+		block: &'a Block<'c>,
+		r#type: Type<'c>,
+		location: Location<'c>,
+	) -> Result<Value<'a>> {
+		append(
+			block,
+			operation::Builder::new("llvm.mlir.null", location)
+				.add_results(&[r#type]),
+		)
+	}
+
+	fn address<'a>(
+		&self,
+		block: &'a Block<'c>,
+		symbol: &str,
+		location: Location<'c>,
+	) -> Result<Value<'a>> {
+		append(
+			block,
+			operation::Builder::new("llvm.mlir.addressof", location)
+				.add_results(&[layout::pointer_type(self.context)])
+				.add_attributes(&NamedAttribute::new_parsed_vec(
+					self.context,
+					&[(
+						"global_name",
+						&format!("@{}", dialect::string(symbol)),
+					)],
+				)?),
+		)
+	}
+
+	/// Gets a type of [`RUNTIME`], which is `None` for `void`.
+	fn r#type(&self, name: &str) -> Option<Type<'c>> {
+		match name {
+			"void" => None,
+			"value" => Some(layout::value_pointer_type(self.context)),
+			"ptr" => Some(layout::pointer_type(self.context)),
+			_ => Type::parse(self.context, name),
+		}
+	}
+}
+
+/// Gets the first source position of a location as `file:line:column`, or
+/// an empty string if it has none.
+fn position(location: Location) -> String {
+	let text = location.to_string();
+	let mut rest = text.as_str();
+
+	// File names are quoted and followed by a line and a column, unlike the
+	// names of name locations and fused locations.
+	while let Some((_, quoted)) = rest.split_once('"') {
+		let Some((file, after)) = quoted.split_once('"') else {
+			break;
+		};
+
+		if let Some(after) = after.strip_prefix(':') {
+			let end = after
+				.find(|character: char| {
+					!character.is_ascii_digit() && character != ':'
+				})
+				.unwrap_or(after.len());
+
+			return format!("{file}:{}", &after[..end]);
+		}
+
+		rest = after;
+	}
+
+	String::new()
+}
+
+/// Gets the name of a name location, such as those of bindings, or an empty
+/// string if it is another kind of location.
+fn binding(location: Location) -> String {
+	let text = location.to_string();
+
+	text.strip_prefix("loc(\"")
+		.and_then(|rest| rest.split_once("\"("))
+		.map(|(name, _)| name.to_owned())
+		.unwrap_or_default()
+}
+
+fn operation_name(operation: OperationRef) -> Result<String> {
+	Ok(operation.name().as_string_ref().as_str()?.to_owned())
+}
+
+fn append<'a>(
+	block: &'a Block,
+	builder: operation::Builder,
+) -> Result<Value<'a>> {
+	Ok(block.append_operation(builder.build()).result(0)?.into())
+}
+
+fn entry(region: Option<RegionRef>) -> Result<BlockRef> {
+	region
+		.context("missing region")?
+		.first_block()
+		.context("empty region")
+}
+
+fn operands<'o, 'a>(
+	frame: &Frame<'_, 'o, 'a>,
+	operation: OperationRef<'o>,
+) -> Result<Vec<Value<'a>>> {
+	(0..operation.operand_count())
+		.map(|index| frame.get(operation.operand(index)?))
+		.collect()
+}
+
+fn strings<'c>(
+	operation: OperationRef<'c>,
+	name: &str,
+) -> Result<Vec<&'c str>> {
+	operation
+		.attribute(name)
+		.and_then(|attribute| attribute.elements())
+		.with_context(|| format!("missing {name} attribute"))?
+		.iter()
+		.map(|element| element.string_value().context("non-string name"))
+		.collect()
+}
+
+/// Gets the values used in a region but defined outside of it, in order of
+/// first use.
+pub(crate) fn captures<'o>(region: RegionRef<'o>) -> Result<Vec<Value<'o>>> {
+	let mut defined = HashSet::new();
+	let mut used = Vec::new();
+	collect_values(region, &mut defined, &mut used)?;
+
+	let mut captured = HashSet::new();
+
+	Ok(used
+		.into_iter()
+		.filter(|value| !defined.contains(value) && captured.insert(*value))
+		.collect())
+}
+
+fn collect_values<'o>(
+	region: RegionRef<'o>,
+	defined: &mut HashSet<Value<'o>>,
+	used: &mut Vec<Value<'o>>,
+) -> Result<()> {
+	let mut block = region.first_block();
+
+	while let Some(current) = block {
+		for index in 0..current.argument_count() {
+			defined.insert(current.argument(index)?.into());
+		}
+
+		let mut operation = current.first_operation();
+
+		while let Some(inner) = operation {
+			for index in 0..inner.operand_count() {
+				used.push(inner.operand(index)?);
+			}
+
+			for index in 0..inner.result_count() {
+				defined.insert(inner.result(index)?.into());
+			}
+
+			for index in 0..inner.region_count() {
+				collect_values(
+					inner.region(index).context("missing region")?,
+					defined,
+					used,
+				)?;
+			}
+
+			operation = inner.next_in_block();
+		}
+
+		block = current.next_in_region();
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{lower::Lowerer, source::Source};
+	use melior::{
+		dialect::Registry, ir::Attribute, utility::register_all_dialects,
+	};
+
+	fn context() -> Context {
+		let registry = Registry::new();
+		register_all_dialects(&registry);
+
+		let context = Context::new();
+		context.append_dialect_registry(&registry);
+		context.load_all_available_dialects();
+		dialect::load(&context);
+		context
+	}
+
+	/// Converts a file, checks that the result verifies and prints it.
+	fn assert_converted(text: &str) -> String {
+		let context = context();
+		let root = rnix::Root::parse(text).tree();
+		let module = Lowerer::new(&context, Source::new("test.nix", text))
+			.lower_root(&root)
+			.unwrap();
+		let converted = convert(&context, &module).unwrap();
+
+		assert!(converted.as_operation().verify());
+		converted.as_operation().to_string()
+	}
+
+	#[test]
+	fn format_positions() {
+		let context = context();
+		let location = Location::new(&context, "a.nix", 1, 2);
+
+		assert_eq!(position(location), "a.nix:1:2");
+		assert_eq!(
+			position(Location::name(&context, "f", location)),
+			"a.nix:1:2"
+		);
+		assert_eq!(
+			position(Location::fused(
+				&context,
+				&[location],
+				Attribute::parse(&context, "\"&&\"").unwrap(),
+			)),
+			"a.nix:1:2"
+		);
+		assert_eq!(position(Location::unknown(&context)), "");
+	}
+
+	#[test]
+	fn name_bindings() {
+		let context = context();
+		let location = Location::new(&context, "a.nix", 1, 2);
+
+		assert_eq!(binding(Location::name(&context, "f", location)), "f");
+		assert_eq!(binding(location), "");
+		assert_eq!(binding(Location::unknown(&context)), "");
+	}
+
+	#[test]
+	fn convert_closures() {
+		assert_converted("let f = x: y: x + y; in f 1 2");
+		assert_converted("({ a, b ? a }@args: args.a) { a = 1; }");
+	}
+
+	#[test]
+	fn convert_recursive_bindings() {
+		assert_converted("let a = b; b = rec { c = d; d = 1; }; in a.c");
+	}
+
+	#[test]
+	fn declare_runtime_functions() {
+		let mut declared =
+			RUNTIME.iter().map(|(name, ..)| *name).collect::<Vec<_>>();
+		let mut exported = mlnx_runtime::symbols()
+			.into_iter()
+			.map(|(name, _)| name)
+			.collect::<Vec<_>>();
+		declared.sort();
+		exported.sort();
+
+		assert_eq!(declared, exported);
+	}
+
+	#[test]
+	fn convert_dynamic_attributes() {
+		let module =
+			assert_converted(r#"let x = "a"; in { ${x} = 1; }.${x}.b or null"#);
+		assert!(module.contains("@nix_attrset_insert_dynamic"));
+		assert!(module.contains("@nix_select_dynamic"));
+
+		assert_converted(r#"{ a.${"b"} = 1; } ? a.${"b"}"#);
+
+		let module =
+			assert_converted(r#"let x = "b"; in { a = { }; } ? a.${x}"#);
+		assert!(module.contains("@nix_has_attr_dynamic"));
+	}
+
+	#[test]
+	fn convert_lists_and_strings() {
+		assert_converted(r#"[ "a${"b"}" ./c ] ++ [ "" ]"#);
+	}
+
+	#[test]
+	fn convert_builtins() {
+		assert_converted("map toString (builtins.attrNames { a = 1; })");
+	}
+
+	#[test]
+	fn convert_integer_arithmetic() {
+		let module = assert_converted(
+			"x: y: [ (x + y) (x - y) (x * y) (x / y) (x < y) ]",
+		);
+
+		for operation in [
+			"llvm.intr.sadd.with.overflow",
+			"llvm.intr.ssub.with.overflow",
+			"llvm.intr.smul.with.overflow",
+			"arith.divsi",
+			"nix_division_by_zero",
+			"nix_overflow",
+			"arith.cmpi slt",
+		] {
+			assert!(module.contains(operation), "missing {operation}");
+		}
+
+		// Other operands are left to the runtime.
+		assert!(module.contains("@nix_add") && module.contains("@nix_lt"));
+	}
+
+	#[test]
+	fn convert_conditionals() {
+		assert_converted("if 1 < 2 && !false then with { x = 1; }; x else 2.5");
+	}
+}
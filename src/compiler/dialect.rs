@@ -0,0 +1,947 @@
+//! The `nix` dialect.
+//!
+//! The dialect is not registered with MLIR: its operations are built through
+//! [`operation::Builder`] in a context allowing unregistered dialects (see
+//! [`load`]). Every Nix value, evaluated or not, has the opaque `!nix.value`
+//! type, and operations with regions terminate them with `nix.yield`. As MLIR
+//! cannot verify unregistered operations, [`verify`] checks their operands,
+//! results, regions and attributes, and [`verifier`] runs it as a pass, which
+//! pipelines over the dialect run after each pass that rewrites it.
+//!
+//! | Operation         | Semantics                                            |
+//! |-------------------|------------------------------------------------------|
+//! | `nix.constant`    | an integer, float, Boolean, string or `null` literal |
+//! | `nix.path`        | an absolute path literal                             |
+//! | `nix.builtin`     | a value of the base environment, such as `builtins`  |
+//! | `nix.file`        | registers the function of a file for `import`        |
+//! | `nix.thunk`       | suspends the computation in its region               |
+//! | `nix.force`       | evaluates a value to weak head normal form           |
+//! | `nix.letrec`      | binds values which may refer to each other           |
+//! | `nix.attrset`     | an attribute set, with static and computed names     |
+//! | `nix.select`      | selects an attribute path, with an optional default  |
+//! | `nix.has_attr`    | tests for an attribute path (`?`)                    |
+//! | `nix.update`      | merges two attribute sets (`//`)                     |
+//! | `nix.list`        | a list of its operands                               |
+//! | `nix.concat`      | concatenates two lists (`++`)                        |
+//! | `nix.interpolate` | coerces its operands to strings and joins them       |
+//! | `nix.lambda`      | a function whose region takes the argument           |
+//! | `nix.formals`     | checks the argument of a function against formals    |
+//! | `nix.apply`       | applies a function to an argument                    |
+//! | `nix.with`        | looks a variable up in the namespaces of `with`      |
+//! | `nix.if`          | a conditional with one region per branch             |
+//! | `nix.assert`      | aborts evaluation unless its operand is `true`       |
+//! | `nix.add`, ...    | checked arithmetic (`add`, `sub`, `mul` and `div`)   |
+//! | `nix.eq`, ...     | comparisons (`eq` and `lt`) and negation (`not`)     |
+
+use std::mem::ManuallyDrop;
+
+use anyhow::{bail, Context as _, Result};
+use melior::{
+	ir::{
+		operation, r#type, Block, Location, NamedAttribute, Operation,
+		OperationRef, Region, Type, Value,
+	},
+	pass::{
+		external::{create_external, RunExternalPass},
+		Pass,
+	},
+	Context, LogicalResult,
+};
+
+/// Makes the `nix` dialect usable in a context.
+pub fn load(context: &Context) {
+	context.set_allow_unregistered_dialects(true);
+}
+
+/// Gets the type of Nix values.
+pub fn value_type(context: &Context) -> Type {
+	Type::parse(context, "!nix.value").expect("nix dialect not loaded")
+}
+
+/// Creates a block whose arguments are Nix values.
+pub fn block<'c>(
+	context: &'c Context,
+	locations: &[Location<'c>],
+) -> Block<'c> {
+	Block::new(
+		&locations
+			.iter()
+			.map(|&location| (value_type(context), location))
+			.collect::<Vec<_>>(),
+	)
+}
+
+/// Creates a `nix.constant` operation of an integer.
+pub fn integer<'c>(
+	context: &'c Context,
+	value: i64,
+	location: Location<'c>,
+) -> Operation<'c> {
+	constant(context, &format!("{value} : i64"), location)
+}
+
+/// Creates a `nix.constant` operation of a float.
+pub fn float<'c>(
+	context: &'c Context,
+	value: f64,
+	location: Location<'c>,
+) -> Operation<'c> {
+	// The bit pattern is printed as MLIR float literals require a period.
+	constant(
+		context,
+		&format!("0x{:016X} : f64", value.to_bits()),
+		location,
+	)
+}
+
+/// Creates a `nix.constant` operation of a Boolean.
+pub fn boolean<'c>(
+	context: &'c Context,
+	value: bool,
+	location: Location<'c>,
+) -> Operation<'c> {
+	constant(context, &value.to_string(), location)
+}
+
+/// Creates a `nix.constant` operation of a string without context.
+pub fn str<'c>(
+	context: &'c Context,
+	value: &str,
+	location: Location<'c>,
+) -> Operation<'c> {
+	constant(context, &string(value), location)
+}
+
+/// Creates a `nix.constant` operation of `null`.
+pub fn null<'c>(context: &'c Context, location: Location<'c>) -> Operation<'c> {
+	constant(context, "unit", location)
+}
+
+/// Creates a `nix.builtin` operation of a value of the base environment other
+/// than `true`, `false` and `null`.
+pub fn builtin<'c>(
+	context: &'c Context,
+	name: &str,
+	location: Location<'c>,
+) -> Operation<'c> {
+	value_operation(
+		context,
+		"nix.builtin",
+		&[],
+		&[("name", &string(name))],
+		location,
+	)
+	.build()
+}
+
+/// Creates a `nix.file` operation, which registers the function of a file
+/// named after its absolute path for `import`.
+pub fn file<'c>(
+	context: &'c Context,
+	path: &str,
+	location: Location<'c>,
+) -> Operation<'c> {
+	operation::Builder::new("nix.file", location)
+		.add_attributes(
+			&NamedAttribute::new_parsed_vec(
+				context,
+				&[("path", &string(path))],
+			)
+			.expect("valid nix attributes"),
+		)
+		.build()
+}
+
+fn constant<'c>(
+	context: &'c Context,
+	value: &str,
+	location: Location<'c>,
+) -> Operation<'c> {
+	value_operation(context, "nix.constant", &[], &[("value", value)], location)
+		.build()
+}
+
+/// Creates a `nix.thunk` operation. The region takes no arguments and yields
+/// the value of the thunk.
+pub fn thunk<'c>(
+	context: &'c Context,
+	body: Region,
+	location: Location<'c>,
+) -> Operation<'c> {
+	value_operation(context, "nix.thunk", &[], &[], location)
+		.add_regions(vec![body])
+		.build()
+}
+
+/// Creates a `nix.force` operation.
+pub fn force<'c>(
+	context: &'c Context,
+	value: Value,
+	location: Location<'c>,
+) -> Operation<'c> {
+	value_operation(context, "nix.force", &[value], &[], location).build()
+}
+
+/// Creates a `nix.yield` operation terminating a region.
+pub fn r#yield<'c>(values: &[Value], location: Location<'c>) -> Operation<'c> {
+	operation::Builder::new("nix.yield", location)
+		.add_operands(values)
+		.build()
+}
+
+/// Creates a `nix.letrec` operation with `count` results. The region takes
+/// one argument per result, standing for the results themselves, and yields
+/// their values.
+pub fn letrec<'c>(
+	context: &'c Context,
+	count: usize,
+	body: Region,
+	location: Location<'c>,
+) -> Operation<'c> {
+	operation::Builder::new("nix.letrec", location)
+		.add_results(&vec![value_type(context); count])
+		.add_regions(vec![body])
+		.build()
+}
+
+/// The name of an attribute, known statically or computed.
+#[derive(Clone, Copy)]
+pub enum Name<'n, 'a> {
+	Static(&'n str),
+	/// A string value, or `null` to omit the attribute of a set.
+	Dynamic(Value<'a>),
+}
+
+/// Creates a `nix.attrset` operation binding each name to the value at the
+/// same position.
+///
+/// The operands are the values of the static names, followed by the name and
+/// value of each attribute with a computed name.
+pub fn attrset<'c>(
+	context: &'c Context,
+	names: &[&str],
+	values: &[Value],
+	dynamic: &[(Value, Value)],
+	location: Location<'c>,
+) -> Operation<'c> {
+	let mut operands = values.to_vec();
+	operands.extend(dynamic.iter().flat_map(|&(name, value)| [name, value]));
+
+	value_operation(
+		context,
+		"nix.attrset",
+		&operands,
+		&[("names", &string_array(names))],
+		location,
+	)
+	.build()
+}
+
+/// Creates a `nix.select` operation, which evaluates to `default` if given
+/// and the attribute path is missing.
+///
+/// Computed names of the path are `unit` in the `path` attribute and operands
+/// after the set.
+pub fn select<'c>(
+	context: &'c Context,
+	set: Value,
+	path: &[Name],
+	default: Option<Value>,
+	location: Location<'c>,
+) -> Operation<'c> {
+	let (path, mut operands) = path_operands(set, path);
+	operands.extend(default);
+
+	value_operation(
+		context,
+		"nix.select",
+		&operands,
+		&[("path", &path)],
+		location,
+	)
+	.build()
+}
+
+/// Creates a `nix.has_attr` operation, with a path as in [`select`].
+pub fn has_attr<'c>(
+	context: &'c Context,
+	set: Value,
+	path: &[Name],
+	location: Location<'c>,
+) -> Operation<'c> {
+	let (path, operands) = path_operands(set, path);
+
+	value_operation(
+		context,
+		"nix.has_attr",
+		&operands,
+		&[("path", &path)],
+		location,
+	)
+	.build()
+}
+
+fn path_operands<'a>(
+	set: Value<'a>,
+	path: &[Name<'_, 'a>],
+) -> (String, Vec<Value<'a>>) {
+	let mut operands = vec![set];
+	let names = path
+		.iter()
+		.map(|name| match name {
+			Name::Static(name) => string(name),
+			Name::Dynamic(value) => {
+				operands.push(*value);
+				"unit".into()
+			}
+		})
+		.collect::<Vec<_>>();
+
+	(format!("[{}]", names.join(", ")), operands)
+}
+
+/// Creates a `nix.update` operation.
+pub fn update<'c>(
+	context: &'c Context,
+	lhs: Value,
+	rhs: Value,
+	location: Location<'c>,
+) -> Operation<'c> {
+	value_operation(context, "nix.update", &[lhs, rhs], &[], location).build()
+}
+
+/// Creates a `nix.path` operation of an absolute path.
+pub fn path<'c>(
+	context: &'c Context,
+	value: &str,
+	location: Location<'c>,
+) -> Operation<'c> {
+	value_operation(
+		context,
+		"nix.path",
+		&[],
+		&[("value", &string(value))],
+		location,
+	)
+	.build()
+}
+
+/// Creates a `nix.list` operation.
+pub fn list<'c>(
+	context: &'c Context,
+	elements: &[Value],
+	location: Location<'c>,
+) -> Operation<'c> {
+	value_operation(context, "nix.list", elements, &[], location).build()
+}
+
+/// Creates a `nix.interpolate` operation, which evaluates to a string whose
+/// context is the union of those of its parts.
+pub fn interpolate<'c>(
+	context: &'c Context,
+	parts: &[Value],
+	location: Location<'c>,
+) -> Operation<'c> {
+	value_operation(context, "nix.interpolate", parts, &[], location).build()
+}
+
+/// Creates a `nix.lambda` operation. The region takes the argument of the
+/// function and yields its result.
+pub fn lambda<'c>(
+	context: &'c Context,
+	body: Region,
+	location: Location<'c>,
+) -> Operation<'c> {
+	value_operation(context, "nix.lambda", &[], &[], location)
+		.add_regions(vec![body])
+		.build()
+}
+
+/// Creates a `nix.formals` operation checking that `argument` is a set with
+/// the attributes in `required` and, unless there is an ellipsis, no other
+/// attributes than `names`. Errors name the `function` unless it is anonymous.
+pub fn formals<'c>(
+	context: &'c Context,
+	argument: Value,
+	function: Option<&str>,
+	names: &[&str],
+	required: &[&str],
+	ellipsis: bool,
+	location: Location<'c>,
+) -> Operation<'c> {
+	operation::Builder::new("nix.formals", location)
+		.add_operands(&[argument])
+		.add_attributes(
+			&NamedAttribute::new_parsed_vec(
+				context,
+				&[
+					("function", &string(function.unwrap_or_default())),
+					("names", &string_array(names)),
+					("required", &string_array(required)),
+					("ellipsis", &ellipsis.to_string()),
+				],
+			)
+			.expect("valid nix attributes"),
+		)
+		.build()
+}
+
+/// Creates a `nix.apply` operation.
+pub fn apply<'c>(
+	context: &'c Context,
+	function: Value,
+	argument: Value,
+	location: Location<'c>,
+) -> Operation<'c> {
+	value_operation(context, "nix.apply", &[function, argument], &[], location)
+		.build()
+}
+
+/// Creates a `nix.with` operation looking `name` up in `namespaces`, the
+/// innermost first.
+pub fn with<'c>(
+	context: &'c Context,
+	namespaces: &[Value],
+	name: &str,
+	location: Location<'c>,
+) -> Operation<'c> {
+	value_operation(
+		context,
+		"nix.with",
+		namespaces,
+		&[("name", &string(name))],
+		location,
+	)
+	.build()
+}
+
+/// Creates a `nix.if` operation. Both regions take no arguments and yield the
+/// value of their branch.
+pub fn r#if<'c>(
+	context: &'c Context,
+	condition: Value,
+	then: Region,
+	r#else: Region,
+	location: Location<'c>,
+) -> Operation<'c> {
+	value_operation(context, "nix.if", &[condition], &[], location)
+		.add_regions(vec![then, r#else])
+		.build()
+}
+
+/// Creates a `nix.assert` operation.
+pub fn assert<'c>(condition: Value, location: Location<'c>) -> Operation<'c> {
+	operation::Builder::new("nix.assert", location)
+		.add_operands(&[condition])
+		.build()
+}
+
+/// Creates a binary operation such as `nix.add` or `nix.lt`.
+pub fn binary<'c>(
+	context: &'c Context,
+	name: &str,
+	lhs: Value,
+	rhs: Value,
+	location: Location<'c>,
+) -> Operation<'c> {
+	value_operation(context, name, &[lhs, rhs], &[], location).build()
+}
+
+/// Creates a `nix.not` operation.
+pub fn not<'c>(
+	context: &'c Context,
+	value: Value,
+	location: Location<'c>,
+) -> Operation<'c> {
+	value_operation(context, "nix.not", &[value], &[], location).build()
+}
+
+fn value_operation<'c>(
+	context: &'c Context,
+	name: &str,
+	operands: &[Value],
+	attributes: &[(&str, &str)],
+	location: Location<'c>,
+) -> operation::Builder<'c> {
+	operation::Builder::new(name, location)
+		.add_operands(operands)
+		.add_results(&[value_type(context)])
+		.add_attributes(
+			&NamedAttribute::new_parsed_vec(context, attributes)
+				.expect("valid nix attributes"),
+		)
+}
+
+/// Verifies the `nix` operations nested in an operation, such as a module.
+pub fn verify(operation: OperationRef) -> Result<()> {
+	let name = operation_name(operation)?;
+
+	if let Some(name) = name.strip_prefix("nix.") {
+		verify_operation(operation, name).with_context(|| {
+			format!("{}: 'nix.{name}' op", operation.location())
+		})?;
+	}
+
+	for index in 0..operation.region_count() {
+		let mut block = operation
+			.region(index)
+			.and_then(|region| region.first_block());
+
+		while let Some(current) = block {
+			let mut inner = current.first_operation();
+
+			while let Some(operation) = inner {
+				verify(operation)?;
+				inner = operation.next_in_block();
+			}
+
+			block = current.next_in_region();
+		}
+	}
+
+	Ok(())
+}
+
+/// Creates a pass verifying modules, both as MLIR verifies registered
+/// operations and as [`verify`] does the `nix` ones.
+pub fn verifier() -> Pass {
+	thread_local! {
+		// Type IDs live as long as their allocator.
+		static ID: r#type::Id =
+			ManuallyDrop::new(r#type::id::Allocator::new()).allocate_type_id();
+	}
+
+	create_external(
+		Verifier,
+		ID.with(|&id| id),
+		"NixVerifier",
+		"nix-verify",
+		"Verify the nix dialect",
+		"builtin.module",
+	)
+}
+
+#[derive(Clone)]
+struct Verifier;
+
+impl RunExternalPass for Verifier {
+	fn run(&mut self, operation: OperationRef) -> LogicalResult {
+		// MLIR reports the failures of its own verifier.
+		if !operation.verify() {
+			return LogicalResult::failure();
+		}
+
+		match verify(operation) {
+			Ok(()) => LogicalResult::success(),
+			Err(error) => {
+				operation.location().emit_error(&format!("{error:#}"));
+				LogicalResult::failure()
+			}
+		}
+	}
+}
+
+/// Checks the signature of an operation named `nix.{name}`.
+fn verify_operation(operation: OperationRef, name: &str) -> Result<()> {
+	let operands = operation.operand_count();
+	let (expected, results) = match name {
+		"constant" | "path" => {
+			required(operation, &["value"])?;
+			(Some(0), 1)
+		}
+		"builtin" => {
+			required(operation, &["name"])?;
+			(Some(0), 1)
+		}
+		"file" => {
+			required(operation, &["path"])?;
+			(Some(0), 0)
+		}
+		"thunk" => {
+			regions(operation, &[(0, 1)])?;
+			(Some(0), 1)
+		}
+		"lambda" => {
+			regions(operation, &[(1, 1)])?;
+			(Some(0), 1)
+		}
+		"letrec" => {
+			let count = operation.result_count();
+			regions(operation, &[(count, count)])?;
+			(Some(0), count)
+		}
+		"if" => {
+			regions(operation, &[(0, 1), (0, 1)])?;
+			(Some(1), 1)
+		}
+		"attrset" => {
+			let names = array(operation, "names")?;
+
+			if operands < names || !(operands - names).is_multiple_of(2) {
+				bail!(
+					"expects a value per name and pairs of computed names \
+					 and values, found {operands} operands for {names} names"
+				);
+			}
+
+			(None, 1)
+		}
+		"select" | "has_attr" => {
+			let dynamic = operation
+				.attribute("path")
+				.and_then(|path| path.elements())
+				.context("requires a 'path' attribute")?
+				.iter()
+				.filter(|name| name.is_unit())
+				.count();
+			let default = name == "select" && operands == dynamic + 2;
+
+			(Some(1 + dynamic + default as usize), 1)
+		}
+		"with" => {
+			required(operation, &["name"])?;
+
+			if operands == 0 {
+				bail!("expects at least one namespace");
+			}
+
+			(None, 1)
+		}
+		"formals" => {
+			required(
+				operation,
+				&["function", "names", "required", "ellipsis"],
+			)?;
+			(Some(1), 0)
+		}
+		"force" | "not" => (Some(1), 1),
+		"assert" => (Some(1), 0),
+		"apply" | "update" | "concat" | "add" | "sub" | "mul" | "div"
+		| "eq" | "lt" => (Some(2), 1),
+		"list" | "interpolate" => (None, 1),
+		"yield" => {
+			let parent = operation
+				.parent_operation()
+				.map(operation_name)
+				.transpose()?;
+
+			if operation.next_in_block().is_some()
+				|| !matches!(
+					parent.as_deref(),
+					Some("nix.thunk" | "nix.lambda" | "nix.letrec" | "nix.if")
+				) {
+				bail!("must terminate the region of a nix operation");
+			}
+
+			(None, 0)
+		}
+		_ => bail!("is not an operation of the nix dialect"),
+	};
+
+	if let Some(expected) = expected {
+		if operands != expected {
+			bail!("expects {expected} operands, found {operands}");
+		}
+	}
+
+	if operation.result_count() != results {
+		bail!(
+			"expects {results} results, found {}",
+			operation.result_count()
+		);
+	}
+
+	if !matches!(name, "thunk" | "lambda" | "letrec" | "if")
+		&& operation.region_count() != 0
+	{
+		bail!("expects no regions");
+	}
+
+	Ok(())
+}
+
+/// Checks that an operation has regions of a single block, which take the
+/// given number of arguments and yield the given number of values.
+fn regions(operation: OperationRef, expected: &[(usize, usize)]) -> Result<()> {
+	if operation.region_count() != expected.len() {
+		bail!(
+			"expects {} regions, found {}",
+			expected.len(),
+			operation.region_count()
+		);
+	}
+
+	for (index, &(arguments, values)) in expected.iter().enumerate() {
+		let block = operation
+			.region(index)
+			.and_then(|region| region.first_block())
+			.with_context(|| format!("expects a block in region {index}"))?;
+
+		if block.next_in_region().is_some() {
+			bail!("expects a single block in region {index}");
+		}
+
+		if block.argument_count() != arguments {
+			bail!(
+				"expects {arguments} arguments in region {index}, found {}",
+				block.argument_count()
+			);
+		}
+
+		// MLIR only finds registered terminators.
+		let mut terminator = block.first_operation();
+
+		while let Some(next) = terminator.and_then(|last| last.next_in_block())
+		{
+			terminator = Some(next);
+		}
+
+		match terminator {
+			Some(terminator) if operation_name(terminator)? == "nix.yield" => {
+				if terminator.operand_count() != values {
+					bail!(
+						"expects region {index} to yield {values} values, \
+						 found {}",
+						terminator.operand_count()
+					);
+				}
+			}
+			_ => bail!("expects region {index} to end with nix.yield"),
+		}
+	}
+
+	Ok(())
+}
+
+pub(crate) fn operation_name(operation: OperationRef) -> Result<String> {
+	Ok(operation.name().as_string_ref().as_str()?.to_owned())
+}
+
+/// Checks that an operation has attributes.
+fn required(operation: OperationRef, names: &[&str]) -> Result<()> {
+	for name in names {
+		if operation.attribute(name).is_none() {
+			bail!("requires a '{name}' attribute");
+		}
+	}
+
+	Ok(())
+}
+
+/// Gets the length of an array attribute.
+fn array(operation: OperationRef, name: &str) -> Result<usize> {
+	Ok(operation
+		.attribute(name)
+		.and_then(|attribute| attribute.elements())
+		.with_context(|| format!("requires a '{name}' array attribute"))?
+		.len())
+}
+
+/// Formats a string attribute.
+pub fn string(value: &str) -> String {
+	let mut attribute = String::from("\"");
+
+	for byte in value.bytes() {
+		match byte {
+			b'"' | b'\\' => {
+				attribute.push('\\');
+				attribute.push(byte as char);
+			}
+			b' '..=b'~' => attribute.push(byte as char),
+			_ => attribute.push_str(&format!("\\{byte:02X}")),
+		}
+	}
+
+	attribute.push('"');
+	attribute
+}
+
+fn string_array(values: &[&str]) -> String {
+	format!(
+		"[{}]",
+		values
+			.iter()
+			.map(|value| string(value))
+			.collect::<Vec<_>>()
+			.join(", ")
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use melior::ir::{Module, Region};
+
+	fn context() -> Context {
+		let context = Context::new();
+		load(&context);
+		context
+	}
+
+	#[test]
+	fn run_verifier() {
+		let context = context();
+		let location = Location::unknown(&context);
+		let manager = melior::pass::Manager::new(&context);
+		manager.add_pass(verifier());
+
+		let mut module = Module::new(location);
+		module
+			.body()
+			.append_operation(integer(&context, 1, location));
+		manager.run(&mut module).unwrap();
+
+		let mut module = Module::new(location);
+		module.body().append_operation(
+			value_operation(&context, "nix.force", &[], &[], location).build(),
+		);
+		assert!(manager.run(&mut module).is_err());
+	}
+
+	#[test]
+	fn escape_string() {
+		assert_eq!(string("a\"b\\c\n"), r#""a\"b\\c\0A""#);
+	}
+
+	#[test]
+	fn build_thunk() {
+		let context = context();
+		let location = Location::unknown(&context);
+		let module = Module::new(location);
+
+		let body = Region::new();
+		let block = Block::new(&[]);
+		let one = block.append_operation(integer(&context, 1, location));
+		block.append_operation(r#yield(
+			&[one.result(0).unwrap().into()],
+			location,
+		));
+		body.append_block(block);
+
+		let module_body = module.body();
+		let thunk =
+			module_body.append_operation(thunk(&context, body, location));
+		module_body.append_operation(force(
+			&context,
+			thunk.result(0).unwrap().into(),
+			location,
+		));
+
+		assert!(module.as_operation().verify());
+	}
+
+	#[test]
+	fn build_attrset() {
+		let context = context();
+		let location = Location::unknown(&context);
+		let module = Module::new(location);
+		let body = module.body();
+
+		let one = body.append_operation(float(&context, 1.5, location));
+		let name = body.append_operation(str(&context, "b", location));
+		let set = body.append_operation(attrset(
+			&context,
+			&["a"],
+			&[one.result(0).unwrap().into()],
+			&[(
+				name.result(0).unwrap().into(),
+				one.result(0).unwrap().into(),
+			)],
+			location,
+		));
+		let default = body.append_operation(null(&context, location));
+		body.append_operation(select(
+			&context,
+			set.result(0).unwrap().into(),
+			&[
+				Name::Static("a"),
+				Name::Dynamic(name.result(0).unwrap().into()),
+			],
+			Some(default.result(0).unwrap().into()),
+			location,
+		));
+
+		assert!(module.as_operation().verify());
+	}
+
+	#[test]
+	fn build_interpolation() {
+		let context = context();
+		let location = Location::unknown(&context);
+		let module = Module::new(location);
+		let body = module.body();
+
+		let prefix = body.append_operation(str(&context, "a\n", location));
+		let path = body.append_operation(path(&context, "/b", location));
+		let list = body.append_operation(list(
+			&context,
+			&[path.result(0).unwrap().into()],
+			location,
+		));
+		body.append_operation(interpolate(
+			&context,
+			&[
+				prefix.result(0).unwrap().into(),
+				list.result(0).unwrap().into(),
+			],
+			location,
+		));
+
+		assert!(module.as_operation().verify());
+	}
+
+	#[test]
+	fn build_letrec() {
+		let context = context();
+		let location = Location::unknown(&context);
+		let module = Module::new(location);
+
+		let body = Region::new();
+		let block = block(&context, &[location]);
+		block.append_operation(r#yield(
+			&[block.argument(0).unwrap().into()],
+			location,
+		));
+		body.append_block(block);
+
+		module
+			.body()
+			.append_operation(letrec(&context, 1, body, location));
+
+		assert!(module.as_operation().verify());
+	}
+
+	#[test]
+	fn verify_operations() {
+		let context = context();
+		let location = Location::unknown(&context);
+		let module = Module::new(location);
+		let body = module.body();
+
+		let one = body.append_operation(integer(&context, 1, location));
+		let one = one.result(0).unwrap().into();
+		body.append_operation(binary(&context, "nix.add", one, one, location));
+		verify(module.as_operation()).unwrap();
+
+		let error = |operation| {
+			let module = Module::new(location);
+			module.body().append_operation(operation);
+			format!("{:#}", verify(module.as_operation()).unwrap_err())
+		};
+
+		assert!(error(
+			value_operation(&context, "nix.force", &[one, one], &[], location)
+				.build()
+		)
+		.ends_with("'nix.force' op: expects 1 operands, found 2"));
+		assert!(error(r#yield(&[one], location))
+			.ends_with("must terminate the region of a nix operation"));
+		assert!(error(
+			value_operation(&context, "nix.select", &[one], &[], location)
+				.build()
+		)
+		.ends_with("requires a 'path' attribute"));
+		assert!(error(thunk(&context, Region::new(), location))
+			.ends_with("expects a block in region 0"));
+	}
+}
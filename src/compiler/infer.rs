@@ -0,0 +1,921 @@
+//! Static type inference of shaders (see [`shader`](crate::shader)).
+//!
+//! Shaders are statically typed, so that every value they compute has an
+//! MLIR type before they are lowered. Types are inferred from literals, from
+//! the names of types given to `builtins.input` and `builtins.uniform`, and
+//! from the builtins and operators applied to values. Functions are inferred
+//! at each of their applications, and every binding is inferred, even if the
+//! shader does not use it.
+//!
+//! Constructs whose types are only known while evaluating are rejected: lists
+//! of elements of different types, conditionals whose branches have different
+//! types, computed attribute names and recursive functions.
+
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
+
+use anyhow::{bail, Context as _, Result};
+use melior::{ir::Type, Context};
+use rnix::{
+	ast::{self, HasEntry},
+	TextRange,
+};
+use rowan::ast::AstNode;
+
+use crate::{
+	lower::{
+		attr_key, child, collect_definitions, ident_name, literal, Definition,
+		Definitions, Key,
+	},
+	shader::{builtin, symbol, Kind},
+	source::Source,
+};
+
+/// The type of a value of a shader.
+#[derive(Clone)]
+pub enum Shape {
+	/// A Boolean, an integer or a float known while compiling.
+	Constant(Kind),
+	/// A value computed by the shader.
+	Value(Kind),
+	/// A string, with its value if it is known.
+	String(Option<String>),
+	Set(Rc<BTreeMap<String, Rc<Slot>>>),
+	/// A list, with the type of its elements unless it is empty.
+	List(Option<Box<Shape>>),
+	Lambda(ast::Lambda, Rc<Scope>),
+	/// A builtin applied to fewer arguments than it takes.
+	Builtin(&'static str, Vec<(Shape, TextRange)>),
+	/// The `builtins` set.
+	Builtins,
+}
+
+impl Shape {
+	/// Gets the MLIR type of values of this type, if they have one.
+	pub fn r#type<'c>(&self, context: &'c Context) -> Option<Type<'c>> {
+		match self {
+			Self::Constant(kind) | Self::Value(kind) => {
+				Some(kind.r#type(context))
+			}
+			_ => None,
+		}
+	}
+
+	/// Gets the type of an attribute of a set.
+	pub fn attribute(&self, name: &str) -> Option<Shape> {
+		let Self::Set(attributes) = self else {
+			return None;
+		};
+
+		match &*attributes.get(name)?.0.borrow() {
+			State::Done(shape) => Some(shape.clone()),
+			_ => None,
+		}
+	}
+
+	pub fn description(&self) -> String {
+		match self {
+			Self::Constant(Kind::Bool) => "a Boolean".into(),
+			Self::Constant(Kind::Int) => "an integer".into(),
+			Self::Constant(_) => "a float".into(),
+			Self::Value(kind) => format!("a value of type {}", kind.name()),
+			Self::String(_) => "a string".into(),
+			Self::Set(_) | Self::Builtins => "a set".into(),
+			Self::List(Some(element)) => {
+				format!("a list of elements like {}", element.description())
+			}
+			Self::List(None) => "an empty list".into(),
+			Self::Lambda(..) | Self::Builtin(..) => "a function".into(),
+		}
+	}
+
+	/// Tests if a value of this type can be used as a value of a type
+	/// computed by the shader, as literals are.
+	fn fits(&self, kind: Kind) -> bool {
+		match *self {
+			Self::Value(found) => found == kind,
+			Self::Constant(Kind::Bool) => kind == Kind::Bool,
+			Self::Constant(Kind::Int) => kind == Kind::Int || kind.is_float(),
+			Self::Constant(_) => kind.is_float(),
+			_ => false,
+		}
+	}
+
+	fn is_number(&self) -> bool {
+		matches!(self, Self::Constant(Kind::Int | Kind::Float))
+	}
+}
+
+/// A binding of a shader with its inferred type.
+pub struct Binding {
+	pub name: String,
+	/// The range of the value of the binding, or of the name of a parameter.
+	pub range: TextRange,
+	pub shape: Shape,
+}
+
+/// The bindings in scope, with their parent scope.
+#[derive(Default)]
+pub struct Scope {
+	parent: Option<Rc<Scope>>,
+	bindings: RefCell<BTreeMap<String, Rc<Slot>>>,
+}
+
+impl Scope {
+	fn push(self: &Rc<Self>) -> Rc<Self> {
+		Rc::new(Self {
+			parent: Some(self.clone()),
+			bindings: Default::default(),
+		})
+	}
+
+	fn lookup(&self, name: &str) -> Option<Rc<Slot>> {
+		self.bindings
+			.borrow()
+			.get(name)
+			.cloned()
+			.or_else(|| self.parent.as_ref()?.lookup(name))
+	}
+}
+
+/// The type of a binding, inferred when it is first needed.
+pub struct Slot(RefCell<State>);
+
+enum State {
+	/// An expression with its scope and the name it is bound to.
+	Pending(ast::Expr, Rc<Scope>, Option<String>),
+	/// An attribute inherited from a set.
+	Inherited(Rc<Slot>, String, TextRange),
+	Inferring,
+	Done(Shape),
+}
+
+impl Slot {
+	fn pending(
+		expr: &ast::Expr,
+		scope: &Rc<Scope>,
+		name: Option<&str>,
+	) -> Rc<Self> {
+		Rc::new(Self(RefCell::new(State::Pending(
+			expr.clone(),
+			scope.clone(),
+			name.map(Into::into),
+		))))
+	}
+
+	fn done(shape: Shape) -> Rc<Self> {
+		Rc::new(Self(RefCell::new(State::Done(shape))))
+	}
+
+	fn range(&self, default: TextRange) -> TextRange {
+		match &*self.0.borrow() {
+			State::Pending(expr, ..) => expr.syntax().text_range(),
+			_ => default,
+		}
+	}
+}
+
+/// Infers the types of the bindings of a shader, in the order of the
+/// source.
+pub fn infer(source: &Source, expr: &ast::Expr) -> Result<Vec<Binding>> {
+	let mut inferrer = Inferrer {
+		source,
+		bindings: Vec::new(),
+		slots: Vec::new(),
+		applying: Vec::new(),
+	};
+	inferrer.infer(expr, &Rc::default())?;
+
+	// Bindings are inferred lazily, so those which are not used are inferred
+	// last. Inferring them can create more of them.
+	let mut index = 0;
+
+	while let Some(slot) = inferrer.slots.get(index).cloned() {
+		inferrer.force(&slot, expr.syntax().text_range())?;
+		index += 1;
+	}
+
+	let mut bindings = inferrer.bindings;
+	bindings.sort_by_key(|binding| binding.range.start());
+
+	Ok(bindings)
+}
+
+struct Inferrer<'s> {
+	source: &'s Source,
+	bindings: Vec<Binding>,
+	/// The slots of every named binding.
+	slots: Vec<Rc<Slot>>,
+	/// The functions being applied, which may not apply themselves.
+	applying: Vec<ast::Lambda>,
+}
+
+impl Inferrer<'_> {
+	fn infer(&mut self, expr: &ast::Expr, scope: &Rc<Scope>) -> Result<Shape> {
+		let range = expr.syntax().text_range();
+
+		match expr {
+			ast::Expr::Literal(literal) => match literal.kind() {
+				ast::LiteralKind::Integer(_) => Ok(Shape::Constant(Kind::Int)),
+				ast::LiteralKind::Float(_) => Ok(Shape::Constant(Kind::Float)),
+				ast::LiteralKind::Uri(_) => {
+					Err(self.error(range, "URIs are not supported in shaders"))
+				}
+			},
+			ast::Expr::Str(string) => {
+				match literal(&string.normalized_parts()) {
+					Some(value) => Ok(Shape::String(Some(value))),
+					None => Err(self.error(
+						range,
+						"string interpolation is not supported in shaders",
+					)),
+				}
+			}
+			ast::Expr::Ident(ident) => {
+				let name = ident_name(ident)?;
+
+				match scope.lookup(&name) {
+					Some(slot) => self.force(&slot, range),
+					None => match name.as_str() {
+						"true" | "false" => Ok(Shape::Constant(Kind::Bool)),
+						"builtins" => Ok(Shape::Builtins),
+						_ => Err(self.error(
+							range,
+							format!("undefined variable '{name}'"),
+						)),
+					},
+				}
+			}
+			ast::Expr::Paren(paren) => self.infer(&child(paren.expr())?, scope),
+			ast::Expr::LetIn(let_in) => {
+				let definitions = collect_definitions(let_in.entries())?;
+				let inner = scope.push();
+				let bindings = self.bindings(&definitions, &inner, scope)?;
+				*inner.bindings.borrow_mut() = bindings;
+
+				self.infer(&child(let_in.body())?, &inner)
+			}
+			ast::Expr::AttrSet(set) => {
+				let definitions = collect_definitions(set.entries())?;
+				let rec = set.rec_token().is_some();
+				let inner = if rec { scope.push() } else { scope.clone() };
+				let attributes = self.bindings(&definitions, &inner, scope)?;
+
+				if rec {
+					*inner.bindings.borrow_mut() = attributes.clone();
+				}
+
+				Ok(Shape::Set(Rc::new(attributes)))
+			}
+			ast::Expr::Select(select) => {
+				let mut shape = self.infer(&child(select.expr())?, scope)?;
+
+				for attr in child(select.attrpath())?.attrs() {
+					let range = attr.syntax().text_range();
+					let name = match attr_key(&attr)? {
+						Key::Static(name) => name,
+						Key::Dynamic(name) => {
+							return Err(self.dynamic_name(&name));
+						}
+					};
+
+					shape = match self.attribute(&shape, &name, range)? {
+						Some(shape) => shape,
+						None => match select.default_expr() {
+							Some(default) => {
+								return self.infer(&default, scope)
+							}
+							None => {
+								return Err(self.error(
+									range,
+									format!("attribute '{name}' missing"),
+								))
+							}
+						},
+					};
+				}
+
+				Ok(shape)
+			}
+			ast::Expr::List(list) => {
+				let mut element: Option<Shape> = None;
+
+				for item in list.items() {
+					let shape = self.infer(&item, scope)?;
+
+					element = Some(match element {
+						Some(element) => {
+							self.unify(&element, &shape)?.ok_or_else(|| {
+								self.error(
+									item.syntax().text_range(),
+									format!(
+										"lists must be homogeneous in shaders, \
+										 but this element is {} and the \
+										 previous ones are {}",
+										shape.description(),
+										element.description()
+									),
+								)
+							})?
+						}
+						None => shape,
+					});
+				}
+
+				Ok(Shape::List(element.map(Box::new)))
+			}
+			ast::Expr::Lambda(lambda) => {
+				Ok(Shape::Lambda(lambda.clone(), scope.clone()))
+			}
+			ast::Expr::Apply(apply) => {
+				let function = self.infer(&child(apply.lambda())?, scope)?;
+				let argument =
+					Slot::pending(&child(apply.argument())?, scope, None);
+
+				self.apply(function, argument, range)
+			}
+			ast::Expr::IfElse(if_else) => {
+				let condition = child(if_else.condition())?;
+				let condition_shape = self.infer(&condition, scope)?;
+				let then = self.infer(&child(if_else.body())?, scope)?;
+				let r#else = self.infer(&child(if_else.else_body())?, scope)?;
+				let Some(shape) = self.unify(&then, &r#else)? else {
+					return Err(self.error(
+						range,
+						format!(
+							"the branches of a conditional must have the same \
+							 type in shaders, but they are {} and {}",
+							then.description(),
+							r#else.description()
+						),
+					));
+				};
+
+				match condition_shape {
+					Shape::Constant(Kind::Bool) => Ok(shape),
+					// Both branches are computed and one is selected.
+					Shape::Value(Kind::Bool) => match shape {
+						Shape::Constant(kind) | Shape::Value(kind) => {
+							Ok(Shape::Value(kind))
+						}
+						shape => Err(self.error(
+							range,
+							format!(
+								"cannot select between {} in a shader",
+								shape.description()
+							),
+						)),
+					},
+					shape => Err(self.error(
+						condition.syntax().text_range(),
+						format!(
+							"expected a Boolean but found {}",
+							shape.description()
+						),
+					)),
+				}
+			}
+			ast::Expr::Assert(assert) => {
+				let condition = child(assert.condition())?;
+
+				match self.infer(&condition, scope)? {
+					Shape::Constant(Kind::Bool) => {
+						self.infer(&child(assert.body())?, scope)
+					}
+					shape => Err(self.error(
+						condition.syntax().text_range(),
+						format!(
+							"expected a Boolean known while compiling but \
+							 found {}",
+							shape.description()
+						),
+					)),
+				}
+			}
+			ast::Expr::BinOp(binary) => {
+				let operator = binary.operator().context("missing operator")?;
+				let lhs = self.infer(&child(binary.lhs())?, scope)?;
+				let rhs = self.infer(&child(binary.rhs())?, scope)?;
+
+				binary_shape(operator, &lhs, &rhs).ok_or_else(|| {
+					self.error(
+						range,
+						format!(
+							"cannot apply '{}' to {} and {}",
+							symbol(operator),
+							lhs.description(),
+							rhs.description()
+						),
+					)
+				})
+			}
+			ast::Expr::UnaryOp(unary) => {
+				let operator = unary.operator().context("missing operator")?;
+				let operand = self.infer(&child(unary.expr())?, scope)?;
+
+				match (operator, &operand) {
+					(
+						ast::UnaryOpKind::Invert,
+						Shape::Constant(Kind::Bool) | Shape::Value(Kind::Bool),
+					) => Ok(operand),
+					(
+						ast::UnaryOpKind::Negate,
+						Shape::Constant(Kind::Int | Kind::Float),
+					) => Ok(operand),
+					(ast::UnaryOpKind::Negate, Shape::Value(kind))
+						if kind.is_float() || *kind == Kind::Int =>
+					{
+						Ok(operand)
+					}
+					_ => Err(self.error(
+						range,
+						format!(
+							"cannot apply an operator to {}",
+							operand.description()
+						),
+					)),
+				}
+			}
+			_ => {
+				Err(self
+					.error(range, "this construct is not supported in shaders"))
+			}
+		}
+	}
+
+	/// Creates the slots of the bindings of a `let` or a set, inheriting
+	/// from `outer`.
+	fn bindings(
+		&mut self,
+		definitions: &Definitions,
+		scope: &Rc<Scope>,
+		outer: &Rc<Scope>,
+	) -> Result<BTreeMap<String, Rc<Slot>>> {
+		if let Some((name, _)) = definitions.dynamic.first() {
+			return Err(self.dynamic_name(name));
+		}
+
+		let mut slots = BTreeMap::new();
+
+		for (name, definition) in &definitions.named {
+			let slot = match definition {
+				Definition::Expr(expr) => {
+					let slot = Slot::pending(expr, scope, Some(name));
+					self.slots.push(slot.clone());
+					slot
+				}
+				Definition::Nested(nested) => {
+					let attributes = self.bindings(nested, scope, outer)?;
+					Slot::done(Shape::Set(Rc::new(attributes)))
+				}
+				Definition::Inherit(None, ident) => {
+					outer.lookup(name).ok_or_else(|| {
+						self.error(
+							ident.syntax().text_range(),
+							format!("undefined variable '{name}'"),
+						)
+					})?
+				}
+				Definition::Inherit(Some(from), ident) => {
+					Rc::new(Slot(RefCell::new(State::Inherited(
+						Slot::pending(from, outer, None),
+						name.clone(),
+						ident.syntax().text_range(),
+					))))
+				}
+				Definition::Formal(_) => bail!("unexpected formal"),
+			};
+
+			slots.insert(name.clone(), slot);
+		}
+
+		Ok(slots)
+	}
+
+	fn force(&mut self, slot: &Slot, range: TextRange) -> Result<Shape> {
+		let shape = match slot.0.replace(State::Inferring) {
+			State::Done(shape) => shape,
+			State::Inferring => {
+				return Err(self.error(range, "infinite recursion encountered"))
+			}
+			State::Pending(expr, scope, name) => {
+				let shape = self.infer(&expr, &scope)?;
+
+				if let Some(name) = name {
+					self.bindings.push(Binding {
+						name,
+						range: expr.syntax().text_range(),
+						shape: shape.clone(),
+					});
+				}
+
+				shape
+			}
+			State::Inherited(set, name, range) => {
+				let set = self.force(&set, range)?;
+
+				self.attribute(&set, &name, range)?.ok_or_else(|| {
+					self.error(range, format!("attribute '{name}' missing"))
+				})?
+			}
+		};
+		slot.0.replace(State::Done(shape.clone()));
+
+		Ok(shape)
+	}
+
+	/// Infers the type of an attribute of a set, or of a builtin.
+	fn attribute(
+		&mut self,
+		set: &Shape,
+		name: &str,
+		range: TextRange,
+	) -> Result<Option<Shape>> {
+		match set {
+			Shape::Builtins => match builtin(name) {
+				Some((name, _)) => Ok(Some(Shape::Builtin(name, Vec::new()))),
+				None => Err(self.error(
+					range,
+					format!("builtins.{name} is not supported in shaders"),
+				)),
+			},
+			Shape::Set(attributes) => attributes
+				.get(name)
+				.map(|slot| self.force(slot, range))
+				.transpose(),
+			shape => Err(self.error(
+				range,
+				format!("expected a set but found {}", shape.description()),
+			)),
+		}
+	}
+
+	fn apply(
+		&mut self,
+		function: Shape,
+		argument: Rc<Slot>,
+		range: TextRange,
+	) -> Result<Shape> {
+		match function {
+			Shape::Lambda(lambda, scope) => {
+				if self.applying.contains(&lambda) {
+					return Err(self.error(
+						range,
+						"recursive functions are not supported in shaders",
+					));
+				}
+
+				let ast::Param::IdentParam(param) = child(lambda.param())?
+				else {
+					return Err(self.error(
+						lambda.syntax().text_range(),
+						"functions of sets are not supported in shaders",
+					));
+				};
+				let ident = child(param.ident())?;
+				let name = ident_name(&ident)?;
+				let shape = self.force(&argument, range)?;
+				self.bindings.push(Binding {
+					name: name.clone(),
+					range: ident.syntax().text_range(),
+					shape,
+				});
+
+				let scope = scope.push();
+				scope.bindings.borrow_mut().insert(name, argument);
+
+				self.applying.push(lambda.clone());
+				let shape = self.infer(&child(lambda.body())?, &scope);
+				self.applying.pop();
+
+				shape
+			}
+			Shape::Builtin(name, mut arguments) => {
+				let argument_range = argument.range(range);
+				arguments.push((self.force(&argument, range)?, argument_range));
+
+				if builtin(name)
+					.is_some_and(|(_, arity)| arguments.len() < arity)
+				{
+					Ok(Shape::Builtin(name, arguments))
+				} else {
+					self.builtin(name, &arguments)
+				}
+			}
+			shape => Err(self.error(
+				range,
+				format!(
+					"attempt to call something which is not a function but {}",
+					shape.description()
+				),
+			)),
+		}
+	}
+
+	fn builtin(
+		&mut self,
+		name: &str,
+		arguments: &[(Shape, TextRange)],
+	) -> Result<Shape> {
+		match name {
+			"input" | "uniform" => {
+				let (Shape::String(Some(type_name)), _) = &arguments[0] else {
+					return Err(self.error(
+						arguments[0].1,
+						format!(
+							"the type of {name}s must be a string known while \
+							 compiling"
+						),
+					));
+				};
+				let kind = Kind::parse(type_name).ok_or_else(|| {
+					self.error(
+						arguments[0].1,
+						format!("unknown shader type '{type_name}'"),
+					)
+				})?;
+
+				match (name, kind, &arguments[1].0) {
+					("input", Kind::Sampler2D, _) => Err(self.error(
+						arguments[0].1,
+						"inputs cannot be of type sampler2D",
+					)),
+					("input", _, Shape::String(Some(_))) => {
+						Ok(Shape::Value(kind))
+					}
+					("input", _, _) => Err(self.error(
+						arguments[1].1,
+						"the name of an input must be a string known while \
+						 compiling",
+					)),
+					(_, Kind::Sampler2D, Shape::Constant(Kind::Int)) => {
+						Ok(Shape::Value(kind))
+					}
+					(_, Kind::Sampler2D, shape) => Err(self.error(
+						arguments[1].1,
+						format!(
+							"expected a binding but found {}",
+							shape.description()
+						),
+					)),
+					_ => Err(self.error(
+						arguments[0].1,
+						"only sampler2D uniforms are supported",
+					)),
+				}
+			}
+			"texture" => {
+				self.expect(&arguments[0], Kind::Sampler2D)?;
+				self.expect(&arguments[1], Kind::Vector(2))?;
+
+				Ok(Shape::Value(Kind::Vector(4)))
+			}
+			"vec2" | "vec3" | "vec4" => {
+				for argument in arguments {
+					self.expect(argument, Kind::Float)?;
+				}
+
+				Ok(Shape::Value(Kind::Vector(arguments.len() as u32)))
+			}
+			_ => match &arguments[0].0 {
+				Shape::Constant(Kind::Int | Kind::Float) => {
+					Ok(Shape::Constant(Kind::Float))
+				}
+				Shape::Value(kind) if kind.is_float() => {
+					Ok(Shape::Value(*kind))
+				}
+				shape => Err(self.error(
+					arguments[0].1,
+					format!(
+						"expected a float or a vector but found {}",
+						shape.description()
+					),
+				)),
+			},
+		}
+	}
+
+	fn expect(
+		&self,
+		(shape, range): &(Shape, TextRange),
+		kind: Kind,
+	) -> Result<()> {
+		if shape.fits(kind) {
+			Ok(())
+		} else {
+			Err(self.error(
+				*range,
+				format!(
+					"expected a value of type {} but found {}",
+					kind.name(),
+					shape.description()
+				),
+			))
+		}
+	}
+
+	/// Finds a type of which values of both types are, if there is one.
+	fn unify(&mut self, lhs: &Shape, rhs: &Shape) -> Result<Option<Shape>> {
+		Ok(Some(match (lhs, rhs) {
+			(Shape::Constant(lhs), Shape::Constant(rhs)) if lhs == rhs => {
+				Shape::Constant(*lhs)
+			}
+			(lhs, rhs) if lhs.is_number() && rhs.is_number() => {
+				Shape::Constant(Kind::Float)
+			}
+			(&Shape::Value(kind), shape) | (shape, &Shape::Value(kind))
+				if shape.fits(kind) =>
+			{
+				Shape::Value(kind)
+			}
+			(Shape::String(lhs), Shape::String(rhs)) => {
+				Shape::String(lhs.clone().filter(|_| lhs == rhs))
+			}
+			(Shape::List(None), list @ Shape::List(_))
+			| (list @ Shape::List(_), Shape::List(None)) => list.clone(),
+			(Shape::List(Some(lhs)), Shape::List(Some(rhs))) => {
+				match self.unify(lhs, rhs)? {
+					Some(element) => Shape::List(Some(Box::new(element))),
+					None => return Ok(None),
+				}
+			}
+			(Shape::Set(lhs), Shape::Set(rhs)) => {
+				if !lhs.keys().eq(rhs.keys()) {
+					return Ok(None);
+				}
+
+				let mut attributes = BTreeMap::new();
+
+				for ((name, lhs), rhs) in lhs.iter().zip(rhs.values()) {
+					let range = lhs.range(rhs.range(TextRange::default()));
+					let lhs = self.force(lhs, range)?;
+					let rhs = self.force(rhs, range)?;
+
+					match self.unify(&lhs, &rhs)? {
+						Some(shape) => {
+							attributes.insert(name.clone(), Slot::done(shape))
+						}
+						None => return Ok(None),
+					};
+				}
+
+				Shape::Set(Rc::new(attributes))
+			}
+			(Shape::Builtins, Shape::Builtins) => Shape::Builtins,
+			_ => return Ok(None),
+		}))
+	}
+
+	fn dynamic_name(&self, name: &ast::Expr) -> anyhow::Error {
+		self.error(
+			name.syntax().text_range(),
+			"dynamic attribute names are not supported in shaders",
+		)
+	}
+
+	fn error(
+		&self,
+		range: TextRange,
+		message: impl Into<String>,
+	) -> anyhow::Error {
+		self.source.diagnostic(range, message).into()
+	}
+}
+
+/// Infers the type of the result of a binary operator, if it is defined on
+/// operands of the given types.
+fn binary_shape(
+	operator: ast::BinOpKind,
+	lhs: &Shape,
+	rhs: &Shape,
+) -> Option<Shape> {
+	use ast::BinOpKind::*;
+
+	let kind = match (lhs, rhs) {
+		(Shape::Constant(Kind::Int), Shape::Constant(Kind::Int)) => {
+			return match operator {
+				Add | Sub | Mul | Div => Some(Shape::Constant(Kind::Int)),
+				Less | LessOrEq | More | MoreOrEq | Equal | NotEqual => {
+					Some(Shape::Constant(Kind::Bool))
+				}
+				_ => None,
+			}
+		}
+		(lhs, rhs) if lhs.is_number() && rhs.is_number() => {
+			return match operator {
+				Add | Sub | Mul | Div => Some(Shape::Constant(Kind::Float)),
+				Less | LessOrEq | More | MoreOrEq | Equal | NotEqual => {
+					Some(Shape::Constant(Kind::Bool))
+				}
+				_ => None,
+			}
+		}
+		(Shape::Constant(Kind::Bool), Shape::Constant(Kind::Bool)) => {
+			return matches!(
+				operator,
+				And | Or | Implication | Equal | NotEqual
+			)
+			.then_some(Shape::Constant(Kind::Bool))
+		}
+		(Shape::String(lhs), Shape::String(rhs)) => {
+			return match operator {
+				Add => Some(Shape::String(
+					lhs.as_ref()
+						.zip(rhs.as_ref())
+						.map(|(lhs, rhs)| lhs.clone() + rhs),
+				)),
+				Equal | NotEqual => Some(Shape::Constant(Kind::Bool)),
+				_ => None,
+			}
+		}
+		(Shape::Value(lhs), Shape::Value(rhs)) if lhs != rhs => return None,
+		(&Shape::Value(kind), other) | (other, &Shape::Value(kind))
+			if other.fits(kind) =>
+		{
+			kind
+		}
+		_ => return None,
+	};
+
+	match (operator, kind) {
+		(Add | Sub | Mul | Div, _) if kind.is_float() || kind == Kind::Int => {
+			Some(Shape::Value(kind))
+		}
+		(Equal | NotEqual, Kind::Bool | Kind::Int | Kind::Float)
+		| (Less | LessOrEq | More | MoreOrEq, Kind::Int | Kind::Float)
+		| (And | Or | Implication, Kind::Bool) => Some(Shape::Value(Kind::Bool)),
+		_ => None,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::source::Diagnostic;
+
+	fn infer_shader(text: &str) -> Result<Vec<Binding>> {
+		let root = rnix::Root::parse(text).tree();
+
+		infer(&Source::new("gpu.nix", text), &root.expr().unwrap())
+	}
+
+	#[test]
+	fn infer_bindings() {
+		let bindings = infer_shader(include_str!("../test/gpu.nix")).unwrap();
+		let kind = |name: &str| match bindings
+			.iter()
+			.find(|binding| binding.name == name)
+		{
+			Some(Binding {
+				shape: Shape::Value(kind),
+				..
+			}) => Some(*kind),
+			_ => None,
+		};
+
+		assert_eq!(kind("textureSampler"), Some(Kind::Sampler2D));
+		assert_eq!(kind("value"), Some(Kind::Vector(4)));
+
+		let bindings = infer_shader(
+			"let scale = x: x * 2; unused = scale (builtins.vec2 1 2); in \
+			 scale 1.5",
+		)
+		.unwrap();
+
+		let shapes = |name: &str| {
+			bindings
+				.iter()
+				.filter(|binding| binding.name == name)
+				.map(|binding| binding.shape.description())
+				.collect::<Vec<_>>()
+		};
+
+		assert_eq!(shapes("scale"), ["a function"]);
+		assert_eq!(shapes("x"), ["a float", "a value of type vec2"]);
+		assert_eq!(shapes("unused"), ["a value of type vec2"]);
+	}
+
+	#[test]
+	fn reject_dynamic_types() {
+		let error = infer_shader("{ a = [ 1 \"b\" ]; }").err().unwrap();
+		let diagnostic = error.downcast_ref::<Diagnostic>().unwrap();
+
+		assert_eq!(
+			diagnostic.message(),
+			"lists must be homogeneous in shaders, but this element is a \
+			 string and the previous ones are an integer"
+		);
+		assert_eq!(diagnostic.position(), (1, 11));
+
+		let error = infer_shader("let a = \"b\"; in { ${a} = 1; }")
+			.err()
+			.unwrap();
+		let diagnostic = error.downcast_ref::<Diagnostic>().unwrap();
+
+		assert_eq!(
+			diagnostic.message(),
+			"dynamic attribute names are not supported in shaders"
+		);
+		assert!(infer_shader("let f = x: f x; in f 1").is_err());
+		assert!(infer_shader("if true then 1 else \"a\"").is_err());
+	}
+}
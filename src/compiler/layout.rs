@@ -0,0 +1,62 @@
+//! The layout of Nix values in converted code, which mirrors
+//! [`mlnx_runtime::Value`].
+//!
+//! A value is a 32-bit tag (see [`tag`]) followed by a 64-bit payload, which
+//! is the value itself for scalars and a pointer to its contents otherwise.
+//! Converted code only handles pointers to values, allocated by the runtime.
+
+use melior::{dialect::llvm, ir::Type, Context};
+
+pub use mlnx_runtime::value::tag;
+
+/// Gets the type of values.
+pub fn value_type(context: &Context) -> Type {
+	llvm::r#type::r#struct(
+		context,
+		&[Type::integer(context, 32), Type::integer(context, 64)],
+		false,
+	)
+}
+
+/// Gets the type of pointers to values.
+pub fn value_pointer_type(context: &Context) -> Type {
+	llvm::r#type::pointer(value_type(context), 0)
+}
+
+/// Gets the type of pointers to the tags of values.
+pub fn tag_pointer_type(context: &Context) -> Type {
+	llvm::r#type::pointer(Type::integer(context, 32), 0)
+}
+
+/// Gets the type of pointers to the payloads of values.
+pub fn payload_pointer_type(context: &Context) -> Type {
+	llvm::r#type::pointer(Type::integer(context, 64), 0)
+}
+
+/// Gets the type of other pointers, such as environments, strings and
+/// functions.
+pub fn pointer_type(context: &Context) -> Type {
+	Type::parse(context, "!llvm.ptr").expect("llvm dialect loaded")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use melior::{dialect::Registry, utility::register_all_dialects};
+
+	#[test]
+	fn value_layout() {
+		let registry = Registry::new();
+		register_all_dialects(&registry);
+
+		let context = Context::new();
+		context.append_dialect_registry(&registry);
+		context.load_all_available_dialects();
+
+		assert_eq!(
+			value_pointer_type(&context).to_string(),
+			"!llvm.ptr<struct<(i32, i64)>>"
+		);
+		assert_eq!(std::mem::size_of::<mlnx_runtime::Value>(), 16);
+	}
+}
@@ -0,0 +1,13 @@
+//! A compiler from the Nix language to MLIR.
+
+pub mod cache;
+pub mod convert;
+pub mod dialect;
+pub mod infer;
+pub mod layout;
+pub mod lower;
+pub mod partial;
+pub mod resolve;
+pub mod shader;
+pub mod source;
+pub mod spirv;
@@ -0,0 +1,1548 @@
+//! Lowering of rnix syntax trees to the `nix` dialect.
+//!
+//! A program is lowered with every Nix file it refers to by a path literal,
+//! each to a function named after its absolute path, so that `import` can
+//! evaluate them whether their paths are literals or computed.
+//!
+//! Variables are resolved (see [`resolve`]) before lowering, and each lexical
+//! binding is an SSA value so that only lookups in the namespaces of `with`
+//! remain dynamic. The values of bindings, attributes, list elements and
+//! function arguments are suspended in `nix.thunk`s unless they need no
+//! evaluation, as literals, lambdas and variables.
+
+use std::{collections::BTreeMap, fs, path::Path, rc::Rc};
+
+use anyhow::{bail, Context as _, Result};
+use melior::{
+	ir::{
+		operation, Attribute, Block, Location, Module, NamedAttribute,
+		Operation, Region, Value,
+	},
+	Context,
+};
+use mlnx_runtime::value::canonicalize;
+use rnix::{
+	ast::{self, AstToken, HasEntry},
+	SyntaxNode,
+};
+use rowan::ast::AstNode;
+
+use crate::{
+	cache::{Cache, Entry},
+	dialect,
+	resolve::{self, Resolution, Variable},
+	source::Source,
+};
+
+/// The bindings of a `let`, an attribute set or a nested attribute path.
+#[derive(Default)]
+pub(crate) struct Definitions {
+	pub(crate) named: BTreeMap<String, Definition>,
+	/// Attributes whose names are computed, as in `${x} = 1`.
+	pub(crate) dynamic: Vec<(ast::Expr, Definition)>,
+}
+
+/// A binding as written in a `let`, an attribute set or formals.
+pub(crate) enum Definition {
+	Expr(ast::Expr),
+	Nested(Definitions),
+	Inherit(Option<ast::Expr>, ast::Ident),
+	/// An attribute of the argument of a function, with its default.
+	Formal(Option<ast::Expr>),
+}
+
+struct Scope<'a> {
+	parent: Option<Rc<Scope<'a>>>,
+	frame: Frame<'a>,
+}
+
+enum Frame<'a> {
+	Values(BTreeMap<String, Value<'a>>),
+	/// The namespace of a `with`, as a possibly suspended attribute set.
+	With(Value<'a>),
+}
+
+impl<'a> Scope<'a> {
+	fn root() -> Rc<Self> {
+		Rc::new(Self {
+			parent: None,
+			frame: Frame::Values(Default::default()),
+		})
+	}
+
+	fn push(self: &Rc<Self>, frame: Frame<'a>) -> Rc<Self> {
+		Rc::new(Self {
+			parent: Some(self.clone()),
+			frame,
+		})
+	}
+
+	/// Looks a lexical variable up.
+	fn lexical(&self, name: &str) -> Option<Value<'a>> {
+		match &self.frame {
+			Frame::Values(values) if values.contains_key(name) => {
+				values.get(name).copied()
+			}
+			_ => self.parent.as_ref()?.lexical(name),
+		}
+	}
+
+	/// Gets the namespaces of the enclosing `with` expressions, the innermost
+	/// first.
+	fn namespaces(&self) -> Vec<Value<'a>> {
+		let mut namespaces = Vec::new();
+		let mut scope = Some(self);
+
+		while let Some(current) = scope {
+			if let Frame::With(namespace) = current.frame {
+				namespaces.push(namespace);
+			}
+
+			scope = current.parent.as_deref();
+		}
+
+		namespaces
+	}
+}
+
+/// A name in an attribute path.
+pub(crate) enum Key {
+	Static(String),
+	Dynamic(ast::Expr),
+}
+
+/// The name of the function evaluating a program, which is not `main` so that
+/// executables can define their own (see
+/// [`convert_executable`](crate::convert::convert_executable)).
+pub const MAIN: &str = "nix_main";
+
+/// Lowers a Nix program to a [`MAIN`] function returning its value.
+pub struct Lowerer<'c> {
+	context: &'c Context,
+	/// The file being lowered.
+	source: Source,
+	resolution: Resolution,
+	/// The absolute paths of the files to lower, the root first.
+	files: Vec<String>,
+	cache: Option<Cache>,
+	/// The paths the file being lowered refers to, for its cache entry.
+	references: Vec<String>,
+	/// The location of the expression being lowered.
+	location: Location<'c>,
+}
+
+impl<'c> Lowerer<'c> {
+	pub fn new(context: &'c Context, source: Source) -> Self {
+		Self {
+			context,
+			location: Location::new(context, source.path(), 1, 1),
+			source,
+			resolution: Resolution::default(),
+			files: Vec::new(),
+			cache: None,
+			references: Vec::new(),
+		}
+	}
+
+	/// Reuses the functions of files lowered before from a cache, and caches
+	/// the others.
+	pub fn cache(mut self, cache: Cache) -> Self {
+		self.cache = Some(cache);
+		self
+	}
+
+	/// Lowers a parsed file and the files it refers to into a module
+	/// containing a [`MAIN`] function, which returns the value of the file in
+	/// weak head normal form.
+	pub fn lower_root(&mut self, root: &ast::Root) -> Result<Module<'c>> {
+		let location = self.location;
+		let module = Module::new(location);
+		let path = std::path::absolute(self.source.path())?;
+		let path = canonicalize(&path.to_string_lossy());
+		self.files.push(path.clone());
+		self.lower_cached(&module, &path, |lowerer, module| {
+			lowerer.lower_file(module, &path, root)
+		})?;
+
+		// Files are queued as their paths are lowered.
+		for index in 1.. {
+			let Some(path) = self.files.get(index).cloned() else {
+				break;
+			};
+			let text = fs::read_to_string(&path)
+				.with_context(|| format!("Failed to read {path}"))?;
+
+			self.location = Location::new(self.context, &path, 1, 1);
+			self.source = Source::new(path.clone(), text);
+			self.lower_cached(&module, &path, |lowerer, module| {
+				let parsed = rnix::Root::parse(lowerer.source.text());
+
+				if let Some(error) = parsed.errors().first() {
+					bail!("Failed to parse {path}: {error}");
+				}
+
+				lowerer.lower_file(module, &path, &parsed.tree())
+			})?;
+		}
+
+		self.location = location;
+		self.lower_main(&module)?;
+
+		Ok(module)
+	}
+
+	/// Lowers the current file to a function with `lower`, or reuses its
+	/// function from the cache if it has it.
+	fn lower_cached(
+		&mut self,
+		module: &Module<'c>,
+		path: &str,
+		lower: impl FnOnce(&mut Self, &Module<'c>) -> Result<()>,
+	) -> Result<()> {
+		let Some(cache) = self.cache.clone() else {
+			return lower(self, module);
+		};
+
+		if let Some(entry) = cache.get(self.context, path, &self.source) {
+			for reference in entry.references().unwrap_or_default() {
+				self.refer(&reference);
+			}
+
+			let function = entry.function().context("empty cache entry")?;
+			module.body().append_operation((*function).clone());
+			return Ok(());
+		}
+
+		// The file is lowered on its own so that its function can be cached
+		// before being moved to the module.
+		let file = Module::new(self.location);
+		self.references.clear();
+		lower(self, &file)?;
+
+		let function = file
+			.body()
+			.first_operation()
+			.context("file without function")?;
+		let entry = Entry::new(self.context, &function, &self.references)?;
+		cache.insert(path, &self.source, &entry)?;
+		module.body().append_operation((*function).clone());
+
+		Ok(())
+	}
+
+	/// Lowers a file to a function named after its path.
+	fn lower_file(
+		&mut self,
+		module: &Module<'c>,
+		path: &str,
+		root: &ast::Root,
+	) -> Result<()> {
+		let expr = root.expr().context("file contains no expression")?;
+		self.resolution = resolve::resolve(&self.source, &expr)?;
+		let block = Block::new(&[]);
+
+		{
+			let value = self.lower_expr(&block, &Scope::root(), &expr)?;
+			self.r#return(&block, value)?;
+		}
+
+		self.function(module, path, block)
+	}
+
+	/// Lowers a [`MAIN`] function, which registers the functions of the files
+	/// and imports the root one.
+	fn lower_main(&self, module: &Module<'c>) -> Result<()> {
+		let block = Block::new(&[]);
+		self.lower_imports(&block)?;
+		self.function(module, MAIN, block)
+	}
+
+	fn lower_imports(&self, block: &Block<'c>) -> Result<()> {
+		for path in &self.files {
+			block.append_operation(dialect::file(
+				self.context,
+				path,
+				self.location,
+			));
+		}
+
+		let import = self.append(
+			block,
+			dialect::builtin(self.context, "import", self.location),
+		)?;
+		let path = self.append(
+			block,
+			dialect::path(self.context, &self.files[0], self.location),
+		)?;
+		// The root file is not imported from anywhere in the sources, so the
+		// import has no call site to trace errors to.
+		let value = self.append(
+			block,
+			dialect::apply(
+				self.context,
+				import,
+				path,
+				Location::unknown(self.context),
+			),
+		)?;
+
+		self.r#return(block, value)
+	}
+
+	/// Terminates the body of a function with its value in weak head normal
+	/// form.
+	fn r#return(&self, block: &Block<'c>, value: Value) -> Result<()> {
+		let value = self.append(
+			block,
+			dialect::force(self.context, value, self.location),
+		)?;
+
+		block.append_operation(
+			operation::Builder::new("func.return", self.location)
+				.add_operands(&[value])
+				.build(),
+		);
+
+		Ok(())
+	}
+
+	/// Appends a function taking no arguments to a module.
+	fn function(
+		&self,
+		module: &Module<'c>,
+		name: &str,
+		block: Block<'c>,
+	) -> Result<()> {
+		let region = Region::new();
+		region.append_block(block);
+
+		module.body().append_operation(
+			operation::Builder::new("func.func", self.location)
+				.add_attributes(&NamedAttribute::new_parsed_vec(
+					self.context,
+					&[
+						(
+							"function_type",
+							&format!(
+								"() -> {}",
+								dialect::value_type(self.context)
+							),
+						),
+						("sym_name", &dialect::string(name)),
+					],
+				)?)
+				.add_regions(vec![region])
+				.build(),
+		);
+
+		Ok(())
+	}
+
+	fn lower_expr<'a>(
+		&mut self,
+		block: &'a Block<'c>,
+		scope: &Rc<Scope<'a>>,
+		expr: &ast::Expr,
+	) -> Result<Value<'a>> {
+		self.lower_named(block, scope, expr, None)
+	}
+
+	/// Lowers an expression, attaching `name` to its location if it is the
+	/// value of a binding.
+	fn lower_named<'a>(
+		&mut self,
+		block: &'a Block<'c>,
+		scope: &Rc<Scope<'a>>,
+		expr: &ast::Expr,
+		name: Option<&str>,
+	) -> Result<Value<'a>> {
+		let location = self.named_location(expr.syntax(), name);
+		let outer = std::mem::replace(&mut self.location, location);
+		let value = match expr {
+			// Functions are named after their binding in error messages.
+			ast::Expr::Lambda(lambda) => {
+				self.lower_lambda(block, scope, lambda, name)
+			}
+			_ => self.lower_syntax(block, scope, expr),
+		};
+		self.location = outer;
+
+		value
+	}
+
+	fn lower_syntax<'a>(
+		&mut self,
+		block: &'a Block<'c>,
+		scope: &Rc<Scope<'a>>,
+		expr: &ast::Expr,
+	) -> Result<Value<'a>> {
+		match expr {
+			ast::Expr::Literal(literal) => match literal.kind() {
+				ast::LiteralKind::Integer(integer) => {
+					let value = integer.value().context("invalid integer")?;
+
+					self.append(
+						block,
+						dialect::integer(self.context, value, self.location),
+					)
+				}
+				ast::LiteralKind::Float(float) => {
+					let value = float.value().context("invalid float")?;
+
+					self.append(
+						block,
+						dialect::float(self.context, value, self.location),
+					)
+				}
+				ast::LiteralKind::Uri(_) => bail!("URIs are not supported"),
+			},
+			ast::Expr::Ident(ident) => self.variable(block, scope, ident),
+			ast::Expr::Paren(paren) => {
+				self.lower_expr(block, scope, &child(paren.expr())?)
+			}
+			ast::Expr::LetIn(let_in) => {
+				let definitions = collect_definitions(let_in.entries())?;
+
+				if !definitions.dynamic.is_empty() {
+					bail!("dynamic attributes not allowed in let");
+				}
+
+				let values =
+					self.letrec(block, scope, &definitions.named, None)?;
+
+				self.lower_expr(
+					block,
+					&scope.push(Frame::Values(values)),
+					&child(let_in.body())?,
+				)
+			}
+			ast::Expr::AttrSet(set) => {
+				let definitions = collect_definitions(set.entries())?;
+
+				if set.rec_token().is_some() {
+					let values =
+						self.letrec(block, scope, &definitions.named, None)?;
+					let inner = scope.push(Frame::Values(values.clone()));
+					let dynamic = self.dynamic_attributes(
+						block,
+						&inner,
+						scope,
+						&definitions.dynamic,
+						None,
+					)?;
+					let names =
+						values.keys().map(String::as_str).collect::<Vec<_>>();
+
+					self.append(
+						block,
+						dialect::attrset(
+							self.context,
+							&names,
+							&values.values().copied().collect::<Vec<_>>(),
+							&dynamic,
+							self.location,
+						),
+					)
+				} else {
+					self.attrset(block, scope, scope, &definitions, None)
+				}
+			}
+			ast::Expr::Select(select) => {
+				let set =
+					self.lower_expr(block, scope, &child(select.expr())?)?;
+				let keys = attrpath_keys(&child(select.attrpath())?)?;
+				let path = self.path(block, scope, &keys)?;
+				let default = select
+					.default_expr()
+					.map(|default| self.thunk(block, scope, &default, None))
+					.transpose()?;
+
+				self.append(
+					block,
+					dialect::select(
+						self.context,
+						set,
+						&path,
+						default,
+						self.location,
+					),
+				)
+			}
+			ast::Expr::HasAttr(has_attr) => {
+				let set =
+					self.lower_expr(block, scope, &child(has_attr.expr())?)?;
+				let keys = attrpath_keys(&child(has_attr.attrpath())?)?;
+				let path = self.path(block, scope, &keys)?;
+
+				self.append(
+					block,
+					dialect::has_attr(self.context, set, &path, self.location),
+				)
+			}
+			ast::Expr::Lambda(lambda) => {
+				self.lower_lambda(block, scope, lambda, None)
+			}
+			ast::Expr::Apply(apply) => {
+				let function =
+					self.lower_expr(block, scope, &child(apply.lambda())?)?;
+				let argument =
+					self.thunk(block, scope, &child(apply.argument())?, None)?;
+
+				self.append(
+					block,
+					dialect::apply(
+						self.context,
+						function,
+						argument,
+						self.location,
+					),
+				)
+			}
+			ast::Expr::IfElse(if_else) => {
+				let condition = self.lower_expr(
+					block,
+					scope,
+					&child(if_else.condition())?,
+				)?;
+				let then = self.branch(scope, &child(if_else.body())?)?;
+				let r#else =
+					self.branch(scope, &child(if_else.else_body())?)?;
+
+				self.append(
+					block,
+					dialect::r#if(
+						self.context,
+						condition,
+						then,
+						r#else,
+						self.location,
+					),
+				)
+			}
+			ast::Expr::Assert(assert) => {
+				let condition =
+					self.lower_expr(block, scope, &child(assert.condition())?)?;
+				block.append_operation(dialect::assert(
+					condition,
+					self.location,
+				));
+
+				self.lower_expr(block, scope, &child(assert.body())?)
+			}
+			ast::Expr::With(with) => {
+				// The namespace is only evaluated when a variable is looked up
+				// in it.
+				let namespace =
+					self.thunk(block, scope, &child(with.namespace())?, None)?;
+
+				self.lower_expr(
+					block,
+					&scope.push(Frame::With(namespace)),
+					&child(with.body())?,
+				)
+			}
+			ast::Expr::BinOp(binary) => self.lower_binary(block, scope, binary),
+			ast::Expr::UnaryOp(unary) => {
+				let operand =
+					self.lower_expr(block, scope, &child(unary.expr())?)?;
+
+				match unary.operator().context("missing operator")? {
+					ast::UnaryOpKind::Invert => self.append(
+						block,
+						dialect::not(self.context, operand, self.location),
+					),
+					ast::UnaryOpKind::Negate => {
+						// Negation is subtraction from zero.
+						let location = self.desugared("-", &[]);
+						let zero = self.append(
+							block,
+							dialect::integer(self.context, 0, location),
+						)?;
+
+						self.append(
+							block,
+							dialect::binary(
+								self.context,
+								"nix.sub",
+								zero,
+								operand,
+								location,
+							),
+						)
+					}
+				}
+			}
+			ast::Expr::Str(string) => self.lower_string(block, scope, string),
+			ast::Expr::Path(path) => {
+				let path = self.resolve_path(path)?;
+				self.refer(&path);
+
+				self.append(
+					block,
+					dialect::path(self.context, &path, self.location),
+				)
+			}
+			ast::Expr::List(list) => {
+				let elements = list
+					.items()
+					.map(|item| self.thunk(block, scope, &item, None))
+					.collect::<Result<Vec<_>>>()?;
+
+				self.append(
+					block,
+					dialect::list(self.context, &elements, self.location),
+				)
+			}
+			ast::Expr::LegacyLet(_) => bail!("legacy let is not supported"),
+			ast::Expr::Root(root) => {
+				self.lower_expr(block, scope, &child(root.expr())?)
+			}
+			ast::Expr::Error(error) => bail!("syntax error at {}", error),
+		}
+	}
+
+	fn lower_binary<'a>(
+		&mut self,
+		block: &'a Block<'c>,
+		scope: &Rc<Scope<'a>>,
+		binary: &ast::BinOp,
+	) -> Result<Value<'a>> {
+		use ast::BinOpKind::*;
+
+		let operator = binary.operator().context("missing operator")?;
+		let lhs = self.lower_expr(block, scope, &child(binary.lhs())?)?;
+		let rhs = child(binary.rhs())?;
+
+		// The right operand of a logical operator is only evaluated if the
+		// left one does not determine the result.
+		if let And | Or | Implication = operator {
+			let (construct, then, r#else) = match operator {
+				And => (
+					"&&",
+					self.branch(scope, &rhs)?,
+					self.constant_branch(false),
+				),
+				Or => (
+					"||",
+					self.constant_branch(true),
+					self.branch(scope, &rhs)?,
+				),
+				_ => (
+					"->",
+					self.branch(scope, &rhs)?,
+					self.constant_branch(true),
+				),
+			};
+
+			return self.append(
+				block,
+				dialect::r#if(
+					self.context,
+					lhs,
+					then,
+					r#else,
+					self.desugared(construct, &[]),
+				),
+			);
+		}
+
+		let rhs = self.lower_expr(block, scope, &rhs)?;
+		let (name, lhs, rhs, negated) = match operator {
+			Concat => ("nix.concat", lhs, rhs, None),
+			Update => {
+				return self.append(
+					block,
+					dialect::update(self.context, lhs, rhs, self.location),
+				)
+			}
+			Add => ("nix.add", lhs, rhs, None),
+			Sub => ("nix.sub", lhs, rhs, None),
+			Mul => ("nix.mul", lhs, rhs, None),
+			Div => ("nix.div", lhs, rhs, None),
+			Equal => ("nix.eq", lhs, rhs, None),
+			NotEqual => ("nix.eq", lhs, rhs, Some("!=")),
+			Less => ("nix.lt", lhs, rhs, None),
+			More => {
+				return self.append(
+					block,
+					dialect::binary(
+						self.context,
+						"nix.lt",
+						rhs,
+						lhs,
+						self.desugared(">", &[]),
+					),
+				)
+			}
+			LessOrEq => ("nix.lt", rhs, lhs, Some("<=")),
+			MoreOrEq => ("nix.lt", lhs, rhs, Some(">=")),
+			And | Or | Implication => unreachable!(),
+		};
+
+		match negated {
+			None => self.append(
+				block,
+				dialect::binary(self.context, name, lhs, rhs, self.location),
+			),
+			Some(construct) => {
+				let location = self.desugared(construct, &[]);
+				let value = self.append(
+					block,
+					dialect::binary(self.context, name, lhs, rhs, location),
+				)?;
+
+				self.append(block, dialect::not(self.context, value, location))
+			}
+		}
+	}
+
+	fn lower_string<'a>(
+		&mut self,
+		block: &'a Block<'c>,
+		scope: &Rc<Scope<'a>>,
+		string: &ast::Str,
+	) -> Result<Value<'a>> {
+		// Indentation is stripped and escapes are replaced in both forms.
+		let parts = string.normalized_parts();
+
+		if let Some(literal) = literal(&parts) {
+			return self.append(
+				block,
+				dialect::str(self.context, &literal, self.location),
+			);
+		}
+
+		let parts = parts
+			.iter()
+			.map(|part| match part {
+				ast::InterpolPart::Literal(literal) => self.append(
+					block,
+					dialect::str(self.context, literal, self.location),
+				),
+				ast::InterpolPart::Interpolation(interpolation) => {
+					self.lower_expr(block, scope, &child(interpolation.expr())?)
+				}
+			})
+			.collect::<Result<Vec<_>>>()?;
+
+		self.append(
+			block,
+			dialect::interpolate(self.context, &parts, self.location),
+		)
+	}
+
+	/// Resolves a path literal to an absolute path as Nix does while parsing:
+	/// relative to the directory of the file and without `.` or `..`.
+	fn resolve_path(&self, path: &ast::Path) -> Result<String> {
+		let mut literal = String::new();
+
+		for part in path.parts() {
+			match part {
+				ast::InterpolPart::Literal(content) => {
+					literal.push_str(content.syntax().text())
+				}
+				ast::InterpolPart::Interpolation(_) => {
+					bail!("interpolated paths are not supported yet")
+				}
+			}
+		}
+
+		let path = if literal.starts_with('<') {
+			bail!("search paths are not supported yet")
+		} else if let Some(path) = literal.strip_prefix("~/") {
+			format!(
+				"{}/{path}",
+				std::env::var("HOME").context("HOME is not set")?
+			)
+		} else if literal.starts_with('/') {
+			literal
+		} else {
+			let file = std::path::absolute(self.source.path())?;
+			let directory = file
+				.parent()
+				.context("source file without directory")?
+				.to_string_lossy();
+
+			format!("{directory}/{literal}")
+		};
+
+		Ok(canonicalize(&path))
+	}
+
+	/// Queues the file a path refers to for lowering if it is a Nix file, or
+	/// a directory with a `default.nix` file.
+	fn refer(&mut self, path: &str) {
+		self.references.push(path.into());
+
+		let file = if Path::new(path).is_dir() {
+			format!("{}/default.nix", path.trim_end_matches('/'))
+		} else {
+			path.into()
+		};
+
+		if file.ends_with(".nix")
+			&& Path::new(&file).is_file()
+			&& !self.files.contains(&file)
+		{
+			self.files.push(file);
+		}
+	}
+
+	fn lower_lambda<'a>(
+		&mut self,
+		block: &'a Block<'c>,
+		scope: &Rc<Scope<'a>>,
+		lambda: &ast::Lambda,
+		name: Option<&str>,
+	) -> Result<Value<'a>> {
+		let param = child(lambda.param())?;
+		let body =
+			dialect::block(self.context, &[self.location_of(param.syntax())]);
+
+		{
+			let argument = body.argument(0)?.into();
+			let scope = match param {
+				ast::Param::IdentParam(param) => scope.push(Frame::Values(
+					[(ident_name(&child(param.ident())?)?, argument)].into(),
+				)),
+				ast::Param::Pattern(pattern) => {
+					let mut definitions = BTreeMap::new();
+					let mut required = Vec::new();
+
+					for entry in pattern.pat_entries() {
+						let name = ident_name(&child(entry.ident())?)?;
+						let default = entry.default();
+
+						if default.is_none() {
+							required.push(name.clone());
+						}
+
+						if definitions
+							.insert(name.clone(), Definition::Formal(default))
+							.is_some()
+						{
+							bail!(
+								"duplicate formal function argument '{name}'"
+							);
+						}
+					}
+
+					body.append_operation(dialect::formals(
+						self.context,
+						argument,
+						name,
+						&definitions
+							.keys()
+							.map(String::as_str)
+							.collect::<Vec<_>>(),
+						&required
+							.iter()
+							.map(String::as_str)
+							.collect::<Vec<_>>(),
+						pattern.ellipsis_token().is_some(),
+						self.location,
+					));
+
+					let scope = match pattern.pat_bind() {
+						Some(bind) => scope.push(Frame::Values(
+							[(ident_name(&child(bind.ident())?)?, argument)]
+								.into(),
+						)),
+						None => scope.clone(),
+					};
+
+					// Defaults may refer to any formal.
+					let values = self.letrec(
+						&body,
+						&scope,
+						&definitions,
+						Some(argument),
+					)?;
+
+					scope.push(Frame::Values(values))
+				}
+			};
+
+			let value =
+				self.lower_expr(&body, &scope, &child(lambda.body())?)?;
+			body.append_operation(dialect::r#yield(&[value], self.location));
+		}
+
+		let region = Region::new();
+		region.append_block(body);
+
+		self.append(block, dialect::lambda(self.context, region, self.location))
+	}
+
+	/// Lowers definitions which may refer to each other to a `nix.letrec`.
+	/// `argument` is the argument of the function whose formals are defined.
+	fn letrec<'a>(
+		&mut self,
+		block: &'a Block<'c>,
+		scope: &Rc<Scope<'a>>,
+		definitions: &BTreeMap<String, Definition>,
+		argument: Option<Value<'a>>,
+	) -> Result<BTreeMap<String, Value<'a>>> {
+		if definitions.is_empty() {
+			return Ok(BTreeMap::new());
+		}
+
+		let body = dialect::block(
+			self.context,
+			&definitions
+				.keys()
+				.map(|name| Location::name(self.context, name, self.location))
+				.collect::<Vec<_>>(),
+		);
+
+		{
+			let inner = scope.push(Frame::Values(
+				definitions
+					.keys()
+					.enumerate()
+					.map(|(index, name)| {
+						Ok((name.clone(), body.argument(index)?.into()))
+					})
+					.collect::<Result<_>>()?,
+			));
+			let values = definitions
+				.iter()
+				.map(|(name, definition)| {
+					self.definition(
+						&body, &inner, scope, name, definition, argument,
+					)
+				})
+				.collect::<Result<Vec<_>>>()?;
+
+			body.append_operation(dialect::r#yield(&values, self.location));
+		}
+
+		let region = Region::new();
+		region.append_block(body);
+
+		let letrec = block.append_operation(dialect::letrec(
+			self.context,
+			definitions.len(),
+			region,
+			self.location,
+		));
+
+		definitions
+			.keys()
+			.enumerate()
+			.map(|(index, name)| {
+				Ok((name.clone(), letrec.result(index)?.into()))
+			})
+			.collect()
+	}
+
+	/// Lowers an attribute set whose values do not refer to each other.
+	fn attrset<'a>(
+		&mut self,
+		block: &'a Block<'c>,
+		scope: &Rc<Scope<'a>>,
+		parent: &Rc<Scope<'a>>,
+		definitions: &Definitions,
+		argument: Option<Value<'a>>,
+	) -> Result<Value<'a>> {
+		let values = definitions
+			.named
+			.iter()
+			.map(|(name, definition)| {
+				self.definition(
+					block, scope, parent, name, definition, argument,
+				)
+			})
+			.collect::<Result<Vec<_>>>()?;
+		let dynamic = self.dynamic_attributes(
+			block,
+			scope,
+			parent,
+			&definitions.dynamic,
+			argument,
+		)?;
+
+		self.append(
+			block,
+			dialect::attrset(
+				self.context,
+				&definitions
+					.named
+					.keys()
+					.map(String::as_str)
+					.collect::<Vec<_>>(),
+				&values,
+				&dynamic,
+				self.location,
+			),
+		)
+	}
+
+	/// Lowers the names and values of attributes with computed names.
+	fn dynamic_attributes<'a>(
+		&mut self,
+		block: &'a Block<'c>,
+		scope: &Rc<Scope<'a>>,
+		parent: &Rc<Scope<'a>>,
+		definitions: &[(ast::Expr, Definition)],
+		argument: Option<Value<'a>>,
+	) -> Result<Vec<(Value<'a>, Value<'a>)>> {
+		definitions
+			.iter()
+			.map(|(name, definition)| {
+				let value = self.definition(
+					block,
+					scope,
+					parent,
+					&name.syntax().text().to_string(),
+					definition,
+					argument,
+				)?;
+
+				Ok((self.lower_expr(block, scope, name)?, value))
+			})
+			.collect()
+	}
+
+	/// Lowers the computed names of an attribute path.
+	fn path<'k, 'a>(
+		&mut self,
+		block: &'a Block<'c>,
+		scope: &Rc<Scope<'a>>,
+		keys: &'k [Key],
+	) -> Result<Vec<dialect::Name<'k, 'a>>> {
+		keys.iter()
+			.map(|key| {
+				Ok(match key {
+					Key::Static(name) => dialect::Name::Static(name),
+					Key::Dynamic(expr) => dialect::Name::Dynamic(
+						self.lower_expr(block, scope, expr)?,
+					),
+				})
+			})
+			.collect()
+	}
+
+	/// Lowers the value of a definition. Plain `inherit` refers to the `parent`
+	/// scope while other definitions are lowered in `scope`.
+	fn definition<'a>(
+		&mut self,
+		block: &'a Block<'c>,
+		scope: &Rc<Scope<'a>>,
+		parent: &Rc<Scope<'a>>,
+		name: &str,
+		definition: &Definition,
+		argument: Option<Value<'a>>,
+	) -> Result<Value<'a>> {
+		match definition {
+			Definition::Expr(expr) => {
+				self.thunk(block, scope, expr, Some(name))
+			}
+			Definition::Nested(definitions) => {
+				self.attrset(block, scope, parent, definitions, argument)
+			}
+			Definition::Inherit(None, ident) => self.thunk(
+				block,
+				parent,
+				&ast::Expr::Ident(ident.clone()),
+				Some(name),
+			),
+			Definition::Inherit(Some(from), ident) => {
+				let location = self.named_location(ident.syntax(), Some(name));
+				let body = Block::new(&[]);
+
+				{
+					let set = self.lower_expr(&body, scope, from)?;
+					let value = self.append(
+						&body,
+						dialect::select(
+							self.context,
+							set,
+							&[dialect::Name::Static(name)],
+							None,
+							location,
+						),
+					)?;
+					body.append_operation(dialect::r#yield(&[value], location));
+				}
+
+				self.suspend(block, body, location)
+			}
+			Definition::Formal(default) => {
+				let argument =
+					argument.context("formal outside of a function")?;
+				let default = default
+					.as_ref()
+					.map(|default| {
+						self.thunk(block, scope, default, Some(name))
+					})
+					.transpose()?;
+
+				self.append(
+					block,
+					dialect::select(
+						self.context,
+						argument,
+						&[dialect::Name::Static(name)],
+						default,
+						self.location,
+					),
+				)
+			}
+		}
+	}
+
+	/// Lowers an expression to a thunk unless it needs no evaluation.
+	fn thunk<'a>(
+		&mut self,
+		block: &'a Block<'c>,
+		scope: &Rc<Scope<'a>>,
+		expr: &ast::Expr,
+		name: Option<&str>,
+	) -> Result<Value<'a>> {
+		if is_value(&self.resolution, expr) {
+			return self.lower_named(block, scope, expr, name);
+		}
+
+		let location = self.named_location(expr.syntax(), name);
+		let body = Block::new(&[]);
+
+		{
+			let value = self.lower_named(&body, scope, expr, name)?;
+			body.append_operation(dialect::r#yield(&[value], location));
+		}
+
+		self.suspend(block, body, location)
+	}
+
+	fn suspend<'a>(
+		&self,
+		block: &'a Block<'c>,
+		body: Block<'c>,
+		location: Location<'c>,
+	) -> Result<Value<'a>> {
+		let region = Region::new();
+		region.append_block(body);
+
+		self.append(block, dialect::thunk(self.context, region, location))
+	}
+
+	/// Lowers an expression to a region yielding its value.
+	fn branch(
+		&mut self,
+		scope: &Rc<Scope<'_>>,
+		expr: &ast::Expr,
+	) -> Result<Region> {
+		let block = Block::new(&[]);
+
+		{
+			let value = self.lower_expr(&block, scope, expr)?;
+			block.append_operation(dialect::r#yield(&[value], self.location));
+		}
+
+		let region = Region::new();
+		region.append_block(block);
+
+		Ok(region)
+	}
+
+	fn constant_branch(&self, value: bool) -> Region {
+		let block = Block::new(&[]);
+		let constant = block.append_operation(dialect::boolean(
+			self.context,
+			value,
+			self.location,
+		));
+		block.append_operation(dialect::r#yield(
+			&[constant.result(0).expect("constant without result").into()],
+			self.location,
+		));
+
+		let region = Region::new();
+		region.append_block(block);
+		region
+	}
+
+	fn variable<'a>(
+		&mut self,
+		block: &'a Block<'c>,
+		scope: &Rc<Scope<'a>>,
+		ident: &ast::Ident,
+	) -> Result<Value<'a>> {
+		let name = ident_name(ident)?;
+		let operation = match self
+			.resolution
+			.variable(ident)
+			.with_context(|| format!("unresolved variable '{name}'"))?
+		{
+			Variable::Lexical => {
+				return scope
+					.lexical(&name)
+					.with_context(|| format!("unbound variable '{name}'"))
+			}
+			Variable::Base => match name.as_str() {
+				"null" => dialect::null(self.context, self.location),
+				"true" | "false" => dialect::boolean(
+					self.context,
+					name == "true",
+					self.location,
+				),
+				_ => dialect::builtin(self.context, &name, self.location),
+			},
+			Variable::With => dialect::with(
+				self.context,
+				&scope.namespaces(),
+				&name,
+				self.location,
+			),
+		};
+
+		self.append(block, operation)
+	}
+
+	fn append<'a>(
+		&self,
+		block: &'a Block<'c>,
+		operation: Operation<'c>,
+	) -> Result<Value<'a>> {
+		Ok(block.append_operation(operation).result(0)?.into())
+	}
+
+	fn location_of(&self, node: &SyntaxNode) -> Location<'c> {
+		self.source.location(self.context, node.text_range())
+	}
+
+	fn named_location(
+		&self,
+		node: &SyntaxNode,
+		name: Option<&str>,
+	) -> Location<'c> {
+		let location = self.location_of(node);
+
+		match name {
+			Some(name) => Location::name(self.context, name, location),
+			None => location,
+		}
+	}
+
+	/// Gets the location of operations a construct is desugared into, which
+	/// fuses the location of the construct with those of `nodes`.
+	fn desugared(
+		&self,
+		construct: &str,
+		nodes: &[&SyntaxNode],
+	) -> Location<'c> {
+		let locations = std::iter::once(self.location)
+			.chain(nodes.iter().map(|node| self.location_of(node)))
+			.collect::<Vec<_>>();
+
+		Location::fused(
+			self.context,
+			&locations,
+			Attribute::parse(self.context, &dialect::string(construct))
+				.expect("valid string attribute"),
+		)
+	}
+}
+
+/// Tests if an expression needs no evaluation, so that it is not worth
+/// suspending.
+fn is_value(resolution: &Resolution, expr: &ast::Expr) -> bool {
+	match expr {
+		ast::Expr::Literal(_)
+		| ast::Expr::Lambda(_)
+		| ast::Expr::List(_)
+		| ast::Expr::Path(_) => true,
+		ast::Expr::Str(string) => literal(&string.normalized_parts()).is_some(),
+		ast::Expr::Paren(paren) => {
+			paren.expr().is_some_and(|expr| is_value(resolution, &expr))
+		}
+		ast::Expr::Ident(ident) => matches!(
+			resolution.variable(ident),
+			Some(Variable::Lexical | Variable::Base)
+		),
+		_ => false,
+	}
+}
+
+pub(crate) fn collect_definitions(
+	entries: impl Iterator<Item = ast::Entry>,
+) -> Result<Definitions> {
+	let mut definitions = Definitions::default();
+
+	for entry in entries {
+		match entry {
+			ast::Entry::Inherit(inherit) => {
+				let from = inherit
+					.from()
+					.map(|from| child(from.expr()))
+					.transpose()?;
+
+				for attr in inherit.attrs() {
+					let ast::Attr::Ident(ident) = attr else {
+						bail!("dynamic attributes are not allowed in inherit");
+					};
+					let name = ident_name(&ident)?;
+
+					if definitions
+						.named
+						.insert(
+							name.clone(),
+							Definition::Inherit(from.clone(), ident),
+						)
+						.is_some()
+					{
+						bail!("attribute '{name}' already defined");
+					}
+				}
+			}
+			ast::Entry::AttrpathValue(entry) => {
+				let keys = attrpath_keys(&child(entry.attrpath())?)?;
+				let value = child(entry.value())?;
+
+				insert_definition(&mut definitions, keys, value)?;
+			}
+		}
+	}
+
+	Ok(definitions)
+}
+
+fn insert_definition(
+	definitions: &mut Definitions,
+	mut keys: Vec<Key>,
+	value: ast::Expr,
+) -> Result<()> {
+	if keys.is_empty() {
+		bail!("empty attribute path");
+	}
+
+	let rest = keys.split_off(1);
+	let key = keys.pop().unwrap();
+
+	let definition = match (key, rest.is_empty()) {
+		(Key::Static(name), true) => {
+			if definitions.named.contains_key(&name) {
+				bail!("attribute '{name}' already defined");
+			}
+
+			definitions.named.insert(name, Definition::Expr(value));
+			return Ok(());
+		}
+		(Key::Static(name), false) => {
+			let nested = definitions
+				.named
+				.entry(name.clone())
+				.or_insert_with(|| Definition::Nested(Default::default()));
+			let Definition::Nested(nested) = nested else {
+				bail!("attribute '{name}' already defined");
+			};
+
+			return insert_definition(nested, rest, value);
+		}
+		(Key::Dynamic(name), true) => (name, Definition::Expr(value)),
+		// Computed names are never merged, so each path gets its own set.
+		(Key::Dynamic(name), false) => {
+			let mut nested = Definitions::default();
+			insert_definition(&mut nested, rest, value)?;
+
+			(name, Definition::Nested(nested))
+		}
+	};
+
+	definitions.dynamic.push(definition);
+
+	Ok(())
+}
+
+fn attrpath_keys(path: &ast::Attrpath) -> Result<Vec<Key>> {
+	path.attrs().map(|attr| attr_key(&attr)).collect()
+}
+
+pub(crate) fn attr_key(attr: &ast::Attr) -> Result<Key> {
+	let string = match attr {
+		ast::Attr::Ident(ident) => return Ok(Key::Static(ident_name(ident)?)),
+		ast::Attr::Str(string) => ast::Expr::Str(string.clone()),
+		ast::Attr::Dynamic(dynamic) => child(dynamic.expr())?,
+	};
+
+	Ok(match &string {
+		ast::Expr::Str(parts) => match literal(&parts.normalized_parts()) {
+			Some(name) => Key::Static(name),
+			None => Key::Dynamic(string),
+		},
+		_ => Key::Dynamic(string),
+	})
+}
+
+/// Gets the value of a string without interpolation.
+pub(crate) fn literal(parts: &[ast::InterpolPart<String>]) -> Option<String> {
+	parts
+		.iter()
+		.map(|part| match part {
+			ast::InterpolPart::Literal(literal) => Some(literal.as_str()),
+			ast::InterpolPart::Interpolation(_) => None,
+		})
+		.collect()
+}
+
+pub(crate) fn ident_name(ident: &ast::Ident) -> Result<String> {
+	Ok(ident
+		.ident_token()
+		.context("missing identifier")?
+		.text()
+		.to_string())
+}
+
+pub(crate) fn child<T>(node: Option<T>) -> Result<T> {
+	node.context("incomplete syntax tree")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::source::Diagnostic;
+
+	fn lower(context: &Context, text: &str) -> Result<String> {
+		dialect::load(context);
+
+		let root = rnix::Root::parse(text).tree();
+		let mut module = Lowerer::new(context, Source::new("test.nix", text))
+			.lower_root(&root)?;
+		let pass_manager = melior::pass::Manager::new(context);
+		pass_manager.add_pass(dialect::verifier());
+		pass_manager.run(&mut module)?;
+
+		Ok(module.as_operation().to_string())
+	}
+
+	#[test]
+	fn suspend_bindings() {
+		let context = Context::new();
+		let module =
+			lower(&context, "let a = 1 + 2; b = a; c = x: x; in b").unwrap();
+
+		assert_eq!(module.matches("\"nix.thunk\"").count(), 1);
+		assert_eq!(module.matches("\"nix.letrec\"").count(), 1);
+	}
+
+	#[test]
+	fn suspend_arguments() {
+		let context = Context::new();
+		let module = lower(&context, "(x: x) (1 + 2)").unwrap();
+
+		assert_eq!(module.matches("\"nix.thunk\"").count(), 1);
+	}
+
+	#[test]
+	fn suspend_list_elements() {
+		let context = Context::new();
+		let module =
+			lower(&context, "[ 1 (1 + 2) \"a\" \"${\"b\"}\" ] ++ [ ]").unwrap();
+
+		assert_eq!(module.matches("\"nix.thunk\"").count(), 2);
+		assert_eq!(module.matches("\"nix.interpolate\"").count(), 1);
+	}
+
+	#[test]
+	fn lower_indented_strings() {
+		let context = Context::new();
+		let module = lower(
+			&context,
+			"let x = \"b\"; in ''\n  a\n    ${x} '''c''' ''${d} ''\\t\n  ''",
+		)
+		.unwrap();
+
+		assert!(module.contains(r#"value = "a\0A  ""#));
+		assert!(module.contains(r#"value = " ''c'' ${d} \09\0A""#));
+	}
+
+	#[test]
+	fn resolve_paths() {
+		let context = Context::new();
+		dialect::load(&context);
+
+		let text = "[ ./a/../b /c/./d ]";
+		let root = rnix::Root::parse(text).tree();
+		let module = Lowerer::new(&context, Source::new("/e/test.nix", text))
+			.lower_root(&root)
+			.unwrap()
+			.as_operation()
+			.to_string();
+
+		assert!(module.contains("value = \"/e/b\""));
+		assert!(module.contains("value = \"/c/d\""));
+	}
+
+	#[test]
+	fn lower_imported_files() {
+		let context = Context::new();
+		dialect::load(&context);
+
+		let directory = std::env::temp_dir().join("mlnx-lower-imported-files");
+		fs::create_dir_all(directory.join("b")).unwrap();
+		fs::write(directory.join("b/default.nix"), "import ../a.nix").unwrap();
+
+		let text = "[ (import ./b) ./a.nix ./c.nix ]";
+		let root = rnix::Root::parse(text).tree();
+		let path = directory.join("a.nix").to_string_lossy().into_owned();
+		fs::write(&path, text).unwrap();
+		let module = Lowerer::new(&context, Source::new(path.clone(), text))
+			.lower_root(&root)
+			.unwrap();
+		let functions = module.as_operation().to_string();
+
+		assert!(module.as_operation().verify());
+		assert!(functions.contains(&format!("sym_name = \"{path}\"")));
+		assert!(functions.contains(&format!(
+			"sym_name = \"{}/b/default.nix\"",
+			directory.to_string_lossy()
+		)));
+		assert!(functions.contains("sym_name = \"nix_main\""));
+		assert_eq!(functions.matches("\"nix.file\"").count(), 2);
+	}
+
+	#[test]
+	fn lower_dynamic_attributes() {
+		let context = Context::new();
+		let module = lower(
+			&context,
+			r#"let x = "a"; in rec { ${x}.b = c; "c" = 1; a.${"d"} = 2; }.${x} ? b"#,
+		)
+		.unwrap();
+
+		assert_eq!(module.matches("\"nix.attrset\"").count(), 3);
+		assert!(module.contains("path = [unit]"));
+		assert_eq!(
+			lower(&context, "let ${\"a\" + \"b\"} = 1; in ab")
+				.unwrap_err()
+				.to_string(),
+			"dynamic attributes not allowed in let"
+		);
+	}
+
+	#[test]
+	fn name_functions() {
+		let context = Context::new();
+		let module = lower(
+			&context,
+			"let f = { a }: a; in [ (f { a = 1; }) ({ b }: b) ]",
+		)
+		.unwrap();
+
+		assert!(module.contains("function = \"f\""));
+		assert!(module.contains("function = \"\""));
+	}
+
+	#[test]
+	fn reject_undefined_variable() {
+		let context = Context::new();
+
+		let error = lower(&context, "let a = b; in a").unwrap_err();
+		let diagnostic = error.downcast_ref::<Diagnostic>().unwrap();
+
+		assert_eq!(diagnostic.message(), "undefined variable 'b'");
+		assert_eq!(diagnostic.position(), (1, 9));
+		assert!(lower(&context, "with {}; let a = b; in a").is_ok());
+	}
+}
@@ -1,10 +1,195 @@
+mod repl;
+
+use std::{
+	fs,
+	io::Write as _,
+	path::PathBuf,
+	process::{Command, Stdio},
+};
+
 use anyhow::{bail, Context as _, Result};
-use melior_next::{dialect, ir::*, pass, utility::*, Context, ExecutionEngine};
+use melior::{dialect, ir::Module, pass, utility::*, Context, ExecutionEngine};
+use rowan::ast::AstNode;
+
+use mlnx::{
+	cache::Cache,
+	convert::{convert, convert_executable},
+	lower::{Lowerer, MAIN},
+	partial, shader,
+	source::Source,
+	spirv,
+};
+use mlnx_runtime::{
+	graph::{self, Graph},
+	Value,
+};
+
+const USAGE: &str = "\
+usage: nix <command> [options] <file>
+       nix build [-o <path>] [--runtime <library>] <file>
+       nix graph [--json] <file> [<attribute path>]
+       nix repl
+
+commands:
+  emit-ast               print the syntax tree
+  emit-mlir              print the module in the nix dialect
+  emit-mlir --lowered    print the module after the lowering pipeline
+  emit-llvm              print the module translated to LLVM IR
+  emit-spirv             write the shader in the file as a SPIR-V binary
+  emit-obj               write an object file for the host, whose main
+                         function prints the value of the file
+  build                  link that object file with the runtime into an
+                         executable, named after the file by default
+  run                    evaluate the file and print its value
+  graph                  print the dependency graph of the packages in the
+                         set at the attribute path, in DOT or in JSON
+  repl                   start an interactive session
+
+options:
+  --shader               compile the file as a shader, with emit-mlir
+  --runtime <library>    the static runtime library to link with, by default
+                         libmlnx_runtime.a next to this executable
+  --cache <directory>    the directory caching lowered files, by default
+                         mlnx in $XDG_CACHE_HOME or ~/.cache
+  --no-cache             lower every file again without caching them
+  --pass-pipeline <pipeline>
+                         run a textual pass pipeline instead of the default
+                         one, e.g. 'builtin.module(convert-scf-to-cf)'";
+
+/// The stage at which the compiler stops.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Stage {
+	Ast,
+	Mlir,
+	LoweredMlir,
+	Llvm,
+	Spirv,
+	Object,
+	Executable,
+	Run,
+	Graph,
+	Repl,
+}
+
+struct Arguments {
+	stage: Stage,
+	pass_pipeline: Option<String>,
+	shader: bool,
+	/// The path of the executable to build.
+	output: Option<String>,
+	runtime: Option<PathBuf>,
+	/// The directory caching lowered files, if any.
+	cache: Option<PathBuf>,
+	file_path: String,
+	attribute_path: String,
+	json: bool,
+}
+
+fn parse_arguments(
+	mut arguments: impl Iterator<Item = String>,
+) -> Result<Arguments> {
+	let mut stage = match arguments.next().as_deref() {
+		Some("emit-ast") => Stage::Ast,
+		Some("emit-mlir") => Stage::Mlir,
+		Some("emit-llvm") => Stage::Llvm,
+		Some("emit-spirv") => Stage::Spirv,
+		Some("emit-obj") => Stage::Object,
+		Some("build") => Stage::Executable,
+		Some("run") => Stage::Run,
+		Some("graph") => Stage::Graph,
+		Some("repl") => Stage::Repl,
+		Some(command) => bail!("unknown command '{command}'\n\n{USAGE}"),
+		None => bail!("no command provided\n\n{USAGE}"),
+	};
+	let mut pass_pipeline = None;
+	let mut shader = false;
+	let mut output = None;
+	let mut runtime = None;
+	let mut cache = Cache::default_directory();
+	let mut file_path = None;
+	let mut attribute_path = None;
+	let mut json = false;
+
+	while let Some(argument) = arguments.next() {
+		match argument.as_str() {
+			"--lowered" if stage == Stage::Mlir => stage = Stage::LoweredMlir,
+			"--json" if stage == Stage::Graph => json = true,
+			"--shader" if matches!(stage, Stage::Mlir | Stage::LoweredMlir) => {
+				shader = true
+			}
+			"-o" if stage == Stage::Executable => {
+				output = Some(arguments.next().context("-o requires a path")?);
+			}
+			"--runtime" if stage == Stage::Executable => {
+				runtime = Some(
+					arguments
+						.next()
+						.context("--runtime requires a library")?
+						.into(),
+				);
+			}
+			"--cache" => {
+				cache = Some(
+					arguments
+						.next()
+						.context("--cache requires a directory")?
+						.into(),
+				);
+			}
+			"--no-cache" => cache = None,
+			"--pass-pipeline" => {
+				pass_pipeline = Some(
+					arguments
+						.next()
+						.context("--pass-pipeline requires a pipeline")?,
+				);
+			}
+			option if option.starts_with("--") => {
+				bail!("unknown option '{option}'\n\n{USAGE}")
+			}
+			_ if file_path.is_some()
+				&& stage == Stage::Graph
+				&& attribute_path.is_none() =>
+			{
+				attribute_path = Some(argument)
+			}
+			_ if file_path.is_some() => {
+				bail!("more than one file provided\n\n{USAGE}")
+			}
+			_ => file_path = Some(argument),
+		}
+	}
+
+	let file_path = match (stage, file_path) {
+		(Stage::Repl, None) => String::new(),
+		(Stage::Repl, Some(_)) => bail!("repl takes no file\n\n{USAGE}"),
+		(_, file_path) => {
+			file_path.context(format!("no file provided\n\n{USAGE}"))?
+		}
+	};
+
+	Ok(Arguments {
+		stage,
+		pass_pipeline,
+		shader: shader || stage == Stage::Spirv,
+		output,
+		runtime,
+		cache,
+		file_path,
+		attribute_path: attribute_path.unwrap_or_default(),
+		json,
+	})
+}
 
 fn main() -> Result<()> {
-	let file_path = std::env::args().nth(1).context("No file path provided")?;
-	let file =
-		std::fs::read_to_string(&file_path).context("Failed to read file")?;
+	let arguments = parse_arguments(std::env::args().skip(1))?;
+
+	if arguments.stage == Stage::Repl {
+		return repl::run();
+	}
+
+	let file = std::fs::read_to_string(&arguments.file_path)
+		.context("Failed to read file")?;
 	let parsed = rnix::Root::parse(&file);
 
 	if !parsed.errors().is_empty() {
@@ -18,79 +203,342 @@ fn main() -> Result<()> {
 
 	let ast = parsed.tree();
 
-	dbg!(&ast);
+	if arguments.stage == Stage::Ast {
+		println!("{:#?}", ast.syntax());
+		return Ok(());
+	}
+
+	let context = create_context();
+
+	if arguments.shader {
+		return compile_shader(&context, arguments, file, &ast);
+	}
+
+	let mut lowerer =
+		Lowerer::new(&context, Source::new(arguments.file_path.clone(), file));
 
+	if let Some(directory) = &arguments.cache {
+		lowerer = lowerer.cache(Cache::new(directory));
+	}
+
+	let mut module =
+		lowerer.lower_root(&ast).context("Failed to lower file")?;
+	verify(&context, &mut module)?;
+
+	if arguments.stage == Stage::Mlir {
+		println!("{}", module.as_operation());
+		return Ok(());
+	}
+
+	partially_evaluate(&context, &mut module)?;
+
+	let mut module = match arguments.stage {
+		Stage::Object | Stage::Executable => {
+			convert_executable(&context, &module)
+		}
+		_ => convert(&context, &module),
+	}
+	.context("Failed to convert module")?;
+	run_pipeline(&context, &mut module, arguments.pass_pipeline.as_deref())?;
+
+	match arguments.stage {
+		Stage::LoweredMlir => println!("{}", module.as_operation()),
+		Stage::Llvm => print!("{}", translate(&module)?),
+		Stage::Object => std::io::stdout().write_all(&object(&module)?)?,
+		Stage::Executable => link(&object(&module)?, &arguments)?,
+		Stage::Graph => evaluate(&module, |value| {
+			let packages = graph::select(value, &arguments.attribute_path);
+			let graph = Graph::new(packages);
+
+			if arguments.json {
+				graph.json()
+			} else {
+				graph.dot()
+			}
+		})?,
+		_ => evaluate(&module, mlnx_runtime::print)?,
+	}
+
+	Ok(())
+}
+
+/// Creates a context with every dialect, including the nix dialect, loaded.
+fn create_context() -> Context {
 	let registry = dialect::Registry::new();
 	register_all_dialects(&registry);
 
 	let context = Context::new();
 	context.append_dialect_registry(&registry);
-	context.get_or_load_dialect("func");
+	context.load_all_available_dialects();
 	register_all_llvm_translations(&context);
+	mlnx::dialect::load(&context);
 
-	let location = Location::unknown(&context);
-	let mut module = Module::new(location);
+	context
+}
 
-	let integer_type = Type::integer(&context, 64);
+/// Folds the parts of a module in the nix dialect known at compile time, and
+/// verifies the result.
+fn partially_evaluate(context: &Context, module: &mut Module) -> Result<()> {
+	let pass_manager = pass::Manager::new(context);
+	pass_manager.add_pass(partial::pass());
+	pass_manager.add_pass(mlnx::dialect::verifier());
+	pass_manager.enable_verifier(true);
+	pass_manager
+		.run(module)
+		.context("Failed to partially evaluate module")?;
 
-	let function = {
-		let region = Region::new();
-		let block = Block::new(&[]);
+	Ok(())
+}
 
-		let constant = operation::Builder::new(
-			"arrith.constant",
-			Location::unknown(&context),
-		)
-		.add_attributes(
-			&NamedAttribute::new_parsed_vec(&context, &[("value", "0")])
-				.context("Failed to parse attributes")?,
-		);
-		block.append_operation(
-			operation::Builder::new("func.return", Location::unknown(&context))
-				.add_operands(vec![constant])
-				.build(),
-		);
+/// Verifies a module in the nix dialect, whose operations MLIR only verifies
+/// as unregistered operations, with the verifier pass of the dialect.
+fn verify(context: &Context, module: &mut Module) -> Result<()> {
+	let pass_manager = pass::Manager::new(context);
+	pass_manager.add_pass(mlnx::dialect::verifier());
+	pass_manager
+		.run(module)
+		.context("Failed to verify module")?;
 
-		region.append_block(block);
-
-		operation::Builder::new("func.func", Location::unknown(&context))
-			.add_attributes(
-				&NamedAttribute::new_parsed_vec(
-					&context,
-					&[
-						("function_type", "() -> i64"),
-						("sym_name", "\"main\""),
-						("llvm.emit_c_interface", "unit"),
-					],
-				)
-				.unwrap(),
-			)
-			.add_regions(vec![region])
-			.build()
-	};
+	Ok(())
+}
 
-	module.body().append_operation(function);
+/// Lowers the control flow and arithmetic left by conversion to the `llvm`
+/// dialect, with either the default passes or a textual pipeline.
+fn run_pipeline(
+	context: &Context,
+	module: &mut Module,
+	pipeline: Option<&str>,
+) -> Result<()> {
+	let pass_manager = pass::Manager::new(context);
+	register_all_passes();
 
-	assert!(module.as_operation().verify());
+	if let Some(pipeline) = pipeline {
+		parse_pass_pipeline(pass_manager.as_operation_pass_manager(), pipeline)
+			.context("Failed to parse pass pipeline")?;
+	} else {
+		pass_manager.add_pass(pass::conversion::convert_scf_to_cf());
+		pass_manager.add_pass(pass::conversion::convert_cf_to_llvm());
+		pass_manager.add_pass(pass::conversion::convert_arithmetic_to_llvm());
+	}
 
-	let pass_manager = pass::Manager::new(&context);
-	register_all_passes();
-	pass_manager.add_pass(pass::conversion::convert_scf_to_cf());
-	pass_manager.add_pass(pass::conversion::convert_cf_to_llvm());
-	pass_manager.add_pass(pass::conversion::convert_func_to_llvm());
-	pass_manager.add_pass(pass::conversion::convert_arithmetic_to_llvm());
 	pass_manager.enable_verifier(true);
 	pass_manager
-		.run(&mut module)
+		.run(module)
 		.context("Failed to run pass manager")?;
 
-	let engine = ExecutionEngine::new(&module, 0, &[], false);
-	let mut result = 0;
+	Ok(())
+}
+
+/// Compiles a shader to the `spirv` dialect, printing it or writing it as a
+/// SPIR-V binary.
+fn compile_shader(
+	context: &Context,
+	arguments: Arguments,
+	file: String,
+	ast: &rnix::ast::Root,
+) -> Result<()> {
+	let mut module =
+		shader::lower(context, &Source::new(arguments.file_path, file), ast)
+			.context("Failed to lower shader")?;
+
+	if !module.as_operation().verify() {
+		bail!("Failed to verify module:\n{}", module.as_operation());
+	}
+
+	if arguments.stage == Stage::Mlir {
+		println!("{}", module.as_operation());
+		return Ok(());
+	}
+
+	shader::convert(context, &mut module)?;
+
+	if arguments.stage == Stage::LoweredMlir {
+		println!("{}", module.as_operation());
+		return Ok(());
+	}
+
+	let binary = spirv::serialize(context, &module)
+		.context("Failed to serialize shader")?;
+	std::io::stdout().write_all(&binary)?;
+
+	Ok(())
+}
+
+/// Translates a module to LLVM IR.
+fn translate(module: &Module) -> Result<String> {
+	Ok(String::from_utf8(mlir_translate(
+		module,
+		"--mlir-to-llvmir",
+	)?)?)
+}
+
+/// Translates a module out of MLIR.
+///
+/// The C API of MLIR 16 has no translations, so the module is piped through
+/// `mlir-translate` instead.
+fn mlir_translate(module: &Module, translation: &str) -> Result<Vec<u8>> {
+	pipe(
+		"mlir-translate",
+		&[translation],
+		module.as_operation().to_string().as_bytes(),
+	)
+	.with_context(|| format!("Failed to translate module with {translation}"))
+}
+
+/// Compiles a module to an object file for the host with `llc`.
+fn object(module: &Module) -> Result<Vec<u8>> {
+	// Executables are position independent by default.
+	pipe(
+		"llc",
+		&["-filetype=obj", "-relocation-model=pic", "-o", "-"],
+		&mlir_translate(module, "--mlir-to-llvmir")?,
+	)
+	.context("Failed to compile module")
+}
+
+/// The native libraries the runtime depends on as a Rust static library, as
+/// printed by `rustc --print native-static-libs` for the target of the
+/// compiler, which is also the one of the executables it builds.
+const NATIVE_LIBRARIES: Option<&[&str]> = if cfg!(target_os = "macos") {
+	Some(&["-lSystem", "-lc", "-lm"])
+} else if cfg!(all(target_os = "linux", target_env = "musl")) {
+	Some(&["-lc"])
+} else if cfg!(target_os = "linux") {
+	Some(&[
+		"-lgcc_s",
+		"-lutil",
+		"-lrt",
+		"-lpthread",
+		"-lm",
+		"-ldl",
+		"-lc",
+	])
+} else if cfg!(target_os = "freebsd") {
+	Some(&[
+		"-lexecinfo",
+		"-lpthread",
+		"-lgcc_s",
+		"-lc",
+		"-lm",
+		"-lrt",
+		"-lutil",
+	])
+} else {
+	None
+};
 
-	unsafe {
-		engine
-			.invoke_packed("add", &mut [&mut result as *mut i64 as *mut ()])
-			.context("Failed to invoke function")?;
+/// Links an object file with the runtime into an executable.
+fn link(object: &[u8], arguments: &Arguments) -> Result<()> {
+	let libraries = NATIVE_LIBRARIES.context(
+		"Failed to link, as the native libraries of this target are unknown",
+	)?;
+	let output = match &arguments.output {
+		Some(output) => output.clone(),
+		None => PathBuf::from(&arguments.file_path)
+			.file_stem()
+			.context("Failed to name executable")?
+			.to_string_lossy()
+			.into_owned(),
+	};
+	let runtime = match &arguments.runtime {
+		Some(runtime) => runtime.clone(),
+		None => std::env::current_exe()?.with_file_name("libmlnx_runtime.a"),
+	};
+
+	if !runtime.exists() {
+		bail!(
+			"Failed to find the runtime library at {}, build it with `cargo \
+			 build -p mlnx_runtime` or pass --runtime",
+			runtime.display()
+		);
+	}
+
+	let object_path = format!("{output}.o");
+	fs::write(&object_path, object)?;
+
+	let status = Command::new("cc")
+		.arg(&object_path)
+		.arg(&runtime)
+		.args(libraries)
+		.arg("-o")
+		.arg(&output)
+		.status()
+		.context("Failed to run cc");
+	fs::remove_file(&object_path)?;
+
+	if !status?.success() {
+		bail!("Failed to link {output}");
+	}
+
+	Ok(())
+}
+
+/// Runs a program on an input and returns its output.
+fn pipe(program: &str, arguments: &[&str], input: &[u8]) -> Result<Vec<u8>> {
+	let mut child = Command::new(program)
+		.args(arguments)
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.spawn()
+		.with_context(|| format!("Failed to run {program}"))?;
+
+	child
+		.stdin
+		.take()
+		.with_context(|| format!("Failed to open {program} input"))?
+		.write_all(input)?;
+
+	let output = child.wait_with_output()?;
+
+	if !output.status.success() {
+		bail!("{program} exited with {}", output.status);
+	}
+
+	Ok(output.stdout)
+}
+
+/// The `main` function of a compiled module, which evaluates the root file.
+type MainFn = extern "C-unwind" fn() -> *mut Value;
+
+/// Compiles a module with an execution engine, which must outlive the values
+/// computed by its `main` function.
+fn compile(module: &Module) -> Result<(ExecutionEngine, MainFn)> {
+	let engine = ExecutionEngine::new(module, 0, &[], false);
+
+	for (name, address) in mlnx_runtime::symbols() {
+		unsafe { engine.register_symbol(name, address) };
+	}
+
+	let main = engine.lookup(MAIN);
+
+	if main.is_null() {
+		bail!("Failed to find main function");
+	}
+
+	// Evaluation errors are panics of the runtime, which unwind through
+	// compiled code to `mlnx_runtime::catch`, as the engine registers its
+	// unwind tables (see the `unwind_through_compiled_code` test of melior).
+	// They would abort at the C boundary of `invoke_packed`, so `main` is
+	// called directly instead.
+	let main = unsafe { std::mem::transmute::<*mut (), MainFn>(main) };
+
+	Ok((engine, main))
+}
+
+/// Runs a module and prints its value, formatted by `output` as `nix eval`
+/// does by default, exiting with an error status if evaluation fails.
+fn evaluate(
+	module: &Module,
+	output: impl FnOnce(*mut Value) -> String,
+) -> Result<()> {
+	let (_engine, main) = compile(module)?;
+
+	match mlnx_runtime::catch(|| output(main())) {
+		Ok(value) => println!("{value}"),
+		Err(error) => {
+			eprintln!("{error}");
+			std::process::exit(1);
+		}
 	}
 
 	Ok(())
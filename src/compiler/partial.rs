@@ -0,0 +1,975 @@
+//! Partial evaluation of the `nix` dialect.
+//!
+//! The pass rewrites a module before conversion until nothing is left to
+//! fold, so that the static parts of files, such as attribute names, string
+//! literals and lists of flags, reach the JIT evaluated:
+//!
+//! - arithmetic, comparisons, negation and interpolation of constants become
+//!   constants, except where they fail at runtime, as on overflow;
+//! - `nix.force` of values in weak head normal form, `nix.thunk` of them and
+//!   `nix.if` and `nix.assert` with constant conditions are replaced by their
+//!   values or taken branch;
+//! - selections of static paths in attribute sets known at compile time,
+//!   `builtins` included, are resolved, and so are `?`, `//` and `++`;
+//! - applications of small lambdas known at compile time are inlined, unless
+//!   they have formals, which are checked at the call site, or call
+//!   themselves;
+//! - operations without effects whose results are unused are erased.
+//!
+//! Values are known through the bindings of `nix.letrec`. Inlined calls are
+//! missing from the traces of errors.
+
+use std::{collections::BTreeMap, mem::ManuallyDrop};
+
+use anyhow::{Context as _, Result};
+use melior::{
+	ir::{
+		block::Argument, operation::ResultValue, r#type, BlockRef, Location,
+		Operation, OperationRef, Value,
+	},
+	pass::{
+		external::{create_external, RunExternalPass},
+		Pass,
+	},
+	Context, LogicalResult,
+};
+
+use crate::{convert::captures, dialect};
+
+/// The maximum number of rounds of rewrites.
+const ROUNDS: usize = 16;
+/// The maximum number of bindings followed to know a value.
+const DEPTH: usize = 16;
+/// The maximum number of operations in an inlined lambda.
+const INLINE_SIZE: usize = 32;
+/// The maximum number of lambdas inlined in a module, which bounds the
+/// unrolling of mutually recursive functions.
+const INLINE_LIMIT: usize = 1024;
+
+/// Creates a pass partially evaluating modules.
+pub fn pass() -> Pass {
+	thread_local! {
+		// Type IDs live as long as their allocator.
+		static ID: r#type::Id =
+			ManuallyDrop::new(r#type::id::Allocator::new()).allocate_type_id();
+	}
+
+	create_external(
+		PartialEvaluation,
+		ID.with(|&id| id),
+		"PartialEvaluation",
+		"nix-partial-evaluation",
+		"Partially evaluate the nix dialect",
+		"builtin.module",
+	)
+}
+
+#[derive(Clone)]
+struct PartialEvaluation;
+
+impl RunExternalPass for PartialEvaluation {
+	fn run(&mut self, operation: OperationRef) -> LogicalResult {
+		let context = operation.context();
+
+		match evaluate(&context, operation) {
+			Ok(()) => LogicalResult::success(),
+			Err(error) => {
+				// The pass manager only reports that the pass failed.
+				operation.location().emit_error(&format!("{error:#}"));
+				LogicalResult::failure()
+			}
+		}
+	}
+}
+
+/// Partially evaluates the operations nested in an operation, such as a
+/// module.
+pub fn evaluate<'c>(
+	context: &'c Context,
+	operation: OperationRef<'c>,
+) -> Result<()> {
+	let mut inlined = 0;
+
+	for _ in 0..ROUNDS {
+		let mut changed = false;
+
+		// Operations are folded after those nested in them, which folding
+		// may erase.
+		for inner in nested(operation, Order::Post) {
+			changed |= fold(context, inner, &mut inlined)?;
+		}
+
+		changed |= eliminate(operation)?;
+
+		if !changed {
+			break;
+		}
+	}
+
+	Ok(())
+}
+
+/// A constant known at compile time.
+#[derive(Clone, Debug, PartialEq)]
+enum Constant {
+	Null,
+	Bool(bool),
+	Int(i64),
+	Float(f64),
+	String(String),
+}
+
+impl Constant {
+	fn number(&self) -> Option<f64> {
+		match *self {
+			Self::Int(value) => Some(value as f64),
+			Self::Float(value) => Some(value),
+			_ => None,
+		}
+	}
+
+	fn operation<'c>(
+		&self,
+		context: &'c Context,
+		location: Location<'c>,
+	) -> Operation<'c> {
+		match self {
+			Self::Null => dialect::null(context, location),
+			&Self::Bool(value) => dialect::boolean(context, value, location),
+			&Self::Int(value) => dialect::integer(context, value, location),
+			&Self::Float(value) => dialect::float(context, value, location),
+			Self::String(value) => dialect::str(context, value, location),
+		}
+	}
+}
+
+/// Rewrites an operation whose operands are known enough, and tells if it
+/// did.
+fn fold<'c>(
+	context: &'c Context,
+	operation: OperationRef<'c>,
+	inlined: &mut usize,
+) -> Result<bool> {
+	let name = operation.name().as_string_ref().as_str()?.to_owned();
+	let operands = (0..operation.operand_count())
+		.map(|index| operation.operand(index))
+		.collect::<Result<Vec<_>, _>>()?;
+
+	let constant = match name.as_str() {
+		"nix.add" | "nix.sub" | "nix.mul" | "nix.div" => {
+			match (constant(operands[0]), constant(operands[1])) {
+				(Some(lhs), Some(rhs)) => arithmetic(&name, lhs, rhs),
+				_ => None,
+			}
+		}
+		"nix.eq" | "nix.lt" => {
+			match (constant(operands[0]), constant(operands[1])) {
+				(Some(lhs), Some(rhs)) => compare(&name, lhs, rhs),
+				_ => None,
+			}
+		}
+		"nix.not" => match constant(operands[0]) {
+			Some(Constant::Bool(value)) => Some(Constant::Bool(!value)),
+			_ => None,
+		},
+		"nix.interpolate" => operands
+			.iter()
+			.map(|&part| match constant(part) {
+				Some(Constant::String(part)) => Some(part),
+				_ => None,
+			})
+			.collect::<Option<String>>()
+			.map(Constant::String),
+		"nix.force" => return force(operation, operands[0]),
+		"nix.thunk" => return hoist(operation),
+		"nix.if" => {
+			let Some(Constant::Bool(condition)) = constant(operands[0]) else {
+				return Ok(false);
+			};
+			let branch = block(operation, if condition { 0 } else { 1 })?;
+			let value = splice(branch, operation)?;
+			replace(operation, value)?;
+
+			return Ok(true);
+		}
+		"nix.assert" => {
+			if constant(operands[0]) != Some(Constant::Bool(true)) {
+				return Ok(false);
+			}
+
+			erase(operation);
+			return Ok(true);
+		}
+		"nix.select" | "nix.has_attr" => return select(context, operation),
+		"nix.update" => return update(context, operation, &operands),
+		"nix.concat" => return concat(context, operation, &operands),
+		"nix.apply" => return inline(operation, &operands, inlined),
+		_ => None,
+	};
+
+	let Some(constant) = constant else {
+		return Ok(false);
+	};
+	let value =
+		insert(operation, constant.operation(context, operation.location()))?;
+	replace(operation, value)?;
+
+	Ok(true)
+}
+
+/// Evaluates arithmetic on constants, unless it fails at runtime.
+fn arithmetic(name: &str, lhs: Constant, rhs: Constant) -> Option<Constant> {
+	match (lhs, rhs) {
+		(Constant::String(lhs), Constant::String(rhs)) if name == "nix.add" => {
+			Some(Constant::String(lhs + &rhs))
+		}
+		(Constant::Int(lhs), Constant::Int(rhs)) => match name {
+			"nix.add" => lhs.checked_add(rhs),
+			"nix.sub" => lhs.checked_sub(rhs),
+			"nix.mul" => lhs.checked_mul(rhs),
+			_ if rhs == 0 => None,
+			_ => lhs.checked_div(rhs),
+		}
+		.map(Constant::Int),
+		(lhs, rhs) => {
+			let (lhs, rhs) = (lhs.number()?, rhs.number()?);
+
+			match name {
+				"nix.add" => Some(lhs + rhs),
+				"nix.sub" => Some(lhs - rhs),
+				"nix.mul" => Some(lhs * rhs),
+				_ if rhs == 0.0 => None,
+				_ => Some(lhs / rhs),
+			}
+			.map(Constant::Float)
+		}
+	}
+}
+
+/// Compares constants as `nix.eq` or `nix.lt`, unless it fails at runtime.
+fn compare(name: &str, lhs: Constant, rhs: Constant) -> Option<Constant> {
+	let (equal, less) = match (&lhs, &rhs) {
+		(Constant::Int(lhs), Constant::Int(rhs)) => {
+			(lhs == rhs, Some(lhs < rhs))
+		}
+		(Constant::String(lhs), Constant::String(rhs)) => {
+			(lhs == rhs, Some(lhs < rhs))
+		}
+		_ => match (lhs.number(), rhs.number()) {
+			(Some(lhs), Some(rhs)) => (lhs == rhs, Some(lhs < rhs)),
+			_ => (lhs == rhs, None),
+		},
+	};
+
+	if name == "nix.eq" {
+		Some(Constant::Bool(equal))
+	} else {
+		less.map(Constant::Bool)
+	}
+}
+
+/// Replaces the forcing of a value known in weak head normal form by the
+/// value.
+fn force<'c>(operation: OperationRef<'c>, value: Value<'c>) -> Result<bool> {
+	let Some(known) = known(value).filter(|&known| is_constructor(known))
+	else {
+		return Ok(false);
+	};
+	let Some(value) = materialize(known.result(0)?.into(), operation)? else {
+		return Ok(false);
+	};
+	replace(operation, value)?;
+
+	Ok(true)
+}
+
+/// Replaces a thunk whose region only constructs values by its value.
+fn hoist(operation: OperationRef) -> Result<bool> {
+	let body = block(operation, 0)?;
+	let terminator = body.terminator().context("thunk without terminator")?;
+	let mut current = body.first_operation();
+
+	while let Some(inner) = current.filter(|&inner| inner != terminator) {
+		if !is_constructor(inner) {
+			return Ok(false);
+		}
+
+		current = inner.next_in_block();
+	}
+
+	let value = splice(body, operation)?;
+	replace(operation, value)?;
+
+	Ok(true)
+}
+
+/// Resolves a selection or a test of a static path in sets known at compile
+/// time.
+fn select<'c>(
+	context: &'c Context,
+	operation: OperationRef<'c>,
+) -> Result<bool> {
+	let Some(path) = operation
+		.attribute("path")
+		.and_then(|path| path.elements())
+		.context("missing path attribute")?
+		.iter()
+		.map(|name| name.string_value())
+		.collect::<Option<Vec<_>>>()
+	else {
+		return Ok(false);
+	};
+	let is_select = operation.name().as_string_ref().as_str()? == "nix.select";
+	let mut attribute = Attribute::Value(operation.operand(0)?);
+
+	for name in path {
+		attribute = match attribute.get(name) {
+			Some(Some(attribute)) => attribute,
+			Some(None) if is_select => match operation.operand(1) {
+				Ok(default) => {
+					replace(operation, default)?;
+					return Ok(true);
+				}
+				Err(_) => return Ok(false),
+			},
+			Some(None) => {
+				let value = insert(
+					operation,
+					Constant::Bool(false)
+						.operation(context, operation.location()),
+				)?;
+				replace(operation, value)?;
+				return Ok(true);
+			}
+			None => return Ok(false),
+		};
+	}
+
+	let value = if !is_select {
+		insert(
+			operation,
+			Constant::Bool(true).operation(context, operation.location()),
+		)?
+	} else {
+		match attribute {
+			Attribute::Value(value) => match materialize(value, operation)? {
+				Some(value) => value,
+				None => return Ok(false),
+			},
+			Attribute::Builtin(name) => insert(
+				operation,
+				dialect::builtin(context, name, operation.location()),
+			)?,
+		}
+	};
+	replace(operation, value)?;
+
+	Ok(true)
+}
+
+/// An attribute of a set known at compile time.
+enum Attribute<'c> {
+	Value(Value<'c>),
+	/// An attribute of `builtins`, created by `nix.builtin`.
+	Builtin(&'c str),
+}
+
+impl<'c> Attribute<'c> {
+	/// Gets an attribute of the attribute, if it is a set known at compile
+	/// time.
+	fn get(&self, name: &'c str) -> Option<Option<Self>> {
+		match *self {
+			Attribute::Value(set) => {
+				let set = known(set)?;
+
+				if is(set, "nix.builtin")
+					&& set.attribute("name")?.string_value()? == "builtins"
+				{
+					return Some(
+						mlnx_runtime::builtins::contains(name)
+							.then_some(Attribute::Builtin(name)),
+					);
+				}
+
+				Some(
+					attributes(set)?
+						.get(name)
+						.map(|&value| Attribute::Value(value)),
+				)
+			}
+			Attribute::Builtin(_) => None,
+		}
+	}
+}
+
+/// Gets the attributes of a `nix.attrset` without computed names.
+fn attributes(set: OperationRef) -> Option<BTreeMap<&str, Value>> {
+	if !is(set, "nix.attrset") {
+		return None;
+	}
+
+	let names = set
+		.attribute("names")?
+		.elements()?
+		.iter()
+		.map(|name| name.string_value())
+		.collect::<Option<Vec<_>>>()?;
+
+	if names.len() != set.operand_count() {
+		return None;
+	}
+
+	names
+		.into_iter()
+		.enumerate()
+		.map(|(index, name)| Some((name, set.operand(index).ok()?)))
+		.collect()
+}
+
+/// Merges sets known at compile time.
+fn update<'c>(
+	context: &'c Context,
+	operation: OperationRef<'c>,
+	operands: &[Value<'c>],
+) -> Result<bool> {
+	let mut merged = BTreeMap::new();
+
+	for &operand in operands {
+		let Some(attributes) = known(operand).and_then(attributes) else {
+			return Ok(false);
+		};
+
+		merged.extend(attributes);
+	}
+
+	if !all_available(merged.values().copied(), operation)? {
+		return Ok(false);
+	}
+
+	let value = insert(
+		operation,
+		dialect::attrset(
+			context,
+			&merged.keys().copied().collect::<Vec<_>>(),
+			&merged.values().copied().collect::<Vec<_>>(),
+			&[],
+			operation.location(),
+		),
+	)?;
+	replace(operation, value)?;
+
+	Ok(true)
+}
+
+/// Concatenates lists known at compile time.
+fn concat<'c>(
+	context: &'c Context,
+	operation: OperationRef<'c>,
+	operands: &[Value<'c>],
+) -> Result<bool> {
+	let mut elements = Vec::new();
+
+	for &operand in operands {
+		let Some(list) = known(operand).filter(|&list| is(list, "nix.list"))
+		else {
+			return Ok(false);
+		};
+
+		for index in 0..list.operand_count() {
+			elements.push(list.operand(index)?);
+		}
+	}
+
+	if !all_available(elements.iter().copied(), operation)? {
+		return Ok(false);
+	}
+
+	let value = insert(
+		operation,
+		dialect::list(context, &elements, operation.location()),
+	)?;
+	replace(operation, value)?;
+
+	Ok(true)
+}
+
+/// Inlines the application of a lambda known at compile time.
+fn inline<'c>(
+	operation: OperationRef<'c>,
+	operands: &[Value<'c>],
+	inlined: &mut usize,
+) -> Result<bool> {
+	let Some(lambda) =
+		known(operands[0]).filter(|&known| is(known, "nix.lambda"))
+	else {
+		return Ok(false);
+	};
+	let body = block(lambda, 0)?;
+
+	if *inlined >= INLINE_LIMIT
+		|| nested(lambda, Order::Pre).len() > INLINE_SIZE
+		|| has_formals(body)
+		|| contains(lambda, operation)
+		|| is_recursive(lambda)?
+	{
+		return Ok(false);
+	}
+
+	let clone = insert_operation(operation, (*lambda).clone())?;
+
+	// Bindings of a `nix.letrec` are only in scope in its region, but its
+	// results stand for them after it.
+	for capture in captures(clone.region(0).context("missing region")?)? {
+		if available(capture, operation)? {
+			continue;
+		}
+
+		let result = binding(capture)
+			.map(|(letrec, index)| letrec.result(index))
+			.transpose()?
+			.map(Value::from);
+
+		match result {
+			Some(result) if available(result, operation)? => {
+				for (user, position) in capture.uses() {
+					if contains(clone, user) {
+						user.set_operand(position, result);
+					}
+				}
+			}
+			_ => {
+				erase(clone);
+				return Ok(false);
+			}
+		}
+	}
+
+	let body = block(clone, 0)?;
+	let argument = Value::from(body.argument(0)?);
+
+	for (user, position) in argument.uses() {
+		user.set_operand(position, operands[1]);
+	}
+
+	let value = splice(body, operation)?;
+	replace(operation, value)?;
+	erase(clone);
+	*inlined += 1;
+
+	Ok(true)
+}
+
+/// Tests if a lambda is bound by a `nix.letrec` whose binding it refers to.
+fn is_recursive(lambda: OperationRef) -> Result<bool> {
+	let result = Value::from(lambda.result(0)?);
+
+	for capture in captures(lambda.region(0).context("missing region")?)? {
+		if let Some((letrec, index)) = binding(capture) {
+			if block(letrec, 0)?
+				.terminator()
+				.context("letrec without terminator")?
+				.operand(index)?
+				== result
+			{
+				return Ok(true);
+			}
+		}
+	}
+
+	Ok(false)
+}
+
+fn has_formals(body: BlockRef) -> bool {
+	let mut current = body.first_operation();
+
+	while let Some(operation) = current {
+		if is(operation, "nix.formals") {
+			return true;
+		}
+
+		current = operation.next_in_block();
+	}
+
+	false
+}
+
+/// Erases operations without effects whose results are unused, and tells if
+/// there were any.
+fn eliminate(operation: OperationRef) -> Result<bool> {
+	let mut changed = false;
+
+	// Users are erased before the operations defining their operands, and
+	// nested operations before those containing them.
+	for inner in nested(operation, Order::Pre).into_iter().rev() {
+		if !is_pure(inner)? {
+			continue;
+		}
+
+		let mut unused = true;
+
+		for index in 0..inner.result_count() {
+			unused &= Value::from(inner.result(index)?).uses().is_empty();
+		}
+
+		if unused {
+			erase(inner);
+			changed = true;
+		}
+	}
+
+	Ok(changed)
+}
+
+/// Tests if an operation only constructs a value in weak head normal form,
+/// without evaluating anything.
+fn is_constructor(operation: OperationRef) -> bool {
+	["nix.constant", "nix.path", "nix.lambda", "nix.list"]
+		.iter()
+		.any(|name| is(operation, name))
+		|| attributes(operation).is_some()
+}
+
+/// Tests if an operation has no effect, such as errors, besides creating its
+/// results.
+fn is_pure(operation: OperationRef) -> Result<bool> {
+	if is_constructor(operation)
+		|| is(operation, "nix.thunk")
+		|| is(operation, "nix.builtin")
+	{
+		return Ok(true);
+	} else if !is(operation, "nix.letrec") {
+		return Ok(false);
+	}
+
+	let body = block(operation, 0)?;
+	let terminator = body.terminator().context("letrec without terminator")?;
+	let mut current = body.first_operation();
+
+	while let Some(inner) = current.filter(|&inner| inner != terminator) {
+		if !is_pure(inner)? {
+			return Ok(false);
+		}
+
+		current = inner.next_in_block();
+	}
+
+	Ok(true)
+}
+
+/// Gets the operation whose result a value is known to be, seeing through
+/// the bindings of `nix.letrec`.
+fn known(mut value: Value) -> Option<OperationRef> {
+	// Bindings may refer to each other in cycles.
+	for _ in 0..DEPTH {
+		let (letrec, index) = match ResultValue::try_from(value) {
+			Ok(result) if is(result.owner(), "nix.letrec") => {
+				(result.owner(), result.result_number())
+			}
+			Ok(result) => return Some(result.owner()),
+			Err(_) => binding(value)?,
+		};
+
+		value = block(letrec, 0).ok()?.terminator()?.operand(index).ok()?;
+	}
+
+	None
+}
+
+/// Gets the constant a value is known to be.
+fn constant(value: Value) -> Option<Constant> {
+	let operation = known(value).filter(|&known| is(known, "nix.constant"))?;
+	let value = operation.attribute("value")?;
+
+	Some(if let Some(value) = value.bool_value() {
+		Constant::Bool(value)
+	} else if let Some(value) = value.integer_value() {
+		Constant::Int(value)
+	} else if let Some(value) = value.float_value() {
+		Constant::Float(value)
+	} else if let Some(value) = value.string_value() {
+		Constant::String(value.into())
+	} else {
+		Constant::Null
+	})
+}
+
+/// Gets the `nix.letrec` a value is a binding of in its region, and the
+/// index of the binding.
+fn binding(value: Value) -> Option<(OperationRef, usize)> {
+	let argument = Argument::try_from(value).ok()?;
+	let letrec = argument.owner().parent_operation()?;
+
+	is(letrec, "nix.letrec").then(|| (letrec, argument.argument_number()))
+}
+
+/// Gets a value usable at an operation, cloning its definition before the
+/// operation if it is a constant out of scope, such as in the region of a
+/// `nix.letrec`.
+fn materialize<'c>(
+	value: Value<'c>,
+	operation: OperationRef<'c>,
+) -> Result<Option<Value<'c>>> {
+	if available(value, operation)? {
+		return Ok(Some(value));
+	}
+
+	match ResultValue::try_from(value) {
+		Ok(result)
+			if result.owner().operand_count() == 0
+				&& result.owner().region_count() == 0
+				&& is_constructor(result.owner()) =>
+		{
+			Ok(Some(insert(operation, (*result.owner()).clone())?))
+		}
+		_ => Ok(None),
+	}
+}
+
+/// Tests if a value is defined before an operation, in its block or in the
+/// block of an operation containing it.
+fn available(value: Value, operation: OperationRef) -> Result<bool> {
+	let (block, definition) = match ResultValue::try_from(value) {
+		Ok(result) => (result.owner().block(), Some(result.owner())),
+		Err(_) => (Some(Argument::try_from(value)?.owner()), None),
+	};
+	let mut current = Some(operation);
+
+	while let Some(inner) = current {
+		if inner.block().is_some() && inner.block() == block {
+			let Some(definition) = definition else {
+				return Ok(true);
+			};
+			let mut following = definition.next_in_block();
+
+			while let Some(next) = following {
+				if next == inner {
+					return Ok(true);
+				}
+
+				following = next.next_in_block();
+			}
+
+			return Ok(false);
+		}
+
+		current = inner.parent_operation();
+	}
+
+	Ok(false)
+}
+
+fn all_available<'c>(
+	values: impl IntoIterator<Item = Value<'c>>,
+	operation: OperationRef<'c>,
+) -> Result<bool> {
+	for value in values {
+		if !available(value, operation)? {
+			return Ok(false);
+		}
+	}
+
+	Ok(true)
+}
+
+/// Tests if an operation is nested in the regions of another.
+fn contains(outer: OperationRef, operation: OperationRef) -> bool {
+	let mut current = operation.parent_operation();
+
+	while let Some(parent) = current {
+		if parent == outer {
+			return true;
+		}
+
+		current = parent.parent_operation();
+	}
+
+	false
+}
+
+/// The order in which [`nested`] lists operations.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Order {
+	/// Each operation before those nested in it.
+	Pre,
+	/// Each operation after those nested in it.
+	Post,
+}
+
+/// Lists the operations nested in an operation, in the order of their blocks.
+fn nested(operation: OperationRef, order: Order) -> Vec<OperationRef> {
+	let mut operations = Vec::new();
+	collect(operation, order, &mut operations);
+	operations
+}
+
+fn collect<'c>(
+	operation: OperationRef<'c>,
+	order: Order,
+	operations: &mut Vec<OperationRef<'c>>,
+) {
+	for index in 0..operation.region_count() {
+		let mut block = operation
+			.region(index)
+			.and_then(|region| region.first_block());
+
+		while let Some(current) = block {
+			let mut inner = current.first_operation();
+
+			while let Some(nested) = inner {
+				if order == Order::Pre {
+					operations.push(nested);
+				}
+
+				collect(nested, order, operations);
+
+				if order == Order::Post {
+					operations.push(nested);
+				}
+
+				inner = nested.next_in_block();
+			}
+
+			block = current.next_in_region();
+		}
+	}
+}
+
+fn is(operation: OperationRef, name: &str) -> bool {
+	operation.name().as_string_ref().as_str() == Ok(name)
+}
+
+fn block(operation: OperationRef, index: usize) -> Result<BlockRef> {
+	operation
+		.region(index)
+		.and_then(|region| region.first_block())
+		.context("missing region")
+}
+
+/// Moves the operations of a block but its terminator before an operation,
+/// and gets the value the block yields.
+fn splice<'c>(
+	block: BlockRef<'c>,
+	operation: OperationRef<'c>,
+) -> Result<Value<'c>> {
+	let terminator = block.terminator().context("block without terminator")?;
+
+	while let Some(inner) =
+		block.first_operation().filter(|&inner| inner != terminator)
+	{
+		inner.move_before(operation);
+	}
+
+	Ok(terminator.operand(0)?)
+}
+
+/// Inserts an operation before another, and gets its result.
+fn insert<'c>(
+	operation: OperationRef<'c>,
+	inserted: Operation<'c>,
+) -> Result<Value<'c>> {
+	Ok(insert_operation(operation, inserted)?.result(0)?.into())
+}
+
+fn insert_operation<'c>(
+	operation: OperationRef<'c>,
+	inserted: Operation<'c>,
+) -> Result<OperationRef<'c>> {
+	Ok(operation
+		.block()
+		.context("operation without block")?
+		.insert_operation_before(operation, inserted))
+}
+
+/// Replaces the uses of the result of an operation by a value, and erases
+/// the operation.
+fn replace<'c>(operation: OperationRef<'c>, value: Value<'c>) -> Result<()> {
+	for (user, position) in Value::from(operation.result(0)?).uses() {
+		user.set_operand(position, value);
+	}
+
+	erase(operation);
+	Ok(())
+}
+
+fn erase(operation: OperationRef) {
+	// The operation is not referred to anymore.
+	drop(unsafe { operation.remove_from_parent() });
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{lower::Lowerer, source::Source};
+	use melior::{dialect::Registry, pass, utility::register_all_dialects};
+
+	fn evaluated(text: &str) -> String {
+		let registry = Registry::new();
+		register_all_dialects(&registry);
+
+		let context = Context::new();
+		context.append_dialect_registry(&registry);
+		context.load_all_available_dialects();
+		dialect::load(&context);
+
+		let root = rnix::Root::parse(text).tree();
+		let mut module = Lowerer::new(&context, Source::new("test.nix", text))
+			.lower_root(&root)
+			.unwrap();
+		let pass_manager = pass::Manager::new(&context);
+		pass_manager.add_pass(super::pass());
+		pass_manager.add_pass(dialect::verifier());
+		pass_manager.run(&mut module).unwrap();
+
+		module.as_operation().to_string()
+	}
+
+	#[test]
+	fn fold_constants() {
+		let module = evaluated(r#"[ (1 + 2 * 3) (1 < 2.5) "a${"b"}" ]"#);
+
+		assert!(!module.contains("nix.add") && !module.contains("nix.mul"));
+		assert!(module.contains("value = 7 : i64"));
+		assert!(module.contains("value = true"));
+		assert!(module.contains(r#"value = "ab""#));
+	}
+
+	#[test]
+	fn keep_errors() {
+		let module = evaluated("[ (9223372036854775807 + 1) (1 / 0) ]");
+
+		assert!(module.contains("nix.add") && module.contains("nix.div"));
+	}
+
+	#[test]
+	fn select_static_paths() {
+		let module = evaluated(
+			"let set = { a.b = 1; }; in [ set.a.b (set.c or 2) (set ? a.b) ]",
+		);
+
+		assert!(!module.contains("nix.select"));
+		assert!(!module.contains("nix.has_attr"));
+
+		let module = evaluated("builtins.length [ ]");
+
+		assert!(module.contains(r#"name = "length""#));
+		assert!(!module.contains("nix.select"));
+	}
+
+	#[test]
+	fn inline_lambdas() {
+		let module = evaluated(
+			"let double = x: x * 2; in if double 2 == 4 then \"a\" else \"b\"",
+		);
+
+		assert!(!module.contains("nix.apply"));
+		assert!(!module.contains("nix.if"));
+
+		let module = evaluated("let f = n: f n; in f 1");
+
+		assert!(module.contains("nix.apply"));
+
+		let module = evaluated("({ a }: a) { a = 1; }");
+
+		assert!(module.contains("nix.apply"));
+	}
+}
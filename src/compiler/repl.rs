@@ -0,0 +1,248 @@
+//! An interactive session, which compiles and evaluates one expression at a
+//! time in a single MLIR context.
+//!
+//! Expressions are compiled as functions of a set of the bindings made with
+//! `name = expr` before them. Bindings are lazy, as in `nix repl`: they bind
+//! the application of their function to a thunk, which is only forced when a
+//! later expression uses it. Each expression is compiled by its own execution
+//! engine, which is kept for the session since the values it computed refer
+//! to its code.
+
+use std::{
+	collections::BTreeMap,
+	io::{self, BufRead as _, Write as _},
+};
+
+use anyhow::{bail, Context as _, Result};
+use melior::{ir::Module, Context, ExecutionEngine};
+
+use mlnx::{convert::convert, lower::Lowerer, source::Source};
+use mlnx_runtime::{builtins::suspend_apply, force, gc, Value};
+
+use crate::{
+	compile, create_context, partially_evaluate, run_pipeline, verify,
+};
+
+const HELP: &str = "\
+<expr>          evaluate and print an expression
+<name> = <expr> bind a name to the value of an expression
+:t <expr>       print the type of the value of an expression
+:mlir <expr>    print the module of an expression in the nix dialect
+:llvm <expr>    print the module of an expression in the llvm dialect
+:?              print this help
+:q              exit the session";
+
+/// What is done with an expression.
+enum Command {
+	Print,
+	Bind(String),
+	Type,
+	Mlir,
+	Llvm,
+}
+
+/// Splits an input line into a command and an expression.
+fn parse(line: &str) -> Result<(Command, &str)> {
+	if let Some(line) = line.strip_prefix(':') {
+		let (command, expr) = line.split_once(' ').unwrap_or((line, ""));
+		let command = match command {
+			"t" => Command::Type,
+			"mlir" => Command::Mlir,
+			"llvm" => Command::Llvm,
+			_ => bail!("unknown command ':{command}', see ':?'"),
+		};
+
+		return Ok((command, expr));
+	}
+
+	if let Some((name, expr)) = line.split_once('=') {
+		let name = name.trim();
+		let identifier =
+			name.chars().next().is_some_and(|first| {
+				first.is_ascii_alphabetic() || first == '_'
+			}) && name.chars().all(|character| {
+				character.is_ascii_alphanumeric() || "_'-".contains(character)
+			});
+
+		if identifier && !expr.starts_with('=') {
+			return Ok((Command::Bind(name.into()), expr));
+		}
+	}
+
+	Ok((Command::Print, line))
+}
+
+struct Session {
+	context: Context,
+	/// The values bound so far, by name.
+	bindings: BTreeMap<String, *mut Value>,
+	engines: Vec<ExecutionEngine>,
+}
+
+impl Session {
+	/// Lowers an expression to a module in the nix dialect, as a function of
+	/// the bindings.
+	fn lower(&self, expr: &str) -> Result<Module> {
+		let names = self.bindings.keys().cloned().collect::<Vec<_>>();
+		let text = format!("{{ {} }}:\n{expr}", names.join(", "));
+		let parsed = rnix::Root::parse(&text);
+
+		if let Some(error) = parsed.errors().first() {
+			bail!("Failed to parse expression: {error}");
+		}
+
+		// Each expression is a file of its own, since files are only
+		// evaluated once.
+		let path = std::env::current_dir()?
+			.join(format!("«repl-{}»", self.engines.len()))
+			.to_string_lossy()
+			.into_owned();
+		let mut module = Lowerer::new(&self.context, Source::new(path, text))
+			.lower_root(&parsed.tree())
+			.context("Failed to lower expression")?;
+		verify(&self.context, &mut module)?;
+
+		Ok(module)
+	}
+
+	/// Lowers an expression to a module in the llvm dialect and passes it to
+	/// a function, since it borrows the module in the nix dialect.
+	fn convert<T>(
+		&self,
+		expr: &str,
+		function: impl FnOnce(&Module) -> Result<T>,
+	) -> Result<T> {
+		let mut module = self.lower(expr)?;
+		partially_evaluate(&self.context, &mut module)?;
+		let mut module = convert(&self.context, &module)
+			.context("Failed to convert module")?;
+		run_pipeline(&self.context, &mut module, None)?;
+
+		function(&module)
+	}
+
+	/// Compiles an expression into a thunk of its value, which evaluates
+	/// nothing until it is forced.
+	fn suspend(&mut self, expr: &str) -> Result<*mut Value> {
+		let (engine, main) = self.convert(expr, compile)?;
+		self.engines.push(engine);
+		let bindings = self.bindings.clone();
+
+		// The file only evaluates to the function of the bindings.
+		mlnx_runtime::catch(|| {
+			let function = main();
+			let _roots = gc::root(&[function]);
+			let scope = Value::AttrSet(bindings.into()).alloc();
+			let _roots = gc::root(&[scope]);
+
+			suspend_apply(function, scope)
+		})
+		.map_err(|error| anyhow::anyhow!("{error}"))
+	}
+
+	/// Evaluates an expression to weak head normal form.
+	fn evaluate(&mut self, expr: &str) -> Result<*mut Value> {
+		let value = self.suspend(expr)?;
+
+		mlnx_runtime::catch(|| force(value))
+			.map_err(|error| anyhow::anyhow!("{error}"))
+	}
+
+	fn execute(&mut self, line: &str) -> Result<()> {
+		match parse(line)? {
+			(Command::Print, expr) => {
+				let value = self.evaluate(expr)?;
+				let text = mlnx_runtime::catch(|| mlnx_runtime::print(value))
+					.map_err(|error| anyhow::anyhow!("{error}"))?;
+				println!("{text}");
+			}
+			(Command::Bind(name), expr) => {
+				// Bindings are only referred to from the session.
+				let value = gc::pin(self.suspend(expr)?);
+				self.bindings.insert(name, value);
+			}
+			(Command::Type, expr) => {
+				let value = self.evaluate(expr)?;
+				println!("{}", unsafe { &*value }.type_name());
+			}
+			(Command::Mlir, expr) => {
+				println!("{}", self.lower(expr)?.as_operation())
+			}
+			(Command::Llvm, expr) => self.convert(expr, |module| {
+				println!("{}", module.as_operation());
+				Ok(())
+			})?,
+		}
+
+		Ok(())
+	}
+}
+
+/// Runs a session on the standard input until it ends or `:q` is entered.
+pub fn run() -> Result<()> {
+	let mut session = Session {
+		context: create_context(),
+		bindings: BTreeMap::new(),
+		engines: Vec::new(),
+	};
+	let mut lines = io::stdin().lock().lines();
+
+	loop {
+		print!("nix-repl> ");
+		io::stdout().flush()?;
+
+		let Some(line) = lines.next().transpose()? else {
+			println!();
+			return Ok(());
+		};
+
+		match line.trim() {
+			"" => {}
+			":q" => return Ok(()),
+			":?" => println!("{HELP}"),
+			line => {
+				if let Err(error) = session.execute(line) {
+					eprintln!("error: {error:#}");
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_commands() {
+		assert!(matches!(parse("1 + 1").unwrap(), (Command::Print, "1 + 1")));
+		assert!(matches!(
+			parse("x = 1").unwrap(),
+			(Command::Bind(name), " 1") if name == "x"
+		));
+		assert!(matches!(parse("x == 1").unwrap(), (Command::Print, _)));
+		assert!(matches!(parse("{ a = 1; }").unwrap(), (Command::Print, _)));
+		assert!(matches!(parse(":t x").unwrap(), (Command::Type, "x")));
+		assert!(matches!(parse(":mlir x").unwrap(), (Command::Mlir, "x")));
+		assert!(matches!(parse(":llvm x").unwrap(), (Command::Llvm, "x")));
+		assert!(parse(":x").is_err());
+	}
+
+	#[test]
+	fn bind_lazily() {
+		let mut session = Session {
+			context: create_context(),
+			bindings: BTreeMap::new(),
+			engines: Vec::new(),
+		};
+
+		session.execute(r#"x = throw "unused""#).unwrap();
+		session.execute("y = [ x ]").unwrap();
+
+		let value = session.evaluate("builtins.length y").unwrap();
+		assert_eq!(mlnx_runtime::print(value), "1");
+
+		let error = session.evaluate("x").unwrap_err();
+		assert!(error.to_string().contains("unused"), "{error}");
+	}
+}
@@ -0,0 +1,383 @@
+//! Resolution of variables in rnix syntax trees.
+//!
+//! Nix scoping is static except for `with`: `let`, recursive attribute sets
+//! and functions bind variables lexically, and a variable which is not bound
+//! lexically nor by the base environment is looked up at runtime in the
+//! namespaces of the enclosing `with` expressions. Resolution runs before
+//! lowering so that undefined variables are reported at their position
+//! whether or not the code using them is ever evaluated.
+
+use std::collections::{HashMap, HashSet};
+
+use rnix::{
+	ast::{self, HasEntry},
+	SyntaxNode, TextRange,
+};
+use rowan::ast::AstNode;
+
+use crate::source::{Diagnostic, Source};
+
+/// The names of the base environment, which no `with` shadows.
+pub const BASE: &[&str] = &[
+	"true",
+	"false",
+	"null",
+	"builtins",
+	"abort",
+	"derivation",
+	"import",
+	"map",
+	"throw",
+	"toString",
+];
+
+/// How a variable is resolved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variable {
+	/// Bound by a `let`, a recursive attribute set or a function.
+	Lexical,
+	/// Bound by the base environment (see [`BASE`]).
+	Base,
+	/// Looked up in the namespaces of the enclosing `with` expressions.
+	With,
+}
+
+/// The resolution of every variable of a syntax tree.
+#[derive(Default)]
+pub struct Resolution {
+	variables: HashMap<TextRange, Variable>,
+}
+
+impl Resolution {
+	/// Gets the resolution of a variable.
+	pub fn variable(&self, ident: &ast::Ident) -> Option<Variable> {
+		self.variables.get(&ident.syntax().text_range()).copied()
+	}
+}
+
+/// Resolves the variables of an expression, failing at the first undefined
+/// one.
+pub fn resolve(
+	source: &Source,
+	expr: &ast::Expr,
+) -> Result<Resolution, Diagnostic> {
+	let mut resolver = Resolver {
+		source,
+		resolution: Resolution::default(),
+	};
+	resolver.expr(&Scope::Root, expr)?;
+
+	Ok(resolver.resolution)
+}
+
+enum Scope<'p> {
+	Root,
+	Lexical(&'p Scope<'p>, HashSet<String>),
+	With(&'p Scope<'p>),
+}
+
+impl Scope<'_> {
+	fn resolve(&self, name: &str) -> Option<Variable> {
+		let mut with = false;
+		let mut scope = self;
+
+		loop {
+			scope = match scope {
+				Scope::Root if BASE.contains(&name) => {
+					return Some(Variable::Base)
+				}
+				Scope::Root if with => return Some(Variable::With),
+				Scope::Root => return None,
+				Scope::Lexical(_, names) if names.contains(name) => {
+					return Some(Variable::Lexical)
+				}
+				Scope::Lexical(parent, _) => parent,
+				Scope::With(parent) => {
+					with = true;
+					parent
+				}
+			};
+		}
+	}
+}
+
+struct Resolver<'s> {
+	source: &'s Source,
+	resolution: Resolution,
+}
+
+impl Resolver<'_> {
+	fn expr(
+		&mut self,
+		scope: &Scope,
+		expr: &ast::Expr,
+	) -> Result<(), Diagnostic> {
+		match expr {
+			ast::Expr::Ident(ident) => {
+				let name = ident.to_string();
+				let variable = scope.resolve(&name).ok_or_else(|| {
+					self.source.diagnostic(
+						ident.syntax().text_range(),
+						format!("undefined variable '{name}'"),
+					)
+				})?;
+
+				self.resolution
+					.variables
+					.insert(ident.syntax().text_range(), variable);
+			}
+			ast::Expr::LetIn(let_in) => {
+				let inner = Scope::Lexical(scope, bound_names(let_in));
+				self.entries(&inner, scope, let_in)?;
+
+				if let Some(body) = let_in.body() {
+					self.expr(&inner, &body)?;
+				}
+			}
+			ast::Expr::AttrSet(set) if set.rec_token().is_some() => {
+				let inner = Scope::Lexical(scope, bound_names(set));
+				self.entries(&inner, scope, set)?;
+			}
+			ast::Expr::AttrSet(set) => self.entries(scope, scope, set)?,
+			ast::Expr::Select(select) => {
+				self.children(scope, select.syntax())?;
+
+				if let Some(path) = select.attrpath() {
+					self.attrpath(scope, &path)?;
+				}
+			}
+			ast::Expr::HasAttr(has_attr) => {
+				self.children(scope, has_attr.syntax())?;
+
+				if let Some(path) = has_attr.attrpath() {
+					self.attrpath(scope, &path)?;
+				}
+			}
+			ast::Expr::Lambda(lambda) => {
+				let mut names = HashSet::new();
+				let mut defaults = Vec::new();
+
+				match lambda.param() {
+					Some(ast::Param::IdentParam(param)) => {
+						names.extend(
+							param.ident().map(|ident| ident.to_string()),
+						);
+					}
+					Some(ast::Param::Pattern(pattern)) => {
+						for entry in pattern.pat_entries() {
+							names.extend(
+								entry.ident().map(|ident| ident.to_string()),
+							);
+							defaults.extend(entry.default());
+						}
+
+						names.extend(
+							pattern
+								.pat_bind()
+								.and_then(|bind| bind.ident())
+								.map(|ident| ident.to_string()),
+						);
+					}
+					None => {}
+				}
+
+				// Defaults may refer to any formal.
+				let inner = Scope::Lexical(scope, names);
+
+				for default in defaults {
+					self.expr(&inner, &default)?;
+				}
+
+				if let Some(body) = lambda.body() {
+					self.expr(&inner, &body)?;
+				}
+			}
+			ast::Expr::With(with) => {
+				if let Some(namespace) = with.namespace() {
+					self.expr(scope, &namespace)?;
+				}
+
+				if let Some(body) = with.body() {
+					self.expr(&Scope::With(scope), &body)?;
+				}
+			}
+			_ => self.children(scope, expr.syntax())?,
+		}
+
+		Ok(())
+	}
+
+	/// Resolves the expressions and interpolations among the children of a
+	/// node.
+	fn children(
+		&mut self,
+		scope: &Scope,
+		node: &SyntaxNode,
+	) -> Result<(), Diagnostic> {
+		for child in node.children() {
+			if let Some(expr) = ast::Expr::cast(child.clone()) {
+				self.expr(scope, &expr)?;
+			} else if let Some(expr) =
+				ast::Interpol::cast(child).and_then(|interpol| interpol.expr())
+			{
+				self.expr(scope, &expr)?;
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Resolves the entries of a `let` or an attribute set, whose values are
+	/// in `scope` while plain `inherit`s refer to `parent`.
+	fn entries(
+		&mut self,
+		scope: &Scope,
+		parent: &Scope,
+		node: &impl HasEntry,
+	) -> Result<(), Diagnostic> {
+		for entry in node.entries() {
+			match entry {
+				ast::Entry::Inherit(inherit) => match inherit.from() {
+					Some(from) => self.children(scope, from.syntax())?,
+					None => {
+						for attr in inherit.attrs() {
+							if let ast::Attr::Ident(ident) = attr {
+								self.expr(parent, &ast::Expr::Ident(ident))?;
+							}
+						}
+					}
+				},
+				ast::Entry::AttrpathValue(entry) => {
+					if let Some(path) = entry.attrpath() {
+						self.attrpath(scope, &path)?;
+					}
+
+					if let Some(value) = entry.value() {
+						self.expr(scope, &value)?;
+					}
+				}
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Resolves the computed names of an attribute path.
+	fn attrpath(
+		&mut self,
+		scope: &Scope,
+		path: &ast::Attrpath,
+	) -> Result<(), Diagnostic> {
+		for attr in path.attrs() {
+			match attr {
+				ast::Attr::Ident(_) => {}
+				ast::Attr::Dynamic(dynamic) => {
+					self.children(scope, dynamic.syntax())?
+				}
+				ast::Attr::Str(string) => {
+					self.children(scope, string.syntax())?
+				}
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// Gets the names bound by a `let` or a recursive attribute set.
+fn bound_names(node: &impl HasEntry) -> HashSet<String> {
+	let mut names = HashSet::new();
+
+	for entry in node.entries() {
+		match entry {
+			ast::Entry::Inherit(inherit) => {
+				names.extend(inherit.attrs().filter_map(|attr| match attr {
+					ast::Attr::Ident(ident) => Some(ident.to_string()),
+					_ => None,
+				}))
+			}
+			ast::Entry::AttrpathValue(entry) => {
+				names.extend(
+					entry
+						.attrpath()
+						.and_then(|path| path.attrs().next())
+						.and_then(|attr| static_name(&attr)),
+				);
+			}
+		}
+	}
+
+	names
+}
+
+/// Gets the name of an attribute unless it is computed.
+fn static_name(attr: &ast::Attr) -> Option<String> {
+	match attr {
+		ast::Attr::Ident(ident) => Some(ident.to_string()),
+		ast::Attr::Str(string) => match string.normalized_parts().as_slice() {
+			[] => Some(String::new()),
+			[ast::InterpolPart::Literal(name)] => Some(name.clone()),
+			_ => None,
+		},
+		ast::Attr::Dynamic(_) => None,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn resolve_text(text: &str) -> Result<Vec<Variable>, Diagnostic> {
+		let root = rnix::Root::parse(text).tree();
+		let resolution =
+			resolve(&Source::new("a.nix", text), &root.expr().unwrap())?;
+
+		let mut variables =
+			resolution.variables.into_iter().collect::<Vec<_>>();
+		variables.sort_by_key(|(range, _)| range.start());
+
+		Ok(variables
+			.into_iter()
+			.map(|(_, variable)| variable)
+			.collect())
+	}
+
+	#[test]
+	fn resolve_scopes() {
+		use Variable::*;
+
+		assert_eq!(
+			resolve_text("let a = b; b = 1; in a").unwrap(),
+			[Lexical, Lexical]
+		);
+		assert_eq!(
+			resolve_text("x: with x; [ x y true ]").unwrap(),
+			[Lexical, Lexical, With, Base]
+		);
+		assert_eq!(
+			resolve_text("with { map = 1; }; map toString").unwrap(),
+			[Base, Base]
+		);
+		assert_eq!(
+			resolve_text("{ a ? b, b }@c: c").unwrap(),
+			[Lexical, Lexical]
+		);
+		assert_eq!(
+			resolve_text("let a = 1; in rec { inherit a; b = a; }").unwrap(),
+			[Lexical, Lexical]
+		);
+	}
+
+	#[test]
+	fn report_undefined_variable() {
+		let error =
+			resolve_text("let\n  a = { inherit b; };\nin a").unwrap_err();
+
+		assert_eq!(error.message(), "undefined variable 'b'");
+		assert_eq!(error.position(), (2, 17));
+
+		let error = resolve_text("{ a = 1; }.${a}").unwrap_err();
+
+		assert_eq!(error.message(), "undefined variable 'a'");
+	}
+}
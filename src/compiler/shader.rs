@@ -0,0 +1,1676 @@
+//! Shaders written in Nix, lowered to the `spirv` dialect.
+//!
+//! A shader is a file evaluating to a set of its `type`, `"vertex"` or
+//! `"fragment"`, and of its outputs, each a set of a `location` and a
+//! `value`:
+//!
+//! ```nix
+//! {
+//!   type = "fragment";
+//!   color = {
+//!     location = 0;
+//!     value = builtins.texture sampler (builtins.input "vec2" "texCoord");
+//!   };
+//! }
+//! ```
+//!
+//! Shaders are evaluated while they are compiled: bindings, sets, functions
+//! and operations on literals are resolved statically, and only the values
+//! computed from inputs and uniforms become operations of the `main` function
+//! of a `spirv.module`. These values come from the builtins of shaders:
+//!
+//! | Builtin                           | Value                                |
+//! |-----------------------------------|--------------------------------------|
+//! | `builtins.input type name`        | the input variable `name`            |
+//! | `builtins.uniform type binding`   | the uniform variable at `binding`    |
+//! | `builtins.texture sampler coords` | a sample of a texture                |
+//! | `builtins.vec2 x y`, ...          | a vector of floats                   |
+//! | `builtins.sin x`, ...             | a mathematical function              |
+//!
+//! The mathematical functions are `abs`, `ceil`, `cos`, `exp`, `floor`,
+//! `log`, `sin` and `sqrt`. The types of values are `bool`, `int`, `float`,
+//! `vec2`, `vec3`, `vec4` and `sampler2D`. Operators and conditionals on them
+//! are lowered to the `arith` dialect and mathematical functions to the
+//! `math` dialect, which [`convert`] converts to the `spirv` dialect. Textures
+//! are sampled with [`SAMPLE`], and
+//! [`spirv::serialize`](crate::spirv::serialize) writes the converted
+//! module as a SPIR-V binary.
+//!
+//! Before a shader is evaluated, the types of its bindings are inferred by
+//! [`infer`](crate::infer), which rejects the constructs whose types could
+//! only be known while evaluating. The bindings inferred to be computed by
+//! the shader are computed with the types inferred for them, even if their
+//! values are known statically.
+
+use std::{
+	cell::RefCell,
+	collections::{BTreeMap, HashMap},
+	rc::Rc,
+};
+
+use anyhow::{bail, Context as _, Result};
+use melior::{
+	ir::{
+		operation, Block, Location, Module, NamedAttribute, Operation, Region,
+		Type, Value,
+	},
+	pass, Context,
+};
+use rnix::{
+	ast::{self, HasEntry},
+	TextRange,
+};
+use rowan::ast::AstNode;
+
+use crate::{
+	dialect,
+	infer::{self, Shape},
+	lower::{
+		attr_key, child, collect_definitions, ident_name, literal, Definition,
+		Definitions, Key,
+	},
+	source::Source,
+};
+
+/// The type of the images which `sampler2D` uniforms bind.
+const SAMPLED_IMAGE: &str = "!spirv.sampled_image<!spirv.image<f32, Dim2D, \
+	NoDepth, NonArrayed, SingleSampled, NeedSampler, Unknown>>";
+
+/// The builtins of shaders, with the number of arguments they take.
+const BUILTINS: &[(&str, usize)] = &[
+	("abs", 1),
+	("ceil", 1),
+	("cos", 1),
+	("exp", 1),
+	("floor", 1),
+	("input", 2),
+	("log", 1),
+	("sin", 1),
+	("sqrt", 1),
+	("texture", 2),
+	("uniform", 2),
+	("vec2", 2),
+	("vec3", 3),
+	("vec4", 4),
+];
+
+/// Gets a builtin of shaders by name, with the number of arguments it takes.
+pub(crate) fn builtin(name: &str) -> Option<(&'static str, usize)> {
+	BUILTINS
+		.iter()
+		.copied()
+		.find(|&(builtin, _)| builtin == name)
+}
+
+/// The operation sampling a texture at coordinates, which the `spirv`
+/// dialect of MLIR 16 lacks. It is unregistered, and
+/// [`spirv::serialize`](crate::spirv::serialize) writes it as `OpImageSampleImplicitLod`.
+pub const SAMPLE: &str = "shader.sample";
+
+/// The type of a value computed by a shader.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Kind {
+	Bool,
+	Int,
+	Float,
+	/// A vector of two to four floats.
+	Vector(u32),
+	Sampler2D,
+}
+
+impl Kind {
+	/// Parses the name of a type, as `vec2`.
+	pub fn parse(name: &str) -> Option<Self> {
+		Some(match name {
+			"bool" => Self::Bool,
+			"int" => Self::Int,
+			"float" => Self::Float,
+			"vec2" => Self::Vector(2),
+			"vec3" => Self::Vector(3),
+			"vec4" => Self::Vector(4),
+			"sampler2D" => Self::Sampler2D,
+			_ => return None,
+		})
+	}
+
+	pub fn name(self) -> String {
+		match self {
+			Self::Bool => "bool".into(),
+			Self::Int => "int".into(),
+			Self::Float => "float".into(),
+			Self::Vector(size) => format!("vec{size}"),
+			Self::Sampler2D => "sampler2D".into(),
+		}
+	}
+
+	/// Gets the MLIR type of values of the type.
+	pub fn r#type(self, context: &Context) -> Type {
+		match self {
+			Self::Bool => Type::integer(context, 1),
+			Self::Int => Type::integer(context, 32),
+			Self::Float => Type::float32(context),
+			Self::Vector(size) => {
+				Type::vector(&[size.into()], Type::float32(context))
+			}
+			Self::Sampler2D => Type::parse(context, SAMPLED_IMAGE)
+				.expect("spirv dialect not loaded"),
+		}
+	}
+
+	pub(crate) fn is_float(self) -> bool {
+		matches!(self, Self::Float | Self::Vector(_))
+	}
+}
+
+/// A value of a shader being compiled, either known statically or computed
+/// by an SSA value.
+#[derive(Clone)]
+enum Term<'a> {
+	Bool(bool),
+	Int(i64),
+	Float(f64),
+	String(String),
+	Set(Rc<BTreeMap<String, Rc<Thunk<'a>>>>),
+	/// A list, whose elements are never used, as no builtin of shaders
+	/// takes lists.
+	List,
+	Lambda(ast::Lambda, Rc<Env<'a>>),
+	/// The `builtins` set.
+	Builtins,
+	/// A builtin applied to fewer arguments than it takes.
+	Builtin(&'static str, Vec<(Term<'a>, TextRange)>),
+	Value(Value<'a>, Kind),
+}
+
+impl Term<'_> {
+	fn description(&self) -> String {
+		match self {
+			Self::Bool(_) => "a Boolean".into(),
+			Self::Int(_) => "an integer".into(),
+			Self::Float(_) => "a float".into(),
+			Self::String(_) => "a string".into(),
+			Self::Set(_) | Self::Builtins => "a set".into(),
+			Self::List => "a list".into(),
+			Self::Lambda(..) | Self::Builtin(..) => "a function".into(),
+			Self::Value(_, kind) => format!("a value of type {}", kind.name()),
+		}
+	}
+
+	/// Gets an integer or a float as a float.
+	fn number(&self) -> Option<f64> {
+		match *self {
+			Self::Int(value) => Some(value as f64),
+			Self::Float(value) => Some(value),
+			_ => None,
+		}
+	}
+}
+
+type Thunk<'a> = RefCell<State<'a>>;
+
+enum State<'a> {
+	/// An expression with its scope and the name of the `let` binding it is
+	/// the value of.
+	Suspended(ast::Expr, Rc<Env<'a>>, Option<String>),
+	/// An attribute inherited from a set.
+	Inherited(Rc<Thunk<'a>>, String, TextRange),
+	Forcing,
+	Done(Term<'a>),
+}
+
+fn suspend<'a>(
+	expr: &ast::Expr,
+	env: &Rc<Env<'a>>,
+	name: Option<&str>,
+) -> Rc<Thunk<'a>> {
+	Rc::new(RefCell::new(State::Suspended(
+		expr.clone(),
+		env.clone(),
+		name.map(Into::into),
+	)))
+}
+
+/// The range of the expression of a thunk, if it is suspended.
+fn range_of(thunk: &Thunk, default: TextRange) -> TextRange {
+	match &*thunk.borrow() {
+		State::Suspended(expr, ..) => expr.syntax().text_range(),
+		_ => default,
+	}
+}
+
+#[derive(Default)]
+struct Env<'a> {
+	parent: Option<Rc<Env<'a>>>,
+	bindings: RefCell<BTreeMap<String, Rc<Thunk<'a>>>>,
+}
+
+impl<'a> Env<'a> {
+	fn push(self: &Rc<Self>) -> Rc<Self> {
+		Rc::new(Self {
+			parent: Some(self.clone()),
+			bindings: Default::default(),
+		})
+	}
+
+	fn lookup(&self, name: &str) -> Option<Rc<Thunk<'a>>> {
+		self.bindings
+			.borrow()
+			.get(name)
+			.cloned()
+			.or_else(|| self.parent.as_ref()?.lookup(name))
+	}
+}
+
+/// A global variable of a shader.
+struct Variable {
+	kind: Kind,
+	/// The storage class of the variable, as `Input`.
+	class: &'static str,
+	decorations: Vec<(&'static str, i64)>,
+}
+
+impl Variable {
+	fn decoration(&self, name: &str) -> Option<i64> {
+		self.decorations.iter().find_map(|&(decoration, value)| {
+			(decoration == name).then_some(value)
+		})
+	}
+}
+
+/// Evaluates a shader, appending the operations computing its outputs to
+/// the body of its `main` function.
+struct Shader<'c, 'a> {
+	context: &'c Context,
+	source: &'a Source,
+	block: &'a Block<'c>,
+	/// The `spirv.GlobalVariable` operations of the variables.
+	globals: Vec<Operation<'c>>,
+	variables: BTreeMap<String, Variable>,
+	/// The input and output variables, which the entry point lists.
+	interface: Vec<String>,
+	/// The name of the binding being evaluated, which uniforms are named
+	/// after.
+	name: Option<String>,
+	/// The types inferred for the bindings computed by the shader, by the
+	/// range of their values.
+	types: HashMap<TextRange, Kind>,
+}
+
+/// Lowers a shader to a module containing a `spirv.module`.
+pub fn lower<'c>(
+	context: &'c Context,
+	source: &Source,
+	root: &ast::Root,
+) -> Result<Module<'c>> {
+	let expr = root.expr().context("file contains no expression")?;
+	let mut types = HashMap::new();
+
+	// The bindings of functions are inferred at each of their applications,
+	// so those inferred with different types are left untyped.
+	for binding in infer::infer(source, &expr)? {
+		let kind = match binding.shape {
+			Shape::Value(kind) => Some(kind),
+			_ => None,
+		};
+		types
+			.entry(binding.range)
+			.and_modify(|known| {
+				if *known != kind {
+					*known = None;
+				}
+			})
+			.or_insert(kind);
+	}
+
+	// Samples are unregistered operations.
+	context.set_allow_unregistered_dialects(true);
+
+	let location = Location::new(context, source.path(), 1, 1);
+	let body = Block::new(&[]);
+
+	let (model, globals, interface) = {
+		let mut shader = Shader {
+			context,
+			source,
+			block: &body,
+			globals: Vec::new(),
+			variables: BTreeMap::new(),
+			interface: Vec::new(),
+			name: None,
+			types: types
+				.into_iter()
+				.filter_map(|(range, kind)| Some((range, kind?)))
+				.collect(),
+		};
+		let model = shader.lower_outputs(&expr)?;
+
+		(model, shader.globals, shader.interface)
+	};
+
+	body.append_operation(
+		operation::Builder::new("spirv.Return", location).build(),
+	);
+
+	let function = Region::new();
+	function.append_block(body);
+
+	let block = Block::new(&[]);
+
+	for global in globals {
+		block.append_operation(global);
+	}
+
+	let interface = interface
+		.iter()
+		.map(|name| format!("@{}", dialect::string(name)))
+		.collect::<Vec<_>>();
+	let main = format!("@{}", dialect::string("main"));
+
+	block.append_operation(
+		operation::Builder::new("spirv.func", location)
+			.add_attributes(&NamedAttribute::new_parsed_vec(
+				context,
+				&[
+					("sym_name", &dialect::string("main")),
+					("function_type", "() -> ()"),
+					("function_control", "#spirv.function_control<None>"),
+				],
+			)?)
+			.add_regions(vec![function])
+			.build(),
+	);
+	block.append_operation(
+		operation::Builder::new("spirv.EntryPoint", location)
+			.add_attributes(&NamedAttribute::new_parsed_vec(
+				context,
+				&[
+					(
+						"execution_model",
+						&format!("#spirv.execution_model<{model}>"),
+					),
+					("fn", &main),
+					("interface", &format!("[{}]", interface.join(", "))),
+				],
+			)?)
+			.build(),
+	);
+
+	if model == "Fragment" {
+		block.append_operation(
+			operation::Builder::new("spirv.ExecutionMode", location)
+				.add_attributes(&NamedAttribute::new_parsed_vec(
+					context,
+					&[
+						("fn", &main),
+						(
+							"execution_mode",
+							"#spirv.execution_mode<OriginUpperLeft>",
+						),
+						("values", "[]"),
+					],
+				)?)
+				.build(),
+		);
+	}
+
+	let region = Region::new();
+	region.append_block(block);
+
+	let module = Module::new(location);
+	module.body().append_operation(
+		operation::Builder::new("spirv.module", location)
+			.add_attributes(&NamedAttribute::new_parsed_vec(
+				context,
+				&[
+					("addressing_model", "#spirv.addressing_model<Logical>"),
+					("memory_model", "#spirv.memory_model<GLSL450>"),
+					("vce_triple", "#spirv.vce<v1.0, [Shader], []>"),
+				],
+			)?)
+			.add_regions(vec![region])
+			.build(),
+	);
+
+	Ok(module)
+}
+
+/// Converts the `arith`, `math` and `cf` operations of a lowered shader to
+/// the `spirv` dialect.
+pub fn convert(context: &Context, module: &mut Module) -> Result<()> {
+	let pass_manager = pass::Manager::new(context);
+	pass_manager.add_pass(pass::conversion::convert_arithmetic_to_spirv());
+	pass_manager.add_pass(pass::conversion::convert_math_to_spirv());
+	pass_manager.add_pass(pass::conversion::convert_cf_to_spirv());
+	pass_manager.enable_verifier(true);
+	pass_manager
+		.run(module)
+		.context("Failed to convert shader to the spirv dialect")?;
+
+	Ok(())
+}
+
+impl<'c, 'a> Shader<'c, 'a> {
+	/// Lowers the outputs of a shader, returning its execution model.
+	fn lower_outputs(&mut self, expr: &ast::Expr) -> Result<&'static str> {
+		let range = expr.syntax().text_range();
+		let Term::Set(outputs) = self.evaluate(expr, &Rc::default())? else {
+			return Err(self.error(
+				range,
+				"a shader must be a set of its type and outputs",
+			));
+		};
+		let model = match outputs.get("type") {
+			Some(r#type) => match self.force(r#type, range)? {
+				Term::String(r#type) if r#type == "vertex" => "Vertex",
+				Term::String(r#type) if r#type == "fragment" => "Fragment",
+				_ => return Err(self.error(
+					range_of(r#type, range),
+					"the type of a shader must be \"vertex\" or \"fragment\"",
+				)),
+			},
+			None => return Err(self.error(range, "the shader has no type")),
+		};
+
+		for (name, output) in outputs.iter() {
+			if name == "type" {
+				continue;
+			}
+
+			let range = range_of(output, range);
+			let Term::Set(output) = self.force(output, range)? else {
+				return Err(self.error(
+					range,
+					format!("output '{name}' must be a set of its location and value"),
+				));
+			};
+			let location = match output.get("location") {
+				Some(location) => match self.force(location, range)? {
+					Term::Int(location) if u32::try_from(location).is_ok() => {
+						location
+					}
+					_ => return Err(self.error(
+						range_of(location, range),
+						"the location of an output must be a natural number",
+					)),
+				},
+				None => {
+					return Err(self.error(
+						range,
+						format!("output '{name}' has no location"),
+					))
+				}
+			};
+			let Some(value) = output.get("value") else {
+				return Err(
+					self.error(range, format!("output '{name}' has no value"))
+				);
+			};
+			let value_range = range_of(value, range);
+			let value = self.force(value, value_range)?;
+			let (value, kind) = self.value(&(value, value_range))?;
+
+			if matches!(kind, Kind::Bool | Kind::Sampler2D) {
+				return Err(self.error(
+					value_range,
+					format!("outputs cannot be of type {}", kind.name()),
+				));
+			}
+
+			self.declare(
+				name,
+				kind,
+				"Output",
+				vec![("location", location)],
+				range,
+			)?;
+			let pointer = self.address(name, range)?;
+			self.block.append_operation(
+				operation::Builder::new("spirv.Store", self.location(range))
+					.add_operands(&[pointer, value])
+					.build(),
+			);
+		}
+
+		Ok(model)
+	}
+
+	fn evaluate(
+		&mut self,
+		expr: &ast::Expr,
+		env: &Rc<Env<'a>>,
+	) -> Result<Term<'a>> {
+		let range = expr.syntax().text_range();
+
+		match expr {
+			ast::Expr::Literal(literal) => match literal.kind() {
+				ast::LiteralKind::Integer(integer) => {
+					Ok(Term::Int(integer.value().context("invalid integer")?))
+				}
+				ast::LiteralKind::Float(float) => {
+					Ok(Term::Float(float.value().context("invalid float")?))
+				}
+				ast::LiteralKind::Uri(_) => {
+					Err(self.error(range, "URIs are not supported in shaders"))
+				}
+			},
+			ast::Expr::Str(string) => {
+				match literal(&string.normalized_parts()) {
+					Some(value) => Ok(Term::String(value)),
+					None => Err(self.error(
+						range,
+						"string interpolation is not supported in shaders",
+					)),
+				}
+			}
+			ast::Expr::Ident(ident) => {
+				let name = ident_name(ident)?;
+
+				match env.lookup(&name) {
+					Some(thunk) => self.force(&thunk, range),
+					None => match name.as_str() {
+						"true" => Ok(Term::Bool(true)),
+						"false" => Ok(Term::Bool(false)),
+						"builtins" => Ok(Term::Builtins),
+						_ => Err(self.error(
+							range,
+							format!("undefined variable '{name}'"),
+						)),
+					},
+				}
+			}
+			ast::Expr::Paren(paren) => {
+				self.evaluate(&child(paren.expr())?, env)
+			}
+			ast::Expr::LetIn(let_in) => {
+				let definitions = collect_definitions(let_in.entries())?;
+				let scope = env.push();
+				let bindings =
+					self.bindings(&definitions, &scope, env, true)?;
+				*scope.bindings.borrow_mut() = bindings;
+
+				self.evaluate(&child(let_in.body())?, &scope)
+			}
+			ast::Expr::AttrSet(set) => {
+				let definitions = collect_definitions(set.entries())?;
+				let rec = set.rec_token().is_some();
+				let scope = if rec { env.push() } else { env.clone() };
+				let attributes =
+					self.bindings(&definitions, &scope, env, false)?;
+
+				if rec {
+					*scope.bindings.borrow_mut() = attributes.clone();
+				}
+
+				Ok(Term::Set(Rc::new(attributes)))
+			}
+			ast::Expr::Select(select) => {
+				let mut term = self.evaluate(&child(select.expr())?, env)?;
+
+				for attr in child(select.attrpath())?.attrs() {
+					let range = attr.syntax().text_range();
+					let name = self.static_name(&attr)?;
+
+					term = match self.attribute(&term, &name, range)? {
+						Some(term) => term,
+						None => match select.default_expr() {
+							Some(default) => {
+								return self.evaluate(&default, env)
+							}
+							None => {
+								return Err(self.error(
+									range,
+									format!("attribute '{name}' missing"),
+								))
+							}
+						},
+					};
+				}
+
+				Ok(term)
+			}
+			ast::Expr::List(_) => Ok(Term::List),
+			ast::Expr::Lambda(lambda) => {
+				Ok(Term::Lambda(lambda.clone(), env.clone()))
+			}
+			ast::Expr::Apply(apply) => {
+				let function = self.evaluate(&child(apply.lambda())?, env)?;
+
+				let argument = suspend(&child(apply.argument())?, env, None);
+
+				self.apply(function, argument, range)
+			}
+			ast::Expr::IfElse(if_else) => self.conditional(if_else, env),
+			ast::Expr::Assert(assert) => {
+				let condition = child(assert.condition())?;
+
+				match self.evaluate(&condition, env)? {
+					Term::Bool(true) => {
+						self.evaluate(&child(assert.body())?, env)
+					}
+					Term::Bool(false) => {
+						Err(self.error(range, "assertion failed"))
+					}
+					term => Err(self.error(
+						condition.syntax().text_range(),
+						format!(
+							"expected a Boolean but found {}",
+							term.description()
+						),
+					)),
+				}
+			}
+			ast::Expr::BinOp(binary) => self.binary(binary, env),
+			ast::Expr::UnaryOp(unary) => self.unary(unary, env),
+			_ => {
+				Err(self
+					.error(range, "this construct is not supported in shaders"))
+			}
+		}
+	}
+
+	/// Suspends the values of the bindings of a `let` or a set, inheriting
+	/// from `outer`.
+	fn bindings(
+		&self,
+		definitions: &Definitions,
+		scope: &Rc<Env<'a>>,
+		outer: &Rc<Env<'a>>,
+		named: bool,
+	) -> Result<BTreeMap<String, Rc<Thunk<'a>>>> {
+		if let Some((name, _)) = definitions.dynamic.first() {
+			return Err(self.error(
+				name.syntax().text_range(),
+				"dynamic attribute names are not supported in shaders",
+			));
+		}
+
+		definitions
+			.named
+			.iter()
+			.map(|(name, definition)| {
+				let thunk = match definition {
+					Definition::Expr(expr) => {
+						suspend(expr, scope, named.then_some(name.as_str()))
+					}
+					Definition::Nested(nested) => {
+						let attributes =
+							self.bindings(nested, scope, outer, false)?;
+
+						Rc::new(RefCell::new(State::Done(Term::Set(Rc::new(
+							attributes,
+						)))))
+					}
+					Definition::Inherit(None, ident) => {
+						outer.lookup(name).ok_or_else(|| {
+							self.error(
+								ident.syntax().text_range(),
+								format!("undefined variable '{name}'"),
+							)
+						})?
+					}
+					Definition::Inherit(Some(from), ident) => {
+						Rc::new(RefCell::new(State::Inherited(
+							suspend(from, outer, None),
+							name.clone(),
+							ident.syntax().text_range(),
+						)))
+					}
+					Definition::Formal(_) => bail!("unexpected formal"),
+				};
+
+				Ok((name.clone(), thunk))
+			})
+			.collect()
+	}
+
+	fn force(
+		&mut self,
+		thunk: &Thunk<'a>,
+		range: TextRange,
+	) -> Result<Term<'a>> {
+		let term = match thunk.replace(State::Forcing) {
+			State::Done(term) => term,
+			State::Forcing => {
+				return Err(self.error(range, "infinite recursion encountered"))
+			}
+			State::Suspended(expr, env, name) => {
+				let outer = std::mem::replace(&mut self.name, name);
+				let term = self.evaluate(&expr, &env);
+				self.name = outer;
+				let range = expr.syntax().text_range();
+
+				match self.types.get(&range) {
+					Some(&kind) => Term::Value(
+						self.value_of_kind(&(term?, range), kind)?,
+						kind,
+					),
+					None => term?,
+				}
+			}
+			State::Inherited(set, name, range) => {
+				let set = self.force(&set, range)?;
+
+				self.attribute(&set, &name, range)?.ok_or_else(|| {
+					self.error(range, format!("attribute '{name}' missing"))
+				})?
+			}
+		};
+		thunk.replace(State::Done(term.clone()));
+
+		Ok(term)
+	}
+
+	fn static_name(&self, attr: &ast::Attr) -> Result<String> {
+		match attr_key(attr)? {
+			Key::Static(name) => Ok(name),
+			Key::Dynamic(name) => Err(self.error(
+				name.syntax().text_range(),
+				"dynamic attribute names are not supported in shaders",
+			)),
+		}
+	}
+
+	/// Selects an attribute of a set, or gets a builtin.
+	fn attribute(
+		&mut self,
+		set: &Term<'a>,
+		name: &str,
+		range: TextRange,
+	) -> Result<Option<Term<'a>>> {
+		match set {
+			Term::Builtins => match builtin(name) {
+				Some((name, _)) => Ok(Some(Term::Builtin(name, Vec::new()))),
+				None => Err(self.error(
+					range,
+					format!("builtins.{name} is not supported in shaders"),
+				)),
+			},
+			Term::Set(attributes) => attributes
+				.get(name)
+				.map(|value| self.force(value, range))
+				.transpose(),
+			term => Err(self.error(
+				range,
+				format!("expected a set but found {}", term.description()),
+			)),
+		}
+	}
+
+	fn apply(
+		&mut self,
+		function: Term<'a>,
+		argument: Rc<Thunk<'a>>,
+		range: TextRange,
+	) -> Result<Term<'a>> {
+		match function {
+			Term::Lambda(lambda, scope) => {
+				let ast::Param::IdentParam(param) = child(lambda.param())?
+				else {
+					return Err(self.error(
+						lambda.syntax().text_range(),
+						"functions of sets are not supported in shaders",
+					));
+				};
+				let scope = scope.push();
+				scope
+					.bindings
+					.borrow_mut()
+					.insert(ident_name(&child(param.ident())?)?, argument);
+
+				self.evaluate(&child(lambda.body())?, &scope)
+			}
+			Term::Builtin(name, mut arguments) => {
+				let argument_range = range_of(&argument, range);
+				arguments.push((self.force(&argument, range)?, argument_range));
+
+				if builtin(name)
+					.is_some_and(|(_, arity)| arguments.len() < arity)
+				{
+					Ok(Term::Builtin(name, arguments))
+				} else {
+					self.builtin(name, &arguments, range)
+				}
+			}
+			function => Err(self.error(
+				range,
+				format!(
+					"attempt to call something which is not a function but {}",
+					function.description()
+				),
+			)),
+		}
+	}
+
+	fn builtin(
+		&mut self,
+		name: &str,
+		arguments: &[(Term<'a>, TextRange)],
+		range: TextRange,
+	) -> Result<Term<'a>> {
+		match name {
+			"input" => {
+				let kind = self.kind(&arguments[0])?;
+				let variable = self.string(&arguments[1])?;
+
+				if kind == Kind::Sampler2D {
+					return Err(self.error(
+						arguments[0].1,
+						"inputs cannot be of type sampler2D",
+					));
+				}
+
+				let next = self
+					.variables
+					.values()
+					.filter(|variable| variable.class == "Input")
+					.count();
+				let location = self
+					.variables
+					.get(&variable)
+					.and_then(|variable| variable.decoration("location"))
+					.unwrap_or(next as i64);
+				self.declare(
+					&variable,
+					kind,
+					"Input",
+					vec![("location", location)],
+					range,
+				)?;
+
+				Ok(Term::Value(self.load(&variable, range)?, kind))
+			}
+			"uniform" => {
+				let kind = self.kind(&arguments[0])?;
+				let binding = match arguments[1].0 {
+					Term::Int(binding) if u32::try_from(binding).is_ok() => {
+						binding
+					}
+					ref term => {
+						return Err(self.error(
+							arguments[1].1,
+							format!(
+								"expected a binding but found {}",
+								term.description()
+							),
+						))
+					}
+				};
+
+				if kind != Kind::Sampler2D {
+					return Err(self.error(
+						arguments[0].1,
+						"only sampler2D uniforms are supported",
+					));
+				}
+
+				let variable =
+					self.name.clone().unwrap_or(format!("uniform{binding}"));
+				self.declare(
+					&variable,
+					kind,
+					"UniformConstant",
+					vec![("descriptor_set", 0), ("binding", binding)],
+					range,
+				)?;
+
+				Ok(Term::Value(self.load(&variable, range)?, kind))
+			}
+			"texture" => {
+				let sampler =
+					self.value_of_kind(&arguments[0], Kind::Sampler2D)?;
+				let coordinates =
+					self.value_of_kind(&arguments[1], Kind::Vector(2))?;
+				let kind = Kind::Vector(4);
+
+				Ok(Term::Value(
+					self.operation(
+						SAMPLE,
+						&[sampler, coordinates],
+						&[],
+						kind,
+						range,
+					)?,
+					kind,
+				))
+			}
+			"vec2" | "vec3" | "vec4" => {
+				let components = arguments
+					.iter()
+					.map(|argument| self.value_of_kind(argument, Kind::Float))
+					.collect::<Result<Vec<_>>>()?;
+				let kind = Kind::Vector(components.len() as u32);
+
+				Ok(Term::Value(
+					self.operation(
+						"spirv.CompositeConstruct",
+						&components,
+						&[],
+						kind,
+						range,
+					)?,
+					kind,
+				))
+			}
+			_ => {
+				let (argument, argument_range) = &arguments[0];
+
+				if let Some(value) = argument.number() {
+					return Ok(Term::Float(match name {
+						"abs" => value.abs(),
+						"ceil" => value.ceil(),
+						"cos" => value.cos(),
+						"exp" => value.exp(),
+						"floor" => value.floor(),
+						"log" => value.ln(),
+						"sin" => value.sin(),
+						_ => value.sqrt(),
+					}));
+				}
+
+				let (value, kind) = self.value(&arguments[0])?;
+
+				if !kind.is_float() {
+					return Err(self.error(
+						*argument_range,
+						format!(
+							"expected a float or a vector but found {}",
+							argument.description()
+						),
+					));
+				}
+
+				let operation = match name {
+					"abs" => "math.absf",
+					"ceil" => "math.ceil",
+					"cos" => "math.cos",
+					"exp" => "math.exp",
+					"floor" => "math.floor",
+					"log" => "math.log",
+					"sin" => "math.sin",
+					_ => "math.sqrt",
+				};
+
+				Ok(Term::Value(
+					self.operation(operation, &[value], &[], kind, range)?,
+					kind,
+				))
+			}
+		}
+	}
+
+	/// Evaluates a conditional, which selects between the values of both of
+	/// its branches if its condition is computed by the shader.
+	fn conditional(
+		&mut self,
+		if_else: &ast::IfElse,
+		env: &Rc<Env<'a>>,
+	) -> Result<Term<'a>> {
+		let range = if_else.syntax().text_range();
+		let condition = child(if_else.condition())?;
+		let then = child(if_else.body())?;
+		let r#else = child(if_else.else_body())?;
+
+		let condition = match self.evaluate(&condition, env)? {
+			Term::Bool(true) => return self.evaluate(&then, env),
+			Term::Bool(false) => return self.evaluate(&r#else, env),
+			Term::Value(value, Kind::Bool) => value,
+			term => {
+				return Err(self.error(
+					condition.syntax().text_range(),
+					format!(
+						"expected a Boolean but found {}",
+						term.description()
+					),
+				))
+			}
+		};
+		let then = (self.evaluate(&then, env)?, then.syntax().text_range());
+		let r#else =
+			(self.evaluate(&r#else, env)?, r#else.syntax().text_range());
+		let kind = match (&then.0, &r#else.0) {
+			(Term::Value(_, kind), _) | (_, Term::Value(_, kind)) => *kind,
+			(Term::Bool(_), Term::Bool(_)) => Kind::Bool,
+			(Term::Int(_), Term::Int(_)) => Kind::Int,
+			(then, r#else)
+				if then.number().is_some() && r#else.number().is_some() =>
+			{
+				Kind::Float
+			}
+			(then, r#else) => {
+				return Err(self.error(
+					range,
+					format!(
+						"cannot select between {} and {} in a shader",
+						then.description(),
+						r#else.description()
+					),
+				))
+			}
+		};
+		let then = self.value_of_kind(&then, kind)?;
+		let r#else = self.value_of_kind(&r#else, kind)?;
+
+		Ok(Term::Value(
+			self.operation(
+				"arith.select",
+				&[condition, then, r#else],
+				&[],
+				kind,
+				range,
+			)?,
+			kind,
+		))
+	}
+
+	fn binary(
+		&mut self,
+		binary: &ast::BinOp,
+		env: &Rc<Env<'a>>,
+	) -> Result<Term<'a>> {
+		use ast::BinOpKind::*;
+
+		let range = binary.syntax().text_range();
+		let operator = binary.operator().context("missing operator")?;
+		let lhs = child(binary.lhs())?;
+		let lhs = (self.evaluate(&lhs, env)?, lhs.syntax().text_range());
+
+		// The right operand of a logical operator is only evaluated if the
+		// left one does not determine the result.
+		match (operator, &lhs.0) {
+			(And, Term::Bool(false)) => return Ok(Term::Bool(false)),
+			(Or, Term::Bool(true)) | (Implication, Term::Bool(false)) => {
+				return Ok(Term::Bool(true))
+			}
+			_ => {}
+		}
+
+		let rhs = child(binary.rhs())?;
+		let rhs = (self.evaluate(&rhs, env)?, rhs.syntax().text_range());
+
+		if let Some(term) = self.fold(operator, &lhs.0, &rhs.0, range)? {
+			return Ok(term);
+		}
+
+		let kind = match (&lhs.0, &rhs.0) {
+			(Term::Value(_, lhs), Term::Value(_, rhs)) if lhs != rhs => {
+				return Err(self.error(
+					range,
+					format!(
+						"cannot apply '{}' to values of types {} and {}",
+						symbol(operator),
+						lhs.name(),
+						rhs.name()
+					),
+				))
+			}
+			(Term::Value(_, kind), _) | (_, Term::Value(_, kind)) => *kind,
+			(lhs, rhs) => {
+				return Err(self.error(
+					range,
+					format!(
+						"cannot apply '{}' to {} and {}",
+						symbol(operator),
+						lhs.description(),
+						rhs.description()
+					),
+				))
+			}
+		};
+		let lhs = self.value_of_kind(&lhs, kind)?;
+		let rhs = self.value_of_kind(&rhs, kind)?;
+
+		// The predicates of `arith.cmpf` and `arith.cmpi`.
+		let (name, predicate) = match (operator, kind) {
+			(Add, _) if kind.is_float() => ("arith.addf", None),
+			(Sub, _) if kind.is_float() => ("arith.subf", None),
+			(Mul, _) if kind.is_float() => ("arith.mulf", None),
+			(Div, _) if kind.is_float() => ("arith.divf", None),
+			(Add, Kind::Int) => ("arith.addi", None),
+			(Sub, Kind::Int) => ("arith.subi", None),
+			(Mul, Kind::Int) => ("arith.muli", None),
+			(Div, Kind::Int) => ("arith.divsi", None),
+			(Equal, Kind::Float) => ("arith.cmpf", Some(1)),
+			(More, Kind::Float) => ("arith.cmpf", Some(2)),
+			(MoreOrEq, Kind::Float) => ("arith.cmpf", Some(3)),
+			(Less, Kind::Float) => ("arith.cmpf", Some(4)),
+			(LessOrEq, Kind::Float) => ("arith.cmpf", Some(5)),
+			(NotEqual, Kind::Float) => ("arith.cmpf", Some(13)),
+			(Equal, Kind::Int | Kind::Bool) => ("arith.cmpi", Some(0)),
+			(NotEqual, Kind::Int | Kind::Bool) => ("arith.cmpi", Some(1)),
+			(Less, Kind::Int) => ("arith.cmpi", Some(2)),
+			(LessOrEq, Kind::Int) => ("arith.cmpi", Some(3)),
+			(More, Kind::Int) => ("arith.cmpi", Some(4)),
+			(MoreOrEq, Kind::Int) => ("arith.cmpi", Some(5)),
+			(And, Kind::Bool) => ("arith.andi", None),
+			(Or, Kind::Bool) => ("arith.ori", None),
+			(Implication, Kind::Bool) => {
+				let not = self.not(lhs, range)?;
+
+				return Ok(Term::Value(
+					self.operation(
+						"arith.ori",
+						&[not, rhs],
+						&[],
+						Kind::Bool,
+						range,
+					)?,
+					Kind::Bool,
+				));
+			}
+			_ => {
+				return Err(self.error(
+					range,
+					format!(
+						"cannot apply '{}' to values of type {}",
+						symbol(operator),
+						kind.name()
+					),
+				))
+			}
+		};
+
+		match predicate {
+			Some(predicate) => Ok(Term::Value(
+				self.operation(
+					name,
+					&[lhs, rhs],
+					&[("predicate", &format!("{predicate} : i64"))],
+					Kind::Bool,
+					range,
+				)?,
+				Kind::Bool,
+			)),
+			None => Ok(Term::Value(
+				self.operation(name, &[lhs, rhs], &[], kind, range)?,
+				kind,
+			)),
+		}
+	}
+
+	/// Applies an operator to statically known operands, if it is defined on
+	/// them.
+	fn fold(
+		&self,
+		operator: ast::BinOpKind,
+		lhs: &Term<'a>,
+		rhs: &Term<'a>,
+		range: TextRange,
+	) -> Result<Option<Term<'a>>> {
+		use ast::BinOpKind::*;
+
+		let overflow = || self.error(range, "integer overflow");
+
+		Ok(Some(match (lhs, rhs) {
+			(&Term::Int(lhs), &Term::Int(rhs)) => match operator {
+				Add => Term::Int(lhs.checked_add(rhs).ok_or_else(overflow)?),
+				Sub => Term::Int(lhs.checked_sub(rhs).ok_or_else(overflow)?),
+				Mul => Term::Int(lhs.checked_mul(rhs).ok_or_else(overflow)?),
+				Div if rhs == 0 => {
+					return Err(self.error(range, "division by zero"))
+				}
+				Div => Term::Int(lhs.checked_div(rhs).ok_or_else(overflow)?),
+				_ => match compare(operator, lhs, rhs) {
+					Some(result) => Term::Bool(result),
+					None => return Ok(None),
+				},
+			},
+			(lhs, rhs) if lhs.number().is_some() && rhs.number().is_some() => {
+				let (lhs, rhs) = (lhs.number().unwrap(), rhs.number().unwrap());
+
+				match operator {
+					Add => Term::Float(lhs + rhs),
+					Sub => Term::Float(lhs - rhs),
+					Mul => Term::Float(lhs * rhs),
+					Div if rhs == 0.0 => {
+						return Err(self.error(range, "division by zero"))
+					}
+					Div => Term::Float(lhs / rhs),
+					_ => match compare(operator, lhs, rhs) {
+						Some(result) => Term::Bool(result),
+						None => return Ok(None),
+					},
+				}
+			}
+			(&Term::Bool(lhs), &Term::Bool(rhs)) => match operator {
+				And => Term::Bool(lhs && rhs),
+				Or => Term::Bool(lhs || rhs),
+				Implication => Term::Bool(!lhs || rhs),
+				Equal => Term::Bool(lhs == rhs),
+				NotEqual => Term::Bool(lhs != rhs),
+				_ => return Ok(None),
+			},
+			(Term::String(lhs), Term::String(rhs)) => match operator {
+				Add => Term::String(format!("{lhs}{rhs}")),
+				Equal => Term::Bool(lhs == rhs),
+				NotEqual => Term::Bool(lhs != rhs),
+				_ => return Ok(None),
+			},
+			_ => return Ok(None),
+		}))
+	}
+
+	fn unary(
+		&mut self,
+		unary: &ast::UnaryOp,
+		env: &Rc<Env<'a>>,
+	) -> Result<Term<'a>> {
+		let range = unary.syntax().text_range();
+		let operator = unary.operator().context("missing operator")?;
+		let operand = self.evaluate(&child(unary.expr())?, env)?;
+
+		match (operator, operand) {
+			(ast::UnaryOpKind::Invert, Term::Bool(value)) => {
+				Ok(Term::Bool(!value))
+			}
+			(ast::UnaryOpKind::Invert, Term::Value(value, Kind::Bool)) => {
+				Ok(Term::Value(self.not(value, range)?, Kind::Bool))
+			}
+			(ast::UnaryOpKind::Negate, Term::Int(value)) => value
+				.checked_neg()
+				.map(Term::Int)
+				.ok_or_else(|| self.error(range, "integer overflow")),
+			(ast::UnaryOpKind::Negate, Term::Float(value)) => {
+				Ok(Term::Float(-value))
+			}
+			(ast::UnaryOpKind::Negate, Term::Value(value, kind))
+				if kind.is_float() =>
+			{
+				Ok(Term::Value(
+					self.operation("arith.negf", &[value], &[], kind, range)?,
+					kind,
+				))
+			}
+			(ast::UnaryOpKind::Negate, Term::Value(value, Kind::Int)) => {
+				let zero = self.constant(&Term::Int(0), Kind::Int, range)?;
+
+				Ok(Term::Value(
+					self.operation(
+						"arith.subi",
+						&[zero, value],
+						&[],
+						Kind::Int,
+						range,
+					)?,
+					Kind::Int,
+				))
+			}
+			(operator, operand) => Err(self.error(
+				range,
+				format!(
+					"cannot apply '{}' to {}",
+					match operator {
+						ast::UnaryOpKind::Invert => "!",
+						ast::UnaryOpKind::Negate => "-",
+					},
+					operand.description()
+				),
+			)),
+		}
+	}
+
+	fn not(&self, value: Value<'a>, range: TextRange) -> Result<Value<'a>> {
+		let r#true = self.constant(&Term::Bool(true), Kind::Bool, range)?;
+
+		self.operation("arith.xori", &[value, r#true], &[], Kind::Bool, range)
+	}
+
+	/// Declares a global variable unless it is already declared the same way.
+	fn declare(
+		&mut self,
+		name: &str,
+		kind: Kind,
+		class: &'static str,
+		decorations: Vec<(&'static str, i64)>,
+		range: TextRange,
+	) -> Result<()> {
+		if let Some(variable) = self.variables.get(name) {
+			if variable.kind == kind
+				&& variable.class == class
+				&& variable.decorations == decorations
+			{
+				return Ok(());
+			}
+
+			return Err(self.error(
+				range,
+				format!("variable '{name}' already declared differently"),
+			));
+		}
+
+		let name_attribute = dialect::string(name);
+		let pointer = self.pointer(kind, class);
+		let values = decorations
+			.iter()
+			.map(|(_, value)| format!("{value} : i32"))
+			.collect::<Vec<_>>();
+		let mut attributes = vec![
+			("sym_name", name_attribute.as_str()),
+			("type", pointer.as_str()),
+		];
+		attributes.extend(
+			decorations
+				.iter()
+				.zip(&values)
+				.map(|((decoration, _), value)| (*decoration, value.as_str())),
+		);
+
+		self.globals.push(
+			operation::Builder::new(
+				"spirv.GlobalVariable",
+				self.location(range),
+			)
+			.add_attributes(&NamedAttribute::new_parsed_vec(
+				self.context,
+				&attributes,
+			)?)
+			.build(),
+		);
+
+		if class != "UniformConstant" {
+			self.interface.push(name.into());
+		}
+
+		self.variables.insert(
+			name.into(),
+			Variable {
+				kind,
+				class,
+				decorations,
+			},
+		);
+
+		Ok(())
+	}
+
+	fn pointer(&self, kind: Kind, class: &str) -> String {
+		pointer(self.context, kind, class)
+	}
+
+	/// Gets a pointer to a global variable.
+	fn address(&self, name: &str, range: TextRange) -> Result<Value<'a>> {
+		let variable = &self.variables[name];
+		let pointer = self.pointer(variable.kind, variable.class);
+
+		self.append(
+			operation::Builder::new(
+				"spirv.mlir.addressof",
+				self.location(range),
+			)
+			.add_attributes(&NamedAttribute::new_parsed_vec(
+				self.context,
+				&[("variable", &format!("@{}", dialect::string(name)))],
+			)?)
+			.add_results(&[Type::parse(self.context, &pointer)
+				.context("invalid pointer type")?])
+			.build(),
+		)
+	}
+
+	fn load(&self, name: &str, range: TextRange) -> Result<Value<'a>> {
+		let pointer = self.address(name, range)?;
+
+		self.append(
+			operation::Builder::new("spirv.Load", self.location(range))
+				.add_operands(&[pointer])
+				.add_results(&[self.variables[name].kind.r#type(self.context)])
+				.build(),
+		)
+	}
+
+	/// Gets a value computed by the shader, or a constant of a literal.
+	fn value(
+		&self,
+		(term, range): &(Term<'a>, TextRange),
+	) -> Result<(Value<'a>, Kind)> {
+		let kind = match term {
+			Term::Value(value, kind) => return Ok((*value, *kind)),
+			Term::Bool(_) => Kind::Bool,
+			Term::Int(_) => Kind::Int,
+			Term::Float(_) => Kind::Float,
+			term => {
+				return Err(self.error(
+					*range,
+					format!(
+						"expected a shader value but found {}",
+						term.description()
+					),
+				))
+			}
+		};
+
+		Ok((self.constant(term, kind, *range)?, kind))
+	}
+
+	/// Gets a value of a type computed by the shader, or a constant of a
+	/// literal of that type.
+	fn value_of_kind(
+		&self,
+		(term, range): &(Term<'a>, TextRange),
+		kind: Kind,
+	) -> Result<Value<'a>> {
+		match (term, kind) {
+			(&Term::Value(value, found), _) if found == kind => Ok(value),
+			(Term::Bool(_), Kind::Bool)
+			| (Term::Int(_), Kind::Int)
+			| (Term::Int(_) | Term::Float(_), Kind::Float | Kind::Vector(_)) => {
+				self.constant(term, kind, *range)
+			}
+			(term, kind) => Err(self.error(
+				*range,
+				format!(
+					"expected a value of type {} but found {}",
+					kind.name(),
+					term.description()
+				),
+			)),
+		}
+	}
+
+	/// Creates a constant of a literal, splatted to vectors.
+	fn constant(
+		&self,
+		term: &Term<'a>,
+		kind: Kind,
+		range: TextRange,
+	) -> Result<Value<'a>> {
+		let value = match (term, kind) {
+			(Term::Bool(value), Kind::Bool) => value.to_string(),
+			(&Term::Int(value), Kind::Int) => format!(
+				"{} : i32",
+				i32::try_from(value).map_err(
+					|_| self.error(range, "integer too large for a shader")
+				)?
+			),
+			// The bit pattern is printed as MLIR float literals require a
+			// period.
+			(term, Kind::Float) if term.number().is_some() => format!(
+				"0x{:08X} : f32",
+				(term.number().unwrap() as f32).to_bits()
+			),
+			// Vectors are constructed from a float, as only scalar constants
+			// are serialized.
+			(term, Kind::Vector(size)) if term.number().is_some() => {
+				let component = self.constant(term, Kind::Float, range)?;
+
+				return self.operation(
+					"spirv.CompositeConstruct",
+					&vec![component; size as usize],
+					&[],
+					kind,
+					range,
+				);
+			}
+			(term, kind) => bail!(
+				"cannot create a constant of type {} from {}",
+				kind.name(),
+				term.description()
+			),
+		};
+
+		self.operation("arith.constant", &[], &[("value", &value)], kind, range)
+	}
+
+	/// Appends an operation computing a value of a type.
+	fn operation(
+		&self,
+		name: &str,
+		operands: &[Value<'a>],
+		attributes: &[(&str, &str)],
+		kind: Kind,
+		range: TextRange,
+	) -> Result<Value<'a>> {
+		self.append(
+			operation::Builder::new(name, self.location(range))
+				.add_operands(operands)
+				.add_attributes(&NamedAttribute::new_parsed_vec(
+					self.context,
+					attributes,
+				)?)
+				.add_results(&[kind.r#type(self.context)])
+				.build(),
+		)
+	}
+
+	fn append(&self, operation: Operation<'c>) -> Result<Value<'a>> {
+		Ok(self.block.append_operation(operation).result(0)?.into())
+	}
+
+	fn kind(&self, argument: &(Term<'a>, TextRange)) -> Result<Kind> {
+		let name = self.string(argument)?;
+
+		Kind::parse(&name).ok_or_else(|| {
+			self.error(argument.1, format!("unknown shader type '{name}'"))
+		})
+	}
+
+	fn string(&self, (term, range): &(Term<'a>, TextRange)) -> Result<String> {
+		match term {
+			Term::String(string) => Ok(string.clone()),
+			term => Err(self.error(
+				*range,
+				format!("expected a string but found {}", term.description()),
+			)),
+		}
+	}
+
+	fn location(&self, range: TextRange) -> Location<'c> {
+		self.source.location(self.context, range)
+	}
+
+	fn error(
+		&self,
+		range: TextRange,
+		message: impl Into<String>,
+	) -> anyhow::Error {
+		self.source.diagnostic(range, message).into()
+	}
+}
+
+/// Gets the type of pointers to values of a type in a storage class, as
+/// `!spirv.ptr<f32, Input>`.
+pub(crate) fn pointer(context: &Context, kind: Kind, class: &str) -> String {
+	format!("!spirv.ptr<{}, {class}>", kind.r#type(context))
+}
+
+/// Compares statically known operands.
+fn compare<T: PartialOrd>(
+	operator: ast::BinOpKind,
+	lhs: T,
+	rhs: T,
+) -> Option<bool> {
+	use ast::BinOpKind::*;
+
+	Some(match operator {
+		Equal => lhs == rhs,
+		NotEqual => lhs != rhs,
+		Less => lhs < rhs,
+		LessOrEq => lhs <= rhs,
+		More => lhs > rhs,
+		MoreOrEq => lhs >= rhs,
+		_ => return None,
+	})
+}
+
+pub(crate) fn symbol(operator: ast::BinOpKind) -> &'static str {
+	use ast::BinOpKind::*;
+
+	match operator {
+		Concat => "++",
+		Update => "//",
+		Add => "+",
+		Sub => "-",
+		Mul => "*",
+		Div => "/",
+		And => "&&",
+		Equal => "==",
+		Implication => "->",
+		Less => "<",
+		LessOrEq => "<=",
+		More => ">",
+		MoreOrEq => ">=",
+		NotEqual => "!=",
+		Or => "||",
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::source::Diagnostic;
+	use melior::{dialect::Registry, utility::register_all_dialects};
+
+	fn lower_shader(text: &str) -> Result<String> {
+		let registry = Registry::new();
+		register_all_dialects(&registry);
+
+		let context = Context::new();
+		context.append_dialect_registry(&registry);
+		context.load_all_available_dialects();
+
+		let root = rnix::Root::parse(text).tree();
+		let module = lower(&context, &Source::new("gpu.nix", text), &root)?;
+
+		assert!(module.as_operation().verify());
+
+		Ok(module.as_operation().to_string())
+	}
+
+	#[test]
+	fn lower_fragment_shader() {
+		let module = lower_shader(include_str!("../test/gpu.nix")).unwrap();
+
+		assert!(module.contains("sym_name = \"textureSampler\""));
+		assert!(module.contains("!spirv.ptr<vector<2xf32>, Input>"));
+		assert!(module.contains("!spirv.ptr<vector<4xf32>, Output>"));
+		assert!(module.contains("\"shader.sample\""));
+		assert!(module.contains("#spirv.execution_model<Fragment>"));
+	}
+
+	#[test]
+	fn reject_unknown_types() {
+		let error = lower_shader(
+			"{ type = \"fragment\"; color = { location = 0; value = \
+			 builtins.input \"vec5\" \"a\"; }; }",
+		)
+		.unwrap_err();
+		let diagnostic = error.downcast_ref::<Diagnostic>().unwrap();
+
+		assert_eq!(diagnostic.message(), "unknown shader type 'vec5'");
+		assert_eq!(diagnostic.position(), (1, 69));
+		assert!(lower_shader("{ type = \"compute\"; }").is_err());
+		assert!(lower_shader("builtins.length [ ]").is_err());
+	}
+
+	#[test]
+	fn type_bindings() {
+		let module = lower_shader(
+			"let x = if true then 0.5 else builtins.input \"float\" \"a\"; \
+			 in { type = \"fragment\"; color = { location = 0; value = \
+			 builtins.vec4 (builtins.sin x) 0 0 1; }; }",
+		)
+		.unwrap();
+
+		// `x` is inferred to be computed, so its sine is computed too.
+		assert!(module.contains("math.sin"));
+	}
+}
@@ -0,0 +1,145 @@
+//! Source files and the mapping of their text ranges to MLIR locations.
+
+use std::fmt;
+
+use melior::{ir::Location, Context};
+use rnix::{TextRange, TextSize};
+
+/// A Nix source file.
+pub struct Source {
+	path: String,
+	text: String,
+	line_starts: Vec<TextSize>,
+}
+
+impl Source {
+	pub fn new(path: impl Into<String>, text: impl Into<String>) -> Self {
+		let text = text.into();
+		let line_starts = std::iter::once(TextSize::from(0))
+			.chain(text.match_indices('\n').map(|(offset, _)| {
+				TextSize::try_from(offset + 1).expect("source file too large")
+			}))
+			.collect();
+
+		Self {
+			path: path.into(),
+			text,
+			line_starts,
+		}
+	}
+
+	/// Gets the path of the file.
+	pub fn path(&self) -> &str {
+		&self.path
+	}
+
+	/// Gets the text of the file.
+	pub fn text(&self) -> &str {
+		&self.text
+	}
+
+	/// Gets the one-based line and column of an offset. Columns count
+	/// characters rather than bytes.
+	pub fn position(&self, offset: TextSize) -> (usize, usize) {
+		let line = self.line_starts.partition_point(|&start| start <= offset);
+		let start = usize::from(self.line_starts[line - 1]);
+		let column = self.text[start..usize::from(offset)].chars().count();
+
+		(line, column + 1)
+	}
+
+	/// Gets the location of the start of a text range.
+	pub fn location<'c>(
+		&self,
+		context: &'c Context,
+		range: TextRange,
+	) -> Location<'c> {
+		let (line, column) = self.position(range.start());
+
+		Location::new(context, &self.path, line, column)
+	}
+
+	/// Creates a diagnostic at the start of a text range.
+	pub fn diagnostic(
+		&self,
+		range: TextRange,
+		message: impl Into<String>,
+	) -> Diagnostic {
+		let (line, column) = self.position(range.start());
+
+		Diagnostic {
+			message: message.into(),
+			path: self.path.clone(),
+			line,
+			column,
+		}
+	}
+}
+
+/// An error in a source file, reported before evaluation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+	message: String,
+	path: String,
+	line: usize,
+	column: usize,
+}
+
+impl Diagnostic {
+	/// Gets the message of the diagnostic.
+	pub fn message(&self) -> &str {
+		&self.message
+	}
+
+	/// Gets the one-based line and column of the diagnostic.
+	pub fn position(&self) -> (usize, usize) {
+		(self.line, self.column)
+	}
+
+	/// Gets the location of the diagnostic.
+	pub fn location<'c>(&self, context: &'c Context) -> Location<'c> {
+		Location::new(context, &self.path, self.line, self.column)
+	}
+}
+
+impl fmt::Display for Diagnostic {
+	fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			formatter,
+			"{}\n\n       at {}:{}:{}:",
+			self.message, self.path, self.line, self.column
+		)
+	}
+}
+
+impl std::error::Error for Diagnostic {}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn position() {
+		let source = Source::new("a.nix", "let\n  λ = 1;\nin λ");
+
+		assert_eq!(source.position(0.into()), (1, 1));
+		assert_eq!(source.position(6.into()), (2, 3));
+		assert_eq!(source.position(9.into()), (2, 5));
+		assert_eq!(source.position(17.into()), (3, 4));
+	}
+
+	#[test]
+	fn diagnostic() {
+		let source = Source::new("a.nix", "let\n  a = b;");
+		let diagnostic = source.diagnostic(
+			TextRange::at(10.into(), 1.into()),
+			"undefined variable 'b'",
+		);
+
+		assert_eq!(diagnostic.position(), (2, 7));
+		assert_eq!(
+			diagnostic.to_string(),
+			"undefined variable 'b'\n\n       at a.nix:2:7:"
+		);
+	}
+}
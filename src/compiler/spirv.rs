@@ -0,0 +1,746 @@
+//! Serialization of shaders to SPIR-V binaries.
+//!
+//! The `spirv` dialect of MLIR 16 has no operation sampling an image, so
+//! shaders sample textures with [`SAMPLE`], an operation of their own which
+//! `mlir-translate` cannot serialize. Shaders are serialized here instead,
+//! writing samples as `OpImageSampleImplicitLod`.
+//!
+//! Only what shaders are lowered and converted to is supported: the global
+//! variables, entry point and `main` function of a `spirv.module`, and the
+//! arithmetic, logical and `GLSL.std.450` operations in the single block of
+//! the function. Constants are written with the types and variables, and
+//! equal ones are shared. Modules declare the `Shader` capability and the
+//! `Logical` addressing and `GLSL450` memory models, as shaders are lowered
+//! with.
+
+use std::collections::HashMap;
+
+use anyhow::{bail, Context as _, Result};
+use melior::{
+	ir::{Attribute, Module, OperationRef, Value, ValueLike},
+	Context,
+};
+
+use crate::{
+	dialect::{self, operation_name},
+	shader::{pointer, Kind, SAMPLE},
+};
+
+/// The magic number of SPIR-V modules.
+const MAGIC: u32 = 0x0723_0203;
+/// SPIR-V 1.0.
+const VERSION: u32 = 0x0001_0000;
+
+/// The opcodes of the instructions written by the serializer.
+mod opcode {
+	pub const NAME: u32 = 5;
+	pub const EXT_INST_IMPORT: u32 = 11;
+	pub const EXT_INST: u32 = 12;
+	pub const MEMORY_MODEL: u32 = 14;
+	pub const ENTRY_POINT: u32 = 15;
+	pub const EXECUTION_MODE: u32 = 16;
+	pub const CAPABILITY: u32 = 17;
+	pub const TYPE_VOID: u32 = 19;
+	pub const TYPE_BOOL: u32 = 20;
+	pub const TYPE_INT: u32 = 21;
+	pub const TYPE_FLOAT: u32 = 22;
+	pub const TYPE_VECTOR: u32 = 23;
+	pub const TYPE_IMAGE: u32 = 25;
+	pub const TYPE_SAMPLED_IMAGE: u32 = 27;
+	pub const TYPE_POINTER: u32 = 32;
+	pub const TYPE_FUNCTION: u32 = 33;
+	pub const CONSTANT_TRUE: u32 = 41;
+	pub const CONSTANT_FALSE: u32 = 42;
+	pub const CONSTANT: u32 = 43;
+	pub const FUNCTION: u32 = 54;
+	pub const FUNCTION_END: u32 = 56;
+	pub const VARIABLE: u32 = 59;
+	pub const STORE: u32 = 62;
+	pub const DECORATE: u32 = 71;
+	pub const LABEL: u32 = 248;
+	pub const RETURN: u32 = 253;
+}
+
+/// The operations written as instructions with a result type, a result and
+/// their operands, with their opcodes.
+const INSTRUCTIONS: &[(&str, u32)] = &[
+	("spirv.CompositeConstruct", 80),
+	("spirv.FAdd", 129),
+	("spirv.FDiv", 136),
+	("spirv.FMul", 133),
+	("spirv.FNegate", 127),
+	("spirv.FOrdEqual", 180),
+	("spirv.FOrdGreaterThan", 186),
+	("spirv.FOrdGreaterThanEqual", 190),
+	("spirv.FOrdLessThan", 184),
+	("spirv.FOrdLessThanEqual", 188),
+	("spirv.FSub", 131),
+	("spirv.FUnordNotEqual", 183),
+	("spirv.IAdd", 128),
+	("spirv.IEqual", 170),
+	("spirv.IMul", 132),
+	("spirv.INotEqual", 171),
+	("spirv.ISub", 130),
+	("spirv.Load", 61),
+	("spirv.LogicalAnd", 167),
+	("spirv.LogicalEqual", 164),
+	("spirv.LogicalNot", 168),
+	("spirv.LogicalNotEqual", 165),
+	("spirv.LogicalOr", 166),
+	("spirv.SDiv", 135),
+	("spirv.SGreaterThan", 173),
+	("spirv.SGreaterThanEqual", 175),
+	("spirv.SLessThan", 177),
+	("spirv.SLessThanEqual", 179),
+	("spirv.Select", 169),
+	(SAMPLE, 87),
+];
+
+/// The operations written as instructions of `GLSL.std.450`, with their
+/// numbers in the instruction set.
+const GLSL: &[(&str, u32)] = &[
+	("spirv.GL.Ceil", 9),
+	("spirv.GL.Cos", 14),
+	("spirv.GL.Exp", 27),
+	("spirv.GL.FAbs", 4),
+	("spirv.GL.Floor", 8),
+	("spirv.GL.Log", 28),
+	("spirv.GL.Sin", 13),
+	("spirv.GL.Sqrt", 31),
+];
+
+/// The types of values, which are all of a [`Kind`].
+const KINDS: &[Kind] = &[
+	Kind::Bool,
+	Kind::Int,
+	Kind::Float,
+	Kind::Vector(2),
+	Kind::Vector(3),
+	Kind::Vector(4),
+	Kind::Sampler2D,
+];
+
+/// The storage classes of variables, with their numbers.
+const CLASSES: &[(&str, u32)] =
+	&[("UniformConstant", 0), ("Input", 1), ("Output", 3)];
+
+/// The execution models of entry points, with their numbers.
+const MODELS: &[(&str, u32)] = &[("Vertex", 0), ("Fragment", 4)];
+
+/// The execution modes of entry points, with their numbers.
+const MODES: &[(&str, u32)] = &[("OriginUpperLeft", 7)];
+
+/// The decorations of variables, with their numbers.
+const DECORATIONS: &[(&str, u32)] =
+	&[("location", 30), ("binding", 33), ("descriptor_set", 34)];
+
+/// A type declared by a module.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Key {
+	Void,
+	/// The type of `main`, which takes no arguments and returns nothing.
+	Function,
+	Kind(Kind),
+	/// The type of the images which samplers sample.
+	Image,
+	/// A pointer to a value in a storage class.
+	Pointer(Kind, &'static str),
+}
+
+/// Serializes a shader converted to the `spirv` dialect.
+pub fn serialize(context: &Context, module: &Module) -> Result<Vec<u8>> {
+	let mut spirv = None;
+
+	for operation in operations(module.body().first_operation()) {
+		if operation_name(operation)? == "spirv.module" {
+			spirv = Some(operation);
+		}
+	}
+
+	let body = spirv
+		.context("module contains no spirv.module")?
+		.region(0)
+		.and_then(|region| region.first_block())
+		.context("spirv.module has no body")?;
+	let mut writer = Writer::new(context);
+
+	// Variables and functions are numbered first, as entry points refer to
+	// them and functions to variables.
+	for operation in operations(body.first_operation()) {
+		writer.declare(operation)?;
+	}
+
+	for operation in operations(body.first_operation()) {
+		writer.global(operation)?;
+	}
+
+	Ok(writer.finish())
+}
+
+/// Iterates over an operation and those following it in its block.
+fn operations<'a>(
+	first: Option<OperationRef<'a>>,
+) -> impl Iterator<Item = OperationRef<'a>> {
+	std::iter::successors(first, |operation| operation.next_in_block())
+}
+
+/// Encodes a string as the words of a literal string operand.
+fn words(string: &str) -> Vec<u32> {
+	let mut bytes = string.as_bytes().to_vec();
+	bytes.resize(string.len() / 4 * 4 + 4, 0);
+
+	bytes
+		.chunks_exact(4)
+		.map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+		.collect()
+}
+
+/// Appends an instruction to a section of a module.
+fn instruction(section: &mut Vec<u32>, opcode: u32, operands: &[u32]) {
+	section.push((operands.len() as u32 + 1) << 16 | opcode);
+	section.extend(operands);
+}
+
+/// Finds the number of a named enumerant.
+fn number(values: &[(&str, u32)], name: &str) -> Option<u32> {
+	values
+		.iter()
+		.find_map(|&(value, number)| (value == name).then_some(number))
+}
+
+/// Writes the sections of a module in their logical order.
+struct Writer<'a> {
+	context: &'a Context,
+	/// The bound of the ids of the module, which is the next free one.
+	bound: u32,
+	entry_points: Vec<u32>,
+	execution_modes: Vec<u32>,
+	names: Vec<u32>,
+	decorations: Vec<u32>,
+	/// The types, constants and global variables.
+	globals: Vec<u32>,
+	functions: Vec<u32>,
+	types: HashMap<Key, u32>,
+	/// The constants by type, opcode and value.
+	constants: HashMap<(u32, u32, u32), u32>,
+	/// The global variables and functions, by name and symbol reference.
+	symbols: Vec<(String, Attribute<'a>, u32)>,
+	values: HashMap<Value<'a>, u32>,
+	/// The id of the `GLSL.std.450` instruction set, once it is used.
+	glsl: Option<u32>,
+}
+
+impl<'a> Writer<'a> {
+	fn new(context: &'a Context) -> Self {
+		Self {
+			context,
+			bound: 1,
+			entry_points: Vec::new(),
+			execution_modes: Vec::new(),
+			names: Vec::new(),
+			decorations: Vec::new(),
+			globals: Vec::new(),
+			functions: Vec::new(),
+			types: HashMap::new(),
+			constants: HashMap::new(),
+			symbols: Vec::new(),
+			values: HashMap::new(),
+			glsl: None,
+		}
+	}
+
+	/// Numbers a global variable or a function.
+	fn declare(&mut self, operation: OperationRef<'a>) -> Result<()> {
+		if !matches!(
+			operation_name(operation)?.as_str(),
+			"spirv.GlobalVariable" | "spirv.func"
+		) {
+			return Ok(());
+		}
+
+		let name = string_attribute(operation, "sym_name")?;
+		let reference = Attribute::parse(
+			self.context,
+			&format!("@{}", dialect::string(&name)),
+		)
+		.context("invalid symbol name")?;
+		let id = self.id();
+		self.symbols.push((name, reference, id));
+
+		Ok(())
+	}
+
+	/// Writes an operation of the body of a `spirv.module`.
+	fn global(&mut self, operation: OperationRef<'a>) -> Result<()> {
+		let name = operation_name(operation)?;
+
+		match name.as_str() {
+			"spirv.GlobalVariable" => self.variable(operation),
+			"spirv.func" => self.function(operation),
+			"spirv.EntryPoint" => {
+				let model = self.enumerant(
+					operation,
+					"execution_model",
+					"#spirv.execution_model",
+					MODELS,
+				)?;
+				let (function, name) =
+					self.symbol(attribute(operation, "fn")?)?;
+				let mut operands = vec![model, function];
+				operands.extend(words(&name));
+
+				for variable in attribute(operation, "interface")?
+					.elements()
+					.context("invalid interface")?
+				{
+					operands.push(self.symbol(variable)?.0);
+				}
+
+				instruction(
+					&mut self.entry_points,
+					opcode::ENTRY_POINT,
+					&operands,
+				);
+
+				Ok(())
+			}
+			"spirv.ExecutionMode" => {
+				let (function, _) = self.symbol(attribute(operation, "fn")?)?;
+				let mode = self.enumerant(
+					operation,
+					"execution_mode",
+					"#spirv.execution_mode",
+					MODES,
+				)?;
+				instruction(
+					&mut self.execution_modes,
+					opcode::EXECUTION_MODE,
+					&[function, mode],
+				);
+
+				Ok(())
+			}
+			_ => bail!("{}: cannot serialize '{name}'", operation.location()),
+		}
+	}
+
+	fn variable(&mut self, operation: OperationRef<'a>) -> Result<()> {
+		let name = string_attribute(operation, "sym_name")?;
+		let r#type = attribute(operation, "type")?;
+		let (kind, (class, storage)) = KINDS
+			.iter()
+			.flat_map(|&kind| CLASSES.iter().map(move |&class| (kind, class)))
+			.find(|&(kind, (class, _))| {
+				Attribute::parse(
+					self.context,
+					&pointer(self.context, kind, class),
+				) == Some(r#type)
+			})
+			.with_context(|| format!("unsupported variable type {}", r#type))?;
+		let pointer = self.r#type(Key::Pointer(kind, class));
+		let (id, _) = self.symbol_of(&name)?;
+		instruction(
+			&mut self.globals,
+			opcode::VARIABLE,
+			&[pointer, id, storage],
+		);
+		self.name(id, &name);
+
+		for &(decoration, number) in DECORATIONS {
+			if let Some(value) = operation.attribute(decoration) {
+				let value = value
+					.integer_value()
+					.with_context(|| format!("invalid {decoration}"))?;
+				instruction(
+					&mut self.decorations,
+					opcode::DECORATE,
+					&[id, number, value as u32],
+				);
+			}
+		}
+
+		Ok(())
+	}
+
+	fn function(&mut self, operation: OperationRef<'a>) -> Result<()> {
+		let name = string_attribute(operation, "sym_name")?;
+		let (id, _) = self.symbol_of(&name)?;
+		let void = self.r#type(Key::Void);
+		let r#type = self.r#type(Key::Function);
+		let block = operation
+			.region(0)
+			.and_then(|region| region.first_block())
+			.context("function has no body")?;
+
+		if block.next_in_region().is_some() {
+			bail!("functions of several blocks are not supported");
+		}
+
+		// The function control is `None`.
+		instruction(
+			&mut self.functions,
+			opcode::FUNCTION,
+			&[void, id, 0, r#type],
+		);
+		self.name(id, &name);
+		let label = self.id();
+		instruction(&mut self.functions, opcode::LABEL, &[label]);
+
+		for operation in operations(block.first_operation()) {
+			self.operation(operation)?;
+		}
+
+		instruction(&mut self.functions, opcode::FUNCTION_END, &[]);
+
+		Ok(())
+	}
+
+	/// Writes an operation of the body of a function.
+	fn operation(&mut self, operation: OperationRef<'a>) -> Result<()> {
+		let name = operation_name(operation)?;
+		let operands = (0..operation.operand_count())
+			.map(|index| self.value(operation.operand(index)?))
+			.collect::<Result<Vec<_>>>()?;
+
+		match name.as_str() {
+			"spirv.mlir.addressof" => {
+				let (id, _) = self.symbol(attribute(operation, "variable")?)?;
+				self.values.insert(operation.result(0)?.into(), id);
+			}
+			"spirv.Constant" => {
+				let id = self.constant(operation)?;
+				self.values.insert(operation.result(0)?.into(), id);
+			}
+			"spirv.Store" => {
+				instruction(&mut self.functions, opcode::STORE, &operands)
+			}
+			"spirv.Return" => {
+				instruction(&mut self.functions, opcode::RETURN, &[])
+			}
+			name => {
+				let (opcode, mut prefix) =
+					match (number(INSTRUCTIONS, name), number(GLSL, name)) {
+						(Some(opcode), _) => (opcode, vec![]),
+						(_, Some(number)) => {
+							(opcode::EXT_INST, vec![self.glsl(), number])
+						}
+						_ => bail!(
+							"{}: cannot serialize '{name}'",
+							operation.location()
+						),
+					};
+				let result: Value = operation.result(0)?.into();
+				let r#type = self.r#type(Key::Kind(self.kind(result)?));
+				let id = self.id();
+				prefix.extend(operands);
+				instruction(
+					&mut self.functions,
+					opcode,
+					&[&[r#type, id], &prefix[..]].concat(),
+				);
+				self.values.insert(result, id);
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Declares a scalar constant, unless an equal one is declared.
+	fn constant(&mut self, operation: OperationRef<'a>) -> Result<u32> {
+		let kind = self.kind(operation.result(0)?.into())?;
+		let value = attribute(operation, "value")?;
+		let (opcode, word) = match kind {
+			Kind::Bool => match value.bool_value() {
+				Some(true) => (opcode::CONSTANT_TRUE, None),
+				Some(false) => (opcode::CONSTANT_FALSE, None),
+				None => bail!("invalid Boolean constant"),
+			},
+			Kind::Int => (
+				opcode::CONSTANT,
+				Some(
+					value.integer_value().context("invalid integer constant")?
+						as i32 as u32,
+				),
+			),
+			Kind::Float => (
+				opcode::CONSTANT,
+				Some(
+					(value.float_value().context("invalid float constant")?
+						as f32)
+						.to_bits(),
+				),
+			),
+			kind => {
+				bail!("constants of type {} are not supported", kind.name())
+			}
+		};
+		let r#type = self.r#type(Key::Kind(kind));
+		let key = (r#type, opcode, word.unwrap_or_default());
+
+		if let Some(&id) = self.constants.get(&key) {
+			return Ok(id);
+		}
+
+		let id = self.id();
+		instruction(
+			&mut self.globals,
+			opcode,
+			&[&[r#type, id], &word.into_iter().collect::<Vec<_>>()[..]]
+				.concat(),
+		);
+		self.constants.insert(key, id);
+
+		Ok(id)
+	}
+
+	/// Declares a type unless it is declared.
+	fn r#type(&mut self, key: Key) -> u32 {
+		if let Some(&id) = self.types.get(&key) {
+			return id;
+		}
+
+		let (opcode, operands) = match key {
+			Key::Void => (opcode::TYPE_VOID, vec![]),
+			Key::Function => {
+				(opcode::TYPE_FUNCTION, vec![self.r#type(Key::Void)])
+			}
+			Key::Kind(Kind::Bool) => (opcode::TYPE_BOOL, vec![]),
+			// Integers are signed and of 32 bits.
+			Key::Kind(Kind::Int) => (opcode::TYPE_INT, vec![32, 1]),
+			Key::Kind(Kind::Float) => (opcode::TYPE_FLOAT, vec![32]),
+			Key::Kind(Kind::Vector(size)) => (
+				opcode::TYPE_VECTOR,
+				vec![self.r#type(Key::Kind(Kind::Float)), size],
+			),
+			Key::Kind(Kind::Sampler2D) => {
+				(opcode::TYPE_SAMPLED_IMAGE, vec![self.r#type(Key::Image)])
+			}
+			// The dimension is `2D`, the image is neither a depth image, nor
+			// arrayed, nor multisampled, it is sampled and its format is
+			// `Unknown`.
+			Key::Image => (
+				opcode::TYPE_IMAGE,
+				vec![self.r#type(Key::Kind(Kind::Float)), 1, 0, 0, 0, 1, 0],
+			),
+			Key::Pointer(kind, class) => (
+				opcode::TYPE_POINTER,
+				vec![
+					number(CLASSES, class).unwrap_or_default(),
+					self.r#type(Key::Kind(kind)),
+				],
+			),
+		};
+		let id = self.id();
+		instruction(
+			&mut self.globals,
+			opcode,
+			&[&[id], &operands[..]].concat(),
+		);
+		self.types.insert(key, id);
+
+		id
+	}
+
+	/// Finds the type of a value.
+	fn kind(&self, value: Value) -> Result<Kind> {
+		let r#type = value.r#type();
+
+		KINDS
+			.iter()
+			.copied()
+			.find(|kind| kind.r#type(self.context) == r#type)
+			.with_context(|| format!("unsupported type {}", r#type))
+	}
+
+	/// Finds the number of an enumerant attribute, as
+	/// `#spirv.execution_model<Fragment>`.
+	fn enumerant(
+		&self,
+		operation: OperationRef,
+		name: &str,
+		prefix: &str,
+		values: &[(&str, u32)],
+	) -> Result<u32> {
+		let attribute = attribute(operation, name)?;
+
+		values
+			.iter()
+			.find_map(|&(value, number)| {
+				(Attribute::parse(self.context, &format!("{prefix}<{value}>"))
+					== Some(attribute))
+				.then_some(number)
+			})
+			.with_context(|| format!("unsupported {name} {attribute}"))
+	}
+
+	fn symbol(&self, reference: Attribute) -> Result<(u32, String)> {
+		self.symbols
+			.iter()
+			.find(|(_, symbol, _)| *symbol == reference)
+			.map(|(name, _, id)| (*id, name.clone()))
+			.with_context(|| format!("undefined symbol {reference}"))
+	}
+
+	fn symbol_of(&self, name: &str) -> Result<(u32, String)> {
+		self.symbols
+			.iter()
+			.find(|(symbol, ..)| symbol == name)
+			.map(|(name, _, id)| (*id, name.clone()))
+			.with_context(|| format!("undefined symbol '{name}'"))
+	}
+
+	fn value(&self, value: Value) -> Result<u32> {
+		self.values
+			.get(&value)
+			.copied()
+			.context("value used before it is defined")
+	}
+
+	fn glsl(&mut self) -> u32 {
+		if let Some(id) = self.glsl {
+			return id;
+		}
+
+		let id = self.id();
+		self.glsl = Some(id);
+
+		id
+	}
+
+	fn name(&mut self, id: u32, name: &str) {
+		instruction(
+			&mut self.names,
+			opcode::NAME,
+			&[&[id], &words(name)[..]].concat(),
+		);
+	}
+
+	fn id(&mut self) -> u32 {
+		self.bound += 1;
+		self.bound - 1
+	}
+
+	/// Writes the module, with its header and its capabilities.
+	fn finish(self) -> Vec<u8> {
+		let mut module = vec![MAGIC, VERSION, 0, self.bound, 0];
+
+		// The capability is `Shader`.
+		instruction(&mut module, opcode::CAPABILITY, &[1]);
+
+		if let Some(glsl) = self.glsl {
+			instruction(
+				&mut module,
+				opcode::EXT_INST_IMPORT,
+				&[&[glsl], &words("GLSL.std.450")[..]].concat(),
+			);
+		}
+
+		// The addressing model is `Logical` and the memory model `GLSL450`.
+		instruction(&mut module, opcode::MEMORY_MODEL, &[0, 1]);
+
+		for section in [
+			self.entry_points,
+			self.execution_modes,
+			self.names,
+			self.decorations,
+			self.globals,
+			self.functions,
+		] {
+			module.extend(section);
+		}
+
+		module.iter().flat_map(|word| word.to_le_bytes()).collect()
+	}
+}
+
+fn attribute<'a>(
+	operation: OperationRef<'a>,
+	name: &str,
+) -> Result<Attribute<'a>> {
+	operation
+		.attribute(name)
+		.with_context(|| format!("requires a '{name}' attribute"))
+}
+
+fn string_attribute(operation: OperationRef, name: &str) -> Result<String> {
+	Ok(attribute(operation, name)?
+		.string_value()
+		.with_context(|| format!("'{name}' is not a string"))?
+		.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{shader, source::Source};
+	use melior::{dialect::Registry, utility::register_all_dialects};
+
+	/// Splits a binary into its header and instructions.
+	fn instructions(binary: &[u8]) -> (Vec<u32>, Vec<Vec<u32>>) {
+		let words = binary
+			.chunks_exact(4)
+			.map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+			.collect::<Vec<_>>();
+		let mut instructions = Vec::new();
+		let mut rest = &words[5..];
+
+		while let Some(&first) = rest.first() {
+			let (instruction, next) = rest.split_at((first >> 16) as usize);
+			instructions.push(instruction.to_vec());
+			rest = next;
+		}
+
+		(words[..5].to_vec(), instructions)
+	}
+
+	#[test]
+	fn serialize_fragment_shader() {
+		let registry = Registry::new();
+		register_all_dialects(&registry);
+
+		let context = Context::new();
+		context.append_dialect_registry(&registry);
+		context.load_all_available_dialects();
+
+		let text = include_str!("../test/gpu.nix");
+		let root = rnix::Root::parse(text).tree();
+		let mut module =
+			shader::lower(&context, &Source::new("gpu.nix", text), &root)
+				.unwrap();
+		shader::convert(&context, &mut module).unwrap();
+		let (header, instructions) =
+			instructions(&serialize(&context, &module).unwrap());
+		let find = |opcode: u32| {
+			instructions
+				.iter()
+				.find(|instruction| instruction[0] & 0xffff == opcode)
+				.unwrap()
+		};
+
+		assert_eq!(header[..3], [MAGIC, VERSION, 0]);
+		assert_eq!(find(opcode::CAPABILITY)[1..], [1]);
+
+		let entry_point = find(opcode::ENTRY_POINT);
+		assert_eq!(entry_point[1], 4);
+		assert_eq!(entry_point[3..5], words("main"));
+		// The interface lists the input and the output.
+		assert_eq!(entry_point.len(), 7);
+
+		// The image is sampled at the coordinates loaded from the input.
+		let sample = find(87);
+		let load = |id: u32| {
+			instructions.iter().any(|instruction| {
+				instruction[0] & 0xffff == 61 && instruction[2] == id
+			})
+		};
+		assert_eq!(sample.len(), 5);
+		assert!(load(sample[3]) && load(sample[4]));
+	}
+
+	#[test]
+	fn encode_strings() {
+		assert_eq!(words("main"), [0x6e69_616d, 0]);
+		assert_eq!(words("GLSL.std.450").len(), 4);
+		assert_eq!(words(""), [0]);
+	}
+}
@@ -0,0 +1,43 @@
+//! Native executables, built by the `nix` binary and linked with the runtime
+//! next to it.
+
+use std::{env, fs, process::Command};
+
+#[test]
+fn build_executables() {
+	let directory = env::temp_dir().join("mlnx-build-executables");
+	let executable = directory.join("arithmetic");
+	fs::create_dir_all(&directory).unwrap();
+
+	let output = Command::new(env!("CARGO_BIN_EXE_nix"))
+		.args(["emit-obj", "../test/arithmetic.nix"])
+		.current_dir(env!("CARGO_MANIFEST_DIR"))
+		.output()
+		.unwrap();
+
+	assert!(
+		output.status.success(),
+		"emit-obj failed:\n{}",
+		String::from_utf8_lossy(&output.stderr)
+	);
+	assert!(!output.stdout.is_empty(), "emit-obj wrote no object file");
+
+	let output = Command::new(env!("CARGO_BIN_EXE_nix"))
+		.args(["build", "-o"])
+		.arg(&executable)
+		.arg("../test/arithmetic.nix")
+		.current_dir(env!("CARGO_MANIFEST_DIR"))
+		.output()
+		.unwrap();
+
+	assert!(
+		output.status.success(),
+		"build failed:\n{}",
+		String::from_utf8_lossy(&output.stderr)
+	);
+
+	let output = Command::new(&executable).output().unwrap();
+
+	assert!(output.status.success());
+	assert_eq!(String::from_utf8_lossy(&output.stdout).trim_end(), "3");
+}
@@ -0,0 +1,76 @@
+//! The cache of lowered files, across runs of the `nix` binary on files in a
+//! fresh directory.
+
+use std::{
+	env, fs,
+	path::{Path, PathBuf},
+	process::Command,
+};
+
+#[test]
+fn hit_and_miss_entries() {
+	let directory =
+		env::temp_dir().join(format!("mlnx-cache-{}", std::process::id()));
+	let cache = directory.join("cache");
+	let _ = fs::remove_dir_all(&directory);
+	fs::create_dir_all(&directory).unwrap();
+	fs::write(directory.join("a.nix"), "import ./b.nix + 1").unwrap();
+	fs::write(directory.join("b.nix"), "41").unwrap();
+
+	// Both files miss, and are cached.
+	assert_eq!(run(&directory, &cache), "42");
+	assert_eq!(entries(&cache).len(), 2);
+
+	// Unchanged files hit, which a doctored entry shows.
+	let (path, entry) = entries(&cache)
+		.into_iter()
+		.find(|(_, entry)| entry.contains("value = 41 : i64"))
+		.unwrap();
+	fs::write(path, entry.replace("value = 41 : i64", "value = 99 : i64"))
+		.unwrap();
+
+	assert_eq!(run(&directory, &cache), "100");
+	assert_eq!(entries(&cache).len(), 2);
+
+	// A changed file misses, and gets an entry of its own.
+	fs::write(directory.join("b.nix"), "1").unwrap();
+
+	assert_eq!(run(&directory, &cache), "2");
+	assert_eq!(entries(&cache).len(), 3);
+
+	fs::remove_dir_all(&directory).unwrap();
+}
+
+/// Runs `a.nix` with a cache.
+fn run(directory: &Path, cache: &Path) -> String {
+	let output = Command::new(env!("CARGO_BIN_EXE_nix"))
+		.args(["run", "--cache"])
+		.arg(cache)
+		.arg("a.nix")
+		.current_dir(directory)
+		.output()
+		.unwrap();
+
+	assert!(
+		output.status.success(),
+		"run failed:\n{}",
+		String::from_utf8_lossy(&output.stderr)
+	);
+
+	String::from_utf8_lossy(&output.stdout)
+		.trim_end()
+		.to_owned()
+}
+
+/// Reads the entries of a cache, with their paths.
+fn entries(cache: &Path) -> Vec<(PathBuf, String)> {
+	fs::read_dir(cache)
+		.unwrap()
+		.map(|entry| {
+			let path = entry.unwrap().path();
+			let entry = fs::read_to_string(&path).unwrap();
+
+			(path, entry)
+		})
+		.collect()
+}
@@ -0,0 +1,122 @@
+//! Golden tests of the compiler.
+//!
+//! Every `.nix` file under `src/test` is compiled by the `nix` binary and its
+//! outputs are checked against the snapshots of the file in `snapshots`,
+//! named after the stage they hold:
+//!
+//! | Stage      | Contents                                    |
+//! |------------|---------------------------------------------|
+//! | `mlir`     | the module in the nix dialect (`emit-mlir`) |
+//! | `expected` | the value of the file (`run`)               |
+//! | `err`      | the error the compiler fails with (`run`)   |
+//!
+//! A file is checked at the stages it has snapshots of, and fails the test if
+//! it has none unless it is one of the [`FIXTURES`] of other tests. A stage is
+//! added to a file by creating an empty snapshot of it, which
+//! `cargo insta review` then fills in, and changed outputs are reviewed the
+//! same way.
+//!
+//! Files are compiled by their absolute paths, so that every diagnostic and
+//! location names them the same way, and the test directory is replaced by
+//! `$TEST` in outputs. Trailing whitespace is ignored. Files are lowered
+//! without the cache, which `cache.rs` tests on its own.
+
+use std::{
+	fs,
+	path::Path,
+	process::{Command, Output},
+};
+
+/// The stages of snapshots, with the arguments of the command whose output
+/// they hold.
+const STAGES: &[(&str, &[&str])] = &[
+	("mlir", &["emit-mlir"]),
+	("expected", &["run"]),
+	("err", &["run"]),
+];
+
+/// The files without snapshots, such as shaders, which other tests use.
+const FIXTURES: &[&str] = &["gpu.nix"];
+
+#[test]
+fn golden() {
+	insta::glob!("../../test", "**/*.nix", check);
+}
+
+fn check(path: &Path) {
+	let path = fs::canonicalize(path).unwrap();
+	let snapshots =
+		Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots");
+	let name = path.file_name().unwrap().to_string_lossy();
+	let stages = STAGES
+		.iter()
+		.filter(|(stage, _)| {
+			snapshots
+				.join(format!("golden__{stage}@{name}.snap"))
+				.exists()
+		})
+		.collect::<Vec<_>>();
+
+	if FIXTURES.iter().any(|fixture| *fixture == name) {
+		assert!(stages.is_empty(), "fixture {name} has snapshots");
+		return;
+	}
+
+	assert!(
+		!stages.is_empty(),
+		"{name} has no snapshots, create an empty one of a stage and \
+		 accept it with `cargo insta review`"
+	);
+
+	for (stage, arguments) in stages {
+		let output = run(&path, arguments);
+		let error = *stage == "err";
+
+		if output.status.success() == error {
+			panic!(
+				"unexpected {} running {} on {}:\n{}",
+				if error { "success" } else { "failure" },
+				arguments.join(" "),
+				path.display(),
+				String::from_utf8_lossy(&output.stderr)
+			);
+		}
+
+		let actual = normalize(
+			&path,
+			if error {
+				&output.stderr
+			} else {
+				&output.stdout
+			},
+		);
+
+		insta::assert_snapshot!(*stage, actual);
+	}
+}
+
+/// Runs the compiler on a file, from its directory.
+fn run(path: &Path, arguments: &[&str]) -> Output {
+	Command::new(env!("CARGO_BIN_EXE_nix"))
+		.args(arguments)
+		.arg("--no-cache")
+		.arg(path)
+		.current_dir(path.parent().unwrap())
+		.env("RUST_BACKTRACE", "0")
+		.env("RUST_LIB_BACKTRACE", "0")
+		.output()
+		.unwrap()
+}
+
+fn normalize(path: &Path, output: &[u8]) -> String {
+	let directory = fs::canonicalize(path.parent().unwrap()).unwrap();
+
+	String::from_utf8_lossy(output)
+		.replace(&*directory.to_string_lossy(), "$TEST")
+		.lines()
+		.map(str::trim_end)
+		.collect::<Vec<_>>()
+		.join("\n")
+		.trim_end()
+		.to_owned()
+}
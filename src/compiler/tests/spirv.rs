@@ -0,0 +1,38 @@
+//! Validation of the SPIR-V binaries of shaders with `spirv-val`.
+//!
+//! The test is skipped if `spirv-val`, from SPIRV-Tools, is not installed.
+
+use std::{fs, io, process::Command};
+
+#[test]
+fn validate_shaders() {
+	let output = Command::new(env!("CARGO_BIN_EXE_nix"))
+		.args(["emit-spirv", "../test/gpu.nix"])
+		.current_dir(env!("CARGO_MANIFEST_DIR"))
+		.output()
+		.unwrap();
+
+	assert!(
+		output.status.success(),
+		"emit-spirv failed:\n{}",
+		String::from_utf8_lossy(&output.stderr)
+	);
+
+	let path = std::env::temp_dir().join("mlnx-validate-shaders.spv");
+	fs::write(&path, &output.stdout).unwrap();
+
+	let validation = match Command::new("spirv-val").arg(&path).output() {
+		Ok(validation) => validation,
+		Err(error) if error.kind() == io::ErrorKind::NotFound => {
+			eprintln!("spirv-val is not installed, skipping validation");
+			return;
+		}
+		Err(error) => panic!("failed to run spirv-val: {error}"),
+	};
+
+	assert!(
+		validation.status.success(),
+		"spirv-val rejected the shader:\n{}",
+		String::from_utf8_lossy(&validation.stderr)
+	);
+}
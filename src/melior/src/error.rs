@@ -13,6 +13,7 @@ pub enum Error {
     FunctionResultPosition(String, usize),
     InvokeFunction,
     MemRefExpected(String),
+    OperationOperandPosition(String, usize),
     OperationResultExpected(String),
     OperationResultPosition(String, usize),
     ParsePassPipeline(String),
@@ -45,6 +46,12 @@ impl Display for Error {
             ),
             Self::InvokeFunction => write!(formatter, "failed to invoke JIT-compiled function"),
             Self::MemRefExpected(r#type) => write!(formatter, "mem-ref expected: {type}"),
+            Self::OperationOperandPosition(operation, position) => {
+                write!(
+                    formatter,
+                    "operation operand position {position} out of range: {operation}"
+                )
+            }
             Self::OperationResultExpected(value) => {
                 write!(formatter, "operation result expected: {value}")
             }
@@ -1,6 +1,6 @@
 use crate::mlir_sys::{
     mlirExecutionEngineCreate, mlirExecutionEngineDestroy, mlirExecutionEngineInvokePacked,
-    MlirExecutionEngine,
+    mlirExecutionEngineLookup, mlirExecutionEngineRegisterSymbol, MlirExecutionEngine,
 };
 use crate::{ir::Module, logical_result::LogicalResult, string_ref::StringRef, Error};
 use std::ffi::c_void;
@@ -66,6 +66,32 @@ impl ExecutionEngine {
             Err(Error::InvokeFunction)
         }
     }
+
+    /// Looks up the address of a function in a module, which is null if the
+    /// function is not found.
+    ///
+    /// Unlike [`invoke_packed`](Self::invoke_packed), calling the function
+    /// directly lets panics of registered symbols declared with the
+    /// `C-unwind` ABI unwind through JIT-compiled frames to the caller, as the
+    /// engine registers the unwind tables of the code it compiles.
+    pub fn lookup(&self, name: &str) -> *mut () {
+        unsafe { mlirExecutionEngineLookup(self.raw, StringRef::from(name).to_raw()) as *mut () }
+    }
+
+    /// Registers a symbol with an address, so that JIT-compiled code can call
+    /// functions of the host process which are not exported dynamically.
+    ///
+    /// # Safety
+    ///
+    /// The address must stay valid and match the declaration of the symbol
+    /// in the module for as long as compiled code may use it.
+    pub unsafe fn register_symbol(&self, name: &str, address: *mut ()) {
+        mlirExecutionEngineRegisterSymbol(
+            self.raw,
+            StringRef::from(name).to_raw(),
+            address as *mut c_void,
+        )
+    }
 }
 
 impl Drop for ExecutionEngine {
@@ -135,4 +161,156 @@ mod tests {
         assert_eq!(argument, 42);
         assert_eq!(result, 84);
     }
+
+    #[test]
+    fn lookup() {
+        let registry = dialect::Registry::new();
+        register_all_dialects(&registry);
+
+        let context = Context::new();
+        context.append_dialect_registry(&registry);
+        register_all_llvm_translations(&context);
+
+        let mut module = Module::parse(
+            &context,
+            r#"
+            module {
+                func.func @add(%arg0 : i32) -> i32 {
+                    %res = arith.addi %arg0, %arg0 : i32
+                    return %res : i32
+                }
+            }
+            "#,
+        )
+        .unwrap();
+
+        let pass_manager = pass::Manager::new(&context);
+        pass_manager.add_pass(pass::conversion::convert_func_to_llvm());
+
+        pass_manager
+            .nested_under("func.func")
+            .add_pass(pass::conversion::convert_arithmetic_to_llvm());
+
+        assert_eq!(pass_manager.run(&mut module), Ok(()));
+
+        let engine = ExecutionEngine::new(&module, 2, &[], false);
+        let add = engine.lookup("add");
+
+        assert!(!add.is_null());
+        assert!(engine.lookup("sub").is_null());
+
+        let add = unsafe { std::mem::transmute::<*mut (), extern "C" fn(i32) -> i32>(add) };
+
+        assert_eq!(add(42), 84);
+    }
+
+    #[test]
+    fn unwind_through_compiled_code() {
+        extern "C-unwind" fn fail(_: i32) -> i32 {
+            panic!("failed in a registered symbol");
+        }
+
+        let registry = dialect::Registry::new();
+        register_all_dialects(&registry);
+
+        let context = Context::new();
+        context.append_dialect_registry(&registry);
+        register_all_llvm_translations(&context);
+
+        let mut module = Module::parse(
+            &context,
+            r#"
+            module {
+                func.func private @fail(i32) -> i32
+
+                func.func @call(%arg0 : i32) -> i32 {
+                    %0 = func.call @fail(%arg0) : (i32) -> i32
+                    %1 = arith.addi %0, %arg0 : i32
+                    return %1 : i32
+                }
+            }
+            "#,
+        )
+        .unwrap();
+
+        let pass_manager = pass::Manager::new(&context);
+        pass_manager.add_pass(pass::conversion::convert_func_to_llvm());
+
+        pass_manager
+            .nested_under("func.func")
+            .add_pass(pass::conversion::convert_arithmetic_to_llvm());
+
+        assert_eq!(pass_manager.run(&mut module), Ok(()));
+
+        let engine = ExecutionEngine::new(&module, 2, &[], false);
+
+        unsafe { engine.register_symbol("fail", fail as *mut ()) };
+
+        let call = unsafe {
+            std::mem::transmute::<*mut (), extern "C-unwind" fn(i32) -> i32>(engine.lookup("call"))
+        };
+        let error = std::panic::catch_unwind(|| call(1)).unwrap_err();
+
+        assert_eq!(
+            error.downcast_ref::<&str>(),
+            Some(&"failed in a registered symbol")
+        );
+    }
+
+    #[test]
+    fn register_symbol() {
+        extern "C" fn double(value: i32) -> i32 {
+            value * 2
+        }
+
+        let registry = dialect::Registry::new();
+        register_all_dialects(&registry);
+
+        let context = Context::new();
+        context.append_dialect_registry(&registry);
+        register_all_llvm_translations(&context);
+
+        let mut module = Module::parse(
+            &context,
+            r#"
+            module {
+                func.func private @double(i32) -> i32
+
+                func.func @quadruple(%arg0 : i32) -> i32 attributes { llvm.emit_c_interface } {
+                    %0 = func.call @double(%arg0) : (i32) -> i32
+                    %1 = func.call @double(%0) : (i32) -> i32
+                    return %1 : i32
+                }
+            }
+            "#,
+        )
+        .unwrap();
+
+        let pass_manager = pass::Manager::new(&context);
+        pass_manager.add_pass(pass::conversion::convert_func_to_llvm());
+
+        assert_eq!(pass_manager.run(&mut module), Ok(()));
+
+        let engine = ExecutionEngine::new(&module, 2, &[], false);
+
+        unsafe { engine.register_symbol("double", double as *mut ()) };
+
+        let mut argument = 21;
+        let mut result = -1;
+
+        assert_eq!(
+            unsafe {
+                engine.invoke_packed(
+                    "quadruple",
+                    &mut [
+                        &mut argument as *mut i32 as *mut (),
+                        &mut result as *mut i32 as *mut (),
+                    ],
+                )
+            },
+            Ok(())
+        );
+
+        assert_eq!(result, 84);
+    }
 }
@@ -3,14 +3,16 @@ use r#type::TypeLike;
 
 use super::{r#type, Type};
 use crate::mlir_sys::{
-    mlirAttributeDump, mlirAttributeEqual, mlirAttributeGetContext, mlirAttributeGetNull,
-    mlirAttributeGetType, mlirAttributeGetTypeID, mlirAttributeIsAAffineMap, mlirAttributeIsAArray,
-    mlirAttributeIsABool, mlirAttributeIsADenseElements, mlirAttributeIsADenseFPElements,
+    mlirArrayAttrGetElement, mlirArrayAttrGetNumElements, mlirAttributeDump, mlirAttributeEqual,
+    mlirAttributeGetContext, mlirAttributeGetNull, mlirAttributeGetType, mlirAttributeGetTypeID,
+    mlirAttributeIsAAffineMap, mlirAttributeIsAArray, mlirAttributeIsABool,
+    mlirAttributeIsADenseElements, mlirAttributeIsADenseFPElements,
     mlirAttributeIsADenseIntElements, mlirAttributeIsADictionary, mlirAttributeIsAElements,
     mlirAttributeIsAFloat, mlirAttributeIsAInteger, mlirAttributeIsAIntegerSet,
     mlirAttributeIsAOpaque, mlirAttributeIsASparseElements, mlirAttributeIsAString,
     mlirAttributeIsASymbolRef, mlirAttributeIsAType, mlirAttributeIsAUnit, mlirAttributeParseGet,
-    mlirAttributePrint, MlirAttribute,
+    mlirAttributePrint, mlirBoolAttrGetValue, mlirFloatAttrGetValueDouble,
+    mlirIntegerAttrGetValueInt, mlirStringAttrGetValue, MlirAttribute,
 };
 use crate::{
     context::{Context, ContextRef},
@@ -21,6 +23,7 @@ use std::{
     ffi::c_void,
     fmt::{self, Debug, Display, Formatter},
     marker::PhantomData,
+    slice, str,
 };
 
 /// An attribute.
@@ -170,6 +173,54 @@ impl<'c> Attribute<'c> {
         !self.is_null() && unsafe { mlirAttributeIsAUnit(self.raw) }
     }
 
+    /// Gets the elements of an array attribute.
+    pub fn elements(&self) -> Option<Vec<Self>> {
+        self.is_array().then(|| unsafe {
+            (0..mlirArrayAttrGetNumElements(self.raw))
+                .map(|index| Self::from_raw(mlirArrayAttrGetElement(self.raw, index)))
+                .collect()
+        })
+    }
+
+    /// Gets the value of a Boolean attribute.
+    pub fn bool_value(&self) -> Option<bool> {
+        self.is_bool()
+            .then(|| unsafe { mlirBoolAttrGetValue(self.raw) })
+    }
+
+    /// Gets the value of a float attribute.
+    pub fn float_value(&self) -> Option<f64> {
+        self.is_float()
+            .then(|| unsafe { mlirFloatAttrGetValueDouble(self.raw) })
+    }
+
+    /// Gets the value of an integer attribute.
+    pub fn integer_value(&self) -> Option<i64> {
+        self.is_integer()
+            .then(|| unsafe { mlirIntegerAttrGetValueInt(self.raw) })
+    }
+
+    /// Gets the value of a string attribute, if it is valid UTF-8.
+    pub fn string_value(&self) -> Option<&'c str> {
+        if !self.is_string() {
+            return None;
+        }
+
+        unsafe {
+            let string = mlirStringAttrGetValue(self.raw);
+
+            if string.length == 0 {
+                Some("")
+            } else {
+                str::from_utf8(slice::from_raw_parts(
+                    string.data as *const u8,
+                    string.length,
+                ))
+                .ok()
+            }
+        }
+    }
+
     /// Gets a context.
     pub fn context(&self) -> ContextRef<'c> {
         unsafe { ContextRef::from_raw(mlirAttributeGetContext(self.raw)) }
@@ -308,6 +359,56 @@ mod tests {
         assert!(Attribute::null().is_null());
     }
 
+    #[test]
+    fn elements() {
+        let context = Context::new();
+
+        assert_eq!(
+            Attribute::parse(&context, "[1, 2]").unwrap().elements(),
+            Some(vec![
+                Attribute::parse(&context, "1").unwrap(),
+                Attribute::parse(&context, "2").unwrap()
+            ])
+        );
+        assert_eq!(Attribute::parse(&context, "1").unwrap().elements(), None);
+    }
+
+    #[test]
+    fn values() {
+        let context = Context::new();
+
+        assert_eq!(
+            Attribute::parse(&context, "true").unwrap().bool_value(),
+            Some(true)
+        );
+        assert_eq!(
+            Attribute::parse(&context, "1.5 : f64")
+                .unwrap()
+                .float_value(),
+            Some(1.5)
+        );
+        assert_eq!(
+            Attribute::parse(&context, "-42 : i64")
+                .unwrap()
+                .integer_value(),
+            Some(-42)
+        );
+        assert_eq!(
+            Attribute::parse(&context, r#""foo""#)
+                .unwrap()
+                .string_value(),
+            Some("foo")
+        );
+        assert_eq!(
+            Attribute::parse(&context, "\"\"").unwrap().string_value(),
+            Some("")
+        );
+        assert_eq!(
+            Attribute::parse(&context, "42").unwrap().string_value(),
+            None
+        );
+    }
+
     #[test]
     fn is_array() {
         assert!(Attribute::parse(&Context::new(), "[]").unwrap().is_array());
@@ -259,6 +259,57 @@ impl<'c> BlockRef<'c> {
             Some(Self::from_raw(raw))
         }
     }
+
+    /// Gets an argument at a position.
+    ///
+    /// Unlike [`Block::argument`], the returned argument borrows the region
+    /// owning the block rather than this reference.
+    pub fn argument(self, position: usize) -> Result<Argument<'c>, Error> {
+        unsafe {
+            if position < self.argument_count() {
+                Ok(Argument::from_raw(mlirBlockGetArgument(
+                    self.raw,
+                    position as isize,
+                )))
+            } else {
+                Err(Error::BlockArgumentPosition(self.to_string(), position))
+            }
+        }
+    }
+
+    /// Gets the first operation.
+    pub fn first_operation(self) -> Option<OperationRef<'c>> {
+        unsafe { OperationRef::from_option_raw(mlirBlockGetFirstOperation(self.raw)) }
+    }
+
+    /// Inserts an operation before another.
+    ///
+    /// Unlike [`Block::insert_operation_before`], the returned operation
+    /// borrows the region owning the block rather than this reference.
+    pub fn insert_operation_before(self, one: OperationRef, other: Operation) -> OperationRef<'c> {
+        unsafe {
+            let other = other.into_raw();
+
+            mlirBlockInsertOwnedOperationBefore(self.raw, one.to_raw(), other);
+
+            OperationRef::from_raw(other)
+        }
+    }
+
+    /// Gets the terminator operation.
+    pub fn terminator(self) -> Option<OperationRef<'c>> {
+        unsafe { OperationRef::from_option_raw(mlirBlockGetTerminator(self.raw)) }
+    }
+
+    /// Gets the operation whose region contains the block.
+    pub fn parent_operation(self) -> Option<OperationRef<'c>> {
+        unsafe { OperationRef::from_option_raw(mlirBlockGetParentOperation(self.raw)) }
+    }
+
+    /// Gets a next block in a region.
+    pub fn next_in_region(self) -> Option<Self> {
+        unsafe { Self::from_option_raw(mlirBlockGetNextInRegion(self.raw)) }
+    }
 }
 
 impl<'a> Deref for BlockRef<'a> {
@@ -19,7 +19,7 @@ impl<'a> Argument<'a> {
         unsafe { mlirBlockArgumentGetArgNumber(self.value.to_raw()) as usize }
     }
 
-    pub fn owner(&self) -> BlockRef {
+    pub fn owner(&self) -> BlockRef<'a> {
         unsafe { BlockRef::from_raw(mlirBlockArgumentGetOwner(self.value.to_raw())) }
     }
 
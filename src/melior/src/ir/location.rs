@@ -1,6 +1,7 @@
 use crate::mlir_sys::{
-    mlirLocationEqual, mlirLocationFileLineColGet, mlirLocationFusedGet, mlirLocationGetContext,
-    mlirLocationNameGet, mlirLocationPrint, mlirLocationUnknownGet, MlirLocation,
+    mlirEmitError, mlirLocationEqual, mlirLocationFileLineColGet, mlirLocationFusedGet,
+    mlirLocationGetContext, mlirLocationNameGet, mlirLocationPrint, mlirLocationUnknownGet,
+    MlirLocation,
 };
 use crate::{
     context::{Context, ContextRef},
@@ -9,7 +10,7 @@ use crate::{
     utility::{into_raw_array, print_callback},
 };
 use std::{
-    ffi::c_void,
+    ffi::{c_void, CString},
     fmt::{self, Display, Formatter},
     marker::PhantomData,
 };
@@ -67,6 +68,15 @@ impl<'c> Location<'c> {
         unsafe { ContextRef::from_raw(mlirLocationGetContext(self.raw)) }
     }
 
+    /// Emits an error diagnostic at the location, which the handlers of the
+    /// context receive, or which is printed to the standard error stream if it
+    /// has none.
+    pub fn emit_error(&self, message: &str) {
+        let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+
+        unsafe { mlirEmitError(self.raw, message.as_ptr()) }
+    }
+
     pub(crate) unsafe fn from_raw(raw: MlirLocation) -> Self {
         Self {
             raw,
@@ -163,6 +173,13 @@ mod tests {
         );
     }
 
+    #[test]
+    fn emit_error() {
+        let context = Context::new();
+
+        Location::new(&context, "foo", 42, 42).emit_error("bar\0baz");
+    }
+
     #[test]
     fn display() {
         let context = Context::new();
@@ -4,17 +4,21 @@ mod builder;
 mod result;
 
 pub use self::{builder::Builder, result::ResultValue};
-use super::{BlockRef, Identifier, RegionRef, Value};
+use super::{Attribute, BlockRef, Identifier, Location, RegionRef, Value, ValueLike};
 use crate::mlir_sys::{
     mlirOpPrintingFlagsCreate, mlirOpPrintingFlagsEnableDebugInfo, mlirOperationClone,
-    mlirOperationDestroy, mlirOperationDump, mlirOperationEqual, mlirOperationGetBlock,
-    mlirOperationGetContext, mlirOperationGetName, mlirOperationGetNextInBlock,
-    mlirOperationGetNumRegions, mlirOperationGetNumResults, mlirOperationGetRegion,
-    mlirOperationGetResult, mlirOperationPrintWithFlags, mlirOperationVerify, MlirOperation,
+    mlirOperationDestroy, mlirOperationDump, mlirOperationEqual, mlirOperationGetAttributeByName,
+    mlirOperationGetBlock, mlirOperationGetContext, mlirOperationGetLocation, mlirOperationGetName,
+    mlirOperationGetNextInBlock, mlirOperationGetNumOperands, mlirOperationGetNumRegions,
+    mlirOperationGetNumResults, mlirOperationGetOperand, mlirOperationGetParentOperation,
+    mlirOperationGetRegion, mlirOperationGetResult, mlirOperationMoveBefore,
+    mlirOperationPrintWithFlags, mlirOperationRemoveFromParent, mlirOperationSetOperand,
+    mlirOperationVerify, MlirOperation,
 };
 use crate::utility::print_debug_callback;
 use crate::{
     context::{Context, ContextRef},
+    string_ref::StringRef,
     utility::print_callback,
     Error,
 };
@@ -49,6 +53,44 @@ impl<'c> Operation<'c> {
         unsafe { BlockRef::from_option_raw(mlirOperationGetBlock(self.raw)) }
     }
 
+    /// Gets a location.
+    pub fn location(&self) -> Location<'c> {
+        unsafe { Location::from_raw(mlirOperationGetLocation(self.raw)) }
+    }
+
+    /// Gets an operand at a position.
+    pub fn operand(&self, position: usize) -> Result<Value, Error> {
+        unsafe {
+            if position < self.operand_count() {
+                Ok(Value::from_raw(mlirOperationGetOperand(
+                    self.raw,
+                    position as isize,
+                )))
+            } else {
+                Err(Error::OperationOperandPosition(self.to_string(), position))
+            }
+        }
+    }
+
+    /// Gets a number of operands.
+    pub fn operand_count(&self) -> usize {
+        unsafe { mlirOperationGetNumOperands(self.raw) as usize }
+    }
+
+    /// Gets an attribute by its name.
+    pub fn attribute(&self, name: &str) -> Option<Attribute<'c>> {
+        unsafe {
+            let attribute =
+                mlirOperationGetAttributeByName(self.raw, StringRef::from(name).to_raw());
+
+            if attribute.ptr.is_null() {
+                None
+            } else {
+                Some(Attribute::from_raw(attribute))
+            }
+        }
+    }
+
     /// Gets a result at a position.
     pub fn result(&self, position: usize) -> Result<result::ResultValue, Error> {
         unsafe {
@@ -218,6 +260,97 @@ impl<'a> OperationRef<'a> {
             Some(Self::from_raw(raw))
         }
     }
+
+    /// Gets a result at a position.
+    ///
+    /// Unlike [`Operation::result`], the returned value borrows the block
+    /// owning the operation rather than this reference.
+    pub fn result(self, position: usize) -> Result<result::ResultValue<'a>, Error> {
+        unsafe {
+            if position < self.result_count() {
+                Ok(result::ResultValue::from_raw(mlirOperationGetResult(
+                    self.raw,
+                    position as isize,
+                )))
+            } else {
+                Err(Error::OperationResultPosition(self.to_string(), position))
+            }
+        }
+    }
+
+    /// Gets an operand at a position.
+    ///
+    /// Unlike [`Operation::operand`], the returned value borrows the block
+    /// owning the operation rather than this reference.
+    pub fn operand(self, position: usize) -> Result<Value<'a>, Error> {
+        unsafe {
+            if position < self.operand_count() {
+                Ok(Value::from_raw(mlirOperationGetOperand(
+                    self.raw,
+                    position as isize,
+                )))
+            } else {
+                Err(Error::OperationOperandPosition(self.to_string(), position))
+            }
+        }
+    }
+
+    /// Gets a region at an index.
+    ///
+    /// Unlike [`Operation::region`], the returned region borrows the block
+    /// owning the operation rather than this reference.
+    pub fn region(self, index: usize) -> Option<RegionRef<'a>> {
+        unsafe {
+            if index < self.region_count() {
+                Some(RegionRef::from_raw(mlirOperationGetRegion(
+                    self.raw,
+                    index as isize,
+                )))
+            } else {
+                None
+            }
+        }
+    }
+
+    /// Gets the next operation in the same block.
+    pub fn next_in_block(self) -> Option<Self> {
+        unsafe { Self::from_option_raw(mlirOperationGetNextInBlock(self.raw)) }
+    }
+
+    /// Gets the block containing the operation.
+    ///
+    /// Unlike [`Operation::block`], the returned block borrows the region
+    /// owning it rather than this reference.
+    pub fn block(self) -> Option<BlockRef<'a>> {
+        unsafe { BlockRef::from_option_raw(mlirOperationGetBlock(self.raw)) }
+    }
+
+    /// Gets the operation whose region contains this one.
+    pub fn parent_operation(self) -> Option<Self> {
+        unsafe { Self::from_option_raw(mlirOperationGetParentOperation(self.raw)) }
+    }
+
+    /// Sets an operand at a position.
+    pub fn set_operand(self, position: usize, value: Value) {
+        unsafe { mlirOperationSetOperand(self.raw, position as isize, value.to_raw()) }
+    }
+
+    /// Moves the operation before another one, possibly in another block.
+    pub fn move_before(self, other: Self) {
+        unsafe { mlirOperationMoveBefore(self.raw, other.raw) }
+    }
+
+    /// Removes the operation from its block and assumes its ownership.
+    ///
+    /// # Safety
+    ///
+    /// This function might invalidate existing references to the operation if
+    /// you drop it too early.
+    pub unsafe fn remove_from_parent(self) -> Operation<'a> {
+        mlirOperationRemoveFromParent(self.raw);
+
+        Operation::from_raw(self.raw)
+    }
 }
 
 impl<'a> Deref for OperationRef<'a> {
@@ -253,7 +386,7 @@ mod tests {
     use super::*;
     use crate::{
         context::Context,
-        ir::{Block, Location},
+        ir::{Block, Location, NamedAttribute, Type, ValueLike},
     };
     use pretty_assertions::assert_eq;
 
@@ -313,6 +446,87 @@ mod tests {
         );
     }
 
+    #[test]
+    fn result_outlives_reference() {
+        let context = Context::new();
+        context.set_allow_unregistered_dialects(true);
+        let block = Block::new(&[]);
+        let r#type = Type::parse(&context, "index").unwrap();
+
+        let value: Value = block
+            .append_operation(
+                Builder::new("foo", Location::unknown(&context))
+                    .add_results(&[r#type])
+                    .build(),
+            )
+            .result(0)
+            .unwrap()
+            .into();
+
+        assert_eq!(value.r#type(), r#type);
+    }
+
+    #[test]
+    fn operand() {
+        let context = Context::new();
+        context.set_allow_unregistered_dialects(true);
+        let location = Location::unknown(&context);
+        let block = Block::new(&[(Type::index(&context), location)]);
+        let argument = block.argument(0).unwrap().into();
+
+        let operation = block.append_operation(
+            Builder::new("foo", location)
+                .add_operands(&[argument])
+                .build(),
+        );
+
+        assert_eq!(operation.operand_count(), 1);
+        assert_eq!(operation.operand(0), Ok(argument));
+        assert!(matches!(
+            operation.operand(1),
+            Err(Error::OperationOperandPosition(_, 1))
+        ));
+    }
+
+    #[test]
+    fn attribute() {
+        let context = Context::new();
+        context.set_allow_unregistered_dialects(true);
+        let operation = Builder::new("foo", Location::unknown(&context))
+            .add_attributes(
+                &NamedAttribute::new_parsed_vec(&context, &[("bar", "42 : i64")]).unwrap(),
+            )
+            .build();
+
+        assert_eq!(
+            operation
+                .attribute("bar")
+                .and_then(|bar| bar.integer_value()),
+            Some(42)
+        );
+        assert_eq!(operation.attribute("baz"), None);
+    }
+
+    #[test]
+    fn next_in_block_outlives_reference() {
+        let context = Context::new();
+        context.set_allow_unregistered_dialects(true);
+        let location = Location::unknown(&context);
+        let block = Block::new(&[]);
+        block.append_operation(Builder::new("foo", location).build());
+        block.append_operation(Builder::new("bar", location).build());
+
+        let mut names = vec![];
+        let mut operation = block.first_operation();
+
+        while let Some(current) = operation {
+            names.push(current.name().as_string_ref().as_str().unwrap().to_string());
+            operation = current.next_in_block();
+        }
+
+        assert_eq!(names, ["foo", "bar"]);
+    }
+
     #[test]
     fn region_none() {
         let context = Context::new();
@@ -17,7 +17,7 @@ impl<'a> ResultValue<'a> {
         unsafe { mlirOpResultGetResultNumber(self.value.to_raw()) as usize }
     }
 
-    pub fn owner(&self) -> OperationRef {
+    pub fn owner(&self) -> OperationRef<'a> {
         unsafe { OperationRef::from_raw(mlirOpResultGetOwner(self.value.to_raw())) }
     }
 
@@ -121,6 +121,14 @@ impl<'a> RegionRef<'a> {
             Some(Self::from_raw(raw))
         }
     }
+
+    /// Gets the first block in a region.
+    ///
+    /// Unlike [`Region::first_block`], the returned block borrows the
+    /// operation owning the region rather than this reference.
+    pub fn first_block(self) -> Option<BlockRef<'a>> {
+        unsafe { BlockRef::from_option_raw(mlirRegionGetFirstBlock(self.raw)) }
+    }
 }
 
 impl<'a> Deref for RegionRef<'a> {
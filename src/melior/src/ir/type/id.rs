@@ -16,6 +16,10 @@ impl Id {
     pub(crate) const unsafe fn from_raw(raw: MlirTypeID) -> Self {
         Self { raw }
     }
+
+    pub(crate) const unsafe fn to_raw(self) -> MlirTypeID {
+        self.raw
+    }
 }
 
 impl PartialEq for Id {
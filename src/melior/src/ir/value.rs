@@ -1,12 +1,16 @@
 mod value_like;
 
 pub use self::value_like::ValueLike;
-use super::{block, operation, Type};
-use crate::mlir_sys::{mlirValueEqual, mlirValuePrint, MlirValue};
+use super::{block, operation, OperationRef, Type};
+use crate::mlir_sys::{
+    mlirOpOperandGetNextUse, mlirOpOperandGetOperandNumber, mlirOpOperandGetOwner,
+    mlirOpOperandIsNull, mlirValueEqual, mlirValueGetFirstUse, mlirValuePrint, MlirValue,
+};
 use crate::utility::print_callback;
 use std::{
     ffi::c_void,
     fmt::{self, Debug, Display, Formatter},
+    hash::{Hash, Hasher},
     marker::PhantomData,
 };
 
@@ -20,6 +24,26 @@ pub struct Value<'a> {
 }
 
 impl<'a> Value<'a> {
+    /// Gets the uses of the value, as the operations using it and the
+    /// positions of the value among their operands.
+    pub fn uses(&self) -> Vec<(OperationRef<'a>, usize)> {
+        let mut uses = vec![];
+
+        unsafe {
+            let mut operand = mlirValueGetFirstUse(self.raw);
+
+            while !mlirOpOperandIsNull(operand) {
+                uses.push((
+                    OperationRef::from_raw(mlirOpOperandGetOwner(operand)),
+                    mlirOpOperandGetOperandNumber(operand) as usize,
+                ));
+                operand = mlirOpOperandGetNextUse(operand);
+            }
+        }
+
+        uses
+    }
+
     pub(crate) unsafe fn from_raw(value: MlirValue) -> Self {
         Self {
             raw: value,
@@ -42,6 +66,13 @@ impl<'a> PartialEq for Value<'a> {
 
 impl<'a> Eq for Value<'a> {}
 
+impl<'a> Hash for Value<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Values are equal if and only if they refer to the same value.
+        self.raw.ptr.hash(state);
+    }
+}
+
 impl<'a> Display for Value<'a> {
     fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
         let mut data = (formatter, Ok(()));
@@ -1,6 +1,7 @@
 //! Passes and pass managers.
 
 pub mod conversion;
+pub mod external;
 mod manager;
 mod operation_manager;
 pub mod transform;
@@ -20,6 +21,10 @@ impl Pass {
         }
     }
 
+    pub(crate) const unsafe fn from_raw(raw: MlirPass) -> Self {
+        Self { raw }
+    }
+
     pub(crate) const unsafe fn to_raw(&self) -> MlirPass {
         self.raw
     }
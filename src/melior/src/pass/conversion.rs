@@ -3,7 +3,8 @@
 use super::Pass;
 use crate::mlir_sys::{
     mlirCreateConversionArithToLLVMConversionPass, mlirCreateConversionConvertAffineForToGPU,
-    mlirCreateConversionConvertAffineToStandard, mlirCreateConversionConvertAsyncToLLVM,
+    mlirCreateConversionConvertAffineToStandard, mlirCreateConversionConvertArithToSPIRV,
+    mlirCreateConversionConvertAsyncToLLVM,
     mlirCreateConversionConvertControlFlowToLLVM, mlirCreateConversionConvertControlFlowToSPIRV,
     mlirCreateConversionConvertFuncToLLVM, mlirCreateConversionConvertMathToLLVM,
     mlirCreateConversionConvertMathToLibm, mlirCreateConversionConvertMathToSPIRV,
@@ -41,6 +42,11 @@ pub fn convert_index_to_llvm() -> Pass {
     Pass::from_raw_fn(mlirCreateConversionConvertIndexToLLVMPass)
 }
 
+/// Creates a pass to convert the `arith` dialect to the `spirv` dialect.
+pub fn convert_arithmetic_to_spirv() -> Pass {
+    Pass::from_raw_fn(mlirCreateConversionConvertArithToSPIRV)
+}
+
 /// Creates a pass to convert the `cf` dialect to the `spirv` dialect.
 pub fn convert_cf_to_spirv() -> Pass {
     Pass::from_raw_fn(mlirCreateConversionConvertControlFlowToSPIRV)
@@ -0,0 +1,120 @@
+//! External passes, implemented in Rust.
+
+use super::Pass;
+use crate::{
+    ir::{r#type, OperationRef},
+    logical_result::LogicalResult,
+    mlir_sys::{
+        mlirCreateExternalPass, mlirExternalPassSignalFailure, MlirExternalPass,
+        MlirExternalPassCallbacks, MlirOperation,
+    },
+    string_ref::StringRef,
+};
+use std::ffi::c_void;
+
+/// The code of an external pass.
+///
+/// Pass managers may clone a pass to run it on several operations in
+/// parallel.
+pub trait RunExternalPass: Clone {
+    /// Runs the pass on an operation. A failure stops the pass manager.
+    fn run(&mut self, operation: OperationRef) -> LogicalResult;
+}
+
+/// Creates an external pass running on the operations named `operation_name`,
+/// or on any operation if it is empty.
+///
+/// `id` identifies the pass and must live as long as it.
+pub fn create_external<T: RunExternalPass + 'static>(
+    pass: T,
+    id: r#type::Id,
+    name: &str,
+    argument: &str,
+    description: &str,
+    operation_name: &str,
+) -> Pass {
+    unsafe {
+        Pass::from_raw(mlirCreateExternalPass(
+            id.to_raw(),
+            StringRef::from(name).to_raw(),
+            StringRef::from(argument).to_raw(),
+            StringRef::from(description).to_raw(),
+            StringRef::from(operation_name).to_raw(),
+            0,
+            std::ptr::null_mut(),
+            MlirExternalPassCallbacks {
+                construct: Some(construct),
+                destruct: Some(destruct::<T>),
+                initialize: None,
+                clone: Some(clone::<T>),
+                run: Some(run::<T>),
+            },
+            Box::into_raw(Box::new(pass)) as *mut c_void,
+        ))
+    }
+}
+
+unsafe extern "C" fn construct(_: *mut c_void) {}
+
+unsafe extern "C" fn destruct<T: RunExternalPass>(pass: *mut c_void) {
+    drop(Box::from_raw(pass as *mut T));
+}
+
+unsafe extern "C" fn clone<T: RunExternalPass>(pass: *mut c_void) -> *mut c_void {
+    Box::into_raw(Box::new((*(pass as *mut T)).clone())) as *mut c_void
+}
+
+unsafe extern "C" fn run<T: RunExternalPass>(
+    operation: MlirOperation,
+    external: MlirExternalPass,
+    pass: *mut c_void,
+) {
+    if (*(pass as *mut T))
+        .run(OperationRef::from_raw(operation))
+        .is_failure()
+    {
+        mlirExternalPassSignalFailure(external);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        context::Context,
+        ir::{r#type::id::Allocator, Location, Module},
+        pass::Manager,
+    };
+    use std::{cell::Cell, rc::Rc};
+
+    #[derive(Clone)]
+    struct Count(Rc<Cell<usize>>);
+
+    impl RunExternalPass for Count {
+        fn run(&mut self, _: OperationRef) -> LogicalResult {
+            self.0.set(self.0.get() + 1);
+            LogicalResult::success()
+        }
+    }
+
+    #[test]
+    fn run_external_pass() {
+        let context = Context::new();
+        let mut module = Module::new(Location::unknown(&context));
+        let mut allocator = Allocator::new();
+        let count = Rc::new(Cell::new(0));
+        let manager = Manager::new(&context);
+
+        manager.add_pass(create_external(
+            Count(count.clone()),
+            allocator.allocate_type_id(),
+            "count",
+            "count",
+            "counts the modules it runs on",
+            "builtin.module",
+        ));
+        manager.run(&mut module).unwrap();
+
+        assert_eq!(count.get(), 1);
+    }
+}
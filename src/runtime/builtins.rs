@@ -0,0 +1,873 @@
+//! Built-in functions, the values of the `builtins` set.
+//!
+//! A built-in function is a [`PrimOp`] applied to its arguments one at a time
+//! like a curried lambda, and called once it has all of them. Only `builtins`
+//! itself and the few functions Nix also binds globally, such as `map`, are
+//! reachable without selecting them from `builtins`.
+
+use crate::{
+	derivation::{derivation, derivation_strict},
+	error::{catch, throw, EvalError},
+	exports::{
+		apply, attributes, boolean, coerce, integer, list, string,
+		string_with_context, Coercion,
+	},
+	files, gc,
+	store::{self, check, store_dir},
+	thunk::{force, Thunk},
+	value::{print, Env, NixString, Value},
+};
+use mlnx_store::{nar::Kind, Algorithm, Hash};
+use regex::Regex;
+use std::{collections::BTreeMap, fs};
+
+/// The code of a built-in function, called with all of its arguments.
+pub type PrimOpFn = fn(&[*mut Value]) -> *mut Value;
+
+/// A built-in function with the arguments it is applied to so far.
+#[derive(Clone)]
+pub struct PrimOp {
+	pub name: &'static str,
+	pub arity: usize,
+	pub function: PrimOpFn,
+	pub arguments: Vec<*mut Value>,
+}
+
+impl PrimOp {
+	/// Applies the function to one more argument, calling it if it is the
+	/// last one.
+	pub fn apply(&self, argument: *mut Value) -> *mut Value {
+		let mut primop = self.clone();
+		primop.arguments.push(argument);
+
+		if primop.arguments.len() == primop.arity {
+			let _roots = gc::root(&primop.arguments);
+			(primop.function)(&primop.arguments)
+		} else {
+			Value::PrimOp(primop.into()).alloc()
+		}
+	}
+}
+
+/// The built-in functions with their arity.
+const PRIMOPS: &[(&str, usize, PrimOpFn)] = &[
+	("abort", 1, abort),
+	("attrNames", 1, attr_names),
+	("attrValues", 1, attr_values),
+	("bitAnd", 2, bit_and),
+	("bitOr", 2, bit_or),
+	("bitXor", 2, bit_xor),
+	("concatLists", 1, concat_lists),
+	("deepSeq", 2, deep_seq),
+	("derivation", 1, derivation),
+	("derivationStrict", 1, derivation_strict),
+	("elemAt", 2, elem_at),
+	("filter", 2, filter),
+	("foldl'", 3, foldl),
+	("genList", 2, gen_list),
+	("getAttr", 2, get_attr),
+	("hasAttr", 2, has_attr),
+	("hashFile", 2, hash_file),
+	("hashString", 2, hash_string),
+	("import", 1, import),
+	("length", 1, length),
+	("listToAttrs", 1, list_to_attrs),
+	("map", 2, map),
+	("path", 1, path),
+	("replaceStrings", 3, replace_strings),
+	("seq", 2, seq),
+	("split", 2, split),
+	("throw", 1, throw_),
+	("toFile", 2, to_file),
+	("toString", 1, to_string),
+	("trace", 2, trace),
+	("tryEval", 1, try_eval),
+	("typeOf", 1, type_of),
+];
+
+thread_local! {
+	static BUILTINS: *mut Value = gc::pin({
+		let _pause = gc::pause();
+
+		Value::AttrSet(
+			PRIMOPS
+				.iter()
+				.map(|&(name, arity, function)| {
+					let primop = PrimOp {
+						name,
+						arity,
+						function,
+						arguments: Vec::new(),
+					};
+
+					(name.to_owned(), Value::PrimOp(primop.into()).alloc())
+				})
+				.collect::<BTreeMap<_, _>>()
+				.into(),
+		)
+		.alloc()
+	});
+}
+
+/// Tests if `builtins` has an attribute, without creating `builtins`.
+pub fn contains(name: &str) -> bool {
+	PRIMOPS.iter().any(|&(primop, ..)| primop == name)
+}
+
+/// Gets `builtins` or one of its attributes.
+pub fn get(name: &str) -> *mut Value {
+	let builtins = BUILTINS.with(|&builtins| builtins);
+
+	if name == "builtins" {
+		return builtins;
+	}
+
+	match attributes(builtins).get(name) {
+		Some(&value) => value,
+		None => unreachable!("unknown built-in '{name}'"),
+	}
+}
+
+unsafe extern "C-unwind" fn apply_env(env: *mut Env) -> *mut Value {
+	let env = &*env;
+	apply(env.0[0], env.0[1], None)
+}
+
+/// Suspends the application of a function to an argument.
+pub fn suspend_apply(function: *mut Value, argument: *mut Value) -> *mut Value {
+	let env = Env(vec![function, argument]).alloc();
+	Value::Thunk(Thunk::new(apply_env, env).into()).alloc()
+}
+
+unsafe extern "C-unwind" fn get_attr_env(env: *mut Env) -> *mut Value {
+	let env = &*env;
+	get_attr(&env.0)
+}
+
+/// Suspends the selection of an attribute.
+pub(crate) fn suspend_select(set: *mut Value, name: &str) -> *mut Value {
+	let env = Env(vec![Value::string(name).alloc(), set]).alloc();
+	Value::Thunk(Thunk::new(get_attr_env, env).into()).alloc()
+}
+
+fn new_list(elements: Vec<*mut Value>) -> *mut Value {
+	Value::List(elements.into()).alloc()
+}
+
+/// Coerces a value to a string as interpolation does.
+fn coerce_string(value: *mut Value) -> NixString {
+	let mut string = NixString::default();
+	coerce(value, &mut string, Coercion::PLAIN);
+	string
+}
+
+fn abort(arguments: &[*mut Value]) -> *mut Value {
+	throw(format!(
+		"evaluation aborted with the following error message: '{}'",
+		coerce_string(arguments[0]).value
+	))
+}
+
+fn attr_names(arguments: &[*mut Value]) -> *mut Value {
+	let names = attributes(arguments[0]).keys();
+	let _pause = gc::pause();
+
+	new_list(
+		names
+			.map(|name| Value::string(name.as_str()).alloc())
+			.collect(),
+	)
+}
+
+fn attr_values(arguments: &[*mut Value]) -> *mut Value {
+	new_list(attributes(arguments[0]).values().copied().collect())
+}
+
+/// Combines the bits of two integers.
+fn bitwise(
+	arguments: &[*mut Value],
+	operator: fn(i64, i64) -> i64,
+) -> *mut Value {
+	Value::Int(operator(integer(arguments[0]), integer(arguments[1]))).alloc()
+}
+
+fn bit_and(arguments: &[*mut Value]) -> *mut Value {
+	bitwise(arguments, |lhs, rhs| lhs & rhs)
+}
+
+fn bit_or(arguments: &[*mut Value]) -> *mut Value {
+	bitwise(arguments, |lhs, rhs| lhs | rhs)
+}
+
+fn bit_xor(arguments: &[*mut Value]) -> *mut Value {
+	bitwise(arguments, |lhs, rhs| lhs ^ rhs)
+}
+
+fn concat_lists(arguments: &[*mut Value]) -> *mut Value {
+	new_list(
+		list(arguments[0])
+			.iter()
+			.flat_map(|&element| list(element))
+			.copied()
+			.collect(),
+	)
+}
+
+/// Forces a value and everything it contains.
+fn deep_force(value: *mut Value) {
+	match unsafe { &*force(value) } {
+		Value::List(elements) => {
+			elements.iter().for_each(|&element| deep_force(element))
+		}
+		Value::AttrSet(attributes) => {
+			attributes.values().for_each(|&value| deep_force(value))
+		}
+		_ => {}
+	}
+}
+
+fn deep_seq(arguments: &[*mut Value]) -> *mut Value {
+	deep_force(arguments[0]);
+	arguments[1]
+}
+
+fn elem_at(arguments: &[*mut Value]) -> *mut Value {
+	let elements = list(arguments[0]);
+	let index = integer(arguments[1]);
+
+	match usize::try_from(index)
+		.ok()
+		.and_then(|index| elements.get(index))
+	{
+		Some(&element) => element,
+		None => throw(format!("list index {index} is out of bounds")),
+	}
+}
+
+fn filter(arguments: &[*mut Value]) -> *mut Value {
+	new_list(
+		list(arguments[1])
+			.iter()
+			.copied()
+			.filter(|&element| boolean(apply(arguments[0], element, None)))
+			.collect(),
+	)
+}
+
+fn foldl(arguments: &[*mut Value]) -> *mut Value {
+	list(arguments[2]).iter().fold(
+		force(arguments[1]),
+		|accumulator, &element| {
+			force(apply(apply(arguments[0], accumulator, None), element, None))
+		},
+	)
+}
+
+fn gen_list(arguments: &[*mut Value]) -> *mut Value {
+	let length = integer(arguments[1]);
+
+	if length < 0 {
+		throw(format!("cannot create list of size {length}"));
+	}
+
+	let _pause = gc::pause();
+
+	new_list(
+		(0..length)
+			.map(|index| suspend_apply(arguments[0], Value::Int(index).alloc()))
+			.collect(),
+	)
+}
+
+fn get_attr(arguments: &[*mut Value]) -> *mut Value {
+	let name = string(arguments[0]);
+
+	match attributes(arguments[1]).get(name) {
+		Some(&value) => value,
+		None => throw(format!("attribute '{name}' missing")),
+	}
+}
+
+fn has_attr(arguments: &[*mut Value]) -> *mut Value {
+	let name = string(arguments[0]);
+	Value::Bool(attributes(arguments[1]).contains_key(name)).alloc()
+}
+
+/// Coerces a path or a string of an absolute path to a path.
+fn coerce_path(value: *mut Value) -> String {
+	let path = coerce_string(value).value;
+
+	if !path.starts_with('/') {
+		throw(format!(
+			"string '{path}' doesn't represent an absolute path"
+		));
+	}
+
+	path
+}
+
+fn hash_algorithm(value: *mut Value) -> Algorithm {
+	check(Algorithm::parse(string(value)))
+}
+
+fn hash_file(arguments: &[*mut Value]) -> *mut Value {
+	let algorithm = hash_algorithm(arguments[0]);
+	let path = coerce_path(arguments[1]);
+	let contents = fs::read(&path).unwrap_or_else(|error| {
+		throw(format!("cannot read '{path}': {error}"))
+	});
+
+	Value::string(algorithm.digest(&contents).base16()).alloc()
+}
+
+fn hash_string(arguments: &[*mut Value]) -> *mut Value {
+	let algorithm = hash_algorithm(arguments[0]);
+	let hash = algorithm.digest(string(arguments[1]).as_bytes());
+	Value::string(hash.base16()).alloc()
+}
+
+/// Imports a compiled file by a path or a string of an absolute path.
+fn import(arguments: &[*mut Value]) -> *mut Value {
+	files::import(&coerce_path(arguments[0]))
+}
+
+fn length(arguments: &[*mut Value]) -> *mut Value {
+	Value::Int(list(arguments[0]).len() as i64).alloc()
+}
+
+/// Creates a set from a list of `{ name, value }` sets, where the first
+/// occurrence of a name wins.
+fn list_to_attrs(arguments: &[*mut Value]) -> *mut Value {
+	let mut set = BTreeMap::new();
+
+	for &element in list(arguments[0]) {
+		let attributes = attributes(element);
+		let attribute = |name| match attributes.get(name) {
+			Some(&value) => value,
+			None => throw(format!("attribute '{name}' missing")),
+		};
+
+		set.entry(string(attribute("name")).to_owned())
+			.or_insert_with(|| attribute("value"));
+	}
+
+	Value::AttrSet(set.into()).alloc()
+}
+
+fn map(arguments: &[*mut Value]) -> *mut Value {
+	let elements = list(arguments[1]);
+	let _pause = gc::pause();
+
+	new_list(
+		elements
+			.iter()
+			.map(|&element| suspend_apply(arguments[0], element))
+			.collect(),
+	)
+}
+
+/// Adds a path to the store as a source, with the attributes `path`, `name`,
+/// `filter`, `recursive` and `sha256`.
+fn path(arguments: &[*mut Value]) -> *mut Value {
+	let attributes = attributes(arguments[0]);
+
+	if let Some(name) = attributes.keys().find(|name| {
+		!["path", "name", "filter", "recursive", "sha256"]
+			.contains(&name.as_str())
+	}) {
+		throw(format!("unsupported argument '{name}' to 'builtins.path'"));
+	}
+
+	let Some(&path) = attributes.get("path") else {
+		throw("missing required 'path' attribute in the first argument to builtins.path")
+	};
+	let path = coerce_path(path);
+	let name = match attributes.get("name") {
+		Some(&name) => string(name).to_owned(),
+		None => path.rsplit('/').next().unwrap_or_default().to_owned(),
+	};
+	let filter = attributes.get("filter").copied();
+	let recursive = attributes
+		.get("recursive")
+		.is_none_or(|&recursive| boolean(recursive));
+	let expected = attributes
+		.get("sha256")
+		.map(|&hash| check(Hash::parse(string(hash), Some(Algorithm::Sha256))));
+
+	let target = store::add_path(
+		&store_dir(),
+		&path,
+		&name,
+		&mut |path, kind: Kind| {
+			let Some(filter) = filter else { return true };
+			let path = Value::string(path.to_string_lossy()).alloc();
+			let kind = Value::string(kind.name()).alloc();
+
+			boolean(apply(apply(filter, path, None), kind, None))
+		},
+		recursive,
+		expected.as_ref(),
+	);
+
+	Value::String(
+		NixString {
+			value: target.clone(),
+			context: [target].into(),
+		}
+		.into(),
+	)
+	.alloc()
+}
+
+/// Replaces every occurrence of the strings of a list by the corresponding
+/// strings of another, trying them in order at each position. An empty string
+/// matches at every position, including the end.
+fn replace_strings(arguments: &[*mut Value]) -> *mut Value {
+	let from = list(arguments[0]);
+	let to = list(arguments[1]);
+
+	if from.len() != to.len() {
+		throw(
+			"'from' and 'to' arguments passed to builtins.replaceStrings \
+			 have different lengths",
+		);
+	}
+
+	let from = from.iter().map(|&from| string(from)).collect::<Vec<_>>();
+	// Replacements are only coerced when used.
+	let mut replacements = vec![None; to.len()];
+	let input = coerce_string(arguments[2]);
+	let bytes = input.value.as_bytes();
+	let mut output = Vec::new();
+	let mut context = input.context.clone();
+	let mut position = 0;
+
+	while position <= bytes.len() {
+		let matched = from
+			.iter()
+			.position(|from| bytes[position..].starts_with(from.as_bytes()));
+
+		if let Some(index) = matched {
+			let replacement: &mut NixString = replacements[index]
+				.get_or_insert_with(|| coerce_string(to[index]));
+
+			output.extend_from_slice(replacement.value.as_bytes());
+			context.extend(replacement.context.iter().cloned());
+		}
+
+		match matched {
+			Some(index) if !from[index].is_empty() => {
+				position += from[index].len()
+			}
+			_ => {
+				output.extend(bytes.get(position));
+				position += 1;
+			}
+		}
+	}
+
+	Value::String(
+		NixString {
+			value: String::from_utf8_lossy(&output).into_owned(),
+			context,
+		}
+		.into(),
+	)
+	.alloc()
+}
+
+fn seq(arguments: &[*mut Value]) -> *mut Value {
+	force(arguments[0]);
+	arguments[1]
+}
+
+/// Splits a string by a regular expression into a list of the strings between
+/// matches and, for each match, the list of its groups, which are `null` if
+/// they do not participate in the match.
+///
+/// Nix matches POSIX extended regular expressions, while the `regex` crate
+/// implements a superset of their syntax with other semantics: alternatives
+/// are preferred in order rather than by the length of their match, so
+/// `a|ab` only matches the `a` of `ab`, and Perl classes such as `\d` and lazy
+/// repetitions are accepted. Patterns which do not depend on these
+/// differences split strings as Nix does.
+fn split(arguments: &[*mut Value]) -> *mut Value {
+	let pattern = string(arguments[0]);
+	let regex = Regex::new(pattern).unwrap_or_else(|_| {
+		throw(format!("invalid regular expression '{pattern}'"))
+	});
+	let input = coerce_string(arguments[1]).value;
+	let _pause = gc::pause();
+	let mut elements = Vec::new();
+	let mut end = 0;
+
+	for captures in regex.captures_iter(&input) {
+		let whole = captures.get(0).expect("match has a whole group");
+
+		elements.push(Value::string(&input[end..whole.start()]).alloc());
+		elements.push(new_list(
+			captures
+				.iter()
+				.skip(1)
+				.map(|group| match group {
+					Some(group) => Value::string(group.as_str()).alloc(),
+					None => Value::Null.alloc(),
+				})
+				.collect(),
+		));
+		end = whole.end();
+	}
+
+	elements.push(Value::string(&input[end..]).alloc());
+	new_list(elements)
+}
+
+fn throw_(arguments: &[*mut Value]) -> *mut Value {
+	EvalError::new(coerce_string(arguments[0]).value)
+		.catchable()
+		.raise()
+}
+
+/// Writes a string to a file of the store, which may refer to other store
+/// paths but not to outputs of derivations.
+fn to_file(arguments: &[*mut Value]) -> *mut Value {
+	let name = string(arguments[0]);
+	let contents = string_with_context(arguments[1]);
+
+	if contents
+		.context
+		.iter()
+		.any(|path| path.starts_with('!') || path.starts_with('='))
+	{
+		throw(format!(
+			"in 'toFile': the file '{name}' cannot refer to derivation outputs"
+		));
+	}
+
+	store::check_name(name);
+	let path =
+		store_dir().make_text_path(name, &contents.value, &contents.context);
+	store::write(&path, &contents.value);
+
+	Value::String(
+		NixString {
+			value: path.clone(),
+			context: [path].into(),
+		}
+		.into(),
+	)
+	.alloc()
+}
+
+fn to_string(arguments: &[*mut Value]) -> *mut Value {
+	let mut string = NixString::default();
+	coerce(arguments[0], &mut string, Coercion::TO_STRING);
+	Value::String(string.into()).alloc()
+}
+
+/// Prints a value to the standard error as Nix does, strings without quotes,
+/// and evaluates to the second argument.
+fn trace(arguments: &[*mut Value]) -> *mut Value {
+	match unsafe { &*force(arguments[0]) } {
+		Value::String(string) => eprintln!("trace: {}", string.value),
+		_ => eprintln!("trace: {}", print(arguments[0])),
+	}
+
+	arguments[1]
+}
+
+/// Evaluates a value to weak head normal form, catching the errors of
+/// `throw` and failed assertions but not the others, such as type errors.
+fn try_eval(arguments: &[*mut Value]) -> *mut Value {
+	let (success, value) = match catch(|| force(arguments[0])) {
+		Ok(value) => (true, value),
+		Err(error) if error.is_catchable() => {
+			(false, Value::Bool(false).alloc())
+		}
+		Err(error) => error.raise(),
+	};
+	let success = Value::Bool(success).alloc();
+
+	Value::AttrSet(
+		BTreeMap::from([("success".into(), success), ("value".into(), value)])
+			.into(),
+	)
+	.alloc()
+}
+
+fn type_of(arguments: &[*mut Value]) -> *mut Value {
+	let name = match unsafe { &*force(arguments[0]) } {
+		Value::Null => "null",
+		Value::Bool(_) => "bool",
+		Value::Int(_) => "int",
+		Value::Float(_) => "float",
+		Value::String(_) => "string",
+		Value::Path(_) => "path",
+		Value::List(_) => "list",
+		Value::AttrSet(_) => "set",
+		Value::Lambda(_) | Value::PrimOp(_) => "lambda",
+		Value::Thunk(_) => unreachable!("forced value is a thunk"),
+	};
+
+	Value::string(name).alloc()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::error::catch;
+
+	fn call(name: &str, arguments: &[*mut Value]) -> *mut Value {
+		arguments.iter().fold(get(name), |function, &argument| {
+			apply(function, argument, None)
+		})
+	}
+
+	fn string(value: &str) -> *mut Value {
+		Value::string(value).alloc()
+	}
+
+	fn int(value: i64) -> *mut Value {
+		Value::Int(value).alloc()
+	}
+
+	fn list(elements: &[*mut Value]) -> *mut Value {
+		new_list(elements.to_vec())
+	}
+
+	fn set(attributes: &[(&str, *mut Value)]) -> *mut Value {
+		Value::AttrSet(
+			attributes
+				.iter()
+				.map(|&(name, value)| (name.to_owned(), value))
+				.collect::<BTreeMap<_, _>>()
+				.into(),
+		)
+		.alloc()
+	}
+
+	fn error(evaluate: impl FnOnce() -> *mut Value) -> String {
+		catch(|| print(evaluate()))
+			.unwrap_err()
+			.message()
+			.to_owned()
+	}
+
+	#[test]
+	fn apply_partially() {
+		let length = call("typeOf", &[get("length")]);
+		let elem_at = call("elemAt", &[list(&[int(1), int(2)])]);
+
+		assert_eq!(print(length), r#""lambda""#);
+		assert_eq!(print(elem_at), "<PRIMOP-APP>");
+		assert_eq!(print(apply(elem_at, int(1), None)), "2");
+		assert_eq!(print(call("seq", &[int(1), string("a")])), r#""a""#);
+		assert_eq!(
+			error(|| apply(elem_at, int(2), None)),
+			"list index 2 is out of bounds"
+		);
+	}
+
+	#[test]
+	fn combine_bits() {
+		assert_eq!(print(call("bitAnd", &[int(12), int(10)])), "8");
+		assert_eq!(print(call("bitOr", &[int(12), int(10)])), "14");
+		assert_eq!(print(call("bitXor", &[int(12), int(-1)])), "-13");
+		assert_eq!(
+			error(|| call("bitAnd", &[int(1), string("2")])),
+			"value is a string while an integer was expected"
+		);
+	}
+
+	#[test]
+	fn transform_lists() {
+		let invalid = call("filter", &[get("hasAttr")]);
+		let lists = list(&[list(&[int(1)]), list(&[]), list(&[int(2)])]);
+
+		assert_eq!(print(call("concatLists", &[lists])), "[ 1 2 ]");
+		assert_eq!(print(call("map", &[get("length"), lists])), "[ 1 0 1 ]");
+		assert_eq!(
+			print(call("genList", &[get("toString"), int(3)])),
+			r#"[ "0" "1" "2" ]"#
+		);
+		assert_eq!(
+			error(|| apply(invalid, list(&[int(1)]), None)),
+			"value is a partially applied built-in function while a Boolean \
+			 was expected"
+		);
+	}
+
+	#[test]
+	fn fold_strictly() {
+		let oops = suspend_apply(get("throw"), string("oops"));
+
+		assert_eq!(
+			print(call(
+				"foldl'",
+				&[get("seq"), int(0), list(&[int(1), int(2)])]
+			)),
+			"2"
+		);
+		assert_eq!(
+			error(|| call("foldl'", &[get("seq"), int(0), list(&[oops])])),
+			"oops"
+		);
+	}
+
+	#[test]
+	fn convert_attrsets() {
+		let pairs = list(&[
+			set(&[("name", string("b")), ("value", int(1))]),
+			set(&[("name", string("a")), ("value", int(2))]),
+			set(&[("name", string("b")), ("value", int(3))]),
+		]);
+		let set = call("listToAttrs", &[pairs]);
+
+		assert_eq!(print(set), "{ a = 2; b = 1; }");
+		assert_eq!(print(call("attrNames", &[set])), r#"[ "a" "b" ]"#);
+		assert_eq!(print(call("attrValues", &[set])), "[ 2 1 ]");
+		assert_eq!(print(call("getAttr", &[string("a"), set])), "2");
+		assert_eq!(print(call("hasAttr", &[string("c"), set])), "false");
+	}
+
+	#[test]
+	fn convert_to_strings() {
+		let value = list(&[
+			int(1),
+			Value::Bool(true).alloc(),
+			Value::Null.alloc(),
+			list(&[]),
+			Value::Float(1.5).alloc(),
+		]);
+
+		assert_eq!(print(call("toString", &[value])), r#""1 1  1.500000""#);
+		assert_eq!(
+			error(|| call("toString", &[get("map")])),
+			"cannot coerce a built-in function to a string"
+		);
+	}
+
+	#[test]
+	fn replace_strings() {
+		let replace = |from: &[&str], to: &[&str], input: &str| {
+			// The strings are only referred to from vectors until the lists
+			// are created.
+			let pause = gc::pause();
+			let from =
+				from.iter().map(|&from| string(from)).collect::<Vec<_>>();
+			let to = to.iter().map(|&to| string(to)).collect::<Vec<_>>();
+			let arguments = [list(&from), list(&to), string(input)];
+			drop(pause);
+
+			print(call("replaceStrings", &arguments))
+		};
+
+		assert_eq!(replace(&["o", "a"], &["a", "o"], "foobar"), r#""faabor""#);
+		assert_eq!(replace(&["", "b"], &["-", "x"], "ab"), r#""-a-b-""#);
+		assert_eq!(replace(&["ab", "a"], &["1", "2"], "aab"), r#""21""#);
+	}
+
+	#[test]
+	fn split_strings() {
+		assert_eq!(
+			print(call("split", &[string("(a)|b"), string("xaybz")])),
+			r#"[ "x" [ "a" ] "y" [ null ] "z" ]"#
+		);
+		assert_eq!(
+			print(call("split", &[string(","), string("")])),
+			r#"[ "" ]"#
+		);
+		assert_eq!(
+			error(|| call("split", &[string("("), string("")])),
+			"invalid regular expression '('"
+		);
+	}
+
+	#[test]
+	fn abort_evaluation() {
+		let oops = suspend_apply(get("throw"), string("oops"));
+
+		assert_eq!(error(|| call("throw", &[string("oops")])), "oops");
+		assert_eq!(
+			error(|| call("abort", &[string("oops")])),
+			"evaluation aborted with the following error message: 'oops'"
+		);
+		assert_eq!(error(|| call("deepSeq", &[list(&[oops]), int(1)])), "oops");
+		assert_eq!(print(call("seq", &[list(&[oops]), int(1)])), "1");
+	}
+
+	#[test]
+	fn try_evaluation() {
+		let oops = suspend_apply(get("throw"), string("oops"));
+		let abort = suspend_apply(get("abort"), string("oops"));
+		let length = suspend_apply(get("length"), int(1));
+
+		assert_eq!(
+			print(call("tryEval", &[int(1)])),
+			"{ success = true; value = 1; }"
+		);
+		assert_eq!(
+			print(call("tryEval", &[oops])),
+			"{ success = false; value = false; }"
+		);
+		assert_eq!(
+			error(|| call("tryEval", &[abort])),
+			"evaluation aborted with the following error message: 'oops'"
+		);
+		assert_eq!(
+			error(|| call("tryEval", &[length])),
+			"value is an integer while a list was expected"
+		);
+	}
+
+	#[test]
+	fn hash_strings_and_files() {
+		let path = std::env::temp_dir().join("mlnx-builtins-hash-files");
+		fs::write(&path, "abc").unwrap();
+		let path = string(path.to_str().unwrap());
+
+		for (algorithm, hash) in [
+			("md5", "900150983cd24fb0d6963f7d28e17f72"),
+			("sha1", "a9993e364706816aba3e25717850c26c9cd0d89d"),
+		] {
+			assert_eq!(
+				print(call("hashString", &[string(algorithm), string("abc")])),
+				format!("\"{hash}\"")
+			);
+			assert_eq!(
+				print(call("hashFile", &[string(algorithm), path])),
+				format!("\"{hash}\"")
+			);
+		}
+
+		assert_eq!(
+			error(|| call("hashString", &[string("sha3"), string("abc")])),
+			"unknown hash algorithm 'sha3'"
+		);
+	}
+
+	#[test]
+	fn reject_invalid_store_arguments() {
+		let output = Value::String(
+			NixString {
+				value: "/nix/store/a".into(),
+				context: ["!out!/nix/store/a.drv".into()].into(),
+			}
+			.into(),
+		)
+		.alloc();
+
+		assert_eq!(
+			error(|| call("toFile", &[string("a"), output])),
+			"in 'toFile': the file 'a' cannot refer to derivation outputs"
+		);
+		assert_eq!(
+			error(|| call("path", &[set(&[("url", string("/a"))])])),
+			"unsupported argument 'url' to 'builtins.path'"
+		);
+		assert_eq!(
+			error(|| call("path", &[set(&[("path", string("a"))])])),
+			"string 'a' doesn't represent an absolute path"
+		);
+	}
+}
@@ -0,0 +1,599 @@
+//! Derivations, written to the store as `.drv` files in the ATerm format.
+//!
+//! Output paths and `.drv` paths are computed as `nix-instantiate` computes
+//! them, so that the `.drv` files are byte-identical. Strings refer to
+//! derivations through their context, which holds `!output!path.drv` for an
+//! output of a derivation and `=path.drv` for a derivation itself.
+
+use crate::{
+	builtins::suspend_select,
+	error::throw,
+	exports::{attributes, boolean, coerce, list, string, Coercion},
+	gc,
+	store::{self, check, check_name, store_dir},
+	thunk::{force, Thunk},
+	value::{Env, NixString, Value},
+};
+use mlnx_store::{
+	hash::{base16, sha256},
+	Algorithm, Hash,
+};
+use std::{
+	cell::RefCell,
+	collections::{BTreeMap, BTreeSet, HashMap},
+};
+
+/// A derivation as stored in a `.drv` file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Derivation {
+	pub outputs: BTreeMap<String, Output>,
+	/// The derivations this one depends on, with the outputs it uses.
+	pub input_derivations: BTreeMap<String, BTreeSet<String>>,
+	pub input_sources: BTreeSet<String>,
+	pub system: String,
+	pub builder: String,
+	pub arguments: Vec<String>,
+	pub env: BTreeMap<String, String>,
+}
+
+/// An output of a derivation, whose path is empty while it is computed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Output {
+	pub path: String,
+	/// The hash algorithm of a fixed output, as `r:sha256` if the hash is
+	/// of the serialisation of the output.
+	pub hash_algorithm: String,
+	pub hash: String,
+}
+
+/// A derivation written by this evaluation.
+struct Written {
+	/// The hash modulo fixed-output derivations.
+	hash: [u8; 32],
+	input_derivations: Vec<String>,
+}
+
+thread_local! {
+	/// The derivations written so far, by their paths.
+	static DERIVATIONS: RefCell<HashMap<String, Written>> =
+		RefCell::new(HashMap::new());
+}
+
+impl Derivation {
+	/// Formats the derivation in the ATerm format, with input derivations
+	/// given separately so that they may be replaced by their hashes.
+	fn unparse(
+		&self,
+		input_derivations: &BTreeMap<String, BTreeSet<String>>,
+	) -> String {
+		let mut text = String::from("Derive([");
+
+		list_into(&mut text, &self.outputs, |text, (name, output)| {
+			text.push('(');
+			string_into(text, name);
+			text.push(',');
+			string_into(text, &output.path);
+			text.push(',');
+			string_into(text, &output.hash_algorithm);
+			text.push(',');
+			string_into(text, &output.hash);
+			text.push(')');
+		});
+		text.push_str("],[");
+		list_into(&mut text, input_derivations, |text, (path, outputs)| {
+			text.push('(');
+			string_into(text, path);
+			text.push_str(",[");
+			list_into(text, outputs, |text, output| string_into(text, output));
+			text.push_str("])");
+		});
+		text.push_str("],[");
+		list_into(&mut text, &self.input_sources, |text, path| {
+			string_into(text, path)
+		});
+		text.push_str("],");
+		string_into(&mut text, &self.system);
+		text.push(',');
+		string_into(&mut text, &self.builder);
+		text.push_str(",[");
+		list_into(&mut text, &self.arguments, |text, argument| {
+			string_into(text, argument)
+		});
+		text.push_str("],[");
+		list_into(&mut text, &self.env, |text, (name, value)| {
+			text.push('(');
+			string_into(text, name);
+			text.push(',');
+			string_into(text, value);
+			text.push(')');
+		});
+		text.push_str("])");
+
+		text
+	}
+
+	/// Hashes the derivation with its input derivations replaced by their own
+	/// hashes, so that the hash only changes with the outputs of fixed-output
+	/// derivations rather than with how they are built.
+	fn hash_modulo(&self) -> [u8; 32] {
+		if let Some(output) = self
+			.outputs
+			.get("out")
+			.filter(|output| self.outputs.len() == 1 && !output.hash.is_empty())
+		{
+			return sha256(
+				format!(
+					"fixed:out:{}:{}:{}",
+					output.hash_algorithm, output.hash, output.path
+				)
+				.as_bytes(),
+			);
+		}
+
+		let input_derivations = self
+			.input_derivations
+			.iter()
+			.map(|(path, outputs)| {
+				let hash = DERIVATIONS
+					.with_borrow(|derivations| {
+						derivations.get(path).map(|written| written.hash)
+					})
+					.unwrap_or_else(|| {
+						throw(format!(
+							"derivation '{path}' was not instantiated by this \
+							 evaluation"
+						))
+					});
+
+				(base16(&hash), outputs.clone())
+			})
+			.collect();
+
+		sha256(self.unparse(&input_derivations).as_bytes())
+	}
+}
+
+fn list_into<T>(
+	text: &mut String,
+	elements: impl IntoIterator<Item = T>,
+	mut element_into: impl FnMut(&mut String, T),
+) {
+	for (index, element) in elements.into_iter().enumerate() {
+		if index > 0 {
+			text.push(',');
+		}
+
+		element_into(text, element);
+	}
+}
+
+fn string_into(text: &mut String, value: &str) {
+	text.push('"');
+
+	for character in value.chars() {
+		match character {
+			'"' => text.push_str("\\\""),
+			'\\' => text.push_str("\\\\"),
+			'\n' => text.push_str("\\n"),
+			'\r' => text.push_str("\\r"),
+			'\t' => text.push_str("\\t"),
+			character => text.push(character),
+		}
+	}
+
+	text.push('"');
+}
+
+/// Coerces an attribute of a derivation to a string as Nix does, gathering
+/// its context.
+fn coerce_attribute(
+	value: *mut Value,
+	context: &mut BTreeSet<String>,
+) -> String {
+	let mut string = NixString::default();
+	coerce(value, &mut string, Coercion::DERIVATION);
+	context.extend(string.context);
+	string.value
+}
+
+/// Instantiates a derivation from the attributes given to `derivation`,
+/// computing its output paths and the path and contents of its `.drv` file.
+pub fn instantiate(
+	attributes: &BTreeMap<String, *mut Value>,
+) -> (Derivation, String, String) {
+	let name = match attributes.get("name") {
+		Some(&name) => string(name).to_owned(),
+		None => throw("required attribute 'name' missing"),
+	};
+	check_name(&name);
+
+	let ignore_nulls = attributes
+		.get("__ignoreNulls")
+		.is_some_and(|&ignore_nulls| boolean(ignore_nulls));
+	let mut derivation = Derivation::default();
+	let mut context = BTreeSet::new();
+	let mut outputs = vec!["out".to_owned()];
+
+	for (key, &value) in attributes {
+		match key.as_str() {
+			"__ignoreNulls" => {}
+			_ if ignore_nulls
+				&& matches!(unsafe { &*force(value) }, Value::Null) => {}
+			"__structuredAttrs" | "__contentAddressed" | "__impure" => {
+				throw(format!("'{key}' derivations are not supported yet"))
+			}
+			"args" => {
+				for &argument in list(value) {
+					let argument = coerce_attribute(argument, &mut context);
+					derivation.arguments.push(argument);
+				}
+			}
+			_ => {
+				let value = coerce_attribute(value, &mut context);
+
+				match key.as_str() {
+					"builder" => derivation.builder = value.clone(),
+					"system" => derivation.system = value.clone(),
+					"outputs" => {
+						outputs = value
+							.split_whitespace()
+							.map(String::from)
+							.collect();
+					}
+					_ => {}
+				}
+
+				derivation.env.insert(key.clone(), value);
+			}
+		}
+	}
+
+	if !attributes.contains_key("builder") {
+		throw("required attribute 'builder' missing");
+	} else if !attributes.contains_key("system") {
+		throw("required attribute 'system' missing");
+	}
+
+	for element in context {
+		if let Some(rest) = element.strip_prefix('!') {
+			let (output, path) =
+				rest.split_once('!').expect("valid derivation context");
+			derivation
+				.input_derivations
+				.entry(path.into())
+				.or_default()
+				.insert(output.into());
+		} else if element.starts_with('=') {
+			throw(
+				"depending on the closure of a derivation is not supported yet",
+			);
+		} else {
+			derivation.input_sources.insert(element);
+		}
+	}
+
+	let mut names = BTreeSet::new();
+
+	for output in &outputs {
+		if output == "drv" {
+			throw("invalid derivation output name 'drv'");
+		} else if !names.insert(output) {
+			throw(format!("duplicate derivation output '{output}'"));
+		}
+	}
+
+	if let Some(&hash) = attributes.get("outputHash") {
+		if outputs != ["out"] {
+			throw("multiple outputs are not supported in fixed-output derivations");
+		}
+
+		let algorithm = attributes
+			.get("outputHashAlgo")
+			.map(|&algorithm| string(algorithm))
+			.filter(|algorithm| !algorithm.is_empty())
+			.map(|algorithm| check(Algorithm::parse(algorithm)));
+		let hash = check(Hash::parse(string(hash), algorithm));
+		let recursive =
+			match attributes.get("outputHashMode").map(|&mode| string(mode)) {
+				None | Some("flat") => false,
+				Some("recursive") => true,
+				Some(mode) => throw(format!(
+					"invalid value '{mode}' for 'outputHashMode'"
+				)),
+			};
+		let path = store_dir().make_fixed_output_path(recursive, &hash, &name);
+
+		derivation.env.insert("out".into(), path.clone());
+		derivation.outputs.insert(
+			"out".into(),
+			Output {
+				path,
+				hash_algorithm: format!(
+					"{}{}",
+					if recursive { "r:" } else { "" },
+					hash.algorithm.name()
+				),
+				hash: hash.base16(),
+			},
+		);
+	} else {
+		for output in &outputs {
+			derivation.env.insert(output.clone(), String::new());
+			derivation.outputs.insert(output.clone(), Output::default());
+		}
+
+		let hash = derivation.hash_modulo();
+		let store = store_dir();
+
+		for output in &outputs {
+			let path = store.make_output_path(output, &hash, &name);
+			derivation.env.insert(output.clone(), path.clone());
+			derivation.outputs.get_mut(output).expect("output").path = path;
+		}
+	}
+
+	let text = derivation.unparse(&derivation.input_derivations);
+	let references = derivation
+		.input_sources
+		.iter()
+		.chain(derivation.input_derivations.keys())
+		.cloned()
+		.collect();
+	let path =
+		store_dir().make_text_path(&format!("{name}.drv"), &text, &references);
+
+	(derivation, path, text)
+}
+
+fn context_string(value: &str, context: String) -> *mut Value {
+	Value::String(
+		NixString {
+			value: value.into(),
+			context: [context].into(),
+		}
+		.into(),
+	)
+	.alloc()
+}
+
+/// Records a derivation written by this evaluation, so that other derivations
+/// may depend on it.
+pub(crate) fn record(path: &str, derivation: &Derivation) {
+	let written = Written {
+		hash: derivation.hash_modulo(),
+		input_derivations: derivation
+			.input_derivations
+			.keys()
+			.cloned()
+			.collect(),
+	};
+	DERIVATIONS.with_borrow_mut(|derivations| {
+		derivations.insert(path.into(), written)
+	});
+}
+
+/// Gets the paths of the input derivations of a derivation written by this
+/// evaluation.
+pub fn input_derivations(path: &str) -> Option<Vec<String>> {
+	DERIVATIONS.with_borrow(|derivations| {
+		derivations
+			.get(path)
+			.map(|written| written.input_derivations.clone())
+	})
+}
+
+/// Instantiates a derivation and writes its `.drv` file, evaluating to a set
+/// of the path of the `.drv` file and of each output path.
+pub fn derivation_strict(arguments: &[*mut Value]) -> *mut Value {
+	let (derivation, path, text) = instantiate(attributes(arguments[0]));
+	store::write(&path, &text);
+	record(&path, &derivation);
+
+	let _pause = gc::pause();
+	let mut set = BTreeMap::from([(
+		"drvPath".to_owned(),
+		context_string(&path, format!("={path}")),
+	)]);
+
+	for (name, output) in &derivation.outputs {
+		set.insert(
+			name.clone(),
+			context_string(&output.path, format!("!{name}!{path}")),
+		);
+	}
+
+	Value::AttrSet(set.into()).alloc()
+}
+
+unsafe extern "C-unwind" fn strict_env(env: *mut Env) -> *mut Value {
+	let env = &*env;
+	derivation_strict(&env.0)
+}
+
+/// Creates the set of a derivation as `derivation` does, with an attribute
+/// set per output, instantiating the derivation when one of its paths is
+/// used.
+pub fn derivation(arguments: &[*mut Value]) -> *mut Value {
+	let attributes = attributes(arguments[0]);
+	let outputs = match attributes.get("outputs") {
+		Some(&outputs) => list(outputs)
+			.iter()
+			.map(|&output| string(output).to_owned())
+			.collect(),
+		None => vec!["out".to_owned()],
+	};
+
+	let _pause = gc::pause();
+	let env = Env(vec![arguments[0]]).alloc();
+	let strict = Value::Thunk(Thunk::new(strict_env, env).into()).alloc();
+	let sets = outputs
+		.iter()
+		.map(|_| Value::AttrSet(BTreeMap::new().into()).alloc())
+		.collect::<Vec<_>>();
+
+	let mut common = attributes.clone();
+	common.extend(outputs.iter().cloned().zip(sets.iter().copied()));
+	common.insert("all".into(), Value::List(sets.clone().into()).alloc());
+	common.insert("drvAttrs".into(), arguments[0]);
+
+	for (output, &set) in outputs.iter().zip(&sets) {
+		let mut attributes = common.clone();
+		attributes.insert("outPath".into(), suspend_select(strict, output));
+		attributes.insert("drvPath".into(), suspend_select(strict, "drvPath"));
+		attributes.insert("type".into(), Value::string("derivation").alloc());
+		attributes.insert(
+			"outputName".into(),
+			Value::string(output.as_str()).alloc(),
+		);
+
+		// The sets refer to each other, so they are filled after their
+		// creation.
+		unsafe { *set = Value::AttrSet(attributes.into()) };
+	}
+
+	match sets.first() {
+		Some(&set) => set,
+		None => throw("derivation has no outputs"),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::error::catch;
+
+	fn set(attributes: &[(&str, *mut Value)]) -> BTreeMap<String, *mut Value> {
+		attributes
+			.iter()
+			.map(|&(name, value)| (name.to_owned(), value))
+			.collect()
+	}
+
+	fn string(value: &str) -> *mut Value {
+		Value::string(value).alloc()
+	}
+
+	#[test]
+	fn unparse_derivation() {
+		let derivation = Derivation {
+			outputs: [("out".into(), Output::default())].into(),
+			input_sources: ["/nix/store/a".into()].into(),
+			system: "x86_64-linux".into(),
+			builder: "/bin/sh".into(),
+			arguments: vec!["-c".into(), "echo \"$out\"\n".into()],
+			env: [("out".into(), String::new())].into(),
+			..Default::default()
+		};
+		let inputs = [(
+			"/nix/store/b.drv".to_owned(),
+			BTreeSet::from(["dev".to_owned(), "out".to_owned()]),
+		)]
+		.into();
+
+		assert_eq!(
+			derivation.unparse(&inputs),
+			r#"Derive([("out","","","")],[("/nix/store/b.drv",["dev","out"])],["/nix/store/a"],"x86_64-linux","/bin/sh",["-c","echo \"$out\"\n"],[("out","")])"#
+		);
+	}
+
+	#[test]
+	fn instantiate_derivation() {
+		let attributes = set(&[
+			("name", string("hello")),
+			("system", string("x86_64-linux")),
+			("builder", string("/bin/sh")),
+			(
+				"outputs",
+				Value::List(vec![string("out"), string("dev")].into()).alloc(),
+			),
+			("flag", Value::Bool(true).alloc()),
+		]);
+		let (derivation, path, text) = instantiate(&attributes);
+		let out = &derivation.outputs["out"].path;
+
+		assert!(path.ends_with("-hello.drv"));
+		assert!(out.ends_with("-hello"));
+		assert!(derivation.outputs["dev"].path.ends_with("-hello-dev"));
+		assert_eq!(derivation.env["flag"], "1");
+		assert_eq!(derivation.env["outputs"], "out dev");
+		assert_eq!(&derivation.env["out"], out);
+		assert!(text.starts_with(&format!(
+			r#"Derive([("dev","{}","",""),("out","{out}","","")],[],[]"#,
+			derivation.outputs["dev"].path
+		)));
+		assert_eq!(instantiate(&attributes).1, path);
+	}
+
+	/// The derivation of the Nix Pills, whose paths and `.drv` file are those
+	/// written by `nix-instantiate`.
+	#[test]
+	fn instantiate_known_derivation() {
+		let (_, path, text) = instantiate(&set(&[
+			("name", string("myname")),
+			("builder", string("mybuilder")),
+			("system", string("mysystem")),
+		]));
+		let out = "/nix/store/40s0qmrfb45vlh6610rk29ym318dswdr-myname";
+
+		assert_eq!(
+			path,
+			"/nix/store/z3hhlxbckx4g3n9sw91nnvlkjvyw754p-myname.drv"
+		);
+		assert_eq!(
+			text,
+			format!(
+				r#"Derive([("out","{out}","","")],[],[],"mysystem","mybuilder",[],[("builder","mybuilder"),("name","myname"),("out","{out}"),("system","mysystem")])"#
+			)
+		);
+	}
+
+	#[test]
+	fn instantiate_fixed_output_derivation() {
+		let attributes = set(&[
+			("name", string("source")),
+			("system", string("builtin")),
+			("builder", string("builtin:fetchurl")),
+			("outputHashMode", string("recursive")),
+			(
+				"outputHash",
+				string("sha256-ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0="),
+			),
+		]);
+		let (derivation, _, _) = instantiate(&attributes);
+		let output = &derivation.outputs["out"];
+
+		assert_eq!(output.hash_algorithm, "r:sha256");
+		assert_eq!(
+			output.hash,
+			"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+		);
+		assert_eq!(
+			output.path,
+			store_dir().make_store_path("source", &sha256(b"abc"), "source")
+		);
+	}
+
+	#[test]
+	fn reject_invalid_derivations() {
+		let error = |attributes: &[(&str, *mut Value)]| {
+			catch(|| instantiate(&set(attributes)))
+				.unwrap_err()
+				.message()
+				.to_owned()
+		};
+
+		assert_eq!(
+			error(&[("name", string("a"))]),
+			"required attribute 'builder' missing"
+		);
+		assert_eq!(
+			error(&[
+				("name", string("a")),
+				("builder", string("b")),
+				("system", string("c")),
+				("outputs", Value::List(vec![string("drv")].into()).alloc()),
+			]),
+			"invalid derivation output name 'drv'"
+		);
+	}
+}
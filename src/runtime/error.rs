@@ -0,0 +1,240 @@
+//! Evaluation errors.
+
+use std::{
+	fmt,
+	panic::{self, AssertUnwindSafe},
+};
+
+/// An error aborting evaluation, such as a type error or `throw`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EvalError {
+	message: String,
+	/// The source position the error is reported at, as `file:line:column`.
+	position: Option<String>,
+	/// What was being evaluated when the error occurred, the innermost first.
+	trace: Vec<Frame>,
+	/// Whether `builtins.tryEval` catches the error, as it does for `throw`
+	/// and failed assertions.
+	catchable: bool,
+}
+
+/// A frame of the trace of an error, such as a call of a function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+	/// What was being evaluated, as `while calling 'f'`.
+	pub description: String,
+	pub position: Option<String>,
+}
+
+impl EvalError {
+	pub fn new(message: impl Into<String>) -> Self {
+		Self {
+			message: message.into(),
+			position: None,
+			trace: Vec::new(),
+			catchable: false,
+		}
+	}
+
+	/// Reports the error at a source position.
+	pub fn at(mut self, position: impl Into<String>) -> Self {
+		self.position = Some(position.into());
+		self
+	}
+
+	/// Makes the error catchable by `builtins.tryEval`.
+	pub fn catchable(mut self) -> Self {
+		self.catchable = true;
+		self
+	}
+
+	/// Gets the message of the error.
+	pub fn message(&self) -> &str {
+		&self.message
+	}
+
+	/// Gets the source position of the error.
+	pub fn position(&self) -> Option<&str> {
+		self.position.as_deref()
+	}
+
+	/// Gets the trace of the error, the innermost frame first.
+	pub fn trace(&self) -> &[Frame] {
+		&self.trace
+	}
+
+	/// Tests if `builtins.tryEval` catches the error.
+	pub fn is_catchable(&self) -> bool {
+		self.catchable
+	}
+
+	/// Aborts evaluation with the error, unwinding to the closest [`catch`].
+	pub fn raise(self) -> ! {
+		panic::resume_unwind(Box::new(self))
+	}
+}
+
+/// The indentation of the lines following the first one, as Nix prints
+/// errors.
+const INDENT: &str = "       ";
+
+impl fmt::Display for EvalError {
+	fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+		if self.trace.is_empty() {
+			write!(formatter, "error: {}", self.message)?;
+		} else {
+			write!(formatter, "error:")?;
+			let mut frames = self.trace.iter().rev().peekable();
+
+			while let Some(frame) = frames.next() {
+				write!(formatter, "\n{INDENT}… {}", frame.description)?;
+
+				if let Some(position) = &frame.position {
+					write!(formatter, "\n\n{INDENT}  at {position}:")?;
+				}
+
+				// Frames of recursive functions are repeated, so they are
+				// only printed once.
+				let mut duplicates = 0;
+
+				while frames.next_if_eq(&frame).is_some() {
+					duplicates += 1;
+				}
+
+				if duplicates > 0 {
+					write!(
+						formatter,
+						"\n\n{INDENT}({duplicates} duplicate frames omitted)"
+					)?;
+				}
+
+				writeln!(formatter)?;
+			}
+
+			write!(formatter, "\n{INDENT}error: {}", self.message)?;
+		}
+
+		if let Some(position) = &self.position {
+			write!(formatter, "\n\n{INDENT}at {position}:")?;
+		}
+
+		Ok(())
+	}
+}
+
+impl std::error::Error for EvalError {}
+
+/// Aborts evaluation with an error, unwinding to the closest [`catch`].
+pub fn throw(message: impl Into<String>) -> ! {
+	EvalError::new(message).raise()
+}
+
+/// Aborts evaluation with an error reported at a source position, unless it
+/// is unknown.
+pub fn throw_at(message: impl Into<String>, position: Option<&str>) -> ! {
+	let error = EvalError::new(message);
+
+	match position {
+		Some(position) => error.at(position),
+		None => error,
+	}
+	.raise()
+}
+
+/// Runs an evaluation, catching the errors it throws. Panics other than
+/// evaluation errors keep unwinding.
+pub fn catch<T>(evaluate: impl FnOnce() -> T) -> Result<T, EvalError> {
+	panic::catch_unwind(AssertUnwindSafe(evaluate)).map_err(|payload| {
+		match payload.downcast::<EvalError>() {
+			Ok(error) => *error,
+			Err(payload) => panic::resume_unwind(payload),
+		}
+	})
+}
+
+/// Runs an evaluation, adding a frame to the trace of the errors it throws.
+pub fn trace<T>(
+	description: impl FnOnce() -> String,
+	position: Option<&str>,
+	evaluate: impl FnOnce() -> T,
+) -> T {
+	match catch(evaluate) {
+		Ok(value) => value,
+		Err(mut error) => {
+			error.trace.push(Frame {
+				description: description(),
+				position: position.map(String::from),
+			});
+			error.raise()
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn catch_error() {
+		assert_eq!(
+			catch(|| throw("oops")),
+			Err::<(), _>(EvalError::new("oops"))
+		);
+		assert_eq!(catch(|| 42), Ok(42));
+	}
+
+	#[test]
+	fn display_position() {
+		let error = catch(|| throw_at("oops", Some("a.nix:1:2"))).unwrap_err();
+
+		assert_eq!(error.position(), Some("a.nix:1:2"));
+		assert_eq!(error.to_string(), "error: oops\n\n       at a.nix:1:2:");
+	}
+
+	#[test]
+	fn display_trace() {
+		let error = catch(|| {
+			trace(
+				|| "while evaluating 'a'".into(),
+				Some("a.nix:1:1"),
+				|| {
+					trace(
+						|| "while calling 'f'".into(),
+						Some("a.nix:1:5"),
+						|| {
+							trace(
+								|| "while calling 'f'".into(),
+								Some("a.nix:1:5"),
+								|| throw("oops"),
+							)
+						},
+					)
+				},
+			)
+		})
+		.unwrap_err();
+
+		assert_eq!(error.trace().len(), 3);
+		assert_eq!(
+			error.to_string(),
+			"error:
+       … while evaluating 'a'
+
+         at a.nix:1:1:
+
+       … while calling 'f'
+
+         at a.nix:1:5:
+
+       (1 duplicate frames omitted)
+
+       error: oops"
+		);
+	}
+
+	#[test]
+	#[should_panic(expected = "bug")]
+	fn resume_panic() {
+		let _ = catch(|| panic!("bug"));
+	}
+}
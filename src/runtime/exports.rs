@@ -0,0 +1,1435 @@
+//! The functions called by compiled code.
+//!
+//! Values are passed as `*mut Value` and Booleans as `i32`. Names and strings
+//! are passed as a pointer and a length, and attribute paths as their names joined by
+//! NUL bytes, which Nix strings cannot contain. Source positions are passed
+//! as names of the form `file:line:column`, empty if unknown. Every pointer must come from
+//! the runtime or point to a valid name, which compiled code guarantees.
+
+#![allow(clippy::missing_safety_doc)]
+
+use crate::{
+	builtins,
+	error::{self, catch, throw, throw_at, EvalError},
+	files::{self, FileFn},
+	gc, store,
+	thunk::{force, Thunk},
+	value::{
+		canonicalize, print, Closure, Env, LambdaFn, NixString, ThunkFn, Value,
+	},
+};
+use std::{cell::Cell, collections::BTreeMap};
+
+macro_rules! exports {
+	($($name:ident),* $(,)?) => {
+		/// Gets the name and address of every function called by compiled
+		/// code.
+		pub fn symbols() -> Vec<(&'static str, *mut ())> {
+			vec![$((stringify!($name), $name as *mut ())),*]
+		}
+	};
+}
+
+exports!(
+	nix_null,
+	nix_bool,
+	nix_int,
+	nix_float,
+	nix_string,
+	nix_path,
+	nix_builtin,
+	nix_file,
+	nix_env_new,
+	nix_env_set,
+	nix_env_get,
+	nix_thunk,
+	nix_blank,
+	nix_bind,
+	nix_force,
+	nix_lambda,
+	nix_apply,
+	nix_formals,
+	nix_attrset_new,
+	nix_attrset_insert,
+	nix_attrset_insert_dynamic,
+	nix_select,
+	nix_select_dynamic,
+	nix_has_attr,
+	nix_has_attr_dynamic,
+	nix_update,
+	nix_list_new,
+	nix_list_set,
+	nix_concat,
+	nix_interpolate,
+	nix_with,
+	nix_truthy,
+	nix_assert,
+	nix_add,
+	nix_sub,
+	nix_mul,
+	nix_div,
+	nix_overflow,
+	nix_division_by_zero,
+	nix_eq,
+	nix_lt,
+	nix_not,
+	nix_run,
+);
+
+unsafe fn name<'a>(pointer: *const u8, length: i64) -> &'a str {
+	if length == 0 {
+		""
+	} else {
+		std::str::from_utf8_unchecked(std::slice::from_raw_parts(
+			pointer,
+			length as usize,
+		))
+	}
+}
+
+thread_local! {
+	/// The position of the innermost call from compiled code, which functions
+	/// report errors about their argument at.
+	static CALL_SITE: Cell<Option<&'static str>> = const { Cell::new(None) };
+}
+
+/// Gets a source position, which lives as long as compiled code.
+unsafe fn position(pointer: *const u8, length: i64) -> Option<&'static str> {
+	Some(name(pointer, length)).filter(|position| !position.is_empty())
+}
+
+unsafe fn path<'a>(pointer: *const u8, length: i64) -> Vec<&'a str> {
+	name(pointer, length).split('\0').collect()
+}
+
+pub(crate) fn attributes<'a>(
+	value: *mut Value,
+) -> &'a BTreeMap<String, *mut Value> {
+	match unsafe { &*force(value) } {
+		Value::AttrSet(attributes) => attributes,
+		value => throw(format!(
+			"value is {} while a set was expected",
+			value.type_name()
+		)),
+	}
+}
+
+pub(crate) fn list<'a>(value: *mut Value) -> &'a [*mut Value] {
+	match unsafe { &*force(value) } {
+		Value::List(elements) => elements,
+		value => throw(format!(
+			"value is {} while a list was expected",
+			value.type_name()
+		)),
+	}
+}
+
+pub(crate) fn string<'a>(value: *mut Value) -> &'a str {
+	&string_with_context(value).value
+}
+
+pub(crate) fn string_with_context<'a>(value: *mut Value) -> &'a NixString {
+	match unsafe { &*force(value) } {
+		Value::String(string) => string,
+		value => throw(format!(
+			"value is {} while a string was expected",
+			value.type_name()
+		)),
+	}
+}
+
+/// Gets the names of an attribute path computed at runtime.
+unsafe fn names<'a>(names: *mut Env) -> Vec<&'a str> {
+	let names = &*names;
+	names.0.iter().map(|&name| string(name)).collect()
+}
+
+pub(crate) fn integer(value: *mut Value) -> i64 {
+	match unsafe { &*force(value) } {
+		Value::Int(value) => *value,
+		value => throw(format!(
+			"value is {} while an integer was expected",
+			value.type_name()
+		)),
+	}
+}
+
+pub(crate) fn boolean(value: *mut Value) -> bool {
+	match unsafe { &*force(value) } {
+		Value::Bool(value) => *value,
+		value => throw(format!(
+			"value is {} while a Boolean was expected",
+			value.type_name()
+		)),
+	}
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn nix_null() -> *mut Value {
+	Value::Null.alloc()
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn nix_bool(value: i32) -> *mut Value {
+	Value::Bool(value != 0).alloc()
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn nix_int(value: i64) -> *mut Value {
+	Value::Int(value).alloc()
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn nix_float(value: f64) -> *mut Value {
+	Value::Float(value).alloc()
+}
+
+/// Creates a string without context.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn nix_string(
+	pointer: *const u8,
+	length: i64,
+) -> *mut Value {
+	Value::string(name(pointer, length)).alloc()
+}
+
+/// Creates a path, which compiled code makes absolute.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn nix_path(
+	pointer: *const u8,
+	length: i64,
+) -> *mut Value {
+	Value::Path(name(pointer, length).to_owned().into()).alloc()
+}
+
+/// Gets a value of the base environment other than `true`, `false` and
+/// `null`, such as `builtins` or `map`.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn nix_builtin(
+	pointer: *const u8,
+	length: i64,
+) -> *mut Value {
+	builtins::get(name(pointer, length))
+}
+
+/// Registers the code of a compiled file for `import`.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn nix_file(
+	pointer: *const u8,
+	length: i64,
+	function: FileFn,
+) {
+	files::register(name(pointer, length), function)
+}
+
+/// Creates an environment of `size` captured values.
+#[no_mangle]
+pub extern "C-unwind" fn nix_env_new(size: i64) -> *mut Env {
+	Env(vec![std::ptr::null_mut(); size as usize]).alloc()
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn nix_env_set(
+	env: *mut Env,
+	index: i64,
+	value: *mut Value,
+) {
+	let env = &mut *env;
+	env.0[index as usize] = value;
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn nix_env_get(
+	env: *mut Env,
+	index: i64,
+) -> *mut Value {
+	let env = &*env;
+	env.0[index as usize]
+}
+
+/// Creates a thunk, named after the binding at a position it is the value
+/// of unless the name is empty.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn nix_thunk(
+	function: ThunkFn,
+	env: *mut Env,
+	name_pointer: *const u8,
+	name_length: i64,
+	site_pointer: *const u8,
+	site_length: i64,
+) -> *mut Value {
+	let thunk = match name(name_pointer, name_length) {
+		"" => Thunk::new(function, env),
+		name => Thunk::new(function, env)
+			.named(name, position(site_pointer, site_length)),
+	};
+
+	Value::Thunk(thunk.into()).alloc()
+}
+
+/// Creates a recursive binding, bound with [`nix_bind`].
+#[no_mangle]
+pub extern "C-unwind" fn nix_blank() -> *mut Value {
+	Value::Thunk(Thunk::blank().into()).alloc()
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn nix_bind(blank: *mut Value, value: *mut Value) {
+	match &*blank {
+		Value::Thunk(thunk) => thunk.bind(value),
+		_ => unreachable!("binding a value which is not blank"),
+	}
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn nix_force(value: *mut Value) -> *mut Value {
+	force(value)
+}
+
+/// Creates a lambda, named after the binding it is the value of unless the
+/// name is empty.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn nix_lambda(
+	function: LambdaFn,
+	env: *mut Env,
+	name_pointer: *const u8,
+	name_length: i64,
+) -> *mut Value {
+	let name = name(name_pointer, name_length);
+
+	Value::Lambda(
+		Closure {
+			function,
+			env,
+			name,
+		}
+		.into(),
+	)
+	.alloc()
+}
+
+/// Applies a function to an argument at a call site.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn nix_apply(
+	function: *mut Value,
+	argument: *mut Value,
+	site_pointer: *const u8,
+	site_length: i64,
+) -> *mut Value {
+	apply(function, argument, position(site_pointer, site_length))
+}
+
+/// Applies a function to an argument, at an unknown call site if called by
+/// the runtime itself. Errors are traced to calls at known sites.
+pub fn apply(
+	function: *mut Value,
+	argument: *mut Value,
+	site: Option<&'static str>,
+) -> *mut Value {
+	let function = unsafe { &*force(function) };
+	let description = || match function {
+		Value::Lambda(closure) if closure.name.is_empty() => {
+			"while calling anonymous lambda".into()
+		}
+		Value::Lambda(closure) => format!("while calling '{}'", closure.name),
+		Value::PrimOp(primop) => {
+			format!("while calling the '{}' builtin", primop.name)
+		}
+		_ => unreachable!(),
+	};
+	let call = || {
+		CALL_SITE.set(site);
+
+		match function {
+			Value::Lambda(closure) => unsafe {
+				(closure.function)(closure.env, argument)
+			},
+			Value::PrimOp(primop) => primop.apply(argument),
+			_ => unreachable!(),
+		}
+	};
+
+	match function {
+		Value::Lambda(_) | Value::PrimOp(_) if site.is_some() => {
+			error::trace(description, site, call)
+		}
+		Value::Lambda(_) | Value::PrimOp(_) => call(),
+		value => throw_at(
+			format!(
+				"attempt to call something which is not a function but {}",
+				value.type_name()
+			),
+			site,
+		),
+	}
+}
+
+/// Checks the argument of a function with formals against their names, the
+/// names of those without defaults and whether there is an ellipsis. Errors
+/// are reported at the call site, and name the function unless it is
+/// anonymous.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn nix_formals(
+	argument: *mut Value,
+	function_pointer: *const u8,
+	function_length: i64,
+	names: *const u8,
+	names_length: i64,
+	required: *const u8,
+	required_length: i64,
+	ellipsis: i32,
+) {
+	let site = CALL_SITE.get();
+	let function = match name(function_pointer, function_length) {
+		"" => "anonymous lambda",
+		function => function,
+	};
+	let attributes = attributes(argument);
+
+	for name in path(required, required_length) {
+		if !name.is_empty() && !attributes.contains_key(name) {
+			throw_at(
+				format!(
+					"function '{function}' called without required argument \
+					 '{name}'"
+				),
+				site,
+			);
+		}
+	}
+
+	if ellipsis == 0 {
+		let names = path(names, names_length);
+
+		for name in attributes.keys() {
+			if !names.contains(&name.as_str()) {
+				throw_at(
+					format!(
+						"function '{function}' called with unexpected \
+						 argument '{name}'"
+					),
+					site,
+				);
+			}
+		}
+	}
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn nix_attrset_new() -> *mut Value {
+	Value::AttrSet(BTreeMap::new().into()).alloc()
+}
+
+/// Inserts an attribute into a set under construction.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn nix_attrset_insert(
+	set: *mut Value,
+	name_pointer: *const u8,
+	name_length: i64,
+	value: *mut Value,
+) {
+	let Value::AttrSet(attributes) = &mut *set else {
+		unreachable!("inserting into a value which is not a set")
+	};
+	let name = name(name_pointer, name_length);
+
+	if attributes.insert(name.into(), value).is_some() {
+		throw(format!("attribute '{name}' already defined"));
+	}
+}
+
+/// Inserts an attribute with a computed name into a set under construction,
+/// unless the name is `null`.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn nix_attrset_insert_dynamic(
+	set: *mut Value,
+	name: *mut Value,
+	value: *mut Value,
+) {
+	if let Value::Null = &*force(name) {
+		return;
+	}
+
+	let Value::AttrSet(attributes) = &mut *set else {
+		unreachable!("inserting into a value which is not a set")
+	};
+	let name = string(name);
+
+	if attributes.insert(name.into(), value).is_some() {
+		throw(format!("dynamic attribute '{name}' already defined"));
+	}
+}
+
+/// Selects an attribute path, evaluating to `default` when it is missing
+/// unless `default` is null.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn nix_select(
+	set: *mut Value,
+	path_pointer: *const u8,
+	path_length: i64,
+	default: *mut Value,
+) -> *mut Value {
+	select(set, &path(path_pointer, path_length), default)
+}
+
+/// Selects an attribute path whose names are computed, as `a.${b}`.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn nix_select_dynamic(
+	set: *mut Value,
+	path: *mut Env,
+	default: *mut Value,
+) -> *mut Value {
+	// The names borrow from the strings of the path.
+	let _roots = gc::root(&(*path).0);
+	select(set, &names(path), default)
+}
+
+fn select(set: *mut Value, path: &[&str], default: *mut Value) -> *mut Value {
+	let mut value = set;
+
+	for &name in path {
+		let attribute = match unsafe { &*force(value) } {
+			Value::AttrSet(attributes) if !default.is_null() => {
+				attributes.get(name)
+			}
+			_ if !default.is_null() => None,
+			_ => attributes(value).get(name),
+		};
+
+		value = match attribute {
+			Some(&attribute) => attribute,
+			None if !default.is_null() => return default,
+			None => throw(format!("attribute '{name}' missing")),
+		};
+	}
+
+	value
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn nix_has_attr(
+	set: *mut Value,
+	path_pointer: *const u8,
+	path_length: i64,
+) -> *mut Value {
+	has_attr(set, &path(path_pointer, path_length))
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn nix_has_attr_dynamic(
+	set: *mut Value,
+	path: *mut Env,
+) -> *mut Value {
+	let _roots = gc::root(&(*path).0);
+	has_attr(set, &names(path))
+}
+
+fn has_attr(set: *mut Value, path: &[&str]) -> *mut Value {
+	let mut value = set;
+
+	for &name in path {
+		value = match unsafe { &*force(value) } {
+			Value::AttrSet(attributes) => match attributes.get(name) {
+				Some(&attribute) => attribute,
+				None => return nix_bool(0),
+			},
+			_ => return nix_bool(0),
+		};
+	}
+
+	nix_bool(1)
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn nix_update(
+	lhs: *mut Value,
+	rhs: *mut Value,
+) -> *mut Value {
+	// The attributes of the left operand are copied before the right operand
+	// is forced.
+	let _roots = gc::root(&[lhs, rhs]);
+	let mut attributes = attributes(lhs).clone();
+	attributes.extend(
+		self::attributes(rhs)
+			.iter()
+			.map(|(name, &value)| (name.clone(), value)),
+	);
+
+	Value::AttrSet(attributes.into()).alloc()
+}
+
+/// Creates a list of `size` elements, set with [`nix_list_set`].
+#[no_mangle]
+pub extern "C-unwind" fn nix_list_new(size: i64) -> *mut Value {
+	Value::List(vec![std::ptr::null_mut(); size as usize].into()).alloc()
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn nix_list_set(
+	list: *mut Value,
+	index: i64,
+	value: *mut Value,
+) {
+	let Value::List(elements) = &mut *list else {
+		unreachable!("setting an element of a value which is not a list")
+	};
+
+	elements[index as usize] = value;
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn nix_concat(
+	lhs: *mut Value,
+	rhs: *mut Value,
+) -> *mut Value {
+	let _roots = gc::root(&[lhs, rhs]);
+	Value::List([list(lhs), list(rhs)].concat().into()).alloc()
+}
+
+/// Interpolates values into a string, as `"a${b}c"` does with the parts `a`,
+/// `b` and `c`.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn nix_interpolate(parts: *mut Env) -> *mut Value {
+	let parts = &*parts;
+	let _roots = gc::root(&parts.0);
+	let mut string = NixString::default();
+
+	for &part in &parts.0 {
+		coerce(part, &mut string, Coercion::INTERPOLATE);
+	}
+
+	Value::String(string.into()).alloc()
+}
+
+/// How [`coerce`] coerces values to strings.
+#[derive(Clone, Copy)]
+pub(crate) struct Coercion {
+	/// Whether Booleans, numbers, `null` and lists are coerced too.
+	pub more: bool,
+	/// Whether paths are copied to the store, so that the string refers to
+	/// the copy.
+	pub copy: bool,
+}
+
+impl Coercion {
+	pub const PLAIN: Self = Self {
+		more: false,
+		copy: false,
+	};
+	pub const INTERPOLATE: Self = Self {
+		more: false,
+		copy: true,
+	};
+	pub const TO_STRING: Self = Self {
+		more: true,
+		copy: false,
+	};
+	pub const DERIVATION: Self = Self {
+		more: true,
+		copy: true,
+	};
+}
+
+/// Coerces a value to a string, appending it and its context to `output`.
+pub(crate) fn coerce(
+	value: *mut Value,
+	output: &mut NixString,
+	coercion: Coercion,
+) {
+	let value = force(value);
+	let _root = gc::root(&[value]);
+	let more = coercion.more;
+
+	match unsafe { &*value } {
+		Value::String(string) => output.push(string),
+		Value::Path(path) if coercion.copy => {
+			let path = store::copy_path(path);
+			output.value.push_str(&path);
+			output.context.insert(path);
+		}
+		Value::Path(path) => output.value.push_str(path),
+		Value::AttrSet(attributes) => {
+			if let Some(&function) = attributes.get("__toString") {
+				coerce(apply(function, value, None), output, coercion)
+			} else if let Some(&path) = attributes.get("outPath") {
+				coerce(path, output, coercion)
+			} else {
+				throw("cannot coerce a set to a string")
+			}
+		}
+		Value::Bool(true) if more => output.value.push('1'),
+		Value::Bool(false) | Value::Null if more => {}
+		Value::Int(value) if more => output.value.push_str(&value.to_string()),
+		Value::Float(value) if more => {
+			output.value.push_str(&format!("{value:.6}"))
+		}
+		Value::List(elements) if more => {
+			for (index, &element) in elements.iter().enumerate() {
+				coerce(element, output, coercion);
+
+				// As Nix does, empty lists are not followed by a separator.
+				let empty = matches!(
+					unsafe { &*force(element) },
+					Value::List(elements) if elements.is_empty()
+				);
+
+				if index + 1 < elements.len() && !empty {
+					output.value.push(' ');
+				}
+			}
+		}
+		value => {
+			throw(format!("cannot coerce {} to a string", value.type_name()))
+		}
+	}
+}
+
+/// Looks a variable up in the namespaces of nested `with` expressions, the
+/// innermost first.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn nix_with(
+	namespaces: *mut Env,
+	count: i64,
+	name_pointer: *const u8,
+	name_length: i64,
+) -> *mut Value {
+	let name = name(name_pointer, name_length);
+	let namespaces = &*namespaces;
+
+	for &namespace in &namespaces.0[..count as usize] {
+		if let Some(&value) = attributes(namespace).get(name) {
+			return value;
+		}
+	}
+
+	throw(format!("undefined variable '{name}'"))
+}
+
+/// Tests a condition, which must be a Boolean.
+#[no_mangle]
+pub extern "C-unwind" fn nix_truthy(value: *mut Value) -> i32 {
+	boolean(value) as i32
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn nix_assert(condition: *mut Value) {
+	if !boolean(condition) {
+		EvalError::new("assertion failed").catchable().raise();
+	}
+}
+
+enum Numbers {
+	Ints(i64, i64),
+	Floats(f64, f64),
+}
+
+fn numbers(
+	lhs: *mut Value,
+	rhs: *mut Value,
+	site: Option<&'static str>,
+) -> Numbers {
+	let (lhs, rhs) = unsafe { (&*force(lhs), &*force(rhs)) };
+
+	match (lhs, rhs) {
+		(Value::Int(lhs), Value::Int(rhs)) => Numbers::Ints(*lhs, *rhs),
+		(Value::Int(lhs), Value::Float(rhs)) => {
+			Numbers::Floats(*lhs as f64, *rhs)
+		}
+		(Value::Float(lhs), Value::Int(rhs)) => {
+			Numbers::Floats(*lhs, *rhs as f64)
+		}
+		(Value::Float(lhs), Value::Float(rhs)) => Numbers::Floats(*lhs, *rhs),
+		(Value::Int(_) | Value::Float(_), value) | (value, _) => throw_at(
+			format!(
+				"value is {} while a number was expected",
+				value.type_name()
+			),
+			site,
+		),
+	}
+}
+
+/// Applies the arithmetic operator `symbol` to numbers. Integers are signed
+/// 64-bit integers whose overflow is an error.
+fn arithmetic(
+	lhs: *mut Value,
+	rhs: *mut Value,
+	site: Option<&'static str>,
+	symbol: char,
+	int: fn(i64, i64) -> Option<i64>,
+	float: fn(f64, f64) -> f64,
+) -> *mut Value {
+	match numbers(lhs, rhs, site) {
+		Numbers::Ints(lhs, rhs) => match int(lhs, rhs) {
+			Some(result) => Value::Int(result),
+			None => overflow(lhs, symbol, rhs, site),
+		},
+		Numbers::Floats(lhs, rhs) => Value::Float(float(lhs, rhs)),
+	}
+	.alloc()
+}
+
+/// Reports the overflow of an arithmetic operator on integers, which compiled
+/// code applies itself, at the position of the operator.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn nix_overflow(
+	lhs: i64,
+	symbol: u32,
+	rhs: i64,
+	site_pointer: *const u8,
+	site_length: i64,
+) -> *mut Value {
+	overflow(
+		lhs,
+		char::from_u32(symbol).unwrap_or(char::REPLACEMENT_CHARACTER),
+		rhs,
+		position(site_pointer, site_length),
+	)
+}
+
+fn overflow(lhs: i64, symbol: char, rhs: i64, site: Option<&str>) -> ! {
+	let verb = match symbol {
+		'+' => "adding",
+		'-' => "subtracting",
+		'*' => "multiplying",
+		_ => "dividing",
+	};
+
+	throw_at(
+		format!("integer overflow in {verb} {lhs} {symbol} {rhs}"),
+		site,
+	)
+}
+
+/// Reports a division of integers by zero, which compiled code checks for
+/// itself, at the position of the operator.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn nix_division_by_zero(
+	site_pointer: *const u8,
+	site_length: i64,
+) -> *mut Value {
+	throw_at("division by zero", position(site_pointer, site_length))
+}
+
+/// Adds numbers, or concatenates strings or paths as the type of the left
+/// operand dictates, reporting errors at the position of the operator.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn nix_add(
+	lhs: *mut Value,
+	rhs: *mut Value,
+	site_pointer: *const u8,
+	site_length: i64,
+) -> *mut Value {
+	add(lhs, rhs, position(site_pointer, site_length))
+}
+
+/// Adds numbers or concatenates strings or paths. The context of
+/// concatenated strings is the union of their contexts.
+pub fn add(
+	lhs: *mut Value,
+	rhs: *mut Value,
+	site: Option<&'static str>,
+) -> *mut Value {
+	match unsafe { &*force(lhs) } {
+		Value::String(_) => {
+			let mut string = NixString::default();
+			coerce(lhs, &mut string, Coercion::INTERPOLATE);
+			coerce(rhs, &mut string, Coercion::INTERPOLATE);
+
+			Value::String(string.into()).alloc()
+		}
+		Value::Path(path) => {
+			let mut suffix = NixString::default();
+			coerce(rhs, &mut suffix, Coercion::PLAIN);
+
+			if !suffix.context.is_empty() {
+				throw_at(
+					"a string that refers to a store path cannot be appended \
+					 to a path",
+					site,
+				);
+			}
+
+			Value::Path(canonicalize(&format!("{path}{}", suffix.value)).into())
+				.alloc()
+		}
+		_ => arithmetic(lhs, rhs, site, '+', i64::checked_add, |lhs, rhs| {
+			lhs + rhs
+		}),
+	}
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn nix_sub(
+	lhs: *mut Value,
+	rhs: *mut Value,
+	site_pointer: *const u8,
+	site_length: i64,
+) -> *mut Value {
+	sub(lhs, rhs, position(site_pointer, site_length))
+}
+
+pub fn sub(
+	lhs: *mut Value,
+	rhs: *mut Value,
+	site: Option<&'static str>,
+) -> *mut Value {
+	arithmetic(lhs, rhs, site, '-', i64::checked_sub, |lhs, rhs| lhs - rhs)
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn nix_mul(
+	lhs: *mut Value,
+	rhs: *mut Value,
+	site_pointer: *const u8,
+	site_length: i64,
+) -> *mut Value {
+	mul(lhs, rhs, position(site_pointer, site_length))
+}
+
+pub fn mul(
+	lhs: *mut Value,
+	rhs: *mut Value,
+	site: Option<&'static str>,
+) -> *mut Value {
+	arithmetic(lhs, rhs, site, '*', i64::checked_mul, |lhs, rhs| lhs * rhs)
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn nix_div(
+	lhs: *mut Value,
+	rhs: *mut Value,
+	site_pointer: *const u8,
+	site_length: i64,
+) -> *mut Value {
+	div(lhs, rhs, position(site_pointer, site_length))
+}
+
+/// Divides numbers, truncating the quotient of integers.
+pub fn div(
+	lhs: *mut Value,
+	rhs: *mut Value,
+	site: Option<&'static str>,
+) -> *mut Value {
+	match numbers(lhs, rhs, site) {
+		Numbers::Ints(_, 0) | Numbers::Floats(_, 0.0) => {
+			throw_at("division by zero", site)
+		}
+		_ => arithmetic(lhs, rhs, site, '/', i64::checked_div, |lhs, rhs| {
+			lhs / rhs
+		}),
+	}
+}
+
+/// Compares values for equality, forcing them as deeply as needed.
+#[no_mangle]
+pub extern "C-unwind" fn nix_eq(
+	lhs: *mut Value,
+	rhs: *mut Value,
+) -> *mut Value {
+	Value::Bool(equal(lhs, rhs)).alloc()
+}
+
+pub(crate) fn equal(lhs: *mut Value, rhs: *mut Value) -> bool {
+	let (lhs, rhs) = (force(lhs), force(rhs));
+	let _roots = gc::root(&[lhs, rhs]);
+
+	match unsafe { (&*lhs, &*rhs) } {
+		(Value::Null, Value::Null) => true,
+		(Value::Bool(lhs), Value::Bool(rhs)) => lhs == rhs,
+		(Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+			match numbers(lhs, rhs, None) {
+				Numbers::Ints(lhs, rhs) => lhs == rhs,
+				Numbers::Floats(lhs, rhs) => lhs == rhs,
+			}
+		}
+		(Value::String(lhs), Value::String(rhs)) => lhs.value == rhs.value,
+		(Value::Path(lhs), Value::Path(rhs)) => lhs == rhs,
+		(Value::List(lhs), Value::List(rhs)) => {
+			lhs.len() == rhs.len()
+				&& lhs
+					.iter()
+					.zip(rhs.iter())
+					.all(|(&lhs, &rhs)| equal(lhs, rhs))
+		}
+		(Value::AttrSet(lhs), Value::AttrSet(rhs)) => {
+			lhs.len() == rhs.len()
+				&& lhs.iter().zip(rhs.iter()).all(
+					|((lhs_name, &lhs), (rhs_name, &rhs))| {
+						lhs_name == rhs_name && equal(lhs, rhs)
+					},
+				)
+		}
+		_ => false,
+	}
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn nix_lt(
+	lhs: *mut Value,
+	rhs: *mut Value,
+	site_pointer: *const u8,
+	site_length: i64,
+) -> *mut Value {
+	Value::Bool(less(lhs, rhs, position(site_pointer, site_length))).alloc()
+}
+
+/// Compares values as `<` does: numbers, strings and paths, and lists
+/// lexicographically.
+pub fn less(
+	lhs: *mut Value,
+	rhs: *mut Value,
+	site: Option<&'static str>,
+) -> bool {
+	let (lhs, rhs) = (force(lhs), force(rhs));
+	let _roots = gc::root(&[lhs, rhs]);
+
+	match unsafe { (&*lhs, &*rhs) } {
+		(Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+			match numbers(lhs, rhs, site) {
+				Numbers::Ints(lhs, rhs) => lhs < rhs,
+				Numbers::Floats(lhs, rhs) => lhs < rhs,
+			}
+		}
+		(Value::String(lhs), Value::String(rhs)) => lhs.value < rhs.value,
+		(Value::Path(lhs), Value::Path(rhs)) => lhs < rhs,
+		(Value::List(lhs), Value::List(rhs)) => {
+			for (&lhs, &rhs) in lhs.iter().zip(rhs.iter()) {
+				if !equal(lhs, rhs) {
+					return less(lhs, rhs, site);
+				}
+			}
+
+			lhs.len() < rhs.len()
+		}
+		(lhs, rhs) => throw_at(
+			format!(
+				"cannot compare {} with {}",
+				lhs.type_name(),
+				rhs.type_name()
+			),
+			site,
+		),
+	}
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn nix_not(value: *mut Value) -> *mut Value {
+	Value::Bool(!boolean(value)).alloc()
+}
+
+/// Evaluates a program for the `main` function of an executable, printing
+/// its value or the error it fails with, and returns the exit status.
+#[no_mangle]
+pub extern "C-unwind" fn nix_run(
+	main: extern "C-unwind" fn() -> *mut Value,
+) -> i32 {
+	match catch(|| print(main())) {
+		Ok(value) => {
+			println!("{value}");
+			0
+		}
+		Err(error) => {
+			eprintln!("{error}");
+			1
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::error::Frame;
+
+	fn set(attributes: &[(&str, *mut Value)]) -> *mut Value {
+		let set = nix_attrset_new();
+
+		for &(name, value) in attributes {
+			unsafe {
+				nix_attrset_insert(set, name.as_ptr(), name.len() as i64, value)
+			};
+		}
+
+		set
+	}
+
+	fn select(set: *mut Value, path: &str, default: *mut Value) -> *mut Value {
+		unsafe { nix_select(set, path.as_ptr(), path.len() as i64, default) }
+	}
+
+	#[test]
+	fn select_path() {
+		let set = set(&[("a", set(&[("b", nix_int(1))]))]);
+
+		assert_eq!(print(select(set, "a\0b", std::ptr::null_mut())), "1");
+		assert_eq!(print(select(set, "a\0c", nix_null())), "null");
+		assert_eq!(print(select(set, "a\0b\0c", nix_null())), "null");
+		assert_eq!(
+			catch(|| select(set, "b", std::ptr::null_mut()))
+				.err()
+				.unwrap()
+				.message(),
+			"attribute 'b' missing"
+		);
+	}
+
+	#[test]
+	fn select_dynamic_path() {
+		let set = set(&[("a", set(&[("b", nix_int(1))]))]);
+		let path = |names: &[&str]| {
+			let _pause = gc::pause();
+			Env(names.iter().map(|name| string(name)).collect()).alloc()
+		};
+
+		assert_eq!(
+			print(unsafe {
+				nix_select_dynamic(set, path(&["a", "b"]), std::ptr::null_mut())
+			}),
+			"1"
+		);
+		assert_eq!(
+			print(unsafe { nix_has_attr_dynamic(set, path(&["a", "c"])) }),
+			"false"
+		);
+	}
+
+	#[test]
+	fn insert_dynamic_attribute() {
+		let set = set(&[("a", nix_null())]);
+
+		unsafe { nix_attrset_insert_dynamic(set, nix_null(), nix_int(1)) };
+		unsafe { nix_attrset_insert_dynamic(set, string("b"), nix_int(2)) };
+
+		assert_eq!(print(set), "{ a = null; b = 2; }");
+		assert_eq!(
+			catch(|| unsafe {
+				nix_attrset_insert_dynamic(set, string("a"), nix_int(3))
+			})
+			.err()
+			.unwrap()
+			.message(),
+			"dynamic attribute 'a' already defined"
+		);
+	}
+
+	#[test]
+	fn reject_duplicate_attribute() {
+		assert_eq!(
+			catch(|| set(&[("a", nix_null()), ("a", nix_null())]))
+				.err()
+				.unwrap()
+				.message(),
+			"attribute 'a' already defined"
+		);
+	}
+
+	#[test]
+	fn check_formals() {
+		let check = |argument, ellipsis| {
+			catch(|| unsafe {
+				nix_formals(
+					argument,
+					"f".as_ptr(),
+					1,
+					"a\0b".as_ptr(),
+					3,
+					"a".as_ptr(),
+					1,
+					ellipsis,
+				)
+			})
+			.map_err(|error| error.message().to_owned())
+		};
+
+		assert_eq!(check(set(&[("a", nix_null())]), 0), Ok(()));
+		assert_eq!(
+			check(set(&[("b", nix_null())]), 0),
+			Err("function 'f' called without required argument 'a'".into())
+		);
+		assert_eq!(
+			check(set(&[("a", nix_null()), ("c", nix_null())]), 0),
+			Err("function 'f' called with unexpected argument 'c'".into())
+		);
+		assert_eq!(
+			check(set(&[("a", nix_null()), ("c", nix_null())]), 1),
+			Ok(())
+		);
+	}
+
+	unsafe extern "C-unwind" fn anonymous(
+		_: *mut Env,
+		argument: *mut Value,
+	) -> *mut Value {
+		nix_formals(
+			argument,
+			"".as_ptr(),
+			0,
+			"".as_ptr(),
+			0,
+			"a".as_ptr(),
+			1,
+			0,
+		);
+		argument
+	}
+
+	#[test]
+	fn report_call_site() {
+		let function = unsafe {
+			nix_lambda(anonymous, std::ptr::null_mut(), "".as_ptr(), 0)
+		};
+		let apply = |function, site: &'static str| {
+			catch(|| unsafe {
+				nix_apply(
+					function,
+					nix_attrset_new(),
+					site.as_ptr(),
+					site.len() as i64,
+				)
+			})
+			.unwrap_err()
+		};
+
+		let error = apply(function, "a.nix:1:2");
+		assert_eq!(
+			error.message(),
+			"function 'anonymous lambda' called without required argument 'a'"
+		);
+		assert_eq!(error.position(), Some("a.nix:1:2"));
+		assert_eq!(
+			error.trace(),
+			[Frame {
+				description: "while calling anonymous lambda".into(),
+				position: Some("a.nix:1:2".into()),
+			}]
+		);
+
+		let error = apply(nix_int(1), "");
+		assert_eq!(
+			error.message(),
+			"attempt to call something which is not a function but an integer"
+		);
+		assert_eq!(error.position(), None);
+	}
+
+	#[test]
+	fn arithmetic() {
+		assert_eq!(print(add(nix_int(1), nix_int(2), None)), "3");
+		assert_eq!(print(mul(nix_int(3), nix_float(0.5), None)), "1.5");
+		assert_eq!(print(div(nix_int(7), nix_int(2), None)), "3");
+		assert_eq!(print(div(nix_int(-7), nix_int(2), None)), "-3");
+		assert_eq!(
+			catch(|| div(nix_int(1), nix_int(0), None))
+				.err()
+				.unwrap()
+				.message(),
+			"division by zero"
+		);
+		assert_eq!(
+			catch(|| sub(nix_int(1), nix_bool(1), None))
+				.err()
+				.unwrap()
+				.message(),
+			"value is a Boolean while a number was expected"
+		);
+	}
+
+	#[test]
+	fn check_overflow() {
+		let overflow = |evaluate: fn() -> *mut Value| {
+			catch(evaluate).err().unwrap().message().to_owned()
+		};
+
+		assert_eq!(
+			overflow(|| add(nix_int(i64::MAX), nix_int(1), None)),
+			"integer overflow in adding 9223372036854775807 + 1"
+		);
+		assert_eq!(
+			overflow(|| sub(nix_int(i64::MIN), nix_int(1), None)),
+			"integer overflow in subtracting -9223372036854775808 - 1"
+		);
+		assert_eq!(
+			overflow(|| mul(nix_int(i64::MAX), nix_int(2), None)),
+			"integer overflow in multiplying 9223372036854775807 * 2"
+		);
+		assert_eq!(
+			overflow(|| div(nix_int(i64::MIN), nix_int(-1), None)),
+			"integer overflow in dividing -9223372036854775808 / -1"
+		);
+		assert_eq!(
+			print(add(nix_float(f64::MAX), nix_int(i64::MAX), None)),
+			"1.79769e+308"
+		);
+	}
+
+	#[test]
+	fn report_operator_site() {
+		let site = "a.nix:1:3";
+		let error = catch(|| unsafe {
+			nix_div(nix_int(1), nix_int(0), site.as_ptr(), site.len() as i64)
+		})
+		.unwrap_err();
+
+		assert_eq!(error.message(), "division by zero");
+		assert_eq!(error.position(), Some(site));
+
+		let error = catch(|| unsafe {
+			nix_lt(nix_int(1), nix_null(), site.as_ptr(), site.len() as i64)
+		})
+		.unwrap_err();
+
+		assert_eq!(error.message(), "cannot compare an integer with null");
+		assert_eq!(error.position(), Some(site));
+
+		let error = catch(|| unsafe {
+			nix_overflow(
+				i64::MAX,
+				'*' as u32,
+				2,
+				site.as_ptr(),
+				site.len() as i64,
+			)
+		})
+		.unwrap_err();
+
+		assert_eq!(
+			error.message(),
+			"integer overflow in multiplying 9223372036854775807 * 2"
+		);
+		assert_eq!(error.position(), Some(site));
+
+		let error = catch(|| unsafe {
+			nix_division_by_zero(site.as_ptr(), site.len() as i64)
+		})
+		.unwrap_err();
+
+		assert_eq!(error.message(), "division by zero");
+		assert_eq!(error.position(), Some(site));
+	}
+
+	#[test]
+	fn compare() {
+		assert_eq!(print(nix_eq(nix_int(1), nix_float(1.0))), "true");
+		assert_eq!(
+			print(nix_eq(set(&[("a", nix_int(1))]), set(&[("a", nix_int(1))]))),
+			"true"
+		);
+		assert_eq!(print(nix_eq(nix_null(), nix_bool(0))), "false");
+		assert!(less(nix_int(1), nix_float(1.5), None));
+	}
+
+	fn string(value: &str) -> *mut Value {
+		unsafe { nix_string(value.as_ptr(), value.len() as i64) }
+	}
+
+	fn list(elements: &[*mut Value]) -> *mut Value {
+		let list = nix_list_new(elements.len() as i64);
+
+		for (index, &element) in elements.iter().enumerate() {
+			unsafe { nix_list_set(list, index as i64, element) };
+		}
+
+		list
+	}
+
+	fn interpolate(parts: &[*mut Value]) -> *mut Value {
+		unsafe { nix_interpolate(&mut Env(parts.to_vec())) }
+	}
+
+	#[test]
+	fn concat() {
+		let list = nix_concat(list(&[nix_int(1)]), list(&[nix_int(2)]));
+
+		assert_eq!(print(list), "[ 1 2 ]");
+		assert_eq!(
+			catch(|| nix_concat(list, nix_null()))
+				.err()
+				.unwrap()
+				.message(),
+			"value is null while a list was expected"
+		);
+	}
+
+	#[test]
+	fn interpolate_strings() {
+		let set = set(&[("outPath", string("/b"))]);
+
+		assert_eq!(
+			print(interpolate(&[string("a"), set, string("c")])),
+			r#""a/bc""#
+		);
+		assert_eq!(
+			catch(|| interpolate(&[nix_int(1)]))
+				.err()
+				.unwrap()
+				.message(),
+			"cannot coerce an integer to a string"
+		);
+	}
+
+	#[test]
+	fn concatenate_strings() {
+		let path =
+			|path: &str| unsafe { nix_path(path.as_ptr(), path.len() as i64) };
+		let dependency = Value::String(
+			NixString {
+				value: "/nix/store/a".into(),
+				context: ["/nix/store/a".into()].into(),
+			}
+			.into(),
+		)
+		.alloc();
+
+		let concatenated = add(string("b"), dependency, None);
+		let Value::String(concatenated) = (unsafe { &*concatenated }) else {
+			unreachable!()
+		};
+		assert_eq!(concatenated.value, "b/nix/store/a");
+		assert_eq!(concatenated.context.len(), 1);
+
+		assert_eq!(print(add(path("/a"), string("/../b"), None)), "/b");
+		assert_eq!(
+			catch(|| add(path("/a"), dependency, None))
+				.err()
+				.unwrap()
+				.message(),
+			"a string that refers to a store path cannot be appended to a path"
+		);
+	}
+
+	#[test]
+	fn compare_lists() {
+		let lhs = list(&[nix_int(1), string("a")]);
+
+		assert!(less(lhs, list(&[nix_int(1), string("b")]), None));
+		assert!(!less(lhs, list(&[nix_int(1)]), None));
+		assert_eq!(
+			print(nix_eq(lhs, list(&[nix_int(1), string("a")]))),
+			"true"
+		);
+	}
+
+	#[test]
+	fn update() {
+		let set = nix_update(
+			set(&[("a", nix_int(1)), ("b", nix_int(2))]),
+			set(&[("b", nix_int(3))]),
+		);
+
+		assert_eq!(print(set), "{ a = 1; b = 3; }");
+	}
+
+	#[test]
+	fn run_program() {
+		extern "C-unwind" fn succeed() -> *mut Value {
+			nix_int(1)
+		}
+
+		extern "C-unwind" fn fail() -> *mut Value {
+			throw("oops")
+		}
+
+		assert_eq!(nix_run(succeed), 0);
+		assert_eq!(nix_run(fail), 1);
+	}
+}
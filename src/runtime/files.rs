@@ -0,0 +1,143 @@
+//! Compiled files, which `import` evaluates at most once.
+//!
+//! The compiler compiles every file a program refers to by a path literal to
+//! a function, which compiled code registers with [`register`] before
+//! evaluating the program, so that `import` works on paths computed at
+//! runtime as long as they refer to one of these files.
+
+use crate::{error::throw, gc, value::Value};
+use std::{cell::RefCell, collections::HashMap, path::Path};
+
+/// The code of a file, returning its value in weak head normal form.
+pub type FileFn = unsafe extern "C-unwind" fn() -> *mut Value;
+
+#[derive(Clone, Copy)]
+enum State {
+	Compiled(FileFn),
+	/// The file is being evaluated, so importing it again would not
+	/// terminate.
+	Evaluating,
+	Done(*mut Value),
+}
+
+thread_local! {
+	static FILES: RefCell<HashMap<String, State>> =
+		RefCell::new(HashMap::new());
+	/// The files being evaluated, the outermost first.
+	static CHAIN: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Registers the code of a file by its absolute path.
+pub fn register(path: &str, function: FileFn) {
+	FILES.with_borrow_mut(|files| {
+		files
+			.entry(path.into())
+			.or_insert(State::Compiled(function));
+	});
+}
+
+/// Restores a file whose evaluation is unwinding, so that importing it again
+/// reports the error again rather than a cycle.
+struct Restore<'a> {
+	path: &'a str,
+	function: FileFn,
+}
+
+impl Drop for Restore<'_> {
+	fn drop(&mut self) {
+		set(self.path, State::Compiled(self.function));
+		CHAIN.with_borrow_mut(|chain| chain.pop());
+	}
+}
+
+fn set(path: &str, state: State) {
+	FILES.with_borrow_mut(|files| files.insert(path.into(), state));
+}
+
+/// Imports a file, or the `default.nix` file of a directory, by its absolute
+/// path.
+pub fn import(path: &str) -> *mut Value {
+	let path = if Path::new(path).is_dir() {
+		format!("{}/default.nix", path.trim_end_matches('/'))
+	} else {
+		path.into()
+	};
+
+	match FILES.with_borrow(|files| files.get(&path).copied()) {
+		Some(State::Done(value)) => value,
+		Some(State::Evaluating) => {
+			let chain = CHAIN.with_borrow(|chain| {
+				let start = chain
+					.iter()
+					.position(|file| *file == path)
+					.unwrap_or_default();
+
+				chain[start..].join(" -> ")
+			});
+
+			throw(format!("import cycle: {chain} -> {path}"))
+		}
+		Some(State::Compiled(function)) => {
+			set(&path, State::Evaluating);
+			CHAIN.with_borrow_mut(|chain| chain.push(path.clone()));
+			let restore = Restore {
+				path: &path,
+				function,
+			};
+
+			let value = unsafe { function() };
+
+			std::mem::forget(restore);
+			CHAIN.with_borrow_mut(|chain| chain.pop());
+			set(&path, State::Done(value));
+			gc::pin(value)
+		}
+		None => {
+			throw(format!("cannot import '{path}', since it was not compiled"))
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{error::catch, value::print};
+
+	unsafe extern "C-unwind" fn a() -> *mut Value {
+		import("/b.nix")
+	}
+
+	unsafe extern "C-unwind" fn b() -> *mut Value {
+		import("/a.nix")
+	}
+
+	unsafe extern "C-unwind" fn c() -> *mut Value {
+		Value::Int(1).alloc()
+	}
+
+	#[test]
+	fn import_once() {
+		register("/c.nix", c);
+		let value = import("/c.nix");
+
+		assert_eq!(print(value), "1");
+		assert_eq!(import("/c.nix"), value);
+		assert_eq!(
+			catch(|| import("/d.nix")).unwrap_err().message(),
+			"cannot import '/d.nix', since it was not compiled"
+		);
+	}
+
+	#[test]
+	fn report_cycle() {
+		register("/a.nix", a);
+		register("/b.nix", b);
+
+		for _ in 0..2 {
+			assert_eq!(
+				catch(|| import("/a.nix")).unwrap_err().message(),
+				"import cycle: /a.nix -> /b.nix -> /a.nix"
+			);
+		}
+	}
+}
@@ -0,0 +1,439 @@
+//! Garbage collection of values and environments.
+//!
+//! Every value and environment is allocated on a heap per thread and freed
+//! once it is unreachable, by marking what is reachable from the roots and
+//! sweeping the rest. Compiled code keeps values in registers and on the
+//! machine stack without describing where, so the stack of the thread is
+//! scanned conservatively: any word holding the address of an object, or of
+//! a byte inside it, keeps it alive. Objects are never moved, so compiled code
+//! needs neither stack maps nor a shadow stack, and is lowered as if values
+//! were never freed.
+//!
+//! The runtime also holds values in Rust collections, which the collector
+//! does not see, so it is told about them:
+//! - [`pin`] keeps values for the rest of the thread, such as `builtins` and
+//!   imported files,
+//! - [`root`] keeps values while a function uses them, such as the arguments
+//!   of a built-in function, and
+//! - [`pause`] prevents collections while a function allocates values before
+//!   linking them together.
+//!
+//! Setting the `MLNX_GC_STRESS` environment variable, or calling
+//! [`set_stress`], collects on every allocation, so that values missing from
+//! the roots are freed as early as possible.
+
+use crate::value::{Env, Value};
+use std::{
+	cell::{Cell, RefCell},
+	collections::{BTreeMap, HashSet},
+	mem::size_of,
+};
+
+/// The number of objects below which the heap is never collected.
+const MINIMUM_THRESHOLD: usize = 1 << 16;
+
+#[derive(Clone, Copy)]
+enum Kind {
+	Value,
+	Env,
+}
+
+impl Kind {
+	fn size(self) -> usize {
+		match self {
+			Self::Value => size_of::<Value>(),
+			Self::Env => size_of::<Env>(),
+		}
+	}
+}
+
+struct Heap {
+	/// The kinds of the objects by address.
+	objects: BTreeMap<usize, Kind>,
+	/// The number of objects from which the heap is collected.
+	threshold: usize,
+	pinned: Vec<*mut Value>,
+	/// The values rooted by functions, the innermost last.
+	roots: Vec<*mut Value>,
+}
+
+thread_local! {
+	static HEAP: RefCell<Heap> = const {
+		RefCell::new(Heap {
+			objects: BTreeMap::new(),
+			threshold: MINIMUM_THRESHOLD,
+			pinned: Vec::new(),
+			roots: Vec::new(),
+		})
+	};
+	static PAUSES: Cell<usize> = const { Cell::new(0) };
+	static STRESS: Cell<bool> =
+		Cell::new(std::env::var_os("MLNX_GC_STRESS").is_some());
+	/// The end of the stack of the thread, or `None` if it is unknown, in
+	/// which case the heap is never collected.
+	static STACK_END: Option<usize> = stack_end();
+}
+
+/// Allocates a value.
+pub(crate) fn value(value: Value) -> *mut Value {
+	allocate(value, Kind::Value)
+}
+
+/// Allocates an environment.
+pub(crate) fn env(env: Env) -> *mut Env {
+	allocate(env, Kind::Env)
+}
+
+fn allocate<T>(object: T, kind: Kind) -> *mut T {
+	let pointer = Box::into_raw(Box::new(object));
+	let full = HEAP.with_borrow_mut(|heap| {
+		heap.objects.insert(pointer as usize, kind);
+		heap.objects.len() >= heap.threshold
+	});
+
+	// The new object is not linked to anything yet.
+	if (full || STRESS.get()) && PAUSES.get() == 0 {
+		collect_with(Some(pointer as usize));
+	}
+
+	pointer
+}
+
+/// Keeps a value alive for the rest of the thread.
+pub fn pin(value: *mut Value) -> *mut Value {
+	HEAP.with_borrow_mut(|heap| heap.pinned.push(value));
+	value
+}
+
+/// Keeps values alive until the guard is dropped.
+#[must_use]
+pub struct Roots {
+	length: usize,
+}
+
+impl Drop for Roots {
+	fn drop(&mut self) {
+		HEAP.with_borrow_mut(|heap| heap.roots.truncate(self.length));
+	}
+}
+
+/// Keeps values alive while a function uses them, which it does not need to
+/// do for values it only refers to from the stack.
+pub fn root(values: &[*mut Value]) -> Roots {
+	HEAP.with_borrow_mut(|heap| {
+		let length = heap.roots.len();
+		heap.roots.extend_from_slice(values);
+		Roots { length }
+	})
+}
+
+/// Prevents collections until the guard is dropped.
+#[must_use]
+pub struct Pause(());
+
+impl Drop for Pause {
+	fn drop(&mut self) {
+		PAUSES.set(PAUSES.get() - 1);
+	}
+}
+
+/// Prevents collections while values are only referred to from Rust
+/// collections, such as the elements of a list being created.
+pub fn pause() -> Pause {
+	PAUSES.set(PAUSES.get() + 1);
+	Pause(())
+}
+
+/// Collects on every allocation, or stops doing so.
+pub fn set_stress(stress: bool) {
+	STRESS.set(stress);
+}
+
+/// Gets the number of objects allocated and not freed yet.
+pub fn count() -> usize {
+	HEAP.with_borrow(|heap| heap.objects.len())
+}
+
+/// Frees the objects which are unreachable, unless collections are paused.
+pub fn collect() {
+	if PAUSES.get() == 0 {
+		collect_with(None);
+	}
+}
+
+#[cfg(target_arch = "x86_64")]
+type Registers = [usize; 6];
+
+#[cfg(target_arch = "aarch64")]
+type Registers = [usize; 11];
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+type Registers = [usize; 0];
+
+/// Copies the callee-saved registers, which may hold values of the callers,
+/// to the stack.
+#[inline(always)]
+fn spill(registers: &mut Registers) {
+	#[cfg(target_arch = "x86_64")]
+	unsafe {
+		std::arch::asm!(
+			"mov [{0}], rbx",
+			"mov [{0} + 8], rbp",
+			"mov [{0} + 16], r12",
+			"mov [{0} + 24], r13",
+			"mov [{0} + 32], r14",
+			"mov [{0} + 40], r15",
+			in(reg) registers.as_mut_ptr(),
+			options(nostack, preserves_flags),
+		)
+	};
+
+	#[cfg(target_arch = "aarch64")]
+	unsafe {
+		std::arch::asm!(
+			"stp x19, x20, [{0}]",
+			"stp x21, x22, [{0}, #16]",
+			"stp x23, x24, [{0}, #32]",
+			"stp x25, x26, [{0}, #48]",
+			"stp x27, x28, [{0}, #64]",
+			"str x29, [{0}, #80]",
+			in(reg) registers.as_mut_ptr(),
+			options(nostack, preserves_flags),
+		)
+	};
+
+	#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+	let _ = registers;
+}
+
+#[cfg(target_os = "linux")]
+fn stack_end() -> Option<usize> {
+	use std::mem::MaybeUninit;
+
+	if cfg!(not(any(target_arch = "x86_64", target_arch = "aarch64"))) {
+		return None;
+	}
+
+	unsafe {
+		let mut attributes = MaybeUninit::uninit();
+
+		if libc::pthread_getattr_np(
+			libc::pthread_self(),
+			attributes.as_mut_ptr(),
+		) != 0
+		{
+			return None;
+		}
+
+		let mut start = std::ptr::null_mut();
+		let mut size = 0;
+		let result = libc::pthread_attr_getstack(
+			attributes.as_ptr(),
+			&mut start,
+			&mut size,
+		);
+		libc::pthread_attr_destroy(attributes.as_mut_ptr());
+
+		(result == 0).then(|| start as usize + size)
+	}
+}
+
+#[cfg(target_os = "macos")]
+fn stack_end() -> Option<usize> {
+	let end = unsafe { libc::pthread_get_stackaddr_np(libc::pthread_self()) };
+	Some(end as usize)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn stack_end() -> Option<usize> {
+	None
+}
+
+/// Collects the heap, keeping an object which is not linked to anything yet.
+/// Everything from the frame of this function to the end of the stack is
+/// scanned, so it must not be inlined into functions holding values.
+#[inline(never)]
+fn collect_with(object: Option<usize>) {
+	let Some(end) = STACK_END.with(|&end| end) else {
+		return;
+	};
+
+	let mut registers = Registers::default();
+	spill(&mut registers);
+	let start = std::hint::black_box(&registers).as_ptr() as usize;
+
+	HEAP.with_borrow_mut(|heap| heap.collect(start..end, object));
+}
+
+/// Marks the objects a value or an environment refers to.
+pub(crate) struct Tracer<'a> {
+	objects: &'a BTreeMap<usize, Kind>,
+	marked: &'a HashSet<usize>,
+	pending: &'a mut Vec<usize>,
+}
+
+impl Tracer<'_> {
+	/// Marks an object, ignoring null pointers, such as the unset elements of
+	/// a list being created.
+	pub(crate) fn mark<T>(&mut self, pointer: *mut T) {
+		let address = pointer as usize;
+
+		if self.objects.contains_key(&address)
+			&& !self.marked.contains(&address)
+		{
+			self.pending.push(address);
+		}
+	}
+
+	fn trace(&mut self, address: usize, kind: Kind) {
+		match kind {
+			Kind::Value => match unsafe { &*(address as *const Value) } {
+				Value::List(elements) => {
+					elements.iter().for_each(|&element| self.mark(element))
+				}
+				Value::AttrSet(attributes) => {
+					attributes.values().for_each(|&value| self.mark(value))
+				}
+				Value::Lambda(closure) => self.mark(closure.env),
+				Value::Thunk(thunk) => thunk.trace(self),
+				Value::PrimOp(primop) => primop
+					.arguments
+					.iter()
+					.for_each(|&argument| self.mark(argument)),
+				_ => {}
+			},
+			Kind::Env => unsafe { &*(address as *const Env) }
+				.0
+				.iter()
+				.for_each(|&value| self.mark(value)),
+		}
+	}
+}
+
+impl Heap {
+	/// Finds the object containing an address.
+	fn find(&self, address: usize) -> Option<usize> {
+		let (&start, kind) = self.objects.range(..=address).next_back()?;
+		(address < start + kind.size()).then_some(start)
+	}
+
+	fn collect(
+		&mut self,
+		stack: std::ops::Range<usize>,
+		object: Option<usize>,
+	) {
+		let mut pending = self
+			.pinned
+			.iter()
+			.chain(&self.roots)
+			.map(|&value| value as usize)
+			.chain(object)
+			.filter(|address| self.objects.contains_key(address))
+			.collect::<Vec<_>>();
+
+		for word in stack.step_by(size_of::<usize>()) {
+			let word = unsafe { std::ptr::read_volatile(word as *const usize) };
+			pending.extend(self.find(word));
+		}
+
+		let mut marked = HashSet::new();
+
+		while let Some(address) = pending.pop() {
+			if !marked.insert(address) {
+				continue;
+			}
+
+			let mut tracer = Tracer {
+				objects: &self.objects,
+				marked: &marked,
+				pending: &mut pending,
+			};
+			tracer.trace(address, self.objects[&address]);
+		}
+
+		self.objects.retain(|&address, &mut kind| {
+			let live = marked.contains(&address);
+
+			if !live {
+				match kind {
+					Kind::Value => unsafe {
+						drop(Box::from_raw(address as *mut Value))
+					},
+					Kind::Env => unsafe {
+						drop(Box::from_raw(address as *mut Env))
+					},
+				}
+			}
+
+			live
+		});
+		self.threshold = MINIMUM_THRESHOLD.max(self.objects.len() * 2);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		builtins,
+		error::catch,
+		exports::{add, apply, nix_int},
+		print,
+		thunk::Thunk,
+	};
+
+	/// Allocates values in a frame of its own, so that they are not referred
+	/// to from the stack afterwards.
+	#[inline(never)]
+	fn allocate_garbage(count: usize) {
+		for index in 0..count {
+			std::hint::black_box(nix_int(index as i64));
+		}
+	}
+
+	#[test]
+	fn free_unreachable_values() {
+		let pinned = pin(Value::Int(1).alloc());
+		let before = count();
+		allocate_garbage(1000);
+		collect();
+
+		// A few stale words may remain on the stack.
+		assert!(count() < before + 100);
+		assert_eq!(print(pinned), "1");
+	}
+
+	unsafe extern "C-unwind" fn sum(env: *mut Env) -> *mut Value {
+		let env = &*env;
+		add(env.0[0], env.0[1], None)
+	}
+
+	#[test]
+	fn evaluate_under_stress() {
+		set_stress(true);
+
+		let value = catch(|| {
+			let strings = apply(
+				apply(
+					builtins::get("genList"),
+					builtins::get("toString"),
+					None,
+				),
+				nix_int(3),
+				None,
+			);
+			let env = Env(vec![nix_int(1), nix_int(2)]).alloc();
+			let sum = Value::Thunk(Thunk::new(sum, env).into()).alloc();
+			let set = Value::AttrSet(
+				BTreeMap::from([("a".into(), strings), ("b".into(), sum)])
+					.into(),
+			)
+			.alloc();
+
+			print(apply(builtins::get("attrValues"), set, None))
+		});
+
+		set_stress(false);
+		assert_eq!(value.unwrap(), r#"[ [ "0" "1" "2" ] 3 ]"#);
+	}
+}
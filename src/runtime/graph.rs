@@ -0,0 +1,278 @@
+//! The dependency graph of a set of packages.
+//!
+//! A package depends on another if one of the derivations it is built from,
+//! found by following input derivations from its own, is the derivation of
+//! the other package. Input derivations come from the string contexts of
+//! the attributes of derivations, so only derivations instantiated by this
+//! evaluation are followed.
+
+use crate::{
+	derivation::input_derivations,
+	error::{throw, trace},
+	exports::{attributes, string},
+	gc,
+	thunk::force,
+	value::Value,
+};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Packages by name, with edges from each package to those depending on it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Graph {
+	/// The paths of the `.drv` files of the packages.
+	pub packages: BTreeMap<String, String>,
+	pub edges: BTreeSet<(String, String)>,
+}
+
+/// Selects an attribute path such as `a.b` in a set.
+pub fn select(mut value: *mut Value, path: &str) -> *mut Value {
+	for name in path.split('.').filter(|name| !name.is_empty()) {
+		value = match attributes(value).get(name) {
+			Some(&value) => value,
+			None => throw(format!("attribute '{name}' missing")),
+		};
+	}
+
+	value
+}
+
+fn is_derivation(value: *mut Value) -> bool {
+	let Value::AttrSet(attributes) = (unsafe { &*force(value) }) else {
+		return false;
+	};
+
+	attributes.get("type").is_some_and(|&r#type| {
+		matches!(
+			unsafe { &*force(r#type) },
+			Value::String(r#type) if r#type.value == "derivation"
+		)
+	})
+}
+
+impl Graph {
+	/// Instantiates the derivations of a set of packages, ignoring its other
+	/// attributes, and finds the dependencies between them.
+	pub fn new(packages: *mut Value) -> Self {
+		let _root = gc::root(&[packages]);
+		let mut graph = Self::default();
+
+		for (name, &package) in attributes(packages) {
+			if is_derivation(package) {
+				let path = trace(
+					|| format!("while evaluating the package '{name}'"),
+					None,
+					|| match attributes(package).get("drvPath") {
+						Some(&path) => string(path).to_owned(),
+						None => throw("derivation has no 'drvPath' attribute"),
+					},
+				);
+				graph.packages.insert(name.clone(), path);
+			}
+		}
+
+		let names = graph
+			.packages
+			.iter()
+			.map(|(name, path)| (path.as_str(), name.as_str()))
+			.collect::<HashMap<_, _>>();
+
+		for (name, path) in &graph.packages {
+			let mut visited = BTreeSet::new();
+			let mut pending = input_derivations(path).unwrap_or_default();
+
+			while let Some(input) = pending.pop() {
+				if !visited.insert(input.clone()) {
+					continue;
+				}
+
+				match names.get(input.as_str()) {
+					Some(&dependency) => {
+						graph.edges.insert((dependency.into(), name.clone()));
+					}
+					None => pending
+						.extend(input_derivations(&input).unwrap_or_default()),
+				}
+			}
+		}
+
+		graph
+	}
+
+	/// Formats the graph in the DOT language of Graphviz.
+	pub fn dot(&self) -> String {
+		let mut text = String::from("digraph packages {\n");
+
+		for name in self.packages.keys() {
+			text.push_str(&format!("\t{};\n", quote(name)));
+		}
+
+		for (dependency, dependent) in &self.edges {
+			text.push_str(&format!(
+				"\t{} -> {};\n",
+				quote(dependency),
+				quote(dependent)
+			));
+		}
+
+		text.push('}');
+		text
+	}
+
+	/// Formats the graph in JSON, as a list of packages with the paths of
+	/// their `.drv` files and a list of edges.
+	pub fn json(&self) -> String {
+		let packages = self
+			.packages
+			.iter()
+			.map(|(name, path)| {
+				format!(
+					r#"{{"name":{},"drvPath":{}}}"#,
+					quote(name),
+					quote(path)
+				)
+			})
+			.collect::<Vec<_>>();
+		let edges = self
+			.edges
+			.iter()
+			.map(|(dependency, dependent)| {
+				format!(
+					r#"{{"from":{},"to":{}}}"#,
+					quote(dependency),
+					quote(dependent)
+				)
+			})
+			.collect::<Vec<_>>();
+
+		format!(
+			r#"{{"packages":[{}],"edges":[{}]}}"#,
+			packages.join(","),
+			edges.join(",")
+		)
+	}
+}
+
+/// Quotes a string for both DOT and JSON.
+fn quote(text: &str) -> String {
+	let mut quoted = String::from('"');
+
+	for character in text.chars() {
+		match character {
+			'"' => quoted.push_str("\\\""),
+			'\\' => quoted.push_str("\\\\"),
+			'\n' => quoted.push_str("\\n"),
+			character if character.is_control() => {
+				quoted.push_str(&format!("\\u{:04x}", character as u32))
+			}
+			character => quoted.push(character),
+		}
+	}
+
+	quoted.push('"');
+	quoted
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		derivation::{record, Derivation},
+		error::catch,
+	};
+
+	fn package(path: &str, inputs: &[&str]) -> *mut Value {
+		let derivation = Derivation {
+			input_derivations: inputs
+				.iter()
+				.map(|&input| (input.to_owned(), ["out".to_owned()].into()))
+				.collect(),
+			..Default::default()
+		};
+		record(path, &derivation);
+
+		Value::AttrSet(
+			[
+				("type".to_owned(), Value::string("derivation").alloc()),
+				("drvPath".to_owned(), Value::string(path).alloc()),
+			]
+			.into_iter()
+			.collect::<BTreeMap<_, _>>()
+			.into(),
+		)
+		.alloc()
+	}
+
+	#[test]
+	fn follow_input_derivations() {
+		package("/nix/store/cmake.drv", &[]);
+		let llvm = package("/nix/store/llvm.drv", &["/nix/store/cmake.drv"]);
+		package("/nix/store/wrapper.drv", &["/nix/store/llvm.drv"]);
+		let torch_mlir =
+			package("/nix/store/torch-mlir.drv", &["/nix/store/wrapper.drv"]);
+		let iree = package(
+			"/nix/store/iree.drv",
+			&["/nix/store/llvm.drv", "/nix/store/torch-mlir.drv"],
+		);
+		let packages = Value::AttrSet(
+			[
+				("llvm".to_owned(), llvm),
+				("torch-mlir".to_owned(), torch_mlir),
+				("iree".to_owned(), iree),
+				("version".to_owned(), Value::string("1").alloc()),
+			]
+			.into_iter()
+			.collect::<BTreeMap<_, _>>()
+			.into(),
+		)
+		.alloc();
+		let root = Value::AttrSet(
+			[("packages".to_owned(), packages)]
+				.into_iter()
+				.collect::<BTreeMap<_, _>>()
+				.into(),
+		)
+		.alloc();
+		let graph = Graph::new(select(root, "packages"));
+
+		assert_eq!(
+			graph.dot(),
+			"digraph packages {\n\
+			 \t\"iree\";\n\
+			 \t\"llvm\";\n\
+			 \t\"torch-mlir\";\n\
+			 \t\"llvm\" -> \"iree\";\n\
+			 \t\"llvm\" -> \"torch-mlir\";\n\
+			 \t\"torch-mlir\" -> \"iree\";\n\
+			 }"
+		);
+		assert_eq!(
+			graph.json(),
+			r#"{"packages":[{"name":"iree","drvPath":"/nix/store/iree.drv"},{"name":"llvm","drvPath":"/nix/store/llvm.drv"},{"name":"torch-mlir","drvPath":"/nix/store/torch-mlir.drv"}],"edges":[{"from":"llvm","to":"iree"},{"from":"llvm","to":"torch-mlir"},{"from":"torch-mlir","to":"iree"}]}"#
+		);
+	}
+
+	#[test]
+	fn reject_derivations_without_path() {
+		let broken = Value::AttrSet(
+			[("type".to_owned(), Value::string("derivation").alloc())]
+				.into_iter()
+				.collect::<BTreeMap<_, _>>()
+				.into(),
+		)
+		.alloc();
+		let packages = Value::AttrSet(
+			[("broken".to_owned(), broken)]
+				.into_iter()
+				.collect::<BTreeMap<_, _>>()
+				.into(),
+		)
+		.alloc();
+		let error = catch(|| Graph::new(packages)).unwrap_err();
+
+		assert_eq!(error.message(), "derivation has no 'drvPath' attribute");
+		assert_eq!(
+			error.trace()[0].description,
+			"while evaluating the package 'broken'"
+		);
+	}
+}
@@ -0,0 +1,26 @@
+//! The runtime of compiled Nix code.
+//!
+//! Compiled code manipulates Nix values through pointers to [`Value`]s and
+//! the C-ABI functions of [`exports`], which the compiler registers with the
+//! execution engine under the names returned by [`symbols`]. Evaluation
+//! errors unwind through compiled code up to [`catch`].
+
+// Values are only reachable through pointers allocated by the runtime, which
+// are only freed once unreachable.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+pub mod builtins;
+pub mod derivation;
+pub mod error;
+pub mod exports;
+pub mod files;
+pub mod gc;
+pub mod graph;
+pub mod store;
+pub mod thunk;
+pub mod value;
+
+pub use error::{catch, throw, throw_at, EvalError};
+pub use exports::symbols;
+pub use thunk::force;
+pub use value::{print, NixString, Value};
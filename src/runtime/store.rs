@@ -0,0 +1,173 @@
+//! The local store.
+//!
+//! Store paths are computed by [`mlnx_store`] in the store directory given
+//! by `NIX_STORE_DIR` or `/nix/store`, and store objects are written to that
+//! directory. Errors of the store are thrown as evaluation errors.
+
+use crate::error::throw;
+use mlnx_store::{nar, Algorithm, Hash, StoreDir};
+use std::{cell::RefCell, collections::HashMap, fs, path::Path};
+
+thread_local! {
+	/// The store paths of the paths copied to the store by interpolation, by
+	/// their original paths.
+	static SOURCES: RefCell<HashMap<String, String>> =
+		RefCell::new(HashMap::new());
+}
+
+/// Gets the store directory.
+pub fn store_dir() -> StoreDir {
+	StoreDir::new(
+		std::env::var("NIX_STORE_DIR").unwrap_or_else(|_| "/nix/store".into()),
+	)
+}
+
+/// Gets the result of an operation of the store, throwing its error.
+pub fn check<T>(result: Result<T, mlnx_store::Error>) -> T {
+	result.unwrap_or_else(|error| throw(error.to_string()))
+}
+
+/// Checks that a name is valid for a store path.
+pub fn check_name(name: &str) {
+	check(mlnx_store::path::check_name(name))
+}
+
+/// Writes a file to the store unless it is already there.
+pub fn write(path: &str, contents: &str) {
+	if Path::new(path).exists() {
+		return;
+	}
+
+	fs::create_dir_all(store_dir().as_str())
+		.and_then(|_| fs::write(path, contents))
+		.unwrap_or_else(|error| {
+			throw(format!("cannot write '{path}': {error}"))
+		})
+}
+
+/// Adds a file or a directory to a store as `builtins.path` does, keeping
+/// the files below it which `filter` accepts. With `recursive`, the store
+/// path is computed from the hash of its serialisation, and otherwise from
+/// the hash of its contents, which must then be a regular file. The hash
+/// must be `expected` if it is given.
+pub fn add_path(
+	store: &StoreDir,
+	path: &str,
+	name: &str,
+	filter: nar::Filter,
+	recursive: bool,
+	expected: Option<&Hash>,
+) -> String {
+	check_name(name);
+
+	let source = Path::new(path);
+	let contents = if recursive {
+		let mut archive = Vec::new();
+		check(nar::dump(source, filter, &mut archive));
+		archive
+	} else {
+		fs::read(source).unwrap_or_else(|error| {
+			throw(format!("cannot read '{path}': {error}"))
+		})
+	};
+	let hash = Algorithm::Sha256.digest(&contents);
+
+	if expected.is_some_and(|expected| *expected != hash) {
+		throw(format!(
+			"store path mismatch in (possibly filtered) path added from \
+			 '{path}'"
+		));
+	}
+
+	let target = store.make_fixed_output_path(recursive, &hash, name);
+
+	if !Path::new(&target).exists() {
+		let _ = fs::create_dir_all(store.as_str());
+
+		if recursive {
+			check(nar::restore(&mut contents.as_slice(), Path::new(&target)));
+		} else {
+			fs::write(&target, &contents).unwrap_or_else(|error| {
+				throw(format!("cannot write '{target}': {error}"))
+			});
+		}
+	}
+
+	target
+}
+
+/// Copies a path to the store once, as interpolating it does.
+pub fn copy_path(path: &str) -> String {
+	if let Some(target) =
+		SOURCES.with_borrow(|sources| sources.get(path).cloned())
+	{
+		return target;
+	}
+
+	let name = path.rsplit('/').next().unwrap_or_default();
+	let target =
+		add_path(&store_dir(), path, name, &mut |_, _| true, true, None);
+	SOURCES
+		.with_borrow_mut(|sources| sources.insert(path.into(), target.clone()));
+	target
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::error::catch;
+
+	#[test]
+	fn check_names() {
+		assert!(catch(|| check_name("hello-1.0")).is_ok());
+		assert_eq!(
+			catch(|| check_name("a b")).unwrap_err().message(),
+			"invalid store path name 'a b'"
+		);
+	}
+
+	#[test]
+	fn add_paths() {
+		let directory = std::env::temp_dir().join("mlnx-runtime-add-paths");
+		let _ = fs::remove_dir_all(&directory);
+		fs::create_dir_all(directory.join("source")).unwrap();
+		fs::write(directory.join("source/a"), "a").unwrap();
+		fs::write(directory.join("source/b"), "b").unwrap();
+
+		let store = StoreDir::new(directory.join("store").to_str().unwrap());
+		let source = directory.join("source");
+		let source = source.to_str().unwrap();
+		let path =
+			add_path(&store, source, "source", &mut |_, _| true, true, None);
+
+		assert!(path.ends_with("-source"));
+		assert_eq!(fs::read_to_string(format!("{path}/b")).unwrap(), "b");
+
+		let filtered = add_path(
+			&store,
+			source,
+			"filtered",
+			&mut |path, _| !path.ends_with("b"),
+			true,
+			None,
+		);
+
+		assert!(Path::new(&format!("{filtered}/a")).exists());
+		assert!(!Path::new(&format!("{filtered}/b")).exists());
+		assert_eq!(
+			add_path(
+				&store,
+				&format!("{source}/a"),
+				"a",
+				&mut |_, _| true,
+				false,
+				Some(&Algorithm::Sha256.digest(b"a"))
+			),
+			store.make_fixed_output_path(
+				false,
+				&Algorithm::Sha256.digest(b"a"),
+				"a"
+			)
+		);
+	}
+}
@@ -0,0 +1,213 @@
+//! Thunks, the suspended computations making evaluation lazy.
+
+use crate::{
+	error::{self, throw},
+	gc::{self, Tracer},
+	value::{Env, ThunkFn, Value},
+};
+use std::cell::Cell;
+
+/// A suspended computation, evaluated at most once.
+pub struct Thunk {
+	state: Cell<State>,
+	/// The name of the binding the thunk is the value of, if any, which
+	/// errors are traced to.
+	name: Option<&'static str>,
+	position: Option<&'static str>,
+}
+
+#[derive(Clone, Copy)]
+enum State {
+	/// The computation has not started.
+	Pending(ThunkFn, *mut Env),
+	/// The thunk stands for another value, as the bindings of `nix.letrec`.
+	Indirect(*mut Value),
+	/// A recursive binding whose value is not bound yet.
+	Blank,
+	/// The computation is running, so forcing the thunk again would not
+	/// terminate.
+	Blackhole,
+	/// The computation has finished with a value in weak head normal form.
+	Done(*mut Value),
+}
+
+impl Thunk {
+	/// Creates a thunk running a function.
+	pub fn new(function: ThunkFn, env: *mut Env) -> Self {
+		Self {
+			state: Cell::new(State::Pending(function, env)),
+			name: None,
+			position: None,
+		}
+	}
+
+	/// Names the thunk after the binding at `position` it is the value of.
+	pub fn named(
+		mut self,
+		name: &'static str,
+		position: Option<&'static str>,
+	) -> Self {
+		self.name = Some(name);
+		self.position = position;
+		self
+	}
+
+	/// Creates a thunk to be bound later with [`Thunk::bind`].
+	pub fn blank() -> Self {
+		Self {
+			state: Cell::new(State::Blank),
+			name: None,
+			position: None,
+		}
+	}
+
+	/// Binds a blank thunk to a value.
+	pub fn bind(&self, value: *mut Value) {
+		assert!(
+			matches!(self.state.get(), State::Blank),
+			"thunk bound twice"
+		);
+		self.state.set(State::Indirect(value));
+	}
+
+	/// Marks the environment or the value the thunk refers to. A running
+	/// computation refers to its environment from the stack.
+	pub(crate) fn trace(&self, tracer: &mut Tracer) {
+		match self.state.get() {
+			State::Pending(_, env) => tracer.mark(env),
+			State::Indirect(value) | State::Done(value) => tracer.mark(value),
+			State::Blank | State::Blackhole => {}
+		}
+	}
+}
+
+/// Restores a thunk whose computation is unwinding, so that forcing it again
+/// reports the error again rather than infinite recursion.
+struct Restore<'a> {
+	thunk: &'a Thunk,
+	state: State,
+}
+
+impl Drop for Restore<'_> {
+	fn drop(&mut self) {
+		self.thunk.state.set(self.state);
+	}
+}
+
+/// Evaluates a value to weak head normal form. The result is never a thunk.
+pub fn force(value: *mut Value) -> *mut Value {
+	let Value::Thunk(thunk) = (unsafe { &*value }) else {
+		return value;
+	};
+
+	let state = thunk.state.get();
+	let result = match state {
+		State::Done(result) => return result,
+		State::Blackhole => throw("infinite recursion encountered"),
+		State::Blank => throw("recursive binding used before being bound"),
+		State::Pending(..) | State::Indirect(_) => {
+			thunk.state.set(State::Blackhole);
+			let restore = Restore { thunk, state };
+			// The thunk is only referred to through its state from now on.
+			let _root = gc::root(&[value]);
+
+			let evaluate = || {
+				force(match state {
+					State::Pending(function, env) => unsafe { function(env) },
+					State::Indirect(value) => value,
+					_ => unreachable!(),
+				})
+			};
+			let result = match thunk.name {
+				Some(name) => error::trace(
+					|| format!("while evaluating '{name}'"),
+					thunk.position,
+					evaluate,
+				),
+				None => evaluate(),
+			};
+
+			std::mem::forget(restore);
+			result
+		}
+	};
+
+	thunk.state.set(State::Done(result));
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::error::{catch, EvalError};
+	use std::sync::atomic::{AtomicUsize, Ordering};
+
+	static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+	unsafe extern "C-unwind" fn count(_: *mut Env) -> *mut Value {
+		Value::Int(CALLS.fetch_add(1, Ordering::SeqCst) as i64).alloc()
+	}
+
+	unsafe extern "C-unwind" fn recurse(env: *mut Env) -> *mut Value {
+		force((&(*env).0)[0])
+	}
+
+	unsafe extern "C-unwind" fn fail(_: *mut Env) -> *mut Value {
+		throw("oops")
+	}
+
+	fn int(value: *mut Value) -> i64 {
+		match unsafe { &*force(value) } {
+			Value::Int(value) => *value,
+			_ => panic!("not an integer"),
+		}
+	}
+
+	#[test]
+	fn memoize() {
+		let thunk =
+			Value::Thunk(Thunk::new(count, std::ptr::null_mut()).into())
+				.alloc();
+		let first = int(thunk);
+
+		assert_eq!(int(thunk), first);
+		assert_eq!(CALLS.load(Ordering::SeqCst), first as usize + 1);
+	}
+
+	#[test]
+	fn detect_infinite_recursion() {
+		let env = Env(vec![std::ptr::null_mut()]).alloc();
+		let thunk = Value::Thunk(Thunk::new(recurse, env).into()).alloc();
+		unsafe { (&mut (*env).0)[0] = thunk };
+
+		assert_eq!(
+			catch(|| force(thunk)).err(),
+			Some(EvalError::new("infinite recursion encountered"))
+		);
+	}
+
+	#[test]
+	fn restore_after_error() {
+		let thunk =
+			Value::Thunk(Thunk::new(fail, std::ptr::null_mut()).into()).alloc();
+
+		for _ in 0..2 {
+			assert_eq!(
+				catch(|| force(thunk)).err(),
+				Some(EvalError::new("oops"))
+			);
+		}
+	}
+
+	#[test]
+	fn bind_blank() {
+		let blank = Value::Thunk(Thunk::blank().into()).alloc();
+		let Value::Thunk(thunk) = (unsafe { &*blank }) else {
+			unreachable!()
+		};
+
+		assert!(catch(|| force(blank)).is_err());
+		thunk.bind(Value::Int(1).alloc());
+		assert_eq!(int(blank), 1);
+	}
+}
@@ -0,0 +1,339 @@
+//! Nix values.
+
+use crate::{
+	builtins::PrimOp,
+	gc,
+	thunk::{force, Thunk},
+};
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	fmt::Write,
+};
+
+/// The code of a thunk, called with the environment of the thunk.
+pub type ThunkFn = unsafe extern "C-unwind" fn(*mut Env) -> *mut Value;
+
+/// The code of a lambda, called with the environment of the lambda and its
+/// argument.
+pub type LambdaFn =
+	unsafe extern "C-unwind" fn(*mut Env, *mut Value) -> *mut Value;
+
+/// The values captured by a thunk or a lambda.
+pub struct Env(pub Vec<*mut Value>);
+
+impl Env {
+	/// Allocates an environment.
+	pub fn alloc(self) -> *mut Self {
+		gc::env(self)
+	}
+}
+
+/// The tags of values, which compiled code tests directly.
+pub mod tag {
+	pub const NULL: u32 = 0;
+	pub const BOOL: u32 = 1;
+	pub const INT: u32 = 2;
+	pub const FLOAT: u32 = 3;
+	pub const STRING: u32 = 4;
+	pub const PATH: u32 = 5;
+	pub const LIST: u32 = 6;
+	pub const ATTRSET: u32 = 7;
+	pub const LAMBDA: u32 = 8;
+	pub const THUNK: u32 = 9;
+	pub const PRIMOP: u32 = 10;
+}
+
+/// A Nix value. Values are shared through pointers, freed by the [garbage
+/// collector](gc) once unreachable, so only thunks mutate after construction.
+///
+/// A value is laid out as a 32-bit tag followed by a 64-bit payload, which is
+/// the value itself for scalars and a pointer otherwise.
+#[repr(C, u32)]
+pub enum Value {
+	Null = tag::NULL,
+	Bool(bool) = tag::BOOL,
+	Int(i64) = tag::INT,
+	Float(f64) = tag::FLOAT,
+	String(Box<NixString>) = tag::STRING,
+	Path(Box<String>) = tag::PATH,
+	List(Box<Vec<*mut Value>>) = tag::LIST,
+	AttrSet(Box<BTreeMap<String, *mut Value>>) = tag::ATTRSET,
+	Lambda(Box<Closure>) = tag::LAMBDA,
+	Thunk(Box<Thunk>) = tag::THUNK,
+	PrimOp(Box<PrimOp>) = tag::PRIMOP,
+}
+
+const _: () = assert!(std::mem::size_of::<Value>() == 16);
+
+/// A string with its context, the store paths it refers to.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NixString {
+	pub value: String,
+	pub context: BTreeSet<String>,
+}
+
+impl NixString {
+	/// Creates a string without context.
+	pub fn new(value: impl Into<String>) -> Self {
+		Self {
+			value: value.into(),
+			context: BTreeSet::new(),
+		}
+	}
+
+	/// Appends a string and its context.
+	pub fn push(&mut self, other: &NixString) {
+		self.value.push_str(&other.value);
+		self.context.extend(other.context.iter().cloned());
+	}
+}
+
+/// Removes `.`, `..`, repeated slashes and trailing slashes from an absolute
+/// path without accessing the file system, as Nix does for path values.
+pub fn canonicalize(path: &str) -> String {
+	let mut components = Vec::new();
+
+	for component in path.split('/') {
+		match component {
+			"" | "." => {}
+			".." => {
+				components.pop();
+			}
+			component => components.push(component),
+		}
+	}
+
+	format!("/{}", components.join("/"))
+}
+
+/// A lambda with the values it captures.
+pub struct Closure {
+	pub function: LambdaFn,
+	pub env: *mut Env,
+	/// The name of the binding the lambda is the value of, or an empty
+	/// string if it is anonymous.
+	pub name: &'static str,
+}
+
+impl Value {
+	/// Creates a string value without context.
+	pub fn string(value: impl Into<String>) -> Self {
+		Self::String(NixString::new(value).into())
+	}
+
+	/// Gets the tag of the value.
+	pub fn tag(&self) -> u32 {
+		// The layout starts with the tag.
+		unsafe { *(self as *const Self as *const u32) }
+	}
+
+	/// Allocates a value.
+	pub fn alloc(self) -> *mut Self {
+		gc::value(self)
+	}
+
+	/// Gets the type of the value as phrased in error messages.
+	pub fn type_name(&self) -> &'static str {
+		match self {
+			Self::Null => "null",
+			Self::Bool(_) => "a Boolean",
+			Self::Int(_) => "an integer",
+			Self::Float(_) => "a float",
+			Self::String(_) => "a string",
+			Self::Path(_) => "a path",
+			Self::List(_) => "a list",
+			Self::AttrSet(_) => "a set",
+			Self::Lambda(_) => "a function",
+			Self::Thunk(_) => "a thunk",
+			Self::PrimOp(primop) if primop.arguments.is_empty() => {
+				"a built-in function"
+			}
+			Self::PrimOp(_) => "a partially applied built-in function",
+		}
+	}
+}
+
+/// Forces a value deeply and prints it as `nix-instantiate --eval --strict`
+/// does.
+pub fn print(value: *mut Value) -> String {
+	// Everything printed is reachable from the value.
+	let _root = gc::root(&[value]);
+	let mut output = String::new();
+	print_into(&mut output, value);
+	output
+}
+
+fn print_into(output: &mut String, value: *mut Value) {
+	match unsafe { &*force(value) } {
+		Value::Null => output.push_str("null"),
+		Value::Bool(value) => write!(output, "{value}").unwrap(),
+		Value::Int(value) => write!(output, "{value}").unwrap(),
+		Value::Float(value) => output.push_str(&format_float(*value)),
+		Value::String(string) => print_string(output, &string.value),
+		Value::Path(path) => output.push_str(path),
+		Value::List(elements) => {
+			output.push_str("[ ");
+
+			for &element in elements.iter() {
+				print_into(output, element);
+				output.push(' ');
+			}
+
+			output.push(']');
+		}
+		Value::AttrSet(attributes) => {
+			output.push_str("{ ");
+
+			for (name, &value) in attributes.iter() {
+				print_name(output, name);
+				output.push_str(" = ");
+				print_into(output, value);
+				output.push_str("; ");
+			}
+
+			output.push('}');
+		}
+		Value::Lambda(_) => output.push_str("<LAMBDA>"),
+		Value::PrimOp(primop) if primop.arguments.is_empty() => {
+			output.push_str("<PRIMOP>")
+		}
+		Value::PrimOp(_) => output.push_str("<PRIMOP-APP>"),
+		Value::Thunk(_) => unreachable!("forced value is a thunk"),
+	}
+}
+
+fn print_string(output: &mut String, value: &str) {
+	output.push('"');
+
+	let mut characters = value.chars().peekable();
+
+	while let Some(character) = characters.next() {
+		match character {
+			'"' => output.push_str("\\\""),
+			'\\' => output.push_str("\\\\"),
+			'\n' => output.push_str("\\n"),
+			'\r' => output.push_str("\\r"),
+			'\t' => output.push_str("\\t"),
+			'$' if characters.peek() == Some(&'{') => output.push_str("\\$"),
+			character => output.push(character),
+		}
+	}
+
+	output.push('"');
+}
+
+fn print_name(output: &mut String, name: &str) {
+	let identifier = name
+		.chars()
+		.next()
+		.is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+		&& name.chars().all(|character| {
+			character.is_ascii_alphanumeric() || "_'-".contains(character)
+		});
+
+	if identifier {
+		output.push_str(name);
+	} else {
+		print_string(output, name);
+	}
+}
+
+/// Formats a float as C++ streams do by default, which is how Nix prints
+/// floats: with six significant digits and without trailing zeros.
+pub fn format_float(value: f64) -> String {
+	if !value.is_finite() {
+		return if value.is_nan() {
+			"nan".into()
+		} else if value > 0.0 {
+			"inf".into()
+		} else {
+			"-inf".into()
+		};
+	} else if value == 0.0 {
+		return if value.is_sign_negative() { "-0" } else { "0" }.into();
+	}
+
+	let scientific = format!("{value:.5e}");
+	let (mantissa, exponent) = scientific.split_once('e').unwrap();
+	let exponent = exponent.parse::<i32>().unwrap();
+
+	if (-4..6).contains(&exponent) {
+		let decimals = (5 - exponent) as usize;
+		trim_zeros(format!("{value:.decimals$}"))
+	} else {
+		format!(
+			"{}e{}{:02}",
+			trim_zeros(mantissa.into()),
+			if exponent < 0 { '-' } else { '+' },
+			exponent.abs()
+		)
+	}
+}
+
+fn trim_zeros(number: String) -> String {
+	if number.contains('.') {
+		number.trim_end_matches('0').trim_end_matches('.').into()
+	} else {
+		number
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn format_floats() {
+		assert_eq!(format_float(1.5), "1.5");
+		assert_eq!(format_float(2.0), "2");
+		assert_eq!(format_float(1.0 / 3.0), "0.333333");
+		assert_eq!(format_float(123456.7), "123457");
+		assert_eq!(format_float(1234567.0), "1.23457e+06");
+		assert_eq!(format_float(0.00001), "1e-05");
+		assert_eq!(format_float(-0.5), "-0.5");
+	}
+
+	#[test]
+	fn print_attrset() {
+		let set = Value::AttrSet(
+			BTreeMap::from([
+				("b".into(), Value::Int(1).alloc()),
+				("a b".into(), Value::Null.alloc()),
+				("c".into(), Value::AttrSet(BTreeMap::new().into()).alloc()),
+			])
+			.into(),
+		)
+		.alloc();
+
+		assert_eq!(print(set), r#"{ "a b" = null; b = 1; c = { }; }"#);
+	}
+
+	#[test]
+	fn print_list() {
+		let list = Value::List(
+			vec![
+				Value::string("a\"${b}\n").alloc(),
+				Value::Path(String::from("/c").into()).alloc(),
+				Value::List(Vec::new().into()).alloc(),
+			]
+			.into(),
+		)
+		.alloc();
+
+		assert_eq!(print(list), r#"[ "a\"\${b}\n" /c [ ] ]"#);
+	}
+
+	#[test]
+	fn canonicalize_paths() {
+		assert_eq!(canonicalize("/a/./b//c/../d/"), "/a/b/d");
+		assert_eq!(canonicalize("/.."), "/");
+	}
+
+	#[test]
+	fn read_tags() {
+		assert_eq!(Value::Null.tag(), tag::NULL);
+		assert_eq!(Value::Int(1).tag(), tag::INT);
+		assert_eq!(Value::string("").tag(), tag::STRING);
+		assert_eq!(Value::Thunk(Thunk::blank().into()).tag(), tag::THUNK);
+	}
+}
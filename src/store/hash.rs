@@ -0,0 +1,381 @@
+//! Hashes as Nix computes and encodes them.
+
+use crate::Error;
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+use std::io;
+
+/// The digits of Nix base-32, which omits `e`, `o`, `u` and `t`.
+const BASE32: &[u8; 32] = b"0123456789abcdfghijklmnpqrsvwxyz";
+
+const BASE64: &[u8; 64] =
+	b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// A hash algorithm which Nix supports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Algorithm {
+	Md5,
+	Sha1,
+	Sha256,
+	Sha512,
+}
+
+impl Algorithm {
+	/// Parses the name of an algorithm, as `sha256`.
+	pub fn parse(name: &str) -> Result<Self, Error> {
+		match name {
+			"md5" => Ok(Self::Md5),
+			"sha1" => Ok(Self::Sha1),
+			"sha256" => Ok(Self::Sha256),
+			"sha512" => Ok(Self::Sha512),
+			_ => Err(Error::UnknownAlgorithm(name.into())),
+		}
+	}
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Md5 => "md5",
+			Self::Sha1 => "sha1",
+			Self::Sha256 => "sha256",
+			Self::Sha512 => "sha512",
+		}
+	}
+
+	/// Gets the size in bytes of the hashes of the algorithm.
+	pub fn size(self) -> usize {
+		match self {
+			Self::Md5 => 16,
+			Self::Sha1 => 20,
+			Self::Sha256 => 32,
+			Self::Sha512 => 64,
+		}
+	}
+
+	pub fn digest(self, data: &[u8]) -> Hash {
+		let mut hasher = Hasher::new(self);
+		hasher.update(data);
+		hasher.finish()
+	}
+}
+
+enum State {
+	Md5(Md5),
+	Sha1(Sha1),
+	Sha256(Sha256),
+	Sha512(Sha512),
+}
+
+/// Computes a hash incrementally, as a writer to which data such as a NAR
+/// serialisation is written.
+pub struct Hasher(State);
+
+impl Hasher {
+	pub fn new(algorithm: Algorithm) -> Self {
+		Self(match algorithm {
+			Algorithm::Md5 => State::Md5(Md5::new()),
+			Algorithm::Sha1 => State::Sha1(Sha1::new()),
+			Algorithm::Sha256 => State::Sha256(Sha256::new()),
+			Algorithm::Sha512 => State::Sha512(Sha512::new()),
+		})
+	}
+
+	pub fn update(&mut self, data: &[u8]) {
+		match &mut self.0 {
+			State::Md5(hasher) => hasher.update(data),
+			State::Sha1(hasher) => hasher.update(data),
+			State::Sha256(hasher) => hasher.update(data),
+			State::Sha512(hasher) => hasher.update(data),
+		}
+	}
+
+	pub fn finish(self) -> Hash {
+		let (algorithm, bytes) = match self.0 {
+			State::Md5(hasher) => (Algorithm::Md5, hasher.finalize().to_vec()),
+			State::Sha1(hasher) => {
+				(Algorithm::Sha1, hasher.finalize().to_vec())
+			}
+			State::Sha256(hasher) => {
+				(Algorithm::Sha256, hasher.finalize().to_vec())
+			}
+			State::Sha512(hasher) => {
+				(Algorithm::Sha512, hasher.finalize().to_vec())
+			}
+		};
+
+		Hash { algorithm, bytes }
+	}
+}
+
+impl io::Write for Hasher {
+	fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+		self.update(data);
+		Ok(data.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+/// A hash with its algorithm.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hash {
+	pub algorithm: Algorithm,
+	pub bytes: Vec<u8>,
+}
+
+impl Hash {
+	/// Parses a hash in base 16, Nix base 32 or base 64, prefixed by its
+	/// algorithm as `sha256:...`, or in the SRI format `sha256-...`. The
+	/// algorithm may be omitted if it is given.
+	pub fn parse(
+		text: &str,
+		algorithm: Option<Algorithm>,
+	) -> Result<Self, Error> {
+		let (prefix, digits) =
+			if let Some((prefix, digits)) = text.split_once(':') {
+				(Some(prefix), digits)
+			} else if let Some((prefix, digits)) = text.split_once('-') {
+				(Some(prefix), digits)
+			} else {
+				(None, text)
+			};
+		let algorithm =
+			match (prefix.map(Algorithm::parse).transpose()?, algorithm) {
+				(Some(prefix), Some(algorithm)) if prefix != algorithm => {
+					return Err(Error::InvalidHash(format!(
+						"hash '{text}' should have type '{}'",
+						algorithm.name()
+					)))
+				}
+				(Some(algorithm), _) | (None, Some(algorithm)) => algorithm,
+				(None, None) => {
+					return Err(Error::InvalidHash(format!(
+						"hash '{text}' does not include a type"
+					)))
+				}
+			};
+		let size = algorithm.size();
+
+		let bytes = if digits.len() == 2 * size {
+			decode_base16(digits)
+		} else if digits.len() == (size * 8 - 1) / 5 + 1 {
+			decode_base32(digits, size)
+		} else if digits.len() == (4 * size / 3 + 3) & !3 {
+			decode_base64(digits)
+		} else {
+			None
+		};
+
+		match bytes {
+			Some(bytes) if bytes.len() == size => Ok(Self { algorithm, bytes }),
+			_ => Err(Error::InvalidHash(format!(
+				"invalid {} hash '{text}'",
+				algorithm.name()
+			))),
+		}
+	}
+
+	pub fn base16(&self) -> String {
+		base16(&self.bytes)
+	}
+
+	pub fn base32(&self) -> String {
+		base32(&self.bytes)
+	}
+
+	pub fn base64(&self) -> String {
+		base64(&self.bytes)
+	}
+
+	/// Formats the hash in the SRI format, as `sha256-...`.
+	pub fn sri(&self) -> String {
+		format!("{}-{}", self.algorithm.name(), self.base64())
+	}
+}
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+	Sha256::digest(data).into()
+}
+
+pub fn base16(bytes: &[u8]) -> String {
+	bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Formats bytes in Nix base 32, which starts from the last bit.
+pub fn base32(bytes: &[u8]) -> String {
+	let length = (bytes.len() * 8).div_ceil(5);
+
+	(0..length)
+		.rev()
+		.map(|index| {
+			let bit = index * 5;
+			let (byte, shift) = (bit / 8, bit % 8);
+			let low = bytes[byte] as u32 >> shift;
+			let high = bytes
+				.get(byte + 1)
+				.map_or(0, |&next| (next as u32) << (8 - shift));
+
+			BASE32[((low | high) & 0x1f) as usize] as char
+		})
+		.collect()
+}
+
+/// Formats bytes in padded base 64.
+pub fn base64(bytes: &[u8]) -> String {
+	let mut text = String::new();
+
+	for chunk in bytes.chunks(3) {
+		let buffer = chunk
+			.iter()
+			.enumerate()
+			.fold(0u32, |buffer, (index, &byte)| {
+				buffer | (byte as u32) << (16 - 8 * index)
+			});
+
+		for index in 0..4 {
+			if index <= chunk.len() {
+				let digit = buffer >> (18 - 6 * index) & 0x3f;
+				text.push(BASE64[digit as usize] as char);
+			} else {
+				text.push('=');
+			}
+		}
+	}
+
+	text
+}
+
+/// Folds a hash into `size` bytes by XORing its bytes, as Nix does for the
+/// hashes of store paths.
+pub fn compress(bytes: &[u8], size: usize) -> Vec<u8> {
+	let mut compressed = vec![0; size];
+
+	for (index, byte) in bytes.iter().enumerate() {
+		compressed[index % size] ^= byte;
+	}
+
+	compressed
+}
+
+fn decode_base16(digits: &str) -> Option<Vec<u8>> {
+	(0..digits.len())
+		.step_by(2)
+		.map(|index| u8::from_str_radix(digits.get(index..index + 2)?, 16).ok())
+		.collect()
+}
+
+fn decode_base32(digits: &str, size: usize) -> Option<Vec<u8>> {
+	let mut bytes = vec![0u8; size];
+
+	for (index, digit) in digits.bytes().rev().enumerate() {
+		let digit = BASE32.iter().position(|&other| other == digit)? as u32;
+		let bit = index * 5;
+		let (byte, shift) = (bit / 8, bit % 8);
+
+		bytes[byte] |= (digit << shift) as u8;
+
+		match bytes.get_mut(byte + 1) {
+			Some(next) => *next |= (digit >> (8 - shift)) as u8,
+			None if digit >> (8 - shift) != 0 => return None,
+			None => {}
+		}
+	}
+
+	Some(bytes)
+}
+
+fn decode_base64(digits: &str) -> Option<Vec<u8>> {
+	let mut bytes = Vec::new();
+	let mut buffer = 0u32;
+	let mut bits = 0;
+
+	for digit in digits.trim_end_matches('=').bytes() {
+		let value = BASE64.iter().position(|&other| other == digit)?;
+		buffer = buffer << 6 | value as u32;
+		bits += 6;
+
+		if bits >= 8 {
+			bits -= 8;
+			bytes.push((buffer >> bits) as u8);
+		}
+	}
+
+	Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn compute_known_hashes() {
+		let hash = |algorithm| Algorithm::digest(algorithm, b"abc").base16();
+
+		assert_eq!(hash(Algorithm::Md5), "900150983cd24fb0d6963f7d28e17f72");
+		assert_eq!(
+			hash(Algorithm::Sha1),
+			"a9993e364706816aba3e25717850c26c9cd0d89d"
+		);
+		assert_eq!(
+			hash(Algorithm::Sha256),
+			"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+		);
+		assert_eq!(
+			hash(Algorithm::Sha512),
+			"ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+			 2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+		);
+	}
+
+	#[test]
+	fn encode_hashes() {
+		let hash = Algorithm::Sha256.digest(b"abc");
+
+		assert_eq!(
+			hash.base32(),
+			"1b8m03r63zqhnjf7l5wnldhh7c134ap5vpj0850ymkq1iyzicy5s"
+		);
+		assert_eq!(
+			hash.sri(),
+			"sha256-ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0="
+		);
+		assert_eq!(
+			Algorithm::Sha256.digest(b"").base32(),
+			"0mdqa9w1p6cmli6976v4wi0sw9r4p5prkj7lzfd1877wk11c9c73"
+		);
+		assert_eq!(base64(b"ab"), "YWI=");
+		assert_eq!(compress(&[1, 2, 3], 2), [2, 2]);
+	}
+
+	#[test]
+	fn parse_hashes() {
+		let hash = Algorithm::Sha256.digest(b"abc");
+
+		for text in [
+			"1b8m03r63zqhnjf7l5wnldhh7c134ap5vpj0850ymkq1iyzicy5s",
+			"ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0=",
+			&hash.base16(),
+		] {
+			assert_eq!(
+				Hash::parse(text, Some(Algorithm::Sha256)).unwrap(),
+				hash
+			);
+		}
+
+		assert_eq!(Hash::parse(&hash.sri(), None).unwrap(), hash);
+		assert_eq!(
+			Hash::parse(&format!("sha256:{}", hash.base16()), None).unwrap(),
+			hash
+		);
+		assert_eq!(
+			Hash::parse("sha1:abc", Some(Algorithm::Sha256))
+				.unwrap_err()
+				.to_string(),
+			"hash 'sha1:abc' should have type 'sha256'"
+		);
+		assert!(Hash::parse("abc", None).is_err());
+		assert!(Hash::parse("sha3:abc", None).is_err());
+	}
+}
@@ -0,0 +1,52 @@
+//! Store paths and hashes as Nix computes them.
+//!
+//! This crate implements the parts of the Nix store which evaluation needs
+//! without a Nix daemon: the hash encodings of [`hash`], the NAR
+//! serialisation of files in [`nar`] and the computation of store paths in
+//! [`path`]. It performs no network access and writes nothing to the store.
+
+pub mod hash;
+pub mod nar;
+pub mod path;
+
+pub use hash::{Algorithm, Hash};
+pub use path::StoreDir;
+
+use std::{fmt, io};
+
+/// An error of the store.
+#[derive(Debug)]
+pub enum Error {
+	/// A hash which cannot be parsed.
+	InvalidHash(String),
+	UnknownAlgorithm(String),
+	InvalidName(String),
+	/// A file which is neither regular, a directory nor a symbolic link.
+	UnsupportedFile(String),
+	InvalidArchive(String),
+	/// A file which cannot be read, by its path.
+	Io(String, io::Error),
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::InvalidHash(message) => formatter.write_str(message),
+			Self::UnknownAlgorithm(name) => {
+				write!(formatter, "unknown hash algorithm '{name}'")
+			}
+			Self::InvalidName(name) => {
+				write!(formatter, "invalid store path name '{name}'")
+			}
+			Self::UnsupportedFile(path) => {
+				write!(formatter, "file '{path}' has an unsupported type")
+			}
+			Self::InvalidArchive(message) => formatter.write_str(message),
+			Self::Io(path, error) => {
+				write!(formatter, "cannot read '{path}': {error}")
+			}
+		}
+	}
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,414 @@
+//! The Nix archive format, which serialises files deterministically.
+//!
+//! An archive is a sequence of strings, each prefixed by its length as a
+//! 64-bit little-endian integer and padded with zeros to a multiple of eight
+//! bytes. Files are described by nested `(` ... `)` strings, with the entries
+//! of directories sorted by name, so that only their contents, their type
+//! and whether they are executable matter.
+
+use crate::{
+	hash::{Algorithm, Hash, Hasher},
+	Error,
+};
+use std::{
+	fs,
+	io::{self, Read, Write},
+	os::unix::fs::{symlink, OpenOptionsExt, PermissionsExt},
+	path::Path,
+};
+
+const MAGIC: &str = "nix-archive-1";
+
+/// The type of a file, as given to the filters of [`dump`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+	Regular,
+	Directory,
+	Symlink,
+	Unknown,
+}
+
+impl Kind {
+	fn of(metadata: &fs::Metadata) -> Self {
+		let r#type = metadata.file_type();
+
+		if r#type.is_file() {
+			Self::Regular
+		} else if r#type.is_dir() {
+			Self::Directory
+		} else if r#type.is_symlink() {
+			Self::Symlink
+		} else {
+			Self::Unknown
+		}
+	}
+
+	/// Gets the name of the type, as `builtins.readDir` returns it.
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Regular => "regular",
+			Self::Directory => "directory",
+			Self::Symlink => "symlink",
+			Self::Unknown => "unknown",
+		}
+	}
+}
+
+/// Decides whether a file below the serialised one is included.
+pub type Filter<'a> = &'a mut dyn FnMut(&Path, Kind) -> bool;
+
+fn io_error(path: &Path) -> impl FnOnce(io::Error) -> Error + '_ {
+	move |error| Error::Io(path.display().to_string(), error)
+}
+
+fn write_bytes(sink: &mut dyn Write, bytes: &[u8]) -> io::Result<()> {
+	sink.write_all(&(bytes.len() as u64).to_le_bytes())?;
+	sink.write_all(bytes)?;
+	sink.write_all(&[0; 8][..(8 - bytes.len() % 8) % 8])
+}
+
+fn write_strings(sink: &mut dyn Write, strings: &[&str]) -> io::Result<()> {
+	strings
+		.iter()
+		.try_for_each(|string| write_bytes(sink, string.as_bytes()))
+}
+
+/// Serialises a file, a symbolic link or a directory, as
+/// `nix-store --dump` does, leaving out the files below it which `filter`
+/// rejects.
+pub fn dump(
+	path: &Path,
+	filter: Filter,
+	sink: &mut dyn Write,
+) -> Result<(), Error> {
+	write_strings(sink, &[MAGIC]).map_err(io_error(path))?;
+	dump_node(path, filter, sink)
+}
+
+fn dump_node(
+	path: &Path,
+	filter: Filter,
+	sink: &mut dyn Write,
+) -> Result<(), Error> {
+	let metadata = fs::symlink_metadata(path).map_err(io_error(path))?;
+
+	match Kind::of(&metadata) {
+		Kind::Regular => {
+			let contents = fs::read(path).map_err(io_error(path))?;
+			let executable = metadata.permissions().mode() & 0o100 != 0;
+
+			write_strings(sink, &["(", "type", "regular"])
+				.and_then(|_| {
+					if executable {
+						write_strings(sink, &["executable", ""])
+					} else {
+						Ok(())
+					}
+				})
+				.and_then(|_| write_strings(sink, &["contents"]))
+				.and_then(|_| write_bytes(sink, &contents))
+				.and_then(|_| write_strings(sink, &[")"]))
+				.map_err(io_error(path))
+		}
+		Kind::Symlink => {
+			let target = fs::read_link(path).map_err(io_error(path))?;
+
+			write_strings(sink, &["(", "type", "symlink", "target"])
+				.and_then(|_| {
+					write_bytes(sink, target.as_os_str().as_encoded_bytes())
+				})
+				.and_then(|_| write_strings(sink, &[")"]))
+				.map_err(io_error(path))
+		}
+		Kind::Directory => {
+			let mut entries = fs::read_dir(path)
+				.and_then(|entries| {
+					entries
+						.map(|entry| entry.map(|entry| entry.file_name()))
+						.collect::<io::Result<Vec<_>>>()
+				})
+				.map_err(io_error(path))?;
+			entries.sort();
+
+			write_strings(sink, &["(", "type", "directory"])
+				.map_err(io_error(path))?;
+
+			for name in entries {
+				let child = path.join(&name);
+				let metadata =
+					fs::symlink_metadata(&child).map_err(io_error(&child))?;
+
+				if !filter(&child, Kind::of(&metadata)) {
+					continue;
+				}
+
+				write_strings(sink, &["entry", "(", "name"])
+					.and_then(|_| {
+						write_bytes(sink, name.as_os_str().as_encoded_bytes())
+					})
+					.and_then(|_| write_strings(sink, &["node"]))
+					.map_err(io_error(path))?;
+				dump_node(&child, filter, sink)?;
+				write_strings(sink, &[")"]).map_err(io_error(path))?;
+			}
+
+			write_strings(sink, &[")"]).map_err(io_error(path))
+		}
+		Kind::Unknown => {
+			Err(Error::UnsupportedFile(path.display().to_string()))
+		}
+	}
+}
+
+/// Hashes the serialisation of a file, as `nix-hash --type` does.
+pub fn hash_path(
+	path: &Path,
+	algorithm: Algorithm,
+	filter: Filter,
+) -> Result<Hash, Error> {
+	let mut hasher = Hasher::new(algorithm);
+	dump(path, filter, &mut hasher)?;
+	Ok(hasher.finish())
+}
+
+fn read_bytes(source: &mut dyn Read) -> io::Result<Vec<u8>> {
+	let mut length = [0; 8];
+	source.read_exact(&mut length)?;
+	let length = u64::from_le_bytes(length) as usize;
+	let mut bytes = vec![0; length.next_multiple_of(8)];
+	source.read_exact(&mut bytes)?;
+
+	if bytes[length..].iter().any(|&byte| byte != 0) {
+		return Err(io::Error::new(
+			io::ErrorKind::InvalidData,
+			"non-zero padding",
+		));
+	}
+
+	bytes.truncate(length);
+	Ok(bytes)
+}
+
+/// Reads strings from an archive, reporting unexpected ones.
+struct Reader<'a> {
+	source: &'a mut dyn Read,
+	path: &'a Path,
+}
+
+impl Reader<'_> {
+	fn bytes(&mut self) -> Result<Vec<u8>, Error> {
+		read_bytes(self.source).map_err(io_error(self.path))
+	}
+
+	fn string(&mut self) -> Result<String, Error> {
+		String::from_utf8(self.bytes()?).map_err(|_| self.invalid("a string"))
+	}
+
+	fn expect(&mut self, expected: &str) -> Result<(), Error> {
+		if self.string()? == expected {
+			Ok(())
+		} else {
+			Err(self.invalid(&format!("'{expected}'")))
+		}
+	}
+
+	fn invalid(&self, expected: &str) -> Error {
+		Error::InvalidArchive(format!(
+			"expected {expected} in the archive of '{}'",
+			self.path.display()
+		))
+	}
+}
+
+/// Deserialises an archive to `path`, which must not exist, as
+/// `nix-store --restore` does.
+pub fn restore(source: &mut dyn Read, path: &Path) -> Result<(), Error> {
+	let mut reader = Reader { source, path };
+	reader.expect(MAGIC)?;
+	restore_node(&mut reader, path)
+}
+
+fn restore_node(reader: &mut Reader, path: &Path) -> Result<(), Error> {
+	reader.expect("(")?;
+	reader.expect("type")?;
+
+	match reader.string()?.as_str() {
+		"regular" => {
+			let mut next = reader.string()?;
+			let executable = next == "executable";
+
+			if executable {
+				reader.expect("")?;
+				next = reader.string()?;
+			}
+
+			if next != "contents" {
+				return Err(reader.invalid("'contents'"));
+			}
+
+			let contents = reader.bytes()?;
+			fs::OpenOptions::new()
+				.write(true)
+				.create_new(true)
+				.mode(if executable { 0o555 } else { 0o444 })
+				.open(path)
+				.and_then(|mut file| file.write_all(&contents))
+				.map_err(io_error(path))?;
+		}
+		"symlink" => {
+			reader.expect("target")?;
+			let target = reader.string()?;
+			symlink(target, path).map_err(io_error(path))?;
+		}
+		"directory" => {
+			fs::create_dir(path).map_err(io_error(path))?;
+
+			loop {
+				match reader.string()?.as_str() {
+					")" => return Ok(()),
+					"entry" => {
+						reader.expect("(")?;
+						reader.expect("name")?;
+						let name = reader.string()?;
+
+						if name.is_empty()
+							|| name == "." || name == ".."
+							|| name.contains('/')
+						{
+							return Err(reader.invalid("a file name"));
+						}
+
+						reader.expect("node")?;
+						restore_node(reader, &path.join(name))?;
+						reader.expect(")")?;
+					}
+					_ => return Err(reader.invalid("'entry' or ')'")),
+				}
+			}
+		}
+		_ => return Err(reader.invalid("a file type")),
+	}
+
+	reader.expect(")")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn strings(strings: &[&[u8]]) -> Vec<u8> {
+		let mut bytes = Vec::new();
+
+		for string in strings {
+			write_bytes(&mut bytes, string).unwrap();
+		}
+
+		bytes
+	}
+
+	fn directory(name: &str) -> std::path::PathBuf {
+		let directory = std::env::temp_dir().join(name);
+		let _ = fs::remove_dir_all(&directory);
+		fs::create_dir_all(&directory).unwrap();
+		directory
+	}
+
+	#[test]
+	fn pad_strings() {
+		assert_eq!(strings(&[b""]), [0; 8]);
+		assert_eq!(
+			strings(&[b"abc"]),
+			[3, 0, 0, 0, 0, 0, 0, 0, b'a', b'b', b'c', 0, 0, 0, 0, 0]
+		);
+	}
+
+	#[test]
+	fn dump_directories() {
+		let directory = directory("mlnx-store-dump-directories");
+		fs::write(directory.join("b"), "hello").unwrap();
+		fs::write(directory.join("a"), "").unwrap();
+		fs::set_permissions(
+			directory.join("a"),
+			fs::Permissions::from_mode(0o755),
+		)
+		.unwrap();
+		symlink("b", directory.join("c")).unwrap();
+		fs::write(directory.join("d"), "").unwrap();
+
+		let mut bytes = Vec::new();
+		dump(&directory, &mut |path, _| !path.ends_with("d"), &mut bytes)
+			.unwrap();
+
+		assert_eq!(
+			bytes,
+			strings(&[
+				b"nix-archive-1",
+				b"(",
+				b"type",
+				b"directory",
+				b"entry",
+				b"(",
+				b"name",
+				b"a",
+				b"node",
+				b"(",
+				b"type",
+				b"regular",
+				b"executable",
+				b"",
+				b"contents",
+				b"",
+				b")",
+				b")",
+				b"entry",
+				b"(",
+				b"name",
+				b"b",
+				b"node",
+				b"(",
+				b"type",
+				b"regular",
+				b"contents",
+				b"hello",
+				b")",
+				b")",
+				b"entry",
+				b"(",
+				b"name",
+				b"c",
+				b"node",
+				b"(",
+				b"type",
+				b"symlink",
+				b"target",
+				b"b",
+				b")",
+				b")",
+				b")",
+			])
+		);
+
+		let copy = directory.with_extension("copy");
+		let _ = fs::remove_dir_all(&copy);
+		restore(&mut bytes.as_slice(), &copy).unwrap();
+
+		assert_eq!(fs::read_to_string(copy.join("b")).unwrap(), "hello");
+		assert_eq!(fs::read_link(copy.join("c")).unwrap(), Path::new("b"));
+		assert!(!copy.join("d").exists());
+		assert_eq!(
+			hash_path(&copy, Algorithm::Sha256, &mut |_, _| true).unwrap(),
+			Algorithm::Sha256.digest(&bytes)
+		);
+	}
+
+	#[test]
+	fn reject_invalid_archives() {
+		let bytes = strings(&[b"nix-archive-1", b"(", b"type", b"socket"]);
+		let path = std::env::temp_dir().join("mlnx-store-invalid-archive");
+
+		assert!(matches!(
+			restore(&mut bytes.as_slice(), &path),
+			Err(Error::InvalidArchive(_))
+		));
+	}
+}
@@ -0,0 +1,223 @@
+//! Store paths, computed from the type, hash and name of store objects.
+
+use crate::{
+	hash::{base16, base32, compress, sha256, Algorithm, Hash},
+	Error,
+};
+use std::collections::BTreeSet;
+
+/// The directory of a store, as `/nix/store`, in which store paths are
+/// computed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StoreDir(String);
+
+impl StoreDir {
+	pub fn new(directory: impl Into<String>) -> Self {
+		Self(directory.into())
+	}
+
+	pub fn as_str(&self) -> &str {
+		&self.0
+	}
+
+	/// Computes a store path from the type of the object, as `source` or
+	/// `output:out`, the SHA-256 hash of its contents and its name.
+	pub fn make_store_path(
+		&self,
+		r#type: &str,
+		hash: &[u8],
+		name: &str,
+	) -> String {
+		let fingerprint =
+			format!("{type}:sha256:{}:{}:{name}", base16(hash), self.0);
+
+		format!(
+			"{}/{}-{name}",
+			self.0,
+			base32(&compress(&sha256(fingerprint.as_bytes()), 20))
+		)
+	}
+
+	/// Computes the path of an output of an input-addressed derivation from
+	/// the hash of the derivation modulo fixed-output derivations.
+	pub fn make_output_path(
+		&self,
+		output: &str,
+		hash: &[u8],
+		name: &str,
+	) -> String {
+		let name = if output == "out" {
+			name.into()
+		} else {
+			format!("{name}-{output}")
+		};
+
+		self.make_store_path(&format!("output:{output}"), hash, &name)
+	}
+
+	/// Computes the path of a text file, such as a `.drv` file, which refers
+	/// to other store paths.
+	pub fn make_text_path(
+		&self,
+		name: &str,
+		contents: &str,
+		references: &BTreeSet<String>,
+	) -> String {
+		let mut r#type = String::from("text");
+
+		for reference in references {
+			r#type.push(':');
+			r#type.push_str(reference);
+		}
+
+		self.make_store_path(&r#type, &sha256(contents.as_bytes()), name)
+	}
+
+	/// Computes the path of a fixed-output store object from its hash,
+	/// either of its contents or, with `recursive`, of their serialisation.
+	pub fn make_fixed_output_path(
+		&self,
+		recursive: bool,
+		hash: &Hash,
+		name: &str,
+	) -> String {
+		if recursive && hash.algorithm == Algorithm::Sha256 {
+			self.make_store_path("source", &hash.bytes, name)
+		} else {
+			let method = if recursive { "r:" } else { "" };
+			let fingerprint = format!(
+				"fixed:out:{method}{}:{}:",
+				hash.algorithm.name(),
+				hash.base16()
+			);
+
+			self.make_store_path(
+				"output:out",
+				&sha256(fingerprint.as_bytes()),
+				name,
+			)
+		}
+	}
+}
+
+/// Checks that a name is valid for a store path.
+pub fn check_name(name: &str) -> Result<(), Error> {
+	let valid = !name.is_empty()
+		&& name.len() <= 211
+		&& !name.starts_with('.')
+		&& name.chars().all(|character| {
+			character.is_ascii_alphanumeric() || "+-._?=".contains(character)
+		});
+
+	if valid {
+		Ok(())
+	} else {
+		Err(Error::InvalidName(name.into()))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn compute_store_paths() {
+		let store = StoreDir::new("/nix/store");
+		let path = store.make_text_path("a", "", &BTreeSet::new());
+		let (hash, name) = path
+			.strip_prefix("/nix/store/")
+			.unwrap()
+			.split_once('-')
+			.unwrap();
+
+		assert_eq!(hash.len(), 32);
+		assert_eq!(name, "a");
+		assert_ne!(
+			store.make_output_path("out", &[0; 32], "a"),
+			store.make_output_path("dev", &[0; 32], "a")
+		);
+		assert!(store
+			.make_output_path("dev", &[0; 32], "a")
+			.ends_with("-a-dev"));
+		assert_ne!(
+			StoreDir::new("/store").make_text_path("a", "", &BTreeSet::new()),
+			path.replace("/nix/store", "/store")
+		);
+	}
+
+	/// Paths computed by Nix for the examples of the Nix Pills: a file
+	/// written by `builtins.toFile`, here the `.drv` file of a derivation,
+	/// and its output.
+	#[test]
+	fn compute_known_store_paths() {
+		let store = StoreDir::new("/nix/store");
+		let out = "/nix/store/40s0qmrfb45vlh6610rk29ym318dswdr-myname";
+		let text = format!(
+			r#"Derive([("out","{out}","","")],[],[],"mysystem","mybuilder",[],[("builder","mybuilder"),("name","myname"),("out","{out}"),("system","mysystem")])"#
+		);
+
+		assert_eq!(
+			store.make_text_path("myname.drv", &text, &BTreeSet::new()),
+			"/nix/store/z3hhlxbckx4g3n9sw91nnvlkjvyw754p-myname.drv"
+		);
+	}
+
+	#[test]
+	fn compute_fixed_output_paths() {
+		let store = StoreDir::new("/nix/store");
+		let hash = Algorithm::Sha256.digest(b"abc");
+
+		assert_eq!(
+			store.make_fixed_output_path(true, &hash, "a"),
+			store.make_store_path("source", &hash.bytes, "a")
+		);
+		assert_ne!(
+			store.make_fixed_output_path(false, &hash, "a"),
+			store.make_fixed_output_path(true, &hash, "a")
+		);
+		assert_ne!(
+			store.make_fixed_output_path(
+				true,
+				&Algorithm::Sha1.digest(b"abc"),
+				"a"
+			),
+			store.make_fixed_output_path(true, &hash, "a")
+		);
+	}
+
+	/// Paths computed by Nix for a file containing `mycontent\n`, added with
+	/// `nix-store --add` and fetched by a flat fixed-output derivation as
+	/// `fetchurl` does.
+	#[test]
+	fn compute_known_fixed_output_paths() {
+		let store = StoreDir::new("/nix/store");
+		let hash = |text| Hash::parse(text, Some(Algorithm::Sha256)).unwrap();
+		let nar = hash(
+			"2bfef67de873c54551d884fdab3055d84d573e654efa79db3c0d7b98883f9ee3",
+		);
+		let flat = hash(
+			"f3f3c4763037e059b4d834eaf68595bbc02ba19f6d2a500dce06d124e2cd99bb",
+		);
+
+		assert_eq!(
+			store.make_fixed_output_path(true, &nar, "myfile"),
+			"/nix/store/xv2iccirbrvklck36f1g7vldn5v58vck-myfile"
+		);
+		assert_eq!(
+			store.make_fixed_output_path(false, &flat, "bar"),
+			"/nix/store/a00d5f71k0vp5a6klkls0mvr1f7sx6ch-bar"
+		);
+		assert_eq!(flat, Algorithm::Sha256.digest(b"mycontent\n"));
+	}
+
+	#[test]
+	fn check_names() {
+		assert!(check_name("hello-1.0").is_ok());
+		assert!(check_name(".hidden").is_err());
+		assert!(check_name("a b").is_err());
+		assert_eq!(
+			check_name("").unwrap_err().to_string(),
+			"invalid store path name ''"
+		);
+	}
+}
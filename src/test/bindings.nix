@@ -0,0 +1,8 @@
+let
+  double = x: x * 2;
+  set = rec {
+    a = double 2;
+    b = a + 1;
+  };
+in
+[ set (set.a / 3) "${toString set.b}!" (builtins.length [ 1 2 3 ]) ]
@@ -0,0 +1 @@
+let x = throw "boom"; in x + 1
@@ -0,0 +1,8 @@
+let
+  positive = x: assert x > 0; x;
+in
+[
+  (builtins.tryEval (positive 1))
+  (builtins.tryEval (positive (-1)))
+  (builtins.tryEval (throw "oops")).success
+]